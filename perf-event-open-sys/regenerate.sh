@@ -5,6 +5,11 @@ set -eu
 cd $(dirname $0)
 
 arch=$(uname -m)
+case "$arch" in
+    # Rust's `target_arch` doesn't distinguish endianness.
+    ppc64 | ppc64le) arch=powerpc64 ;;
+    armv*) arch=arm ;;
+esac
 bindings="src/bindings_${arch}.rs"
 if ! [ -f "$bindings" ]; then
     echo "Unexpected architecture name: $arch" >&2
@@ -15,8 +20,19 @@ if ! [ -f "$bindings" ]; then
     exit 1
 fi
 
+# wrapper.h includes <asm/perf_regs.h>, which only the kernel headers for
+# this architecture provide (linux-libc-dev, kernel-headers, and so on).
+if ! echo '#include <asm/perf_regs.h>' | cc -E - > /dev/null 2>&1; then
+    echo "Can't find <asm/perf_regs.h> for $arch." >&2
+    echo "Install your distribution's kernel headers package and try again." >&2
+    exit 1
+fi
+
 (
     cat src/bindings_header.rs
+    # bindgen casts some constants to the type they already have.
+    echo '#![allow(clippy::unnecessary_cast)]'
+    echo
     bindgen                                     \
         --impl-debug                            \
         --with-derive-default                   \
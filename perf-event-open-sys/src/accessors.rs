@@ -0,0 +1,122 @@
+//! Stable, named accessors for fields of anonymous unions in the bindings.
+//!
+//! `bindgen` gives the anonymous unions in the kernel's structs names like
+//! `__bindgen_anon_3`, numbered by position. Those names can change whenever
+//! the bindings are regenerated from newer headers, breaking any code that
+//! uses them. The methods defined here give each union member a fixed name,
+//! matching the member's name in the C headers, so code written against them
+//! keeps compiling across regenerations.
+//!
+//! When regenerating the bindings, update the union paths below to match.
+//! The method names must never change.
+//!
+//! All the members of these unions are plain integers of the same size, so
+//! reading any of them is safe.
+
+use crate::bindings::{
+    __u16, __u32, __u64, perf_event_attr, perf_event_mmap_page, perf_mem_data_src,
+    perf_sample_weight,
+};
+
+macro_rules! union_accessors {
+    ( $( $union:ident . $field:ident , $setter:ident : $ty:ty ; )* ) => {
+        $(
+            #[doc = concat!("Return the `", stringify!($field), "` member of its union.")]
+            #[inline]
+            pub fn $field(&self) -> $ty {
+                unsafe { self.$union.$field }
+            }
+
+            #[doc = concat!("Set the `", stringify!($field), "` member of its union.")]
+            #[inline]
+            pub fn $setter(&mut self, value: $ty) {
+                self.$union.$field = value;
+            }
+        )*
+    };
+}
+
+impl perf_event_attr {
+    union_accessors! {
+        __bindgen_anon_1 . sample_period, set_sample_period: __u64;
+        __bindgen_anon_1 . sample_freq, set_sample_freq: __u64;
+        __bindgen_anon_2 . wakeup_events, set_wakeup_events: __u32;
+        __bindgen_anon_2 . wakeup_watermark, set_wakeup_watermark: __u32;
+        __bindgen_anon_3 . bp_addr, set_bp_addr: __u64;
+        __bindgen_anon_3 . kprobe_func, set_kprobe_func: __u64;
+        __bindgen_anon_3 . uprobe_path, set_uprobe_path: __u64;
+        __bindgen_anon_3 . config1, set_config1: __u64;
+        __bindgen_anon_4 . bp_len, set_bp_len: __u64;
+        __bindgen_anon_4 . kprobe_addr, set_kprobe_addr: __u64;
+        __bindgen_anon_4 . probe_offset, set_probe_offset: __u64;
+        __bindgen_anon_4 . config2, set_config2: __u64;
+    }
+}
+
+impl perf_event_mmap_page {
+    union_accessors! {
+        __bindgen_anon_1 . capabilities, set_capabilities: __u64;
+    }
+
+    /// Return the `cap_user_rdpmc` bit of `capabilities`: whether user space
+    /// may read the counter directly, using `index`.
+    #[inline]
+    pub fn cap_user_rdpmc(&self) -> bool {
+        unsafe { self.__bindgen_anon_1.__bindgen_anon_1.cap_user_rdpmc() != 0 }
+    }
+
+    /// Return the `cap_user_time` bit of `capabilities`: whether the
+    /// `time_shift`, `time_mult`, and `time_offset` fields are valid.
+    #[inline]
+    pub fn cap_user_time(&self) -> bool {
+        unsafe { self.__bindgen_anon_1.__bindgen_anon_1.cap_user_time() != 0 }
+    }
+
+    /// Return the `cap_user_time_zero` bit of `capabilities`: whether the
+    /// `time_zero` field is valid.
+    #[inline]
+    pub fn cap_user_time_zero(&self) -> bool {
+        unsafe { self.__bindgen_anon_1.__bindgen_anon_1.cap_user_time_zero() != 0 }
+    }
+
+    /// Return the `cap_user_time_short` bit of `capabilities`: whether the
+    /// `time_cycles` and `time_mask` fields are valid.
+    #[inline]
+    pub fn cap_user_time_short(&self) -> bool {
+        unsafe { self.__bindgen_anon_1.__bindgen_anon_1.cap_user_time_short() != 0 }
+    }
+}
+
+impl perf_mem_data_src {
+    /// Return the whole value, `val`, as a `u64`.
+    #[inline]
+    pub fn val(&self) -> __u64 {
+        unsafe { self.val }
+    }
+}
+
+impl perf_sample_weight {
+    /// Return the whole weight, `full`, as a `u64`.
+    #[inline]
+    pub fn full(&self) -> __u64 {
+        unsafe { self.full }
+    }
+
+    /// Return the `var1_dw` member of the weight's structured form.
+    #[inline]
+    pub fn var1_dw(&self) -> __u32 {
+        unsafe { self.__bindgen_anon_1.var1_dw }
+    }
+
+    /// Return the `var2_w` member of the weight's structured form.
+    #[inline]
+    pub fn var2_w(&self) -> __u16 {
+        unsafe { self.__bindgen_anon_1.var2_w }
+    }
+
+    /// Return the `var3_w` member of the weight's structured form.
+    #[inline]
+    pub fn var3_w(&self) -> __u16 {
+        unsafe { self.__bindgen_anon_1.var3_w }
+    }
+}
@@ -22,6 +22,7 @@
 #![allow(clippy::missing_safety_doc)]
 #![allow(clippy::too_many_arguments)]
 #![allow(clippy::useless_transmute)]
+#![allow(clippy::unnecessary_cast)]
 
 /* automatically generated by rust-bindgen 0.61.0 */
 
@@ -3145,6 +3146,41 @@ pub const TYPE_INST: bp_type_idx = 0;
 pub const TYPE_DATA: bp_type_idx = 1;
 pub const TYPE_MAX: bp_type_idx = 2;
 pub type bp_type_idx = ::std::os::raw::c_uint;
+pub const PERF_REG_ARM64_X0: perf_event_arm_regs = 0;
+pub const PERF_REG_ARM64_X1: perf_event_arm_regs = 1;
+pub const PERF_REG_ARM64_X2: perf_event_arm_regs = 2;
+pub const PERF_REG_ARM64_X3: perf_event_arm_regs = 3;
+pub const PERF_REG_ARM64_X4: perf_event_arm_regs = 4;
+pub const PERF_REG_ARM64_X5: perf_event_arm_regs = 5;
+pub const PERF_REG_ARM64_X6: perf_event_arm_regs = 6;
+pub const PERF_REG_ARM64_X7: perf_event_arm_regs = 7;
+pub const PERF_REG_ARM64_X8: perf_event_arm_regs = 8;
+pub const PERF_REG_ARM64_X9: perf_event_arm_regs = 9;
+pub const PERF_REG_ARM64_X10: perf_event_arm_regs = 10;
+pub const PERF_REG_ARM64_X11: perf_event_arm_regs = 11;
+pub const PERF_REG_ARM64_X12: perf_event_arm_regs = 12;
+pub const PERF_REG_ARM64_X13: perf_event_arm_regs = 13;
+pub const PERF_REG_ARM64_X14: perf_event_arm_regs = 14;
+pub const PERF_REG_ARM64_X15: perf_event_arm_regs = 15;
+pub const PERF_REG_ARM64_X16: perf_event_arm_regs = 16;
+pub const PERF_REG_ARM64_X17: perf_event_arm_regs = 17;
+pub const PERF_REG_ARM64_X18: perf_event_arm_regs = 18;
+pub const PERF_REG_ARM64_X19: perf_event_arm_regs = 19;
+pub const PERF_REG_ARM64_X20: perf_event_arm_regs = 20;
+pub const PERF_REG_ARM64_X21: perf_event_arm_regs = 21;
+pub const PERF_REG_ARM64_X22: perf_event_arm_regs = 22;
+pub const PERF_REG_ARM64_X23: perf_event_arm_regs = 23;
+pub const PERF_REG_ARM64_X24: perf_event_arm_regs = 24;
+pub const PERF_REG_ARM64_X25: perf_event_arm_regs = 25;
+pub const PERF_REG_ARM64_X26: perf_event_arm_regs = 26;
+pub const PERF_REG_ARM64_X27: perf_event_arm_regs = 27;
+pub const PERF_REG_ARM64_X28: perf_event_arm_regs = 28;
+pub const PERF_REG_ARM64_X29: perf_event_arm_regs = 29;
+pub const PERF_REG_ARM64_LR: perf_event_arm_regs = 30;
+pub const PERF_REG_ARM64_SP: perf_event_arm_regs = 31;
+pub const PERF_REG_ARM64_PC: perf_event_arm_regs = 32;
+pub const PERF_REG_ARM64_MAX: perf_event_arm_regs = 33;
+pub type perf_event_arm_regs = ::std::os::raw::c_uint;
 pub const ENABLE: perf_event_ioctls = 9216;
 pub const DISABLE: perf_event_ioctls = 9217;
 pub const REFRESH: perf_event_ioctls = 9218;
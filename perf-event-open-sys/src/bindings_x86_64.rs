@@ -22,6 +22,7 @@
 #![allow(clippy::missing_safety_doc)]
 #![allow(clippy::too_many_arguments)]
 #![allow(clippy::useless_transmute)]
+#![allow(clippy::unnecessary_cast)]
 
 /* automatically generated by rust-bindgen 0.61.0 */
 
@@ -3236,6 +3237,50 @@ pub const TYPE_INST: bp_type_idx = 0;
 pub const TYPE_DATA: bp_type_idx = 1;
 pub const TYPE_MAX: bp_type_idx = 2;
 pub type bp_type_idx = ::std::os::raw::c_uint;
+pub const PERF_REG_X86_AX: perf_event_x86_regs = 0;
+pub const PERF_REG_X86_BX: perf_event_x86_regs = 1;
+pub const PERF_REG_X86_CX: perf_event_x86_regs = 2;
+pub const PERF_REG_X86_DX: perf_event_x86_regs = 3;
+pub const PERF_REG_X86_SI: perf_event_x86_regs = 4;
+pub const PERF_REG_X86_DI: perf_event_x86_regs = 5;
+pub const PERF_REG_X86_BP: perf_event_x86_regs = 6;
+pub const PERF_REG_X86_SP: perf_event_x86_regs = 7;
+pub const PERF_REG_X86_IP: perf_event_x86_regs = 8;
+pub const PERF_REG_X86_FLAGS: perf_event_x86_regs = 9;
+pub const PERF_REG_X86_CS: perf_event_x86_regs = 10;
+pub const PERF_REG_X86_SS: perf_event_x86_regs = 11;
+pub const PERF_REG_X86_DS: perf_event_x86_regs = 12;
+pub const PERF_REG_X86_ES: perf_event_x86_regs = 13;
+pub const PERF_REG_X86_FS: perf_event_x86_regs = 14;
+pub const PERF_REG_X86_GS: perf_event_x86_regs = 15;
+pub const PERF_REG_X86_R8: perf_event_x86_regs = 16;
+pub const PERF_REG_X86_R9: perf_event_x86_regs = 17;
+pub const PERF_REG_X86_R10: perf_event_x86_regs = 18;
+pub const PERF_REG_X86_R11: perf_event_x86_regs = 19;
+pub const PERF_REG_X86_R12: perf_event_x86_regs = 20;
+pub const PERF_REG_X86_R13: perf_event_x86_regs = 21;
+pub const PERF_REG_X86_R14: perf_event_x86_regs = 22;
+pub const PERF_REG_X86_R15: perf_event_x86_regs = 23;
+pub const PERF_REG_X86_32_MAX: perf_event_x86_regs = 16;
+pub const PERF_REG_X86_64_MAX: perf_event_x86_regs = 24;
+pub const PERF_REG_X86_XMM0: perf_event_x86_regs = 32;
+pub const PERF_REG_X86_XMM1: perf_event_x86_regs = 34;
+pub const PERF_REG_X86_XMM2: perf_event_x86_regs = 36;
+pub const PERF_REG_X86_XMM3: perf_event_x86_regs = 38;
+pub const PERF_REG_X86_XMM4: perf_event_x86_regs = 40;
+pub const PERF_REG_X86_XMM5: perf_event_x86_regs = 42;
+pub const PERF_REG_X86_XMM6: perf_event_x86_regs = 44;
+pub const PERF_REG_X86_XMM7: perf_event_x86_regs = 46;
+pub const PERF_REG_X86_XMM8: perf_event_x86_regs = 48;
+pub const PERF_REG_X86_XMM9: perf_event_x86_regs = 50;
+pub const PERF_REG_X86_XMM10: perf_event_x86_regs = 52;
+pub const PERF_REG_X86_XMM11: perf_event_x86_regs = 54;
+pub const PERF_REG_X86_XMM12: perf_event_x86_regs = 56;
+pub const PERF_REG_X86_XMM13: perf_event_x86_regs = 58;
+pub const PERF_REG_X86_XMM14: perf_event_x86_regs = 60;
+pub const PERF_REG_X86_XMM15: perf_event_x86_regs = 62;
+pub const PERF_REG_X86_XMM_MAX: perf_event_x86_regs = 64;
+pub type perf_event_x86_regs = ::std::os::raw::c_uint;
 pub const ENABLE: perf_event_ioctls = 9216;
 pub const DISABLE: perf_event_ioctls = 9217;
 pub const REFRESH: perf_event_ioctls = 9218;
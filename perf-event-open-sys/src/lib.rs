@@ -9,9 +9,9 @@
 //! would any other system call. The Linux standard C library does not provide a
 //! binding for this function or its associated types and constants.
 //!
-//! Rust analogs to the C types and constants from `<linux/perf_event.h>` and
-//! `<linux/hw_breakpoint.h>`, generated with `bindgen`, are available in the
-//! [`bindings`] module.
+//! Rust analogs to the C types and constants from `<linux/perf_event.h>`,
+//! `<linux/hw_breakpoint.h>`, and the architecture's `<asm/perf_regs.h>`,
+//! generated with `bindgen`, are available in the [`bindings`] module.
 //!
 //! There are several ioctls for use with `perf_event_open` file descriptors;
 //! see the [`ioctls`] module for those.
@@ -73,6 +73,16 @@
 //! packaged by Fedora as:
 //! - x86_64: `kernel-headers-5.19.4-200.fc36.x86_64` (`PERF_ATTR_SIZE_VER7`)
 //! - aarch64: `kernel-headers-5.18.4-201.fc36.aarch64` (`PERF_ATTR_SIZE_VER7`)
+//! - arm: the same headers as x86_64, with the `asm/` headers for 32-bit ARM
+//!   EABI (`PERF_ATTR_SIZE_VER7`).
+//! - loongarch64: the same headers as x86_64, with the `asm/` headers for
+//!   loongarch64, which uses the generic system call table
+//!   (`PERF_ATTR_SIZE_VER7`).
+//! - powerpc64: the same headers as x86_64, with the `asm/` headers for
+//!   powerpc64 (`PERF_ATTR_SIZE_VER7`). The bindings serve both big- and
+//!   little-endian targets.
+//! - s390x: likewise, with the `asm/` headers for s390x
+//!   (`PERF_ATTR_SIZE_VER7`).
 //!
 //! As explained above, bugs aside, it is not necessary to use the version of
 //! these structures that matches the kernel you want to run under, so it should
@@ -87,6 +97,27 @@
 //! If you need features that are available only in a more recent version of the
 //! types than this crate provides, please file an issue.
 //!
+//! ## Stable names for union members
+//!
+//! Several kernel structs contain anonymous unions, which `bindgen` names
+//! `__bindgen_anon_1`, `__bindgen_anon_2`, and so on, by position. These
+//! names are an artifact of the generator, and may change when the bindings
+//! are regenerated. Instead of writing `attrs.__bindgen_anon_1.sample_period`,
+//! use the accessor methods this crate defines, named after the members in
+//! the C headers:
+//!
+//! ```
+//! use perf_event_open_sys as sys;
+//!
+//! let mut attrs = sys::bindings::perf_event_attr::default();
+//! attrs.set_sample_period(10000);
+//! attrs.set_wakeup_events(1);
+//! assert_eq!(attrs.sample_period(), 10000);
+//! ```
+//!
+//! These accessors are part of this crate's stable API: regenerating the
+//! bindings will not change them.
+//!
 //! ## Linux API Backward/Forward Compatibility Strategy
 //!
 //! (This is more detail than necessary if you just want to use the crate. I
@@ -182,10 +213,28 @@
 #[path = "bindings_aarch64.rs"]
 pub mod bindings;
 
+#[cfg(target_arch = "arm")]
+#[path = "bindings_arm.rs"]
+pub mod bindings;
+
+#[cfg(target_arch = "loongarch64")]
+#[path = "bindings_loongarch64.rs"]
+pub mod bindings;
+
+#[cfg(target_arch = "powerpc64")]
+#[path = "bindings_powerpc64.rs"]
+pub mod bindings;
+
+#[cfg(target_arch = "s390x")]
+#[path = "bindings_s390x.rs"]
+pub mod bindings;
+
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[path = "bindings_x86_64.rs"]
 pub mod bindings;
 
+mod accessors;
+
 // Provide actual callable code only on Linux/Android. See "Using perf
 // types on other platforms", in the top-level crate docs.
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -3,6 +3,10 @@
 #include <linux/perf_event.h>
 #include <linux/hw_breakpoint.h>
 
+// for the PERF_REG_* register numbers, used with sample_regs_user and
+// sample_regs_intr
+#include <asm/perf_regs.h>
+
 // for __NR_perf_event_open
 #include <asm/unistd.h>
 
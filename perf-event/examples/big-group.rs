@@ -56,8 +56,8 @@ fn main() -> std::io::Result<()> {
         counts.time_running()
     );
 
-    if counts.time_running() == 0 {
-        println!("Group was never running; no results available.");
+    if let Some(hint) = counts.scheduling_hint() {
+        println!("No results available: {}", hint);
         return Ok(());
     }
 
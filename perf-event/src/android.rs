@@ -0,0 +1,294 @@
+//! Diagnosing Android's restrictions on `perf_event_open`.
+//!
+//! Android restricts `perf_event_open` more than other Linux systems do.
+//! The `security.perf_harden` system property, set by default, has `init`
+//! raise `perf_event_paranoid` to `3`, forbidding unprivileged processes to
+//! use perf events at all; and SELinux policy denies them to apps that
+//! aren't debuggable or profileable. Either way, [`Builder::build`] just
+//! fails with `EACCES`.
+//!
+//! On Android, [`Builder::try_build`] checks for these conditions when the
+//! kernel denies permission, and returns an [`Error::Restricted`] saying
+//! which one is to blame and what to toggle:
+//!
+//!     use perf_event::{Builder, Error};
+//!     use perf_event::events::Software;
+//!
+//!     match Builder::new().kind(Software::TASK_CLOCK).try_build() {
+//!         Ok(counter) => { /* ... */ }
+//!         Err(Error::Restricted { restriction, .. }) => {
+//!             eprintln!("{}", restriction.instructions());
+//!         }
+//!         Err(e) => eprintln!("{}", e),
+//!     }
+//!
+//! Programs run from `adb shell` can lift `security.perf_harden` themselves,
+//! as `simpleperf` does, with [`disable_perf_harden`]. For apps, the
+//! conventions `simpleperf`'s `app_profiler.py` follows apply: the app must
+//! be debuggable, or declare itself profileable to the shell, and the
+//! profiler runs in the app's own SELinux domain via `adb shell run-as
+//! PACKAGE`. [`Conditions`] shows what applies to the calling process.
+//!
+//! This module is available on all targets, but [`Conditions::current`]
+//! finds system properties only on Android.
+//!
+//! [`Builder::build`]: crate::Builder::build
+//! [`Builder::try_build`]: crate::Builder::try_build
+//! [`Error::Restricted`]: crate::Error::Restricted
+
+use crate::paranoid::Level;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+const PERF_HARDEN: &str = "security.perf_harden";
+
+/// The Android platform conditions that affect `perf_event_open`.
+///
+/// Use [`Conditions::current`] to inspect the calling process's, and
+/// [`Conditions::restriction`] to decide which of them is in the way.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Conditions {
+    /// Whether the `security.perf_harden` property is set, or `None` if
+    /// there's no such property, as off Android.
+    pub perf_harden: Option<bool>,
+
+    /// The `perf_event_paranoid` level, if it could be read.
+    pub paranoid: Option<Level>,
+
+    /// Whether SELinux is enforcing its policy, or `None` if that couldn't
+    /// be determined, as when SELinux is absent.
+    pub selinux_enforcing: Option<bool>,
+
+    /// The calling process's SELinux context, like
+    /// `u:r:untrusted_app:s0:c512,c768`, if it could be read.
+    pub selinux_context: Option<String>,
+}
+
+/// An Android restriction that prevents this process from opening a perf
+/// event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Restriction {
+    /// `security.perf_harden` is set, so `perf_event_paranoid` is `3`,
+    /// forbidding unprivileged processes to use perf events at all.
+    PerfHarden,
+
+    /// `perf_event_paranoid` is too high for the requested counter, for
+    /// some reason other than `security.perf_harden`.
+    Paranoid {
+        /// The current level.
+        current: Level,
+
+        /// The most restrictive level that would permit the counter.
+        required: Level,
+    },
+
+    /// SELinux policy forbids processes in this process's domain to use
+    /// perf events.
+    SeLinux {
+        /// The process's SELinux domain, like `untrusted_app`.
+        domain: String,
+    },
+}
+
+impl Conditions {
+    /// Inspect the calling process's conditions.
+    ///
+    /// Anything that can't be determined is left as `None`.
+    pub fn current() -> Conditions {
+        Conditions {
+            perf_harden: property(PERF_HARDEN).map(|value| value == "1"),
+            paranoid: Level::current().ok(),
+            selinux_enforcing: fs::read_to_string("/sys/fs/selinux/enforce")
+                .ok()
+                .map(|value| value.trim() == "1"),
+            selinux_context: fs::read_to_string("/proc/self/attr/current")
+                .ok()
+                .map(|context| context.trim_end_matches(&['\0', '\n'][..]).to_string()),
+        }
+    }
+
+    /// Return the calling process's SELinux domain: the third field of its
+    /// context, like `untrusted_app`.
+    pub fn selinux_domain(&self) -> Option<&str> {
+        self.selinux_context.as_deref()?.split(':').nth(2)
+    }
+
+    /// Return true if the calling process runs in one of the SELinux
+    /// domains Android gives apps, like `untrusted_app` or `platform_app`.
+    ///
+    /// Android's policy doesn't let these domains use perf events. A
+    /// profiler started with `run-as` runs in the `runas_app` domain
+    /// instead, which may, so this returns false for it.
+    pub fn is_app(&self) -> bool {
+        match self.selinux_domain() {
+            Some("runas_app") | None => false,
+            Some(domain) => domain.ends_with("_app") || domain.starts_with("untrusted_app"),
+        }
+    }
+
+    /// Return the restriction most likely to prevent an unprivileged
+    /// process from building a counter that needs `required`, or `None` if
+    /// these conditions don't explain a failure.
+    ///
+    /// `security.perf_harden` is blamed first, since lifting it is the
+    /// usual fix; then `perf_event_paranoid`; then, if the paranoia level
+    /// permits the counter, SELinux.
+    pub fn restriction(&self, required: Level) -> Option<Restriction> {
+        if let Some(current) = self.paranoid {
+            if !current.permits(required) {
+                if current == Level::Disallowed && self.perf_harden == Some(true) {
+                    return Some(Restriction::PerfHarden);
+                }
+                return Some(Restriction::Paranoid { current, required });
+            }
+        } else if self.perf_harden == Some(true) {
+            return Some(Restriction::PerfHarden);
+        }
+
+        if self.selinux_enforcing == Some(true) && self.is_app() {
+            let domain = self.selinux_domain().unwrap_or_default().to_string();
+            return Some(Restriction::SeLinux { domain });
+        }
+        None
+    }
+}
+
+impl Restriction {
+    /// Diagnose a permission failure opening a counter that needs
+    /// `required`, on Android. Elsewhere, return `None`.
+    pub(crate) fn diagnose(required: Level) -> Option<Restriction> {
+        if cfg!(target_os = "android") {
+            Conditions::current().restriction(required)
+        } else {
+            None
+        }
+    }
+
+    /// Return instructions for a user on how to lift this restriction, for
+    /// use in error messages.
+    pub fn instructions(&self) -> String {
+        match self {
+            Restriction::PerfHarden => format!(
+                "the {} property is set, forbidding perf events to unprivileged \
+                 processes; run `adb shell setprop {} 0` (this lasts until the \
+                 device reboots)",
+                PERF_HARDEN, PERF_HARDEN
+            ),
+            Restriction::Paranoid { required, .. } => format!(
+                "this requires /proc/sys/kernel/perf_event_paranoid to be {} or less \
+                 (it allows: {}); on a rooted device, try \
+                 `adb shell su root sh -c 'echo {} > /proc/sys/kernel/perf_event_paranoid'`",
+                required.value(),
+                required.description(),
+                required.value()
+            ),
+            Restriction::SeLinux { domain } => format!(
+                "SELinux forbids perf events to processes in the `{}` domain; make \
+                 the app debuggable, or add <profileable android:shell=\"true\"/> to \
+                 its manifest, and start the profiler with `adb shell run-as PACKAGE`, \
+                 as simpleperf's app_profiler.py does",
+                domain
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Restriction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Restriction::PerfHarden => write!(fmt, "{} is set", PERF_HARDEN),
+            Restriction::Paranoid { current, .. } => {
+                write!(fmt, "perf_event_paranoid is {}", current)
+            }
+            Restriction::SeLinux { domain } => {
+                write!(fmt, "SELinux denies perf events to domain `{}`", domain)
+            }
+        }
+    }
+}
+
+/// Clear the `security.perf_harden` property, if it is set, and wait for
+/// `init` to lower `perf_event_paranoid` to match, as `simpleperf` does.
+///
+/// Return `Ok(true)` if the property was cleared, or `Ok(false)` if it
+/// wasn't set. Only privileged processes and the `adb shell` user may set
+/// the property; for others, this returns an error of kind
+/// [`io::ErrorKind::PermissionDenied`]. Off Android, this always returns
+/// `Ok(false)`.
+pub fn disable_perf_harden() -> io::Result<bool> {
+    if property(PERF_HARDEN).as_deref() != Some("1") {
+        return Ok(false);
+    }
+    set_property(PERF_HARDEN, "0")?;
+
+    // `init` lowers `perf_event_paranoid` when it sees the property change.
+    for _ in 0..10 {
+        if matches!(Level::current(), Ok(level) if level < Level::Disallowed) {
+            return Ok(true);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "couldn't clear {}; run `adb shell setprop {} 0`",
+            PERF_HARDEN, PERF_HARDEN
+        ),
+    ))
+}
+
+/// Return the value of the system property `name`, or `None` if it is
+/// unset or empty.
+#[cfg(target_os = "android")]
+fn property(name: &str) -> Option<String> {
+    use std::ffi::{CStr, CString};
+
+    let name = CString::new(name).ok()?;
+    let mut value = [0 as libc::c_char; libc::PROP_VALUE_MAX as usize];
+    let len = unsafe { libc::__system_property_get(name.as_ptr(), value.as_mut_ptr()) };
+    if len <= 0 {
+        return None;
+    }
+    let value = unsafe { CStr::from_ptr(value.as_ptr()) };
+    Some(value.to_string_lossy().into_owned())
+}
+
+#[cfg(not(target_os = "android"))]
+fn property(_name: &str) -> Option<String> {
+    None
+}
+
+/// Set the system property `name` to `value`.
+#[cfg(target_os = "android")]
+fn set_property(name: &str, value: &str) -> io::Result<()> {
+    use std::ffi::CString;
+
+    let c_name = CString::new(name)?;
+    let c_value = CString::new(value)?;
+    if unsafe { libc::__system_property_set(c_name.as_ptr(), c_value.as_ptr()) } != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "couldn't set {} to {}; run `adb shell setprop {} {}`",
+                name, value, name, value
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "android"))]
+fn set_property(name: &str, _value: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "can't set {}: system properties exist only on Android",
+            name
+        ),
+    ))
+}
@@ -0,0 +1,133 @@
+//! Enabling, disabling, or resetting many independent counters at once.
+//!
+//! A [`Group`] can enable or disable all its members in a single atomic
+//! operation, but counters that can't share a group (say, because they
+//! observe different processes or CPUs) must each be handled with its own
+//! system call. The functions here do that as quickly as possible, to keep
+//! the counters' measurement periods close together:
+//!
+//! -   First they collect the counters' file descriptors, so that no other
+//!     work happens between the system calls.
+//!
+//! -   Then they issue the `ioctl`s back to back, noting each one's `errno`.
+//!
+//! -   Finally, they build any errors. A failure on one counter does not stop
+//!     the others from being processed; instead, all failures are reported
+//!     together in a [`BatchError`].
+//!
+//! For example:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::{batch, Builder};
+//!     use perf_event::events::Software;
+//!
+//!     let mut clock = Builder::new().kind(Software::TASK_CLOCK).build()?;
+//!     let mut faults = Builder::new().kind(Software::PAGE_FAULTS).build()?;
+//!
+//!     batch::enable_all(&mut [&mut clock, &mut faults])?;
+//!     // ... work to be measured ...
+//!     batch::disable_all(&mut [&mut clock, &mut faults])?;
+//!     # Ok(()) }
+//!
+//! [`Group`]: crate::Group
+
+use crate::{sys, Counter};
+use std::fmt;
+use std::io;
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Enable all of `counters`, with the system calls issued back to back.
+///
+/// If any counters can't be enabled, the rest are still enabled, and the
+/// returned [`BatchError`] lists the failures.
+pub fn enable_all(counters: &mut [&mut Counter]) -> Result<(), BatchError> {
+    run(counters, |fd| unsafe { sys::ioctls::ENABLE(fd, 0) })
+}
+
+/// Disable all of `counters`, with the system calls issued back to back.
+///
+/// If any counters can't be disabled, the rest are still disabled, and the
+/// returned [`BatchError`] lists the failures.
+pub fn disable_all(counters: &mut [&mut Counter]) -> Result<(), BatchError> {
+    run(counters, |fd| unsafe { sys::ioctls::DISABLE(fd, 0) })
+}
+
+/// Reset all of `counters` to zero, with the system calls issued back to
+/// back.
+///
+/// If any counters can't be reset, the rest are still reset, and the
+/// returned [`BatchError`] lists the failures.
+pub fn reset_all(counters: &mut [&mut Counter]) -> Result<(), BatchError> {
+    run(counters, |fd| unsafe { sys::ioctls::RESET(fd, 0) })
+}
+
+/// The errors from a batch operation that failed on some counters.
+///
+/// This implements `Into<io::Error>`, so a `?` in a function returning
+/// `io::Result` will accept it.
+#[derive(Debug)]
+pub struct BatchError {
+    failures: Vec<(usize, io::Error)>,
+}
+
+impl BatchError {
+    /// Return the failures, as pairs of an index into the slice of counters
+    /// passed to the batch operation and the error for that counter.
+    pub fn failures(&self) -> &[(usize, io::Error)] {
+        &self.failures
+    }
+
+    /// Return the failures, consuming `self`.
+    pub fn into_failures(self) -> Vec<(usize, io::Error)> {
+        self.failures
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "operation failed on {} counters", self.failures.len())?;
+        for (index, error) in &self.failures {
+            write!(fmt, "\n- counter {}: {}", index, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.failures
+            .first()
+            .map(|(_, e)| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl From<BatchError> for io::Error {
+    fn from(error: BatchError) -> io::Error {
+        io::Error::other(error)
+    }
+}
+
+fn run(counters: &mut [&mut Counter], ioctl: impl Fn(RawFd) -> c_int) -> Result<(), BatchError> {
+    // Do all the allocation and indirection up front.
+    let fds: Vec<RawFd> = counters.iter().map(|c| c.as_raw_fd()).collect();
+    let mut errnos: Vec<c_int> = vec![0; fds.len()];
+
+    for (fd, errno) in fds.iter().zip(errnos.iter_mut()) {
+        if ioctl(*fd) == -1 {
+            *errno = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        }
+    }
+
+    let failures: Vec<(usize, io::Error)> = errnos
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, errno)| errno != 0)
+        .map(|(index, errno)| (index, io::Error::from_raw_os_error(errno)))
+        .collect();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(BatchError { failures })
+    }
+}
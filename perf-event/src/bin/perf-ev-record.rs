@@ -0,0 +1,296 @@
+//! `perf-ev-record`: sample a command or a running process, and save the
+//! records to a file.
+//!
+//! This is a small version of `perf record`, built only on this crate's public
+//! API:
+//!
+//!     perf-ev-record [-e EVENT] [-F FREQ] [-g] [-z lz4|zstd] [-o FILE] COMMAND [ARGS...]
+//!     perf-ev-record [-e EVENT] [-F FREQ] [-g] [-z lz4|zstd] [-o FILE] -p PID [-d SECONDS]
+//!
+//! The output is JSON Lines, as written by `perf_event::spool::RecordWriter`.
+//! The first line is the `ParseConfig` needed to decode the records, and each
+//! line after that is one raw `Record`, as the crate's `serde` support
+//! serializes them. Read the file back with `spool::RecordReader`, and decode
+//! each record with `Record::parse`.
+//!
+//! With `-z`, the output is compressed with LZ4 or Zstandard. This needs the
+//! crate's `lz4` or `zstd` feature.
+//!
+//! Samples include the instruction pointer and thread id, and with `-g`, the
+//! call chain. The file also holds the `MMAP` and `COMM` records needed to
+//! say which executable and which process each sample came from.
+//!
+//! Build it with `cargo build --features cli`.
+
+use libc::pid_t;
+use perf_event::events::{Event, Hardware, Software};
+use perf_event::process::ProcessSampler;
+use perf_event::samples::{RecordEvent, SampleType};
+use perf_event::spawn::spawn_counted;
+use perf_event::spool::{Compression, RecordWriter};
+use perf_event::Builder;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::{exit, Command};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+usage: perf-ev-record [-e EVENT] [-F FREQ] [-g] [-z lz4|zstd] [-o FILE] COMMAND [ARGS...]
+       perf-ev-record [-e EVENT] [-F FREQ] [-g] [-z lz4|zstd] [-o FILE] -p PID [-d SECONDS]";
+
+/// How long to sleep when every ring buffer is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+enum Target {
+    Command(Vec<String>),
+    Pid(pid_t, Duration),
+}
+
+struct Options {
+    event: Event,
+    frequency: u64,
+    sample_type: SampleType,
+    compression: Compression,
+    output: String,
+    target: Target,
+}
+
+/// What we wrote, for the summary.
+#[derive(Default)]
+struct Totals {
+    records: u64,
+    samples: u64,
+    lost: u64,
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("perf-ev-record: {}\n{}", message, USAGE);
+        exit(2);
+    });
+
+    match record(&options) {
+        Ok(totals) => {
+            eprintln!(
+                "perf-ev-record: wrote {} records ({} samples) to {}",
+                totals.records, totals.samples, options.output
+            );
+            if totals.lost > 0 {
+                eprintln!(
+                    "perf-ev-record: lost {} records; try a lower -F",
+                    totals.lost
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("perf-ev-record: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut event = None;
+    let mut frequency = 4000;
+    let mut sample_type = SampleType::IP | SampleType::TID;
+    let mut compression = Compression::None;
+    let mut output = None;
+    let mut pid = None;
+    let mut duration = Duration::from_secs(5);
+
+    let command = loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => break vec![],
+        };
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("option {} requires a value", flag))
+        };
+        match arg.as_str() {
+            "-e" => {
+                let name = value("-e")?;
+                event = Some(Event::parse(&name).map_err(|e| e.to_string())?);
+            }
+            "-F" => {
+                let text = value("-F")?;
+                frequency = text
+                    .parse()
+                    .map_err(|_| format!("bad frequency: {}", text))?;
+            }
+            "-g" => sample_type |= SampleType::CALLCHAIN,
+            "-z" => compression = parse_compression(&value("-z")?)?,
+            "-o" => output = Some(value("-o")?),
+            "-p" => {
+                let text = value("-p")?;
+                pid = Some(text.parse().map_err(|_| format!("bad pid: {}", text))?);
+            }
+            "-d" => {
+                let text = value("-d")?;
+                let secs: f64 = text
+                    .parse()
+                    .map_err(|_| format!("bad duration: {}", text))?;
+                duration = Duration::from_secs_f64(secs);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            "--" => break args.collect(),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => break std::iter::once(arg).chain(args).collect(),
+        }
+    };
+
+    let target = match (pid, command.is_empty()) {
+        (Some(pid), true) => Target::Pid(pid, duration),
+        (None, false) => Target::Command(command),
+        (Some(_), false) => return Err("give either a command or -p, not both".to_string()),
+        (None, true) => return Err("no command given".to_string()),
+    };
+    Ok(Options {
+        event: event.unwrap_or_else(default_event),
+        frequency,
+        sample_type,
+        compression,
+        output: output.unwrap_or_else(|| default_output(compression).to_string()),
+        target,
+    })
+}
+
+/// Parse the argument to `-z`.
+fn parse_compression(name: &str) -> Result<Compression, String> {
+    match name {
+        #[cfg(feature = "lz4")]
+        "lz4" => Ok(Compression::Lz4),
+        #[cfg(feature = "zstd")]
+        "zstd" => Ok(Compression::Zstd(0)),
+        // Whichever of these the features above didn't cover.
+        #[allow(unreachable_patterns)]
+        "lz4" | "zstd" => Err(format!(
+            "perf-ev-record was built without the \"{}\" feature",
+            name
+        )),
+        _ => Err(format!("unknown compression: {}", name)),
+    }
+}
+
+/// Return the output file name to use if none was given.
+fn default_output(compression: Compression) -> &'static str {
+    match compression {
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => "perf-ev.jsonl.lz4",
+        #[cfg(feature = "zstd")]
+        Compression::Zstd(_) => "perf-ev.jsonl.zst",
+        _ => "perf-ev.jsonl",
+    }
+}
+
+/// Return the event to sample if none was given: CPU cycles, like `perf
+/// record`, or if this machine can't count them, the CPU clock.
+fn default_event() -> Event {
+    match Builder::new().kind(Hardware::CPU_CYCLES).build() {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            eprintln!("perf-ev-record: cycles not supported, falling back to cpu-clock");
+            Software::CPU_CLOCK.into()
+        }
+        _ => Hardware::CPU_CYCLES.into(),
+    }
+}
+
+fn record(options: &Options) -> io::Result<Totals> {
+    let (event, frequency, sample_type) = (
+        options.event.clone(),
+        options.frequency,
+        options.sample_type,
+    );
+    let configure = move |builder: Builder<'static>| {
+        builder
+            .kind(event.clone())
+            .sample_frequency(frequency)
+            .sample(sample_type)
+            .mmap(true)
+            .comm(true)
+    };
+
+    let file = File::create(&options.output)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", options.output, e)))?;
+    let file = BufWriter::new(file);
+
+    let totals = match &options.target {
+        Target::Command(argv) => {
+            let (mut child, mut sampler) =
+                spawn_counted(Command::new(&argv[0]).args(&argv[1..]), |pid| {
+                    ProcessSampler::inherit(pid, move |builder| configure(builder).enable_on_exec())
+                })
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", argv[0], e)))?;
+            let mut out =
+                RecordWriter::with_compression(file, sampler.config(), options.compression)?;
+
+            let mut totals = Totals::default();
+            loop {
+                // Check for exit before draining, so that we don't miss
+                // the child's last records.
+                let status = child.try_wait()?;
+                if !write_records(&mut out, &mut sampler, &mut totals)? {
+                    if let Some(status) = status {
+                        if !status.success() {
+                            eprintln!("perf-ev-record: {} exited with {}", argv[0], status);
+                        }
+                        break;
+                    }
+                    sleep(POLL_INTERVAL);
+                }
+            }
+            out.finish()?;
+            totals
+        }
+        Target::Pid(pid, duration) => {
+            let mut sampler = ProcessSampler::observe_process(*pid, configure)?;
+            let mut out =
+                RecordWriter::with_compression(file, sampler.config(), options.compression)?;
+
+            let mut totals = Totals::default();
+            let start = Instant::now();
+            sampler.enable()?;
+            while start.elapsed() < *duration {
+                if !write_records(&mut out, &mut sampler, &mut totals)? {
+                    // This fails once the process has exited.
+                    if sampler.refresh().is_err() {
+                        break;
+                    }
+                    sleep(POLL_INTERVAL);
+                }
+            }
+            sampler.disable()?;
+            write_records(&mut out, &mut sampler, &mut totals)?;
+            out.finish()?;
+            totals
+        }
+    };
+
+    Ok(totals)
+}
+
+/// Write all the records available from `sampler`, and return true if there
+/// were any.
+fn write_records(
+    out: &mut RecordWriter<impl Write>,
+    sampler: &mut ProcessSampler,
+    totals: &mut Totals,
+) -> io::Result<bool> {
+    let config = *sampler.config();
+    let mut any = false;
+    while let Some(record) = sampler.next() {
+        match record.try_parse(&config) {
+            Ok(RecordEvent::Sample(_)) => totals.samples += 1,
+            Ok(RecordEvent::Lost(lost)) => totals.lost += lost.lost,
+            _ => {}
+        }
+        out.write(&record)?;
+        totals.records += 1;
+        any = true;
+    }
+    Ok(any)
+}
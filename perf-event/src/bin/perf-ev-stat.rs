@@ -0,0 +1,246 @@
+//! `perf-ev-stat`: count events in a command or a running process.
+//!
+//! This is a small version of `perf stat`, built only on this crate's public
+//! API. It's useful for checking which events work on a given system:
+//!
+//!     perf-ev-stat [-e EVENT,EVENT...] COMMAND [ARGS...]
+//!     perf-ev-stat [-e EVENT,EVENT...] -p PID [-d SECONDS]
+//!
+//! Build it with `cargo build --features cli`.
+
+use libc::pid_t;
+use perf_event::process::ProcessCounter;
+use perf_event::stat::StatBuilder;
+use perf_event::{Builder, CountAndTime};
+use std::io;
+use std::process::{exit, Command};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+usage: perf-ev-stat [-e EVENT,EVENT...] COMMAND [ARGS...]
+       perf-ev-stat [-e EVENT,EVENT...] -p PID [-d SECONDS]";
+
+enum Target {
+    Command(Vec<String>),
+    Pid(pid_t, Duration),
+}
+
+struct Options {
+    stat: StatBuilder,
+    target: Target,
+}
+
+struct Count {
+    /// The count, scaled up if the counter was multiplexed, or `None` if it
+    /// never ran.
+    value: Option<u64>,
+
+    /// The fraction of the time enabled the counter was running, if less
+    /// than all of it.
+    running: Option<f64>,
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("perf-ev-stat: {}\n{}", message, USAGE);
+        exit(2);
+    });
+
+    let result = match &options.target {
+        Target::Command(argv) => stat_command(&options.stat, argv),
+        Target::Pid(pid, duration) => stat_pid(&options.stat, *pid, *duration),
+    };
+    if let Err(e) = result {
+        eprintln!("perf-ev-stat: {}", e);
+        exit(1);
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut events = None;
+    let mut pid = None;
+    let mut duration = Duration::from_secs(5);
+
+    let command = loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => break vec![],
+        };
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("option {} requires a value", flag))
+        };
+        match arg.as_str() {
+            "-e" => events = Some(value("-e")?),
+            "-p" => {
+                let text = value("-p")?;
+                pid = Some(text.parse().map_err(|_| format!("bad pid: {}", text))?);
+            }
+            "-d" => {
+                let text = value("-d")?;
+                let secs: f64 = text
+                    .parse()
+                    .map_err(|_| format!("bad duration: {}", text))?;
+                duration = Duration::from_secs_f64(secs);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            "--" => break args.collect(),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => break std::iter::once(arg).chain(args).collect(),
+        }
+    };
+
+    let stat = match events {
+        Some(events) => StatBuilder::new()
+            .parse_events(&events)
+            .map_err(|e| e.to_string())?,
+        None => StatBuilder::new().default_events(),
+    };
+
+    let target = match (pid, command.is_empty()) {
+        (Some(pid), true) => Target::Pid(pid, duration),
+        (None, false) => Target::Command(command),
+        (Some(_), false) => return Err("give either a command or -p, not both".to_string()),
+        (None, true) => return Err("no command given".to_string()),
+    };
+    Ok(Options { stat, target })
+}
+
+/// Run `argv` to completion, counting events in it and its descendants
+/// from the moment it calls `exec`.
+fn stat_command(stat: &StatBuilder, argv: &[String]) -> io::Result<()> {
+    let (status, report) = stat
+        .run_command(Command::new(&argv[0]).args(&argv[1..]))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", argv[0], e)))?;
+    if !status.success() {
+        eprintln!("perf-ev-stat: {} exited with {}", argv[0], status);
+    }
+
+    eprintln!("\n Performance counter stats for '{}':\n", argv.join(" "));
+    for entry in report.entries() {
+        print_row(&entry.name, entry.result.as_ref().map(count));
+    }
+    print_elapsed(report.elapsed());
+    if let Some(usage) = report.usage() {
+        eprintln!(
+            "{:>20.9} seconds user\n{:>20.9} seconds sys\n",
+            usage.user_time.as_secs_f64(),
+            usage.system_time.as_secs_f64()
+        );
+    }
+    Ok(())
+}
+
+/// Count events in every thread of process `pid` for `duration`.
+fn stat_pid(stat: &StatBuilder, pid: pid_t, duration: Duration) -> io::Result<()> {
+    let mut built: Vec<(&str, io::Result<ProcessCounter>)> = stat
+        .events()
+        .map(|(name, event)| {
+            let event = event.clone();
+            let counter = ProcessCounter::observe_process(pid, move |tid| {
+                Builder::new().observe_pid(tid).kind(event.clone()).build()
+            });
+            (name, counter)
+        })
+        .collect();
+
+    let start = Instant::now();
+    for (_, counter) in &mut built {
+        if let Ok(counter) = counter {
+            counter.enable()?;
+        }
+    }
+
+    // Pick up new threads as they appear.
+    let tick = Duration::from_millis(100);
+    while start.elapsed() < duration {
+        sleep(tick.min(duration.saturating_sub(start.elapsed())));
+        for (_, counter) in &mut built {
+            if let Ok(counter) = counter {
+                counter.refresh()?;
+            }
+        }
+    }
+
+    for (_, counter) in &mut built {
+        if let Ok(counter) = counter {
+            counter.disable()?;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    eprintln!("\n Performance counter stats for process id '{}':\n", pid);
+    for (name, counter) in &mut built {
+        match counter {
+            Ok(counter) => {
+                let value = counter.read()?;
+                print_row(
+                    name,
+                    Ok(Count {
+                        value: Some(value),
+                        running: None,
+                    }),
+                );
+            }
+            Err(e) => print_row(name, Err(e)),
+        }
+    }
+    print_elapsed(elapsed);
+    Ok(())
+}
+
+/// Scale up `reading` if its counter was multiplexed.
+fn count(reading: &CountAndTime) -> Count {
+    let running = if reading.time_running > 0 && reading.time_running < reading.time_enabled {
+        Some(reading.time_running as f64 / reading.time_enabled as f64)
+    } else {
+        None
+    };
+    Count {
+        value: reading.scaled(),
+        running,
+    }
+}
+
+/// Print one row of the report: an event's count if it could be measured,
+/// and its name.
+fn print_row(name: &str, result: Result<Count, &io::Error>) {
+    match result {
+        Ok(Count {
+            value: Some(value),
+            running,
+        }) => {
+            let note = match running {
+                Some(ratio) => format!("  ({:.2}% of the time)", ratio * 100.0),
+                None => String::new(),
+            };
+            eprintln!("{:>20}      {}{}", group_digits(value), name, note);
+        }
+        Ok(Count { value: None, .. }) => eprintln!("{:>20}      {}", "<not counted>", name),
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            eprintln!("{:>20}      {}", "<not supported>", name)
+        }
+        Err(e) => eprintln!("{:>20}      {}  ({})", "<not counted>", name, e),
+    }
+}
+
+fn print_elapsed(elapsed: Duration) {
+    eprintln!("\n{:>20.9} seconds time elapsed\n", elapsed.as_secs_f64());
+}
+
+/// Format `n` with commas between groups of three digits.
+fn group_digits(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
@@ -0,0 +1,293 @@
+//! `perf-ev-top`: show where a command or a running process spends its time,
+//! updated live.
+//!
+//! This is a small version of `perf top`, built only on this crate's public
+//! API:
+//!
+//!     perf-ev-top [-e EVENT] [-F FREQ] [-d SECONDS] [-n LINES] COMMAND [ARGS...]
+//!     perf-ev-top [-e EVENT] [-F FREQ] [-d SECONDS] [-n LINES] -p PID
+//!
+//! Every `-d` seconds, this prints the instruction addresses that took the
+//! most samples during that interval. There's no symbol table lookup: each
+//! address is shown as the executable file it falls in, from
+//! `/proc/PID/maps`, and its offset in that file, which `addr2line` can
+//! resolve. Each process's mappings are read when its first sample arrives,
+//! and again if a later address falls outside them. It stops when the
+//! command or process exits.
+//!
+//! Build it with `cargo build --features cli`.
+
+use libc::pid_t;
+use perf_event::events::{Event, Hardware, Software};
+use perf_event::process::ProcessSampler;
+use perf_event::samples::{RecordEvent, SampleType};
+use perf_event::spawn::spawn_counted;
+use perf_event::Builder;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::process::{exit, Child, Command};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+usage: perf-ev-top [-e EVENT] [-F FREQ] [-d SECONDS] [-n LINES] COMMAND [ARGS...]
+       perf-ev-top [-e EVENT] [-F FREQ] [-d SECONDS] [-n LINES] -p PID";
+
+/// How long to sleep when every ring buffer is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+enum Target {
+    Command(Vec<String>),
+    Pid(pid_t),
+}
+
+struct Options {
+    /// The event to sample, and its name.
+    event: (String, Event),
+    frequency: u64,
+    interval: Duration,
+    lines: usize,
+    target: Target,
+}
+
+/// An executable mapping from `/proc/PID/maps`.
+struct Mapping {
+    start: u64,
+    end: u64,
+    offset: u64,
+    path: String,
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("perf-ev-top: {}\n{}", message, USAGE);
+        exit(2);
+    });
+
+    if let Err(e) = top(&options) {
+        eprintln!("perf-ev-top: {}", e);
+        exit(1);
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut event = None;
+    let mut frequency = 4000;
+    let mut interval = Duration::from_secs(2);
+    let mut lines = 20;
+    let mut pid = None;
+
+    let command = loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => break vec![],
+        };
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("option {} requires a value", flag))
+        };
+        match arg.as_str() {
+            "-e" => {
+                let name = value("-e")?;
+                let parsed = Event::parse(&name).map_err(|e| e.to_string())?;
+                event = Some((name, parsed));
+            }
+            "-F" => {
+                let text = value("-F")?;
+                frequency = text
+                    .parse()
+                    .map_err(|_| format!("bad frequency: {}", text))?;
+            }
+            "-d" => {
+                let text = value("-d")?;
+                let secs: f64 = text
+                    .parse()
+                    .map_err(|_| format!("bad interval: {}", text))?;
+                interval = Duration::from_secs_f64(secs);
+            }
+            "-n" => {
+                let text = value("-n")?;
+                lines = text
+                    .parse()
+                    .map_err(|_| format!("bad line count: {}", text))?;
+            }
+            "-p" => {
+                let text = value("-p")?;
+                pid = Some(text.parse().map_err(|_| format!("bad pid: {}", text))?);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            "--" => break args.collect(),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => break std::iter::once(arg).chain(args).collect(),
+        }
+    };
+
+    let target = match (pid, command.is_empty()) {
+        (Some(pid), true) => Target::Pid(pid),
+        (None, false) => Target::Command(command),
+        (Some(_), false) => return Err("give either a command or -p, not both".to_string()),
+        (None, true) => return Err("no command given".to_string()),
+    };
+    Ok(Options {
+        event: event.unwrap_or_else(default_event),
+        frequency,
+        interval,
+        lines,
+        target,
+    })
+}
+
+/// Return the event to sample if none was given: CPU cycles, like `perf
+/// top`, or if this machine can't count them, the CPU clock.
+fn default_event() -> (String, Event) {
+    match Builder::new().kind(Hardware::CPU_CYCLES).build() {
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            eprintln!("perf-ev-top: cycles not supported, falling back to cpu-clock");
+            ("cpu-clock".to_string(), Software::CPU_CLOCK.into())
+        }
+        _ => ("cycles".to_string(), Hardware::CPU_CYCLES.into()),
+    }
+}
+
+fn top(options: &Options) -> io::Result<()> {
+    let (event, frequency) = (options.event.1.clone(), options.frequency);
+    let configure = move |builder: Builder<'static>| {
+        builder
+            .kind(event.clone())
+            .sample_frequency(frequency)
+            .sample(SampleType::IP | SampleType::TID)
+    };
+
+    let (mut sampler, mut child): (ProcessSampler, Option<Child>) = match &options.target {
+        Target::Command(argv) => {
+            let (child, sampler) = spawn_counted(Command::new(&argv[0]).args(&argv[1..]), |pid| {
+                ProcessSampler::inherit(pid, move |builder| configure(builder).enable_on_exec())
+            })
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", argv[0], e)))?;
+            (sampler, Some(child))
+        }
+        Target::Pid(pid) => {
+            let mut sampler = ProcessSampler::observe_process(*pid, configure)?;
+            sampler.enable()?;
+            (sampler, None)
+        }
+    };
+
+    let config = *sampler.config();
+    let mut maps: HashMap<u32, Vec<Mapping>> = HashMap::new();
+    let mut done = false;
+    while !done {
+        let start = Instant::now();
+        let mut counts: HashMap<(u32, u64), u64> = HashMap::new();
+        let mut total = 0;
+        while start.elapsed() < options.interval {
+            // Check for exit before draining, so that we don't miss the
+            // last records.
+            done = match &mut child {
+                Some(child) => child.try_wait()?.is_some(),
+                None => sampler.refresh().is_err(),
+            };
+            let mut any = false;
+            while let Some(record) = sampler.next() {
+                any = true;
+                if let Ok(RecordEvent::Sample(sample)) = record.try_parse(&config) {
+                    if let (Some(pid), Some(ip)) = (sample.pid, sample.ip) {
+                        // Catch the process's mappings while it's alive.
+                        maps.entry(pid).or_insert_with(|| read_maps(pid));
+                        *counts.entry((pid, ip)).or_insert(0) += 1;
+                        total += 1;
+                    }
+                }
+            }
+            if done {
+                break;
+            }
+            if !any {
+                sleep(POLL_INTERVAL);
+            }
+        }
+        show(&counts, total, start.elapsed(), &mut maps, options);
+    }
+    Ok(())
+}
+
+/// Print the `options.lines` locations with the most samples, using and
+/// updating the processes' mappings in `maps`.
+fn show(
+    counts: &HashMap<(u32, u64), u64>,
+    total: u64,
+    elapsed: Duration,
+    maps: &mut HashMap<u32, Vec<Mapping>>,
+    options: &Options,
+) {
+    let mut sorted: Vec<_> = counts.iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(a.1));
+
+    // Clear the screen, like `top`.
+    print!("\x1b[H\x1b[2J");
+    println!(
+        "{} samples of {} in {:.1}s\n",
+        total,
+        options.event.0,
+        elapsed.as_secs_f64()
+    );
+    println!("{:>9}  {:>7}  Location", "Overhead", "Pid");
+
+    for (&(pid, ip), &count) in sorted.into_iter().take(options.lines) {
+        let mappings = maps.entry(pid).or_default();
+        if find_mapping(mappings, ip).is_none() {
+            // The process may have mapped something new since we looked.
+            let current = read_maps(pid);
+            if !current.is_empty() {
+                *mappings = current;
+            }
+        }
+        let location = match find_mapping(mappings, ip) {
+            Some(m) => format!("{}+{:#x}", m.path, ip - m.start + m.offset),
+            None => format!("{:#x}", ip),
+        };
+        println!(
+            "{:>8.2}%  {:>7}  {}",
+            count as f64 * 100.0 / total as f64,
+            pid,
+            location
+        );
+    }
+}
+
+fn find_mapping(mappings: &[Mapping], ip: u64) -> Option<&Mapping> {
+    mappings.iter().find(|m| m.start <= ip && ip < m.end)
+}
+
+/// Return process `pid`'s executable file mappings, or nothing if they
+/// can't be read, say because the process has exited.
+fn read_maps(pid: u32) -> Vec<Mapping> {
+    let contents = fs::read_to_string(format!("/proc/{}/maps", pid)).unwrap_or_default();
+    contents.lines().filter_map(parse_mapping).collect()
+}
+
+/// Parse a line of `/proc/PID/maps`, like:
+///
+///     7f2c1a200000-7f2c1a228000 r-xp 00028000 fd:01 1234  /usr/lib/libc.so.6
+///
+/// Return `None` if the mapping isn't an executable file.
+fn parse_mapping(line: &str) -> Option<Mapping> {
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let perms = fields.next()?;
+    let offset = fields.next()?;
+    let path = fields.nth(2)?;
+    if !perms.contains('x') || !path.starts_with('/') {
+        return None;
+    }
+    Some(Mapping {
+        start: u64::from_str_radix(start, 16).ok()?,
+        end: u64::from_str_radix(end, 16).ok()?,
+        offset: u64::from_str_radix(offset, 16).ok()?,
+        path: path.to_string(),
+    })
+}
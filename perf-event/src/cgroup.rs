@@ -0,0 +1,180 @@
+//! Finding cgroups to observe.
+//!
+//! [`Builder::observe_cgroup`] counts events in all the processes belonging
+//! to a cgroup, given a `File` for the cgroup's directory. This module finds
+//! that directory from the cgroup's path within its hierarchy, as it appears
+//! in `/proc/PID/cgroup` or in systemd's unit names:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::cgroup;
+//!
+//!     if let Ok(dir) = cgroup::path("system.slice/cron.service") {
+//!         println!("cron's cgroup is {}", dir.display());
+//!     }
+//!     # Ok(()) }
+//!
+//! [`Builder::observe_cgroup_path`] does the lookup for you.
+//!
+//! The kernel can mount cgroups in two layouts. With cgroup v2, there's a
+//! single unified hierarchy, usually at `/sys/fs/cgroup`. With cgroup v1,
+//! each controller may have its own hierarchy, and perf uses the one with
+//! the `perf_event` controller, usually `/sys/fs/cgroup/perf_event`. Many
+//! systems mount both; when the `perf_event` controller has a v1 hierarchy,
+//! perf uses that, and cgroup directories in the v2 hierarchy won't work.
+//! [`root`] picks the right one.
+//!
+//! [`Builder::observe_cgroup`]: crate::Builder::observe_cgroup
+//! [`Builder::observe_cgroup_path`]: crate::Builder::observe_cgroup_path
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Where the kernel lists the filesystems mounted in our mount namespace.
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// A cgroup hierarchy that perf events can use.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Root {
+    /// Where the hierarchy is mounted.
+    pub mount_point: PathBuf,
+
+    /// True if this is the cgroup v2 unified hierarchy; false if it's a
+    /// cgroup v1 hierarchy with the `perf_event` controller.
+    pub unified: bool,
+}
+
+/// Return the cgroup hierarchy that perf events use.
+///
+/// This is the cgroup v1 hierarchy with the `perf_event` controller, if one
+/// is mounted, and the cgroup v2 unified hierarchy otherwise. If neither is
+/// mounted, this returns an error of kind [`io::ErrorKind::NotFound`].
+pub fn root() -> io::Result<Root> {
+    let mountinfo = fs::read_to_string(MOUNTINFO)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", MOUNTINFO, e)))?;
+    find_root(&mountinfo).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "no cgroup v2 hierarchy or cgroup v1 perf_event hierarchy is mounted",
+        )
+    })
+}
+
+/// Return the directory for the cgroup at `cgroup` within the hierarchy
+/// [`root`] returns.
+///
+/// `cgroup` is a path within the hierarchy, like `"my.slice/my.service"`. A
+/// leading `/`, as in `/proc/PID/cgroup`, is allowed. If there's no such
+/// cgroup, this returns an error of kind [`io::ErrorKind::NotFound`] that
+/// says which hierarchy it looked in.
+pub fn path<P: AsRef<Path>>(cgroup: P) -> io::Result<PathBuf> {
+    let cgroup = cgroup.as_ref();
+    let relative = cgroup.strip_prefix("/").unwrap_or(cgroup);
+    if relative
+        .components()
+        .any(|c| !matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "cgroup path {} must not contain `.` or `..`",
+                cgroup.display()
+            ),
+        ));
+    }
+
+    let root = root()?;
+    let dir = root.mount_point.join(relative);
+    if !dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "cgroup {} not found: no directory {} in the {} hierarchy",
+                cgroup.display(),
+                dir.display(),
+                if root.unified {
+                    "cgroup v2"
+                } else {
+                    "cgroup v1 perf_event"
+                }
+            ),
+        ));
+    }
+    Ok(dir)
+}
+
+/// Open the directory for the cgroup at `cgroup`, as found by [`path`], for
+/// use with [`Builder::observe_cgroup`].
+///
+/// [`Builder::observe_cgroup`]: crate::Builder::observe_cgroup
+pub fn open<P: AsRef<Path>>(cgroup: P) -> io::Result<File> {
+    let dir = path(cgroup)?;
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(&dir)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", dir.display(), e)))
+}
+
+/// Find the hierarchy perf uses in the contents of `/proc/self/mountinfo`.
+fn find_root(mountinfo: &str) -> Option<Root> {
+    let mut unified = None;
+    for line in mountinfo.lines() {
+        // The fields are: mount id, parent id, major:minor, root, mount
+        // point, mount options, optional fields, `-`, filesystem type,
+        // source, and superblock options.
+        let (before, after) = match line.split_once(" - ") {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let mount_point = match before.split(' ').nth(4) {
+            Some(mount_point) => unescape(mount_point),
+            None => continue,
+        };
+        let mut after = after.split(' ');
+        match (after.next(), after.nth(1)) {
+            (Some("cgroup"), Some(options)) if options.split(',').any(|o| o == "perf_event") => {
+                return Some(Root {
+                    mount_point,
+                    unified: false,
+                });
+            }
+            (Some("cgroup2"), _) if unified.is_none() => {
+                unified = Some(Root {
+                    mount_point,
+                    unified: true,
+                });
+            }
+            _ => {}
+        }
+    }
+    unified
+}
+
+/// Decode the octal escapes `mountinfo` uses for spaces and other special
+/// characters in paths.
+fn unescape(field: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match (bytes[i], escape) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(out))
+}
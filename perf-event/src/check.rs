@@ -0,0 +1,257 @@
+//! Probing what this process can measure.
+//!
+//! Whether a given measurement will work depends on the kernel's version
+//! and configuration, the processor, `perf_event_paranoid`, and the calling
+//! process's capabilities. The simplest reliable way to find out is to try:
+//! this module opens a throwaway counter for each [`Feature`], classifies
+//! the result, and gathers it all into a [`Report`] alongside the paranoia
+//! level and capabilities, so that tools can explain what they can't do:
+//!
+//!     use perf_event::check::{self, Feature};
+//!
+//!     let report = check::report();
+//!     if !report.status(Feature::HardwareEvents).is_available() {
+//!         eprintln!("no hardware counters; falling back to software events");
+//!     }
+//!     println!("{}", report);
+//!
+//! Each probe opens and immediately closes a disabled counter, so probing
+//! has no lasting effect.
+
+use crate::events::{Breakpoint, Hardware, Software};
+use crate::paranoid::Level;
+use crate::per_cpu::online_cpus;
+use crate::{Builder, Error};
+use std::fmt;
+
+/// Something a process might want to do with `perf_event_open`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Feature {
+    /// Count software events, like task clock time, in this process.
+    SoftwareEvents,
+
+    /// Count the processor's hardware events, like instructions retired.
+    HardwareEvents,
+
+    /// Count activity in the kernel on this process's behalf.
+    KernelCounting,
+
+    /// Count activity of all processes on a CPU.
+    SystemWide,
+
+    /// Set hardware breakpoints and watchpoints.
+    Breakpoints,
+
+    /// Sample into a ring buffer.
+    Sampling,
+
+    /// Inherit counters into new threads only, with
+    /// [`Builder::inherit_thread`].
+    ///
+    /// [`Builder::inherit_thread`]: crate::Builder::inherit_thread
+    InheritThread,
+}
+
+impl Feature {
+    /// All the features, in the order [`report`] probes them.
+    pub const ALL: [Feature; 7] = [
+        Feature::SoftwareEvents,
+        Feature::HardwareEvents,
+        Feature::KernelCounting,
+        Feature::SystemWide,
+        Feature::Breakpoints,
+        Feature::Sampling,
+        Feature::InheritThread,
+    ];
+
+    /// Return a short description of this feature, for use in reports.
+    pub fn description(self) -> &'static str {
+        match self {
+            Feature::SoftwareEvents => "software events",
+            Feature::HardwareEvents => "hardware events",
+            Feature::KernelCounting => "counting kernel activity",
+            Feature::SystemWide => "system-wide counting",
+            Feature::Breakpoints => "hardware breakpoints",
+            Feature::Sampling => "sampling",
+            Feature::InheritThread => "inherit_thread",
+        }
+    }
+}
+
+/// Whether a [`Feature`] is available to this process.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// The probe succeeded.
+    Available,
+
+    /// The kernel refused permission, because of `perf_event_paranoid` or
+    /// missing capabilities.
+    PermissionDenied,
+
+    /// The kernel or processor doesn't support the feature.
+    Unsupported,
+
+    /// The probe failed some other way; this holds the error message.
+    Failed(String),
+}
+
+impl Status {
+    /// Return true if the feature is available.
+    pub fn is_available(&self) -> bool {
+        *self == Status::Available
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Available => fmt.write_str("available"),
+            Status::PermissionDenied => fmt.write_str("permission denied"),
+            Status::Unsupported => fmt.write_str("not supported"),
+            Status::Failed(message) => write!(fmt, "failed: {}", message),
+        }
+    }
+}
+
+/// The results of probing every [`Feature`], returned by [`report`].
+///
+/// Its `Display` implementation produces a report suitable for showing to a
+/// user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Report {
+    paranoid: Option<Level>,
+    privileged: bool,
+    features: Vec<(Feature, Status)>,
+}
+
+impl Report {
+    /// Return the system's `perf_event_paranoid` level, or `None` if it
+    /// couldn't be read.
+    pub fn paranoid(&self) -> Option<Level> {
+        self.paranoid
+    }
+
+    /// Return true if this process has `CAP_PERFMON` or `CAP_SYS_ADMIN`; see
+    /// [`Level::privileged`].
+    pub fn privileged(&self) -> bool {
+        self.privileged
+    }
+
+    /// Return the status of `feature`.
+    pub fn status(&self, feature: Feature) -> &Status {
+        self.features
+            .iter()
+            .find(|(f, _)| *f == feature)
+            .map(|(_, status)| status)
+            .expect("report covers every feature")
+    }
+
+    /// Iterate over the features and their statuses.
+    pub fn iter(&self) -> impl Iterator<Item = (Feature, &Status)> {
+        self.features
+            .iter()
+            .map(|(feature, status)| (*feature, status))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.paranoid {
+            Some(level) => write!(fmt, "perf_event_paranoid: {}", level)?,
+            None => fmt.write_str("perf_event_paranoid: unknown")?,
+        }
+        write!(
+            fmt,
+            "\nCAP_PERFMON or CAP_SYS_ADMIN: {}",
+            if self.privileged { "yes" } else { "no" }
+        )?;
+        for (feature, status) in &self.features {
+            write!(fmt, "\n{}: {}", feature.description(), status)?;
+        }
+        Ok(())
+    }
+}
+
+/// Probe every [`Feature`], and report the results along with the paranoia
+/// level and capabilities.
+pub fn report() -> Report {
+    Report {
+        paranoid: Level::current().ok(),
+        privileged: Level::privileged(),
+        features: Feature::ALL
+            .iter()
+            .map(|&feature| (feature, probe(feature)))
+            .collect(),
+    }
+}
+
+/// Probe whether this process can use `feature`, by opening a throwaway
+/// counter that needs it.
+pub fn probe(feature: Feature) -> Status {
+    /// Something for the breakpoint probe to watch.
+    static TARGET: u64 = 0;
+
+    let result: Result<(), Error> = match feature {
+        Feature::SoftwareEvents => Builder::new()
+            .kind(Software::TASK_CLOCK)
+            .try_build()
+            .map(drop),
+        Feature::HardwareEvents => Builder::new()
+            .kind(Hardware::INSTRUCTIONS)
+            .try_build()
+            .map(drop),
+        Feature::KernelCounting => Builder::new()
+            .include_kernel()
+            .kind(Software::TASK_CLOCK)
+            .try_build()
+            .map(drop),
+        Feature::SystemWide => {
+            let cpu = online_cpus()
+                .ok()
+                .and_then(|cpus| cpus.first().copied())
+                .unwrap_or(0);
+            Builder::new()
+                .system_wide_on_cpu(cpu)
+                .kind(Software::CPU_CLOCK)
+                .try_build()
+                .map(drop)
+        }
+        Feature::Breakpoints => Builder::new()
+            .kind(Breakpoint::write(&TARGET as *const u64 as u64, 8))
+            .try_build()
+            .map(drop),
+        #[cfg(feature = "sampling")]
+        Feature::Sampling => Builder::new()
+            .kind(Software::TASK_CLOCK)
+            .sample_period(1_000_000)
+            .build_sampler()
+            .map(drop)
+            .map_err(Error::from),
+        // Without a ring buffer, we can only check that the kernel accepts
+        // a sampling event.
+        #[cfg(not(feature = "sampling"))]
+        Feature::Sampling => Builder::new()
+            .kind(Software::TASK_CLOCK)
+            .sample_period(1_000_000)
+            .try_build()
+            .map(drop),
+        Feature::InheritThread => Builder::new()
+            .inherit(true)
+            .inherit_thread(true)
+            .kind(Software::TASK_CLOCK)
+            .try_build()
+            .map(drop),
+    };
+
+    match result {
+        Ok(_) => Status::Available,
+        Err(Error::PermissionDenied { .. }) | Err(Error::Restricted { .. }) => {
+            Status::PermissionDenied
+        }
+        // An `EINVAL` here means the kernel doesn't understand the request.
+        Err(Error::UnsupportedEvent(_))
+        | Err(Error::KernelTooOld(_))
+        | Err(Error::InvalidConfiguration(_)) => Status::Unsupported,
+        Err(e) => Status::Failed(e.to_string()),
+    }
+}
@@ -0,0 +1,335 @@
+//! A global allocator that measures what each allocation costs.
+//!
+//! [`CountingAlloc`] wraps another allocator, usually [`System`], and reads a
+//! per-thread counter before and after each call to it, adding the
+//! difference to the totals for the request's size class. Since the
+//! counters are [`UserReadCounter`]s, on x86 with a hardware event each
+//! read is a single `rdpmc` instruction, cheap enough to leave in place
+//! around every allocation.
+//!
+//! ```no_run
+//! use perf_event::counting_alloc::CountingAlloc;
+//! use std::alloc::System;
+//!
+//! #[global_allocator]
+//! static ALLOC: CountingAlloc<System> = CountingAlloc::new(System);
+//!
+//! fn main() {
+//!     let strings: Vec<String> = (0..10_000).map(|n| n.to_string()).collect();
+//!     drop(strings);
+//!
+//!     for class in ALLOC.size_classes() {
+//!         println!("up to {:>8} bytes: {} allocs, {:.1} instructions each",
+//!                  class.max_size, class.alloc.calls,
+//!                  class.alloc.mean().unwrap_or(0.0));
+//!     }
+//! }
+//! ```
+//!
+//! Each thread opens its counter the first time it allocates. Allocations
+//! made while opening it, and any the wrapped allocator makes itself, pass
+//! straight through unmeasured. If the counter can't be opened, say,
+//! because the processor has no performance monitoring unit, that thread's
+//! allocations are never measured.
+//!
+//! The counts include a little of the work of reading the counter itself,
+//! and on processors or events that don't allow reading the counter
+//! directly, the `read` system calls: compare size classes with each other,
+//! not with the same event counted some other way.
+//!
+//! This is only available when the `"counting_alloc"` feature is enabled.
+//!
+//! [`System`]: std::alloc::System
+//! [`UserReadCounter`]: crate::user_read::UserReadCounter
+
+use crate::events::{Event, Hardware, Software};
+use crate::user_read::UserReadCounter;
+use crate::Builder;
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of size classes: one for each power of two up to
+/// `usize::MAX`, counting one byte as two to the zero.
+const CLASSES: usize = usize::BITS as usize + 1;
+
+/// An allocator that counts an event around each call to `A`, and totals
+/// the counts by size class.
+///
+/// See the [module documentation](self) for details.
+pub struct CountingAlloc<A> {
+    inner: A,
+    event: Kind,
+    classes: [ClassTotals; CLASSES],
+}
+
+/// The event a `CountingAlloc` counts. `Event` itself can't be built in a
+/// `const fn`.
+#[derive(Clone, Copy)]
+enum Kind {
+    Hardware(Hardware),
+    Software(Software),
+}
+
+struct ClassTotals {
+    alloc: Totals,
+    dealloc: Totals,
+    realloc: Totals,
+}
+
+struct Totals {
+    calls: AtomicU64,
+    events: AtomicU64,
+}
+
+/// The measurements for allocator calls in one size class, as returned by
+/// [`CountingAlloc::size_classes`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SizeClass {
+    /// The smallest size in this class, in bytes.
+    pub min_size: usize,
+
+    /// The largest size in this class, in bytes.
+    pub max_size: usize,
+
+    /// Calls to `alloc` and `alloc_zeroed`.
+    pub alloc: Cost,
+
+    /// Calls to `dealloc`.
+    pub dealloc: Cost,
+
+    /// Calls to `realloc`, classed by their new size.
+    pub realloc: Cost,
+}
+
+/// The number of measured calls of some kind, and the total of the counter
+/// across them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Cost {
+    /// The number of calls measured.
+    pub calls: u64,
+
+    /// The total number of events counted during those calls.
+    pub events: u64,
+}
+
+impl Cost {
+    /// Return the mean number of events per call, or `None` if there were
+    /// no calls.
+    pub fn mean(&self) -> Option<f64> {
+        if self.calls == 0 {
+            return None;
+        }
+        Some(self.events as f64 / self.calls as f64)
+    }
+}
+
+/// Which allocator method a measurement is for.
+#[derive(Clone, Copy)]
+enum Op {
+    Alloc,
+    Dealloc,
+    Realloc,
+}
+
+/// This thread's counter, if it has opened one.
+enum Slot {
+    Unopened,
+    Open(UserReadCounter),
+    Failed,
+}
+
+thread_local! {
+    /// True while this thread is inside a `CountingAlloc` method, so that
+    /// allocations made while opening or reading the counter aren't
+    /// measured, or worse, recurse. This has no destructor, so it's
+    /// available until the thread is gone.
+    static BUSY: Cell<bool> = const { Cell::new(false) };
+
+    /// The counter this thread's allocations are measured with. This is
+    /// shared by every `CountingAlloc` in the program, and counts the event
+    /// of whichever one this thread used first.
+    static COUNTER: RefCell<Slot> = const { RefCell::new(Slot::Unopened) };
+}
+
+impl<A> CountingAlloc<A> {
+    /// Return an allocator that counts instructions retired around each
+    /// call to `inner`.
+    pub const fn new(inner: A) -> CountingAlloc<A> {
+        CountingAlloc::hardware(inner, Hardware::INSTRUCTIONS)
+    }
+
+    /// Return an allocator that counts the hardware event `event` around
+    /// each call to `inner`.
+    pub const fn hardware(inner: A, event: Hardware) -> CountingAlloc<A> {
+        CountingAlloc::with_kind(inner, Kind::Hardware(event))
+    }
+
+    /// Return an allocator that counts the software event `event` around
+    /// each call to `inner`.
+    ///
+    /// Software events can't be read without a system call, so this is
+    /// mostly useful on machines without a performance monitoring unit.
+    pub const fn software(inner: A, event: Software) -> CountingAlloc<A> {
+        CountingAlloc::with_kind(inner, Kind::Software(event))
+    }
+
+    const fn with_kind(inner: A, event: Kind) -> CountingAlloc<A> {
+        CountingAlloc {
+            inner,
+            event,
+            classes: [ClassTotals::ZERO; CLASSES],
+        }
+    }
+
+    /// Return the measurements so far for each size class in which there
+    /// has been at least one measured call, from smallest to largest.
+    pub fn size_classes(&self) -> Vec<SizeClass> {
+        self.classes
+            .iter()
+            .enumerate()
+            .map(|(class, totals)| {
+                let (min_size, max_size) = class_bounds(class);
+                SizeClass {
+                    min_size,
+                    max_size,
+                    alloc: totals.alloc.cost(),
+                    dealloc: totals.dealloc.cost(),
+                    realloc: totals.realloc.cost(),
+                }
+            })
+            .filter(|class| class.alloc.calls + class.dealloc.calls + class.realloc.calls > 0)
+            .collect()
+    }
+
+    /// Discard all the measurements so far.
+    pub fn reset(&self) {
+        for totals in &self.classes {
+            for op in [&totals.alloc, &totals.dealloc, &totals.realloc] {
+                op.calls.store(0, Ordering::Relaxed);
+                op.events.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Call `f`, and add what it cost to `op`'s totals for `size`.
+    fn measure<T>(&self, op: Op, size: usize, f: impl FnOnce() -> T) -> T {
+        if BUSY.with(|busy| busy.replace(true)) {
+            return f();
+        }
+
+        let start = self.read_counter();
+        let result = f();
+        if let (Some(start), Some(end)) = (start, self.read_counter()) {
+            let totals = &self.classes[size_class(size)];
+            let totals = match op {
+                Op::Alloc => &totals.alloc,
+                Op::Dealloc => &totals.dealloc,
+                Op::Realloc => &totals.realloc,
+            };
+            totals.calls.fetch_add(1, Ordering::Relaxed);
+            totals
+                .events
+                .fetch_add(end.wrapping_sub(start), Ordering::Relaxed);
+        }
+
+        BUSY.with(|busy| busy.set(false));
+        result
+    }
+
+    /// Return this thread's counter's value, opening it if need be, or
+    /// `None` if it can't be opened or read.
+    fn read_counter(&self) -> Option<u64> {
+        COUNTER
+            .try_with(|slot| {
+                let mut slot = slot.borrow_mut();
+                if let Slot::Unopened = *slot {
+                    *slot = match self.open_counter() {
+                        Some(counter) => Slot::Open(counter),
+                        None => Slot::Failed,
+                    };
+                }
+                match *slot {
+                    Slot::Open(ref mut counter) => counter.read().ok(),
+                    _ => None,
+                }
+            })
+            // The thread is exiting, and has already dropped its counter.
+            .ok()
+            .flatten()
+    }
+
+    fn open_counter(&self) -> Option<UserReadCounter> {
+        let event: Event = match self.event {
+            Kind::Hardware(event) => event.into(),
+            Kind::Software(event) => event.into(),
+        };
+        let mut counter = Builder::new().kind(event).build_user_read().ok()?;
+        counter.enable().ok()?;
+        Some(counter)
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.measure(Op::Alloc, layout.size(), || self.inner.alloc(layout))
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.measure(Op::Alloc, layout.size(), || self.inner.alloc_zeroed(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.measure(Op::Dealloc, layout.size(), || {
+            self.inner.dealloc(ptr, layout)
+        })
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.measure(Op::Realloc, new_size, || {
+            self.inner.realloc(ptr, layout, new_size)
+        })
+    }
+}
+
+impl ClassTotals {
+    // Only used to initialize `CountingAlloc::classes`.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: ClassTotals = ClassTotals {
+        alloc: Totals::ZERO,
+        dealloc: Totals::ZERO,
+        realloc: Totals::ZERO,
+    };
+}
+
+impl Totals {
+    // Only used to initialize `CountingAlloc::classes`.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: Totals = Totals {
+        calls: AtomicU64::new(0),
+        events: AtomicU64::new(0),
+    };
+
+    fn cost(&self) -> Cost {
+        Cost {
+            calls: self.calls.load(Ordering::Relaxed),
+            events: self.events.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Return the size class for a request of `size` bytes: the base-two
+/// logarithm of `size`, rounded up. Zero-sized requests go with one-byte
+/// requests.
+fn size_class(size: usize) -> usize {
+    (usize::BITS - (size.max(1) - 1).leading_zeros()) as usize
+}
+
+/// Return the smallest and largest sizes in size class `class`.
+fn class_bounds(class: usize) -> (usize, usize) {
+    match class {
+        0 => (0, 1),
+        _ if class < CLASSES - 1 => ((1 << (class - 1)) + 1, 1 << class),
+        _ => ((1 << (class - 1)) + 1, usize::MAX),
+    }
+}
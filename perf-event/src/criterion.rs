@@ -0,0 +1,166 @@
+//! Benchmarks that count events, using [Criterion.rs].
+//!
+//! Criterion normally measures how long each iteration of a benchmark
+//! takes. Wall-clock time is noisy, though, and says little about why code
+//! is slow. A [`PerfMeasurement`] measures each iteration by the number of
+//! occurrences of an event instead, like instructions retired or cache
+//! misses:
+//!
+//! ```no_run
+//! use criterion::{criterion_group, criterion_main, Criterion};
+//! use perf_event::criterion::PerfMeasurement;
+//! use perf_event::events::Hardware;
+//!
+//! fn fibonacci(n: u64) -> u64 {
+//!     if n < 2 { n } else { fibonacci(n - 1) + fibonacci(n - 2) }
+//! }
+//!
+//! fn bench(c: &mut Criterion<PerfMeasurement>) {
+//!     c.bench_function("fib 20", |b| b.iter(|| fibonacci(criterion::black_box(20))));
+//! }
+//!
+//! fn instructions() -> Criterion<PerfMeasurement> {
+//!     let measurement = PerfMeasurement::new(Hardware::INSTRUCTIONS, "instructions")
+//!         .expect("couldn't count instructions");
+//!     Criterion::default().with_measurement(measurement)
+//! }
+//!
+//! criterion_group! {
+//!     name = benches;
+//!     config = instructions();
+//!     targets = bench
+//! }
+//! criterion_main!(benches);
+//! ```
+//!
+//! Criterion reports a single value per measurement, so a `PerfMeasurement`
+//! counts a single event. To see several, run the benchmarks once with a
+//! measurement for each.
+//!
+//! The counter observes the thread that creates the `PerfMeasurement`,
+//! which should be the one that runs the benchmarks; Criterion runs them on
+//! the thread that calls `criterion_main`. Counts are scaled up if the
+//! kernel had to multiplex the counter with others.
+//!
+//! This module is available only with the `criterion` feature.
+//!
+//! [Criterion.rs]: https://docs.rs/criterion
+
+use crate::{prorate, Builder, CountAndTime, Counter};
+use ::criterion::measurement::{Measurement, ValueFormatter};
+use ::criterion::Throughput;
+use std::cell::RefCell;
+use std::io;
+
+/// A Criterion [`Measurement`] that counts occurrences of an event.
+///
+/// See the [module documentation](self) for details.
+///
+/// [`Measurement`]: ::criterion::measurement::Measurement
+#[derive(Debug)]
+pub struct PerfMeasurement {
+    counter: RefCell<Counter>,
+    formatter: CountFormatter,
+}
+
+/// Formats counts for Criterion's reports.
+#[derive(Debug)]
+struct CountFormatter {
+    /// What the counter counts, like `"instructions"`.
+    unit: &'static str,
+}
+
+impl PerfMeasurement {
+    /// Count `event` in the calling thread, and report it in units of
+    /// `unit`, like `"instructions"` or `"cache misses"`.
+    pub fn new<E: Into<crate::events::Event>>(
+        event: E,
+        unit: &'static str,
+    ) -> io::Result<PerfMeasurement> {
+        PerfMeasurement::from_counter(Builder::new().kind(event).build()?, unit)
+    }
+
+    /// Measure with `counter`, reporting in units of `unit`.
+    ///
+    /// Use this to count an event in a way [`new`] doesn't, like excluding
+    /// the kernel. `counter` is enabled, if it isn't already, and left
+    /// running; the measurement only ever looks at how much it changes.
+    ///
+    /// [`new`]: PerfMeasurement::new
+    pub fn from_counter(mut counter: Counter, unit: &'static str) -> io::Result<PerfMeasurement> {
+        counter.enable()?;
+        Ok(PerfMeasurement {
+            counter: RefCell::new(counter),
+            formatter: CountFormatter { unit },
+        })
+    }
+
+    fn read(&self) -> CountAndTime {
+        self.counter
+            .borrow_mut()
+            .read_count_and_time()
+            .expect("reading perf counter failed")
+    }
+}
+
+impl Measurement for PerfMeasurement {
+    type Intermediate = CountAndTime;
+    type Value = u64;
+
+    fn start(&self) -> CountAndTime {
+        self.read()
+    }
+
+    fn end(&self, start: CountAndTime) -> u64 {
+        let end = self.read();
+        let count = end.count - start.count;
+        prorate(
+            count,
+            end.time_enabled - start.time_enabled,
+            end.time_running - start.time_running,
+        )
+        .unwrap_or(count)
+    }
+
+    fn add(&self, v1: &u64, v2: &u64) -> u64 {
+        v1 + v2
+    }
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &self.formatter
+    }
+}
+
+impl ValueFormatter for CountFormatter {
+    fn scale_values(&self, _typical: f64, _values: &mut [f64]) -> &'static str {
+        self.unit
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        let (per, unit) = match *throughput {
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) => (n, "per byte"),
+            Throughput::Elements(n) => (n, "per element"),
+        };
+        for value in values {
+            *value /= per as f64;
+        }
+        unit
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        self.unit
+    }
+}
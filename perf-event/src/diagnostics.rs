@@ -0,0 +1,254 @@
+//! Explaining why hardware counters can't be scheduled.
+//!
+//! When a [`Group`] holds more hardware events than the processor has
+//! counters available, the kernel never puts it on the CPU, and all its
+//! counts read as zero. [`Counts::scheduling_failed`] detects this after the
+//! fact; the [`contention`] function here looks at the system to help explain
+//! it.
+//!
+//! For example:
+//!
+//!     use perf_event::diagnostics;
+//!
+//!     let report = diagnostics::contention();
+//!     if report.nmi_watchdog() == Some(true) {
+//!         println!("The NMI watchdog is using one hardware counter.");
+//!     }
+//!     println!("{}", report);
+//!
+//! [`Group`]: crate::Group
+//! [`Counts::scheduling_failed`]: crate::Counts::scheduling_failed
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A snapshot of what might be competing for this machine's hardware
+/// counters.
+///
+/// This is returned by [`contention`]. Its `Display` implementation produces
+/// a report suitable for showing to a user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Contention {
+    nmi_watchdog: Option<bool>,
+    generic_counters: Option<u32>,
+    perf_users: Vec<PerfUser>,
+    unreadable_processes: usize,
+}
+
+/// A process holding open `perf_event` file descriptors.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PerfUser {
+    /// The process's id.
+    pub pid: u32,
+
+    /// The process's command name, from `/proc/PID/comm`.
+    pub command: String,
+
+    /// The number of `perf_event` file descriptors the process has open.
+    pub events: usize,
+}
+
+/// Inspect the system for things competing for hardware counters.
+///
+/// This consults:
+///
+/// -   `/proc/sys/kernel/nmi_watchdog`, since the kernel's NMI watchdog
+///     reserves a hardware counter for itself while enabled;
+///
+/// -   the processor's own description of its performance monitoring unit,
+///     for the number of general-purpose counters each logical processor has
+///     (currently only on Intel x86_64 processors); and
+///
+/// -   the open file descriptors of every process in `/proc`, to find other
+///     processes using `perf_event`. Without sufficient privileges, some
+///     processes can't be inspected; see [`unreadable_processes`].
+///
+/// Any information that can't be obtained is simply left out of the report.
+///
+/// [`unreadable_processes`]: Contention::unreadable_processes
+pub fn contention() -> Contention {
+    let (perf_users, unreadable_processes) = perf_users();
+    Contention {
+        nmi_watchdog: nmi_watchdog(),
+        generic_counters: generic_counters(),
+        perf_users,
+        unreadable_processes,
+    }
+}
+
+impl Contention {
+    /// Return whether the kernel's NMI watchdog is enabled, or `None` if
+    /// `/proc/sys/kernel/nmi_watchdog` couldn't be read.
+    pub fn nmi_watchdog(&self) -> Option<bool> {
+        self.nmi_watchdog
+    }
+
+    /// Return the number of general-purpose hardware counters per logical
+    /// processor, or `None` if it couldn't be determined.
+    ///
+    /// This doesn't include fixed-function counters, which some processors
+    /// provide for a few common events like instructions and cycles.
+    pub fn generic_counters(&self) -> Option<u32> {
+        self.generic_counters
+    }
+
+    /// Return the number of general-purpose counters left for other uses
+    /// once the NMI watchdog has taken its share, or `None` if the number of
+    /// counters couldn't be determined.
+    pub fn available_counters(&self) -> Option<u32> {
+        let reserved = (self.nmi_watchdog == Some(true)) as u32;
+        self.generic_counters.map(|n| n.saturating_sub(reserved))
+    }
+
+    /// Return the processes found holding `perf_event` file descriptors,
+    /// sorted by process id. This may include the calling process.
+    pub fn perf_users(&self) -> &[PerfUser] {
+        &self.perf_users
+    }
+
+    /// Return the number of processes whose file descriptors couldn't be
+    /// inspected, usually for lack of privileges. These may be using
+    /// `perf_event` too.
+    pub fn unreadable_processes(&self) -> usize {
+        self.unreadable_processes
+    }
+}
+
+impl fmt::Display for Contention {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.nmi_watchdog {
+            Some(true) => fmt.write_str(
+                "- the NMI watchdog is enabled, and uses one hardware counter; \
+                 `echo 0 > /proc/sys/kernel/nmi_watchdog` frees it",
+            )?,
+            Some(false) => fmt.write_str("- the NMI watchdog is disabled")?,
+            None => fmt.write_str("- couldn't tell whether the NMI watchdog is enabled")?,
+        }
+
+        match self.generic_counters {
+            Some(n) => write!(
+                fmt,
+                "\n- each processor has {} general-purpose hardware counters",
+                n
+            )?,
+            None => fmt.write_str("\n- couldn't determine the number of hardware counters")?,
+        }
+
+        let own_pid = std::process::id();
+        let others: Vec<&PerfUser> = self
+            .perf_users
+            .iter()
+            .filter(|user| user.pid != own_pid)
+            .collect();
+        if others.is_empty() {
+            fmt.write_str("\n- no other processes have perf events open")?;
+        } else {
+            fmt.write_str("\n- other processes have perf events open:")?;
+            for user in others {
+                write!(
+                    fmt,
+                    "\n    {} ({}): {} events",
+                    user.pid, user.command, user.events
+                )?;
+            }
+        }
+        if self.unreadable_processes > 0 {
+            write!(
+                fmt,
+                "\n- {} processes couldn't be inspected; try running as root",
+                self.unreadable_processes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Read `/proc/sys/kernel/nmi_watchdog`.
+fn nmi_watchdog() -> Option<bool> {
+    let contents = fs::read_to_string("/proc/sys/kernel/nmi_watchdog").ok()?;
+    Some(contents.trim() != "0")
+}
+
+/// Ask the processor how many general-purpose counters it has.
+#[cfg(target_arch = "x86_64")]
+fn generic_counters() -> Option<u32> {
+    use std::arch::x86_64::__cpuid;
+
+    // Leaf 0xA describes Intel's architectural performance monitoring. Older
+    // compilers consider `__cpuid` unsafe.
+    #[allow(unused_unsafe)]
+    let perfmon = unsafe {
+        if __cpuid(0).eax < 0xA {
+            return None;
+        }
+        __cpuid(0xA)
+    };
+
+    // Bits 7..0 are the version; zero means the leaf isn't implemented, as on
+    // AMD processors, or that a hypervisor is hiding the PMU.
+    if perfmon.eax & 0xff == 0 {
+        return None;
+    }
+    Some((perfmon.eax >> 8) & 0xff)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn generic_counters() -> Option<u32> {
+    None
+}
+
+/// Find processes with `perf_event` file descriptors open. Also return the
+/// number of processes we weren't allowed to look at.
+fn perf_users() -> (Vec<PerfUser>, usize) {
+    let mut users = vec![];
+    let mut unreadable = 0;
+
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return (users, unreadable),
+    };
+    for entry in entries.flatten() {
+        let pid: u32 = match entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        match count_perf_fds(&entry.path()) {
+            Some(0) => {}
+            Some(events) => {
+                let command = fs::read_to_string(entry.path().join("comm"))
+                    .map(|s| s.trim_end().to_string())
+                    .unwrap_or_default();
+                users.push(PerfUser {
+                    pid,
+                    command,
+                    events,
+                });
+            }
+            None => unreadable += 1,
+        }
+    }
+
+    users.sort_by_key(|user| user.pid);
+    (users, unreadable)
+}
+
+/// Count the `perf_event` file descriptors in `/proc/PID/fd`, or return
+/// `None` if we can't read it. A process that exits while we're looking has
+/// no file descriptors.
+fn count_perf_fds(process: &Path) -> Option<usize> {
+    let fds = match fs::read_dir(process.join("fd")) {
+        Ok(fds) => fds,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Some(0),
+        Err(_) => return None,
+    };
+    Some(
+        fds.flatten()
+            .filter(|fd| {
+                fs::read_link(fd.path())
+                    .map(|target| target.as_os_str() == "anon_inode:[perf_event]")
+                    .unwrap_or(false)
+            })
+            .count(),
+    )
+}
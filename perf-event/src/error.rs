@@ -0,0 +1,232 @@
+//! Classifying the errors `perf_event_open` returns.
+
+use crate::android::Restriction;
+use crate::paranoid::Level;
+use std::fmt;
+use std::io;
+
+/// A classified error from building a counter.
+///
+/// The kernel reports problems opening a counter with bare `errno` values,
+/// which mean different things in this context than they do elsewhere, and
+/// which don't say what to do about them. Converting an [`io::Error`] from
+/// this crate into an `Error` sorts it into one of the common cases, and its
+/// `Display` implementation explains the likely cause and remedy. Use
+/// [`Builder::try_build`] to get one directly:
+///
+///     use perf_event::{Builder, Error};
+///     use perf_event::events::Software;
+///
+///     match Builder::new().kind(Software::PAGE_FAULTS).try_build() {
+///         Ok(counter) => { /* ... */ }
+///         Err(Error::PermissionDenied { .. }) => eprintln!("can't count page faults here"),
+///         Err(e) => panic!("{}", e),
+///     }
+///
+/// Every variant holds the original `io::Error`, available from
+/// [`io_error`], and converting an `Error` back into an `io::Error` returns
+/// it unchanged, so `raw_os_error` and `kind` still work as before. When the
+/// `Display` output explains the error, it leaves the original out, and the
+/// error's [`source`][std::error::Error::source] returns it instead; when
+/// there's nothing to add, `Display` shows the original error itself.
+///
+/// [`Builder::try_build`]: crate::Builder::try_build
+/// [`io_error`]: Error::io_error
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// `EACCES` or `EPERM`: the calling process lacks the privileges the
+    /// counter requires, usually because of `perf_event_paranoid`.
+    PermissionDenied {
+        /// The most restrictive paranoia level that would permit this
+        /// counter, if known.
+        required: Option<Level>,
+
+        /// The system's paranoia level when the error was classified, if it
+        /// could be read.
+        current: Option<Level>,
+
+        /// The original error.
+        error: io::Error,
+    },
+
+    /// `EACCES` or `EPERM` on Android, where a platform restriction is to
+    /// blame: the `security.perf_harden` property, `perf_event_paranoid`,
+    /// or SELinux policy. See the [`android`] module.
+    ///
+    /// [`android`]: crate::android
+    Restricted {
+        /// What is preventing the counter from being opened.
+        restriction: Restriction,
+
+        /// The original error.
+        error: io::Error,
+    },
+
+    /// `ENOENT`: the kernel or processor doesn't support the requested
+    /// event. The inner error is usually an [`UnsupportedEvent`].
+    ///
+    /// [`UnsupportedEvent`]: crate::events::UnsupportedEvent
+    UnsupportedEvent(io::Error),
+
+    /// `E2BIG`: the kernel doesn't recognize some feature the counter's
+    /// attributes request; it is probably older than the feature.
+    KernelTooOld(io::Error),
+
+    /// `EINVAL`: the kernel rejected the combination of options, or some
+    /// value in it, or the `Builder` caught such a problem itself.
+    InvalidConfiguration(io::Error),
+
+    /// `ENOSPC`: the hardware has no room for the counter, because its
+    /// group is too large, or there are no debug registers left for a
+    /// breakpoint.
+    TooManyEvents(io::Error),
+
+    /// Any other error.
+    Other(io::Error),
+}
+
+impl Error {
+    /// Return the original `io::Error`.
+    pub fn io_error(&self) -> &io::Error {
+        match self {
+            Error::PermissionDenied { error, .. }
+            | Error::Restricted { error, .. }
+            | Error::UnsupportedEvent(error)
+            | Error::KernelTooOld(error)
+            | Error::InvalidConfiguration(error)
+            | Error::TooManyEvents(error)
+            | Error::Other(error) => error,
+        }
+    }
+
+    /// Return the operating system's error number, if the original error
+    /// came directly from a system call.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.io_error().raw_os_error()
+    }
+
+    /// If this is a [`PermissionDenied`] error, record that the counter
+    /// needed `required`, for use in the message. On Android, diagnose the
+    /// restriction responsible, if any, and return a [`Restricted`] error
+    /// instead.
+    ///
+    /// [`PermissionDenied`]: Error::PermissionDenied
+    /// [`Restricted`]: Error::Restricted
+    pub(crate) fn with_required_level(self, level: Level) -> Error {
+        match self {
+            Error::PermissionDenied { current, error, .. } => match Restriction::diagnose(level) {
+                Some(restriction) => Error::Restricted { restriction, error },
+                None => Error::PermissionDenied {
+                    required: Some(level),
+                    current,
+                    error,
+                },
+            },
+            other => other,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        if error.kind() == io::ErrorKind::Unsupported {
+            return Error::UnsupportedEvent(error);
+        }
+        match error.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EPERM) => Error::PermissionDenied {
+                required: None,
+                current: Level::current().ok(),
+                error,
+            },
+            Some(libc::ENOENT) => Error::UnsupportedEvent(error),
+            Some(libc::E2BIG) => Error::KernelTooOld(error),
+            Some(libc::ENOSPC) => Error::TooManyEvents(error),
+            Some(libc::EINVAL) => Error::InvalidConfiguration(error),
+            // The `Builder`'s own checks.
+            _ if error.kind() == io::ErrorKind::InvalidInput => Error::InvalidConfiguration(error),
+            _ => Error::Other(error),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::PermissionDenied { error, .. }
+            | Error::Restricted { error, .. }
+            | Error::UnsupportedEvent(error)
+            | Error::KernelTooOld(error)
+            | Error::InvalidConfiguration(error)
+            | Error::TooManyEvents(error)
+            | Error::Other(error) => error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::PermissionDenied {
+                required, current, ..
+            } => {
+                fmt.write_str("permission denied opening perf event")?;
+                if let Some(current) = current {
+                    write!(fmt, "; perf_event_paranoid is {}", current)?;
+                }
+                match required {
+                    Some(required) => write!(fmt, "; {}", required.instructions()),
+                    None => fmt.write_str(
+                        "; see /proc/sys/kernel/perf_event_paranoid, \
+                         or run with the CAP_PERFMON capability",
+                    ),
+                }
+            }
+            Error::Restricted { restriction, .. } => write!(
+                fmt,
+                "permission denied opening perf event: {}; {}",
+                restriction,
+                restriction.instructions()
+            ),
+            Error::UnsupportedEvent(error) => fmt::Display::fmt(error, fmt),
+            Error::KernelTooOld(_) => fmt.write_str(
+                "the kernel doesn't support some requested counter feature; \
+                 it may be too old",
+            ),
+            Error::InvalidConfiguration(error) if error.raw_os_error().is_some() => fmt.write_str(
+                "the kernel rejected this combination of counter options; \
+                 check for options that conflict, like `inherit` on a group member",
+            ),
+            Error::InvalidConfiguration(error) => fmt::Display::fmt(error, fmt),
+            Error::TooManyEvents(_) => fmt.write_str(
+                "no hardware resources left for this counter; \
+                 try a smaller group, or fewer breakpoints",
+            ),
+            Error::Other(error) => fmt::Display::fmt(error, fmt),
+        }
+    }
+}
+
+impl Error {
+    /// Return true if our `Display` implementation shows the original
+    /// error's message as its own, rather than explaining it.
+    fn is_transparent(&self) -> bool {
+        match self {
+            Error::UnsupportedEvent(_) | Error::Other(_) => true,
+            Error::InvalidConfiguration(error) => error.raw_os_error().is_none(),
+            _ => false,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // Don't report the same message twice: if we've already displayed
+        // the original error, skip to its source.
+        if self.is_transparent() {
+            self.io_error().source()
+        } else {
+            Some(self.io_error())
+        }
+    }
+}
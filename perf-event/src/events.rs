@@ -14,10 +14,21 @@
 //! -   [`Software`] events are counted by the kernel. This includes things
 //!     like context switches, page faults, and so on.
 //!
+//! -   [`OnPmu`] events are hardware or cache events restricted to one of
+//!     the processor's PMUs, for hybrid systems with several kinds of core.
+//!
 //! -   [`Breakpoint`] events correspond to hardware breakpoints. They can
 //!     count read/write accesses to an address as well as execution of an
 //!     instruction address.
 //!
+//! -   [`Tracepoint`] events are static instrumentation points in the kernel,
+//!     like `sched:sched_switch`, found by name in `tracefs`.
+//!
+//! -   [`KProbe`] events are dynamic probes on arbitrary kernel functions.
+//!
+//! -   [`Energy`] events count the energy consumed by parts of the system, as
+//!     reported by Intel's RAPL (and AMD's compatible) interfaces.
+//!
 //! The `Event` type is just an enum with a variant for each of the above types,
 //! which all implement `Into<Event>`.
 //!
@@ -34,6 +45,9 @@
 #![allow(non_camel_case_types)]
 use bitflags::bitflags;
 use perf_event_open_sys::bindings;
+use std::ffi::CString;
+use std::fmt;
+use std::io;
 
 /// Any sort of event. This is a sum of the [`Hardware`],
 /// [`Software`], and [`Cache`] types, which all implement
@@ -43,6 +57,7 @@ use perf_event_open_sys::bindings;
 /// [`Software`]: enum.Software.html
 /// [`Cache`]: struct.Cache.html
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
     #[allow(missing_docs)]
     Hardware(Hardware),
@@ -55,10 +70,85 @@ pub enum Event {
 
     #[allow(missing_docs)]
     Breakpoint(Breakpoint),
+
+    #[allow(missing_docs)]
+    Tracepoint(Tracepoint),
+
+    #[allow(missing_docs)]
+    KProbe(KProbe),
+
+    #[allow(missing_docs)]
+    Energy(Energy),
+
+    #[allow(missing_docs)]
+    Pmu(Pmu),
+
+    #[allow(missing_docs)]
+    OnPmu(OnPmu),
 }
 
 impl Event {
-    pub(crate) fn update_attrs(self, attr: &mut bindings::perf_event_attr) {
+    /// Parse an event name, in the style of the `perf` command-line tool.
+    ///
+    /// This accepts the names `perf list` shows for hardware, software, and
+    /// generic cache events, like `"cycles"`, `"task-clock"`, or
+    /// `"L1-dcache-load-misses"`, along with `perf`'s short aliases like
+    /// `"cs"` for context switches. Matching ignores case.
+    ///
+    /// If `name` isn't recognized, the error lists the closest known names,
+    /// for use in messages like `perf`'s "did you mean" hints:
+    ///
+    ///     use perf_event::events::{Event, Hardware};
+    ///
+    ///     assert_eq!(Event::parse("Instructions").unwrap(),
+    ///                Event::Hardware(Hardware::INSTRUCTIONS));
+    ///
+    ///     let err = Event::parse("cylces").unwrap_err();
+    ///     assert_eq!(err.suggestions(), &["cycles"]);
+    ///     assert_eq!(err.to_string(),
+    ///                "unknown event `cylces`; did you mean `cycles`?");
+    ///
+    /// `Event` also implements [`FromStr`](std::str::FromStr), so you can use
+    /// `str::parse` as well.
+    ///
+    /// This is only available when the `"discovery"` feature is enabled.
+    #[cfg(feature = "discovery")]
+    pub fn parse(name: &str) -> Result<Event, ParseEventError> {
+        let names = event_names();
+        if let Some((_, event)) = names
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+        {
+            return Ok(event.clone());
+        }
+
+        // Suggest names within a modest edit distance, closest first.
+        let lower = name.to_ascii_lowercase();
+        let threshold = std::cmp::max(2, lower.len() / 3);
+        let mut close: Vec<(usize, &String)> = names
+            .iter()
+            .map(|(known, _)| (edit_distance(&lower, &known.to_ascii_lowercase()), known))
+            .filter(|&(distance, _)| distance <= threshold)
+            .collect();
+        close.sort();
+        close.dedup_by(|a, b| a.1 == b.1);
+
+        Err(ParseEventError {
+            name: name.to_string(),
+            suggestions: close
+                .into_iter()
+                .take(3)
+                .map(|(_, known)| known.clone())
+                .collect(),
+        })
+    }
+
+    /// Set the fields of `attr` that select this event.
+    ///
+    /// If this leaves `attr` pointing at data owned by the event, like a
+    /// kprobe's function name, return that data. The caller must keep it
+    /// alive until the event has been opened.
+    pub(crate) fn update_attrs(self, attr: &mut bindings::perf_event_attr) -> Option<CString> {
         match self {
             Event::Hardware(hw) => {
                 attr.type_ = bindings::PERF_TYPE_HARDWARE;
@@ -80,20 +170,209 @@ impl Event {
                 match bp {
                     Breakpoint::Data { access, addr, len } => {
                         attr.bp_type = access.bits();
-                        attr.__bindgen_anon_3.bp_addr = addr;
-                        attr.__bindgen_anon_4.bp_len = len;
+                        attr.set_bp_addr(addr);
+                        attr.set_bp_len(len);
                     }
                     Breakpoint::Code { addr } => {
                         attr.bp_type = bindings::HW_BREAKPOINT_X;
-                        attr.__bindgen_anon_3.bp_addr = addr;
+                        attr.set_bp_addr(addr);
                         // According to the perf_event_open man page, execute breakpoints
                         // should set len to sizeof(long).
-                        attr.__bindgen_anon_4.bp_len = std::mem::size_of::<libc::c_long>() as _;
+                        attr.set_bp_len(std::mem::size_of::<libc::c_long>() as _);
                     }
                 }
             }
+            Event::Tracepoint(tp) => {
+                attr.type_ = bindings::PERF_TYPE_TRACEPOINT;
+                attr.config = tp.id;
+                // Tracepoints always fire in the kernel, so excluding kernel
+                // activity would exclude every hit.
+                attr.set_exclude_kernel(0);
+            }
+            Event::Energy(energy) => {
+                attr.type_ = energy.pmu_type;
+                attr.config = energy.config;
+                // The power PMU rejects events that exclude anything.
+                attr.set_exclude_kernel(0);
+                attr.set_exclude_hv(0);
+            }
+            Event::Pmu(pmu) => {
+                attr.type_ = pmu.pmu_type;
+                attr.config = pmu.config;
+            }
+            Event::OnPmu(on_pmu) => {
+                attr.type_ = on_pmu.type_;
+                attr.config = on_pmu.config();
+            }
+            Event::KProbe(kp) => {
+                attr.type_ = kp.pmu_type;
+                attr.config = kp.config;
+                attr.set_exclude_kernel(0);
+                return match kp.target {
+                    KProbeTarget::Function { name, offset } => {
+                        attr.set_kprobe_func(name.as_ptr() as u64);
+                        attr.set_probe_offset(offset);
+                        Some(name)
+                    }
+                    KProbeTarget::Address(addr) => {
+                        attr.set_kprobe_func(0);
+                        attr.set_kprobe_addr(addr);
+                        None
+                    }
+                };
+            }
+        }
+        None
+    }
+}
+
+#[cfg(feature = "discovery")]
+impl std::str::FromStr for Event {
+    type Err = ParseEventError;
+
+    fn from_str(name: &str) -> Result<Event, ParseEventError> {
+        Event::parse(name)
+    }
+}
+
+/// The error returned by [`Event::parse`] for an unrecognized name.
+#[cfg(feature = "discovery")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseEventError {
+    name: String,
+    suggestions: Vec<String>,
+}
+
+#[cfg(feature = "discovery")]
+impl ParseEventError {
+    /// Return the name that wasn't recognized.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the known event names closest to the one given, most similar
+    /// first. This may be empty.
+    pub fn suggestions(&self) -> &[String] {
+        &self.suggestions
+    }
+}
+
+#[cfg(feature = "discovery")]
+impl fmt::Display for ParseEventError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "unknown event `{}`", self.name)?;
+        if let Some((last, rest)) = self.suggestions.split_last() {
+            fmt.write_str("; did you mean ")?;
+            for suggestion in rest {
+                write!(fmt, "`{}`, ", suggestion)?;
+            }
+            if !rest.is_empty() {
+                fmt.write_str("or ")?;
+            }
+            write!(fmt, "`{}`?", last)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "discovery")]
+impl std::error::Error for ParseEventError {}
+
+/// Return every event name `Event::parse` accepts, with its event.
+#[cfg(feature = "discovery")]
+fn event_names() -> Vec<(String, Event)> {
+    let mut names: Vec<(String, Event)> = vec![];
+    let mut add = |name: &str, event: Event| names.push((name.to_string(), event));
+
+    use Hardware as H;
+    for &(name, hw) in &[
+        ("cpu-cycles", H::CPU_CYCLES),
+        ("cycles", H::CPU_CYCLES),
+        ("instructions", H::INSTRUCTIONS),
+        ("cache-references", H::CACHE_REFERENCES),
+        ("cache-misses", H::CACHE_MISSES),
+        ("branch-instructions", H::BRANCH_INSTRUCTIONS),
+        ("branches", H::BRANCH_INSTRUCTIONS),
+        ("branch-misses", H::BRANCH_MISSES),
+        ("bus-cycles", H::BUS_CYCLES),
+        ("stalled-cycles-frontend", H::STALLED_CYCLES_FRONTEND),
+        ("idle-cycles-frontend", H::STALLED_CYCLES_FRONTEND),
+        ("stalled-cycles-backend", H::STALLED_CYCLES_BACKEND),
+        ("idle-cycles-backend", H::STALLED_CYCLES_BACKEND),
+        ("ref-cycles", H::REF_CPU_CYCLES),
+    ] {
+        add(name, hw.into());
+    }
+
+    use Software as S;
+    for &(name, sw) in &[
+        ("cpu-clock", S::CPU_CLOCK),
+        ("task-clock", S::TASK_CLOCK),
+        ("page-faults", S::PAGE_FAULTS),
+        ("faults", S::PAGE_FAULTS),
+        ("context-switches", S::CONTEXT_SWITCHES),
+        ("cs", S::CONTEXT_SWITCHES),
+        ("cpu-migrations", S::CPU_MIGRATIONS),
+        ("migrations", S::CPU_MIGRATIONS),
+        ("minor-faults", S::PAGE_FAULTS_MIN),
+        ("major-faults", S::PAGE_FAULTS_MAJ),
+        ("alignment-faults", S::ALIGNMENT_FAULTS),
+        ("emulation-faults", S::EMULATION_FAULTS),
+        ("dummy", S::DUMMY),
+        ("bpf-output", S::BPF_OUTPUT),
+        ("cgroup-switches", S::CGROUP_SWITCHES),
+    ] {
+        add(name, sw.into());
+    }
+
+    // Generic cache events are named `CACHE-OPs` for accesses and
+    // `CACHE-OP-misses` for misses.
+    for &(cache, which) in &[
+        ("L1-dcache", WhichCache::L1D),
+        ("L1-icache", WhichCache::L1I),
+        ("LLC", WhichCache::LL),
+        ("dTLB", WhichCache::DTLB),
+        ("iTLB", WhichCache::ITLB),
+        ("branch", WhichCache::BPU),
+        ("node", WhichCache::NODE),
+    ] {
+        for &(op, ops, operation) in &[
+            ("load", "loads", CacheOp::READ),
+            ("store", "stores", CacheOp::WRITE),
+            ("prefetch", "prefetches", CacheOp::PREFETCH),
+        ] {
+            let access = Cache {
+                which,
+                operation,
+                result: CacheResult::ACCESS,
+            };
+            let miss = Cache {
+                result: CacheResult::MISS,
+                ..access.clone()
+            };
+            add(&format!("{}-{}", cache, ops), access.into());
+            add(&format!("{}-{}-misses", cache, op), miss.into());
+        }
+    }
+
+    names
+}
+
+/// Return the Levenshtein distance between `a` and `b`.
+#[cfg(feature = "discovery")]
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ac) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &bc) in b.iter().enumerate() {
+            let substitute = diagonal + (ac != bc) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
         }
     }
+    row[b.len()]
 }
 
 /// Hardware counters.
@@ -110,6 +389,7 @@ impl Event {
 /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
 #[repr(u32)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Hardware {
     /// Total cycles.
     CPU_CYCLES = bindings::PERF_COUNT_HW_CPU_CYCLES,
@@ -142,12 +422,82 @@ pub enum Hardware {
     REF_CPU_CYCLES = bindings::PERF_COUNT_HW_REF_CPU_CYCLES,
 }
 
+impl Hardware {
+    /// Count this event only on the processors belonging to the PMU whose
+    /// type number is `pmu_type`; see [`OnPmu`].
+    pub fn on_pmu(self, pmu_type: u32) -> OnPmu {
+        OnPmu {
+            type_: bindings::PERF_TYPE_HARDWARE,
+            config: self as u64,
+            pmu_type,
+        }
+    }
+}
+
 impl From<Hardware> for Event {
     fn from(hw: Hardware) -> Event {
         Event::Hardware(hw)
     }
 }
 
+/// A [`Hardware`] or [`Cache`] event, counted only on the processors
+/// belonging to a particular PMU.
+///
+/// Hybrid processors, like Intel's Alder Lake and later, have several kinds
+/// of core, each with its own PMU: `cpu_core` for the performance cores and
+/// `cpu_atom` for the efficiency cores. A plain `Hardware` or `Cache` event
+/// on such a system counts on only one kind of core, which makes for
+/// misleading totals when the threads being measured move between them.
+/// Instead, open one counter for each PMU, and add up the results:
+///
+///     # fn main() -> std::io::Result<()> {
+///     use perf_event::Builder;
+///     use perf_event::events::{Hardware, Pmu};
+///
+///     let mut counters = vec![];
+///     for name in ["cpu_core", "cpu_atom"] {
+///         if let Ok(pmu) = Pmu::named(name) {
+///             let cycles = Hardware::CPU_CYCLES.on_pmu(pmu.pmu_type());
+///             counters.push(Builder::new().kind(cycles).build()?);
+///         }
+///     }
+///     # Ok(()) }
+///
+/// The kernel accepts these "extended type" events starting with Linux
+/// 6.0; older kernels reject them with `ENOENT` or `EINVAL`.
+///
+/// Use [`Hardware::on_pmu`] or [`Cache::on_pmu`] to create an `OnPmu`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OnPmu {
+    /// `PERF_TYPE_HARDWARE` or `PERF_TYPE_HW_CACHE`.
+    type_: u32,
+
+    /// The generic event's `config` value, without the PMU type.
+    config: u64,
+
+    pmu_type: u32,
+}
+
+impl OnPmu {
+    /// Return the type number of the PMU this event is restricted to.
+    pub fn pmu_type(&self) -> u32 {
+        self.pmu_type
+    }
+
+    /// Return the `config` value to pass to the kernel: the generic event's
+    /// configuration, with the PMU's type number in the upper 32 bits.
+    pub fn config(&self) -> u64 {
+        self.config | (self.pmu_type as u64) << bindings::PERF_PMU_TYPE_SHIFT
+    }
+}
+
+impl From<OnPmu> for Event {
+    fn from(on_pmu: OnPmu) -> Event {
+        Event::OnPmu(on_pmu)
+    }
+}
+
 /// Software counters, implemented by the kernel.
 ///
 /// Each variant of this enum corresponds to a particular `PERF_COUNT_SW_`...
@@ -156,6 +506,7 @@ impl From<Hardware> for Event {
 /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
 #[repr(u32)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Software {
     /// High-resolution per-CPU timer.
     CPU_CLOCK = bindings::PERF_COUNT_SW_CPU_CLOCK,
@@ -189,6 +540,68 @@ pub enum Software {
 
     /// Placeholder, for collecting informational sample records.
     DUMMY = bindings::PERF_COUNT_SW_DUMMY,
+
+    /// Output from BPF programs, written with `bpf_perf_event_output`.
+    ///
+    /// This event doesn't count anything by itself; it exists so that a BPF
+    /// program can send data to user space through the event's ring buffer.
+    ///
+    /// This requires Linux 4.4 or later.
+    BPF_OUTPUT = bindings::PERF_COUNT_SW_BPF_OUTPUT,
+
+    /// Context switches between tasks in different cgroups.
+    ///
+    /// This requires Linux 5.13 or later.
+    CGROUP_SWITCHES = bindings::PERF_COUNT_SW_CGROUP_SWITCHES,
+}
+
+impl Software {
+    /// All the software events, in order of their `PERF_COUNT_SW_` values.
+    const ALL: [Software; 12] = [
+        Software::CPU_CLOCK,
+        Software::TASK_CLOCK,
+        Software::PAGE_FAULTS,
+        Software::CONTEXT_SWITCHES,
+        Software::CPU_MIGRATIONS,
+        Software::PAGE_FAULTS_MIN,
+        Software::PAGE_FAULTS_MAJ,
+        Software::ALIGNMENT_FAULTS,
+        Software::EMULATION_FAULTS,
+        Software::DUMMY,
+        Software::BPF_OUTPUT,
+        Software::CGROUP_SWITCHES,
+    ];
+
+    /// Return the earliest Linux version supporting this event, as a
+    /// `(major, minor)` pair.
+    ///
+    /// Older kernels reject the event when a [`Counter`] is built; see
+    /// [`UnsupportedEvent`].
+    ///
+    /// [`Counter`]: crate::Counter
+    pub fn min_kernel_version(self) -> (u32, u32) {
+        match self {
+            Software::CPU_CLOCK
+            | Software::TASK_CLOCK
+            | Software::PAGE_FAULTS
+            | Software::CONTEXT_SWITCHES
+            | Software::CPU_MIGRATIONS
+            | Software::PAGE_FAULTS_MIN
+            | Software::PAGE_FAULTS_MAJ
+            | Software::ALIGNMENT_FAULTS
+            | Software::EMULATION_FAULTS => (2, 6),
+            Software::DUMMY => (3, 12),
+            Software::BPF_OUTPUT => (4, 4),
+            Software::CGROUP_SWITCHES => (5, 13),
+        }
+    }
+
+    fn from_config(config: u64) -> Option<Software> {
+        Software::ALL
+            .iter()
+            .copied()
+            .find(|&sw| sw as u64 == config)
+    }
 }
 
 impl From<Software> for Event {
@@ -234,6 +647,7 @@ impl From<Software> for Event {
 /// [`operation`]: enum.CacheOp.html
 /// [`result`]: enum.CacheResult.html
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cache {
     /// Which cache is being monitored? (data, instruction, ...)
     pub which: WhichCache,
@@ -252,6 +666,82 @@ impl From<Cache> for Event {
 }
 
 impl Cache {
+    /// Return an iterator over every combination of cache, operation, and
+    /// result, whether or not the running processor supports it.
+    pub fn all() -> impl Iterator<Item = Cache> {
+        WhichCache::ALL.iter().flat_map(|&which| {
+            CacheOp::ALL.iter().flat_map(move |&operation| {
+                CacheResult::ALL.iter().map(move |&result| Cache {
+                    which,
+                    operation,
+                    result,
+                })
+            })
+        })
+    }
+
+    /// Return true if this process can count this event on the running
+    /// kernel and processor, by trying to open a counter for it.
+    ///
+    /// Many combinations make no sense, like writes to the instruction
+    /// cache, and processors leave out others, so the kernel rejects them
+    /// when a [`Counter`] is built. This returns `Ok(false)` for those. If
+    /// opening the counter fails for some other reason, like not having
+    /// permission to use hardware counters at all, this returns the error.
+    ///
+    /// [`Counter`]: crate::Counter
+    pub fn is_supported(&self) -> io::Result<bool> {
+        match crate::Builder::new().kind(self.clone()).build() {
+            Ok(_) => Ok(true),
+            // The kernel reports combinations it doesn't recognize with
+            // `ENOENT`, and combinations the processor can't count with
+            // `EINVAL` or `EOPNOTSUPP`.
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => Ok(false),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EINVAL | libc::EOPNOTSUPP)) => {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Return the cache events this process can count on the running kernel
+    /// and processor, in the order [`all`] produces them.
+    ///
+    /// This lets a tool offer a menu of events that will actually work:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::events::Cache;
+    ///
+    ///     for cache in Cache::supported()? {
+    ///         println!("{:?} {:?} {:?}", cache.which, cache.operation, cache.result);
+    ///     }
+    ///     # Ok(()) }
+    ///
+    /// This tries opening a counter for each combination; see
+    /// [`is_supported`] for details.
+    ///
+    /// [`all`]: Cache::all
+    /// [`is_supported`]: Cache::is_supported
+    pub fn supported() -> io::Result<Vec<Cache>> {
+        let mut supported = vec![];
+        for cache in Cache::all() {
+            if cache.is_supported()? {
+                supported.push(cache);
+            }
+        }
+        Ok(supported)
+    }
+
+    /// Count this event only on the processors belonging to the PMU whose
+    /// type number is `pmu_type`; see [`OnPmu`].
+    pub fn on_pmu(&self, pmu_type: u32) -> OnPmu {
+        OnPmu {
+            type_: bindings::PERF_TYPE_HW_CACHE,
+            config: self.as_config(),
+            pmu_type,
+        }
+    }
+
     fn as_config(&self) -> u64 {
         self.which as u64 | ((self.operation as u64) << 8) | ((self.result as u64) << 16)
     }
@@ -267,6 +757,7 @@ impl Cache {
 /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WhichCache {
     /// Level 1 data cache.
     L1D = bindings::PERF_COUNT_HW_CACHE_L1D,
@@ -290,6 +781,19 @@ pub enum WhichCache {
     NODE = bindings::PERF_COUNT_HW_CACHE_NODE,
 }
 
+impl WhichCache {
+    /// All the caches, in order of their `PERF_COUNT_HW_CACHE_` values.
+    pub const ALL: [WhichCache; 7] = [
+        WhichCache::L1D,
+        WhichCache::L1I,
+        WhichCache::LL,
+        WhichCache::DTLB,
+        WhichCache::ITLB,
+        WhichCache::BPU,
+        WhichCache::NODE,
+    ];
+}
+
 /// What sort of cache operation we would like to observe.
 ///
 /// This is used in the `Cache` type as part of the identification of a cache
@@ -300,6 +804,7 @@ pub enum WhichCache {
 /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CacheOp {
     /// Read accesses.
     READ = bindings::PERF_COUNT_HW_CACHE_OP_READ,
@@ -311,6 +816,12 @@ pub enum CacheOp {
     PREFETCH = bindings::PERF_COUNT_HW_CACHE_OP_PREFETCH,
 }
 
+impl CacheOp {
+    /// All the operations, in order of their `PERF_COUNT_HW_CACHE_OP_`
+    /// values.
+    pub const ALL: [CacheOp; 3] = [CacheOp::READ, CacheOp::WRITE, CacheOp::PREFETCH];
+}
+
 #[repr(u32)]
 /// What sort of cache result we're interested in observing.
 ///
@@ -325,6 +836,7 @@ pub enum CacheOp {
 ///
 /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CacheResult {
     /// Cache was accessed.
     ACCESS = bindings::PERF_COUNT_HW_CACHE_RESULT_ACCESS,
@@ -333,8 +845,14 @@ pub enum CacheResult {
     MISS = bindings::PERF_COUNT_HW_CACHE_RESULT_MISS,
 }
 
+impl CacheResult {
+    /// Both results, in order of their `PERF_COUNT_HW_CACHE_RESULT_` values.
+    pub const ALL: [CacheResult; 2] = [CacheResult::ACCESS, CacheResult::MISS];
+}
+
 bitflags! {
     /// Memory access mask for a hardware data breakpoint.
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct BreakpointAccess : u32 {
         /// Count when we read the memory location.
         const READ = bindings::HW_BREAKPOINT_R;
@@ -413,10 +931,20 @@ bitflags! {
 ///
 /// - The valid values of len are quite limited. The [`perf_event_open`][man]
 ///   manpage indicates that the only valid values for `bp_len` are 1, 2, 4,
-///   and 8.
+///   and 8. Architectures also constrain the address's alignment. Use
+///   [`validate`] to check a breakpoint against the rules for this
+///   architecture; [`Builder::build`] does so before calling the kernel.
+///
+/// - Processors have only a few debug registers, so only a few breakpoints
+///   can be active in a thread at once. A [`Watchpoints`] set keeps track of
+///   how many are in use.
 ///
 /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
+/// [`validate`]: Breakpoint::validate
+/// [`Builder::build`]: crate::Builder::build
+/// [`Watchpoints`]: crate::watchpoints::Watchpoints
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Breakpoint {
     /// Data breakpoint. Triggers when code reads or writes to the memory area
     /// as configured by the parameters below.
@@ -431,9 +959,13 @@ pub enum Breakpoint {
 
         /// The length of the breakpoint being measured.
         ///
-        /// There are a limited number of valid values for this field. Basically,
-        /// the options are 1, 2, 4, and 8. Setting this field to anything else
-        /// will cause counter creation to fail with an error.
+        /// There are a limited number of valid values for this field. On most
+        /// architectures, the options are 1, 2, 4, and 8; aarch64 accepts any
+        /// length from 1 to 8. See [`validate`] for the exact rules. Setting
+        /// this field to anything else will cause counter creation to fail
+        /// with an error.
+        ///
+        /// [`validate`]: Breakpoint::validate
         len: u64,
     },
 
@@ -487,6 +1019,189 @@ impl Breakpoint {
             len,
         }
     }
+
+    /// Create a data breakpoint that counts the given kinds of `access` to
+    /// the provided memory location.
+    ///
+    /// See the struct field docs for valid values of `len`.
+    pub const fn data(access: BreakpointAccess, addr: u64, len: u64) -> Self {
+        Self::Data { access, addr, len }
+    }
+
+    /// Create a data breakpoint that counts the given kinds of `access` to
+    /// `value`, covering all its bytes.
+    ///
+    /// This saves converting a reference to an address by hand:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::{Breakpoint, BreakpointAccess};
+    ///
+    ///     let total = std::cell::Cell::new(0_u64);
+    ///     let mut counter = Builder::new()
+    ///         .kind(Breakpoint::watch(&total, BreakpointAccess::WRITE)?)
+    ///         .build()?;
+    ///     # Ok(()) }
+    ///
+    /// If `value` is too large or badly aligned for a single breakpoint to
+    /// cover, as [`validate`] checks, this returns an error of kind
+    /// [`io::ErrorKind::InvalidInput`]; consider [`watch_range`] instead.
+    ///
+    /// The breakpoint watches the memory `value` occupies now. The borrow
+    /// ends when this returns, so it's up to the caller to make sure
+    /// `value` stays put while the breakpoint is in use.
+    ///
+    /// [`validate`]: Breakpoint::validate
+    /// [`watch_range`]: Breakpoint::watch_range
+    pub fn watch<T: ?Sized>(value: &T, access: BreakpointAccess) -> io::Result<Self> {
+        let bp = Self::Data {
+            access,
+            addr: value as *const T as *const u8 as usize as u64,
+            len: std::mem::size_of_val(value) as u64,
+        };
+        bp.validate()?;
+        Ok(bp)
+    }
+
+    /// Return the fewest data breakpoints that together cover the `len`
+    /// bytes starting at `addr`, each counting the given kinds of `access`.
+    ///
+    /// Debug registers can only watch small, aligned regions, so a larger or
+    /// misaligned range takes several breakpoints. For example, on x86_64,
+    /// twelve bytes starting at an address that's a multiple of four but not
+    /// of eight need a four-byte breakpoint followed by an eight-byte one.
+    /// Each breakpoint must be built as a separate [`Counter`], and uses a
+    /// debug register; a [`Watchpoints`] set can keep track of them.
+    ///
+    /// If `len` is zero, this returns an error of kind
+    /// [`io::ErrorKind::InvalidInput`].
+    ///
+    /// [`Counter`]: crate::Counter
+    /// [`Watchpoints`]: crate::watchpoints::Watchpoints
+    pub fn watch_range(access: BreakpointAccess, addr: u64, len: u64) -> io::Result<Vec<Self>> {
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't watch an empty range",
+            ));
+        }
+
+        let mut breakpoints = vec![];
+        let mut addr = addr;
+        let end = addr.checked_add(len).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "breakpoint range extends past the end of the address space",
+            )
+        })?;
+        while addr < end {
+            let len = if cfg!(target_arch = "aarch64") {
+                // Anything up to the end of the aligned eight-byte word.
+                (8 - addr % 8).min(end - addr)
+            } else {
+                // The largest power of two that `addr` is aligned to and
+                // that fits in what's left.
+                let max = if cfg!(target_arch = "x86") { 4 } else { 8 };
+                let mut len = max;
+                while !addr.is_multiple_of(len) || len > end - addr {
+                    len /= 2;
+                }
+                len
+            };
+            breakpoints.push(Self::Data { access, addr, len });
+            addr += len;
+        }
+        Ok(breakpoints)
+    }
+}
+
+impl Breakpoint {
+    /// Return true if this is a code breakpoint, false if it watches data.
+    pub const fn is_code(&self) -> bool {
+        matches!(self, Breakpoint::Code { .. })
+    }
+
+    /// Check that this breakpoint's address and length are acceptable to
+    /// this architecture's debug registers.
+    ///
+    /// The kernel rejects some invalid breakpoints with a bare `EINVAL`,
+    /// and on some architectures silently adjusts others to watch a
+    /// different range than was asked for. This catches both, and returns
+    /// an error of kind [`io::ErrorKind::InvalidInput`] saying what's wrong.
+    ///
+    /// The rules are:
+    ///
+    /// - On x86 and x86_64, a data breakpoint's length must be 1, 2, 4, or
+    ///   (on x86_64 only) 8, and its address must be a multiple of its
+    ///   length.
+    ///
+    /// - On aarch64, a data breakpoint's length may be anything from 1 to 8,
+    ///   but it must not extend past the end of the aligned eight-byte word
+    ///   containing its address. Code breakpoints' addresses must be
+    ///   multiples of four.
+    ///
+    /// - s390x has no hardware breakpoints at all, so every breakpoint is
+    ///   rejected, with an error of kind [`io::ErrorKind::Unsupported`].
+    ///
+    /// - Elsewhere, a data breakpoint's length must be 1, 2, 4, or 8.
+    pub fn validate(&self) -> io::Result<()> {
+        match *self {
+            Breakpoint::Code { addr } => check_breakpoint(true, addr, 0),
+            Breakpoint::Data { addr, len, .. } => check_breakpoint(false, addr, len),
+        }
+    }
+
+    /// Validate the breakpoint described by `attrs`, if any.
+    pub(crate) fn check_attrs(attrs: &bindings::perf_event_attr) -> io::Result<()> {
+        if attrs.type_ != bindings::PERF_TYPE_BREAKPOINT {
+            return Ok(());
+        }
+        let code = attrs.bp_type == bindings::HW_BREAKPOINT_X;
+        check_breakpoint(code, attrs.bp_addr(), attrs.bp_len())
+    }
+}
+
+/// Check a breakpoint at `addr` against this architecture's rules. For code
+/// breakpoints, `len` is ignored: we always pass the kernel `sizeof(long)`.
+fn check_breakpoint(code: bool, addr: u64, len: u64) -> io::Result<()> {
+    if cfg!(target_arch = "s390x") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "s390x has no hardware breakpoints",
+        ));
+    }
+    let problem = if code {
+        if cfg!(target_arch = "aarch64") && !addr.is_multiple_of(4) {
+            Some("code breakpoint addresses must be multiples of 4")
+        } else {
+            None
+        }
+    } else if cfg!(target_arch = "aarch64") {
+        if !(1..=8).contains(&len) {
+            Some("data breakpoint length must be from 1 to 8")
+        } else if addr % 8 + len > 8 {
+            Some("data breakpoint must not cross an eight-byte boundary")
+        } else {
+            None
+        }
+    } else if !matches!(len, 1 | 2 | 4 | 8) || (cfg!(target_arch = "x86") && len == 8) {
+        Some("unsupported data breakpoint length")
+    } else if cfg!(any(target_arch = "x86", target_arch = "x86_64")) && !addr.is_multiple_of(len) {
+        Some("data breakpoint address must be a multiple of its length")
+    } else {
+        None
+    };
+
+    match problem {
+        None => Ok(()),
+        Some(problem) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "invalid breakpoint at {:#x}, length {}: {}",
+                addr, len, problem
+            ),
+        )),
+    }
 }
 
 impl From<Breakpoint> for Event {
@@ -494,3 +1209,966 @@ impl From<Breakpoint> for Event {
         Event::Breakpoint(bp)
     }
 }
+
+/// The directories where `tracefs` is usually mounted, in order of preference.
+const TRACEFS_DIRS: &[&str] = &["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// A kernel tracepoint.
+///
+/// Tracepoints are named `SYSTEM:NAME`, like `sched:sched_switch` or
+/// `syscalls:sys_enter_openat`; `perf list tracepoint` shows the ones your
+/// kernel has. [`Tracepoint::new`] looks up a tracepoint's id in `tracefs`,
+/// which is usually mounted on `/sys/kernel/tracing`:
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use perf_event::Builder;
+/// use perf_event::events::Tracepoint;
+///
+/// let switches = Tracepoint::new("sched:sched_switch")?;
+/// let mut counter = Builder::new().kind(switches).build()?;
+/// # Ok(()) }
+/// ```
+///
+/// Since tracepoints are always hit in kernel code, choosing a tracepoint
+/// with [`Builder::kind`] also turns on [`Builder::include_kernel`].
+///
+/// Reading `tracefs` usually requires root privileges, and depending on
+/// `perf_event_paranoid`, so may using tracepoints at all.
+///
+#[cfg_attr(
+    feature = "sampling",
+    doc = "
+When sampling a tracepoint, [`SampleType::RAW`] includes the tracepoint's
+data in each sample.
+
+[`SampleType::RAW`]: crate::samples::SampleType::RAW
+"
+)]
+///
+/// [`Builder::kind`]: crate::Builder::kind
+/// [`Builder::include_kernel`]: crate::Builder::include_kernel
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tracepoint {
+    system: String,
+    name: String,
+    id: u64,
+}
+
+impl Tracepoint {
+    /// Find the tracepoint named `name`, in the form `SYSTEM:NAME`.
+    ///
+    /// If `name` isn't of that form, this returns an error of kind
+    /// [`io::ErrorKind::InvalidInput`]. If there's no such tracepoint, or
+    /// `tracefs` isn't mounted, this returns an error of kind
+    /// [`io::ErrorKind::NotFound`].
+    pub fn new(name: &str) -> io::Result<Tracepoint> {
+        let (system, event) = match name.split_once(':') {
+            Some((system, event)) if !system.is_empty() && !event.is_empty() => (system, event),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("tracepoint name `{}` should be SYSTEM:NAME", name),
+                ))
+            }
+        };
+
+        for dir in TRACEFS_DIRS {
+            let path = format!("{}/events/{}/{}/id", dir, system, event);
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", path, e))),
+            };
+            let id = contents.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected contents in {}: {:?}", path, contents),
+                )
+            })?;
+            return Ok(Tracepoint {
+                system: system.to_string(),
+                name: event.to_string(),
+                id,
+            });
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "tracepoint `{}` not found under {}; is tracefs mounted?",
+                name,
+                TRACEFS_DIRS.join(" or ")
+            ),
+        ))
+    }
+
+    /// Return the tracepoint's system, like `"sched"`.
+    pub fn system(&self) -> &str {
+        &self.system
+    }
+
+    /// Return the tracepoint's name within its system, like
+    /// `"sched_switch"`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the tracepoint's id, which the kernel uses as the event's
+    /// `config` value.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Read the layout of this tracepoint's records from its `format` file
+    /// in `tracefs`, for decoding samples' [`raw`] data.
+    ///
+    /// This is only available when the `"sampling"` feature is enabled.
+    ///
+    /// [`raw`]: crate::samples::Sample::raw
+    #[cfg(feature = "sampling")]
+    pub fn format(&self) -> io::Result<crate::tracepoint::Format> {
+        for dir in TRACEFS_DIRS {
+            let path = format!("{}/events/{}/{}/format", dir, self.system, self.name);
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", path, e))),
+            };
+            return crate::tracepoint::Format::parse(&contents)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)));
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no format file for tracepoint `{}` under {}; is tracefs mounted?",
+                self,
+                TRACEFS_DIRS.join(" or ")
+            ),
+        ))
+    }
+}
+
+impl fmt::Display for Tracepoint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}", self.system, self.name)
+    }
+}
+
+impl From<Tracepoint> for Event {
+    fn from(tp: Tracepoint) -> Self {
+        Event::Tracepoint(tp)
+    }
+}
+
+/// Where the kernel's `power` PMU describes itself.
+const POWER_PMU_DIR: &str = "/sys/bus/event_source/devices/power";
+
+/// A part of the system whose energy use an [`Energy`] event measures.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EnergyDomain {
+    /// `energy-pkg`: the whole processor package (socket).
+    Package,
+
+    /// `energy-cores`: the processor cores alone.
+    Cores,
+
+    /// `energy-gpu`: the integrated graphics.
+    Gpu,
+
+    /// `energy-ram`: the memory attached to the package.
+    Ram,
+
+    /// `energy-psys`: the whole platform, on systems that support it.
+    Psys,
+}
+
+impl EnergyDomain {
+    /// All the domains, in the order above.
+    pub const ALL: [EnergyDomain; 5] = [
+        EnergyDomain::Package,
+        EnergyDomain::Cores,
+        EnergyDomain::Gpu,
+        EnergyDomain::Ram,
+        EnergyDomain::Psys,
+    ];
+
+    /// Return the name of this domain's event in sysfs, like `"energy-pkg"`.
+    pub fn event_name(self) -> &'static str {
+        match self {
+            EnergyDomain::Package => "energy-pkg",
+            EnergyDomain::Cores => "energy-cores",
+            EnergyDomain::Gpu => "energy-gpu",
+            EnergyDomain::Ram => "energy-ram",
+            EnergyDomain::Psys => "energy-psys",
+        }
+    }
+}
+
+/// An energy counter from the kernel's `power` PMU.
+///
+/// Intel processors since Sandy Bridge, and AMD processors since Zen, count
+/// the energy used by the processor package, its cores, attached memory,
+/// and so on, through their RAPL ("Running Average Power Limit")
+/// interfaces. The kernel exposes these as events of its `power` PMU,
+/// described in `/sys/bus/event_source/devices/power`. [`Energy::new`]
+/// reads that description, including the scale that converts counts to
+/// Joules.
+///
+/// Energy counters measure the whole package, not particular processes, so
+/// they must be opened system-wide on one CPU in the package. [`build`]
+/// does this for you, on the first CPU the PMU lists:
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use perf_event::events::{Energy, EnergyDomain};
+/// use std::time::Duration;
+///
+/// let energy = Energy::new(EnergyDomain::Package)?;
+/// let mut counter = energy.build()?;
+/// let measured = counter.measure_for(Duration::from_secs(1))?;
+/// println!("{:.3} J", energy.joules(measured.counts.count));
+/// # Ok(()) }
+/// ```
+///
+/// This usually requires root privileges, or `perf_event_paranoid` of 0
+/// or less.
+///
+/// [`build`]: Energy::build
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Energy {
+    domain: EnergyDomain,
+    pmu_type: u32,
+    config: u64,
+    scale: f64,
+    unit: String,
+    cpu: usize,
+}
+
+// The scale is parsed from sysfs, and is never NaN.
+impl Eq for Energy {}
+
+impl Energy {
+    /// Look up the energy counter for `domain`.
+    ///
+    /// If the system has no `power` PMU, or the PMU doesn't support
+    /// `domain`, this returns an error of kind [`io::ErrorKind::NotFound`].
+    pub fn new(domain: EnergyDomain) -> io::Result<Energy> {
+        let read = |name: &str| -> io::Result<String> {
+            let path = format!("{}/{}", POWER_PMU_DIR, name);
+            std::fs::read_to_string(&path)
+                .map(|contents| contents.trim().to_string())
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+        };
+        let invalid = |what: &str, contents: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unexpected {} for {}: {:?}",
+                    what,
+                    domain.event_name(),
+                    contents
+                ),
+            )
+        };
+
+        let event = domain.event_name();
+        let pmu_type = read("type")?;
+        let pmu_type = pmu_type
+            .parse()
+            .map_err(|_| invalid("PMU type", &pmu_type))?;
+        let spec = read(&format!("events/{}", event))?;
+        let config = spec
+            .strip_prefix("event=")
+            .and_then(parse_sysfs_number)
+            .ok_or_else(|| invalid("event", &spec))?;
+        let scale = read(&format!("events/{}.scale", event))?;
+        let scale = scale.parse().map_err(|_| invalid("scale", &scale))?;
+        let unit = read(&format!("events/{}.unit", event))?;
+        let cpumask = read("cpumask")?;
+        let cpu = cpumask
+            .split([',', '-'])
+            .next()
+            .and_then(|first| first.parse().ok())
+            .ok_or_else(|| invalid("cpumask", &cpumask))?;
+
+        Ok(Energy {
+            domain,
+            pmu_type,
+            config,
+            scale,
+            unit,
+            cpu,
+        })
+    }
+
+    /// Return the energy counters this system supports.
+    pub fn available() -> Vec<Energy> {
+        EnergyDomain::ALL
+            .iter()
+            .filter_map(|&domain| Energy::new(domain).ok())
+            .collect()
+    }
+
+    /// Return the domain this counter measures.
+    pub fn domain(&self) -> EnergyDomain {
+        self.domain
+    }
+
+    /// Return the size of one count, in [`unit`]s.
+    ///
+    /// [`unit`]: Energy::unit
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Return the unit the kernel reports for this counter: always
+    /// `"Joules"`, as of this writing.
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// Return the CPU on which the counter should be opened.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// Convert a count from this counter to Joules.
+    pub fn joules(&self, count: u64) -> f64 {
+        count as f64 * self.scale
+    }
+
+    /// Build a system-wide counter for this event on [`cpu`], counting
+    /// energy used by that CPU's whole package.
+    ///
+    /// [`cpu`]: Energy::cpu
+    pub fn build(&self) -> io::Result<crate::Counter> {
+        crate::Builder::new()
+            .system_wide_on_cpu(self.cpu)
+            .kind(self.clone())
+            .build()
+    }
+}
+
+impl From<Energy> for Event {
+    fn from(energy: Energy) -> Self {
+        Event::Energy(energy)
+    }
+}
+
+/// Parse a number from a sysfs event description, like `0x05` or `5`.
+fn parse_sysfs_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Where the kernel's `kprobe` PMU describes itself.
+const KPROBE_PMU_DIR: &str = "/sys/bus/event_source/devices/kprobe";
+
+/// A dynamic probe on a kernel function or address.
+///
+/// A kprobe hits each time the kernel executes the probed instruction. A
+/// retprobe, created with [`KProbe::function_return`], hits each time the
+/// probed function returns. Both can be counted or sampled like any other
+/// event:
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use perf_event::Builder;
+/// use perf_event::events::KProbe;
+///
+/// let opens = KProbe::function("do_sys_openat2")?;
+/// let mut counter = Builder::new().kind(opens).build()?;
+/// # Ok(()) }
+/// ```
+///
+/// This uses the kernel's `kprobe` PMU, available since Linux 4.17, whose
+/// type number is read from `/sys/bus/event_source/devices/kprobe/type`.
+/// Like tracepoints, kprobes always hit in kernel code, so choosing one with
+/// [`Builder::kind`] also turns on [`Builder::include_kernel`]. Creating
+/// kprobes requires `CAP_PERFMON` or `CAP_SYS_ADMIN`.
+///
+/// [`Builder::kind`]: crate::Builder::kind
+/// [`Builder::include_kernel`]: crate::Builder::include_kernel
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KProbe {
+    pmu_type: u32,
+    config: u64,
+    target: KProbeTarget,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum KProbeTarget {
+    Function { name: CString, offset: u64 },
+    Address(u64),
+}
+
+impl KProbe {
+    /// Probe the entry of the kernel function `name`.
+    pub fn function(name: &str) -> io::Result<KProbe> {
+        KProbe::function_offset(name, 0)
+    }
+
+    /// Probe the instruction `offset` bytes into the kernel function `name`.
+    pub fn function_offset(name: &str, offset: u64) -> io::Result<KProbe> {
+        let name = CString::new(name).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "kprobe function name contains a NUL byte",
+            )
+        })?;
+        Ok(KProbe {
+            pmu_type: kprobe_pmu_type()?,
+            config: 0,
+            target: KProbeTarget::Function { name, offset },
+        })
+    }
+
+    /// Probe returns from the kernel function `name`.
+    pub fn function_return(name: &str) -> io::Result<KProbe> {
+        let mut probe = KProbe::function(name)?;
+        probe.config = 1 << kprobe_retprobe_bit()?;
+        Ok(probe)
+    }
+
+    /// Probe the kernel instruction at `addr`.
+    pub fn address(addr: u64) -> io::Result<KProbe> {
+        Ok(KProbe {
+            pmu_type: kprobe_pmu_type()?,
+            config: 0,
+            target: KProbeTarget::Address(addr),
+        })
+    }
+
+    /// Return true if this is a retprobe.
+    pub fn is_return(&self) -> bool {
+        self.config != 0
+    }
+}
+
+impl From<KProbe> for Event {
+    fn from(kp: KProbe) -> Self {
+        Event::KProbe(kp)
+    }
+}
+
+/// Read a file from the kprobe PMU's sysfs directory, reporting a missing
+/// PMU clearly.
+fn read_kprobe_pmu_file(name: &str) -> io::Result<String> {
+    let path = format!("{}/{}", KPROBE_PMU_DIR, name);
+    std::fs::read_to_string(&path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "the kernel has no kprobe PMU ({} not found); \
+                     it needs Linux 4.17 or later with CONFIG_KPROBE_EVENTS",
+                    path
+                ),
+            )
+        } else {
+            io::Error::new(e.kind(), format!("{}: {}", path, e))
+        }
+    })
+}
+
+fn kprobe_pmu_type() -> io::Result<u32> {
+    let contents = read_kprobe_pmu_file("type")?;
+    contents.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected kprobe PMU type: {:?}", contents),
+        )
+    })
+}
+
+/// Return the `config` bit that makes a kprobe a retprobe. The format file
+/// says something like `config:0`.
+fn kprobe_retprobe_bit() -> io::Result<u32> {
+    let contents = read_kprobe_pmu_file("format/retprobe")?;
+    contents
+        .trim()
+        .strip_prefix("config:")
+        .and_then(|bit| bit.parse().ok())
+        .filter(|&bit| bit < 64)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected kprobe retprobe format: {:?}", contents),
+            )
+        })
+}
+
+/// Where the kernel lists the PMUs it supports, each in a directory holding
+/// its `type` number.
+pub(crate) const PMU_DIR: &str = "/sys/bus/event_source/devices";
+
+/// An event on a PMU listed in `/sys/bus/event_source/devices`, given by the
+/// PMU's name and a raw `config` value.
+///
+/// The kernel assigns PMUs other than the standard hardware, software,
+/// tracepoint and breakpoint PMUs a type number when it registers them, so
+/// their events can only be requested by looking the number up. This is
+/// how to use hardware tracing PMUs like `intel_pt` and `cs_etm`, whose
+/// output goes to a sampler's AUX area:
+///
+///     # fn main() -> std::io::Result<()> {
+///     use perf_event::Builder;
+///     use perf_event::events::Pmu;
+///
+///     if let Ok(pt) = Pmu::named("intel_pt") {
+///         # #[cfg(feature = "sampling")]
+///         let mut tracer = Builder::new()
+///             .kind(pt)
+///             .build_aux_sampler(64)?;
+///     }
+///     # Ok(()) }
+///
+/// The meaning of `config` depends on the PMU; its `format` directory in
+/// sysfs describes the fields.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pmu {
+    pmu_type: u32,
+    config: u64,
+}
+
+impl Pmu {
+    /// Look up the PMU called `name`, with a `config` of zero.
+    ///
+    /// If there is no such PMU, this returns an error of kind
+    /// [`io::ErrorKind::NotFound`].
+    pub fn named(name: &str) -> io::Result<Pmu> {
+        let path = format!("{}/{}/type", PMU_DIR, name);
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let pmu_type = contents.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: unexpected PMU type: {:?}", path, contents),
+            )
+        })?;
+        Ok(Pmu {
+            pmu_type,
+            config: 0,
+        })
+    }
+
+    /// Use `config` as the event's configuration.
+    pub fn config(mut self, config: u64) -> Pmu {
+        self.config = config;
+        self
+    }
+
+    /// Return the PMU's type number.
+    pub fn pmu_type(&self) -> u32 {
+        self.pmu_type
+    }
+}
+
+impl From<Pmu> for Event {
+    fn from(pmu: Pmu) -> Self {
+        Event::Pmu(pmu)
+    }
+}
+
+/// The error the kernel reported for an event it doesn't recognize.
+///
+/// When `perf_event_open` rejects an event with `ENOENT`, meaning that the
+/// running kernel or processor doesn't know the event at all, [`Builder::build`]
+/// returns an [`io::Error`] whose kind is [`io::ErrorKind::Unsupported`] and
+/// whose inner error is an `UnsupportedEvent`. This lets callers fall back
+/// gracefully when an event is unavailable, and explains the problem when
+/// it's a matter of the kernel being too old:
+///
+///     # fn main() -> std::io::Result<()> {
+///     use perf_event::Builder;
+///     use perf_event::events::Software;
+///     use std::io::ErrorKind;
+///
+///     match Builder::new().kind(Software::CGROUP_SWITCHES).build() {
+///         Ok(counter) => { /* ... */ }
+///         Err(e) if e.kind() == ErrorKind::Unsupported => {
+///             println!("Not counting cgroup switches: {}", e);
+///         }
+///         Err(e) => return Err(e),
+///     }
+///     # Ok(()) }
+///
+/// The original operating system error is available as the error's
+/// [`source`][std::error::Error::source].
+///
+/// [`Builder::build`]: crate::Builder::build
+#[derive(Debug)]
+pub struct UnsupportedEvent {
+    type_: u32,
+    config: u64,
+    error: io::Error,
+}
+
+impl UnsupportedEvent {
+    /// If `error` is the kernel's `ENOENT`, wrap it up as an
+    /// `UnsupportedEvent` for the event described by `attrs`. Otherwise,
+    /// return it unchanged.
+    pub(crate) fn check(attrs: &bindings::perf_event_attr, error: io::Error) -> io::Error {
+        if error.raw_os_error() != Some(libc::ENOENT) {
+            return error;
+        }
+        let unsupported = UnsupportedEvent {
+            type_: attrs.type_,
+            config: attrs.config,
+            error,
+        };
+        io::Error::new(io::ErrorKind::Unsupported, unsupported)
+    }
+
+    /// If the rejected event was a [`Software`] event this crate knows, return
+    /// it.
+    pub fn software(&self) -> Option<Software> {
+        if self.type_ != bindings::PERF_TYPE_SOFTWARE {
+            return None;
+        }
+        Software::from_config(self.config)
+    }
+}
+
+impl fmt::Display for UnsupportedEvent {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "the kernel does not support this event (type {}, config {:#x})",
+            self.type_, self.config
+        )?;
+        if let Some(sw) = self.software() {
+            let (major, minor) = sw.min_kernel_version();
+            write!(
+                fmt,
+                "; Software::{:?} requires Linux {}.{} or later",
+                sw, major, minor
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnsupportedEvent {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The error the kernel reported for an event on a dynamic PMU, along with
+/// the PMU's description of its events from sysfs.
+///
+/// PMUs other than the standard hardware, software, tracepoint and
+/// breakpoint PMUs describe the fields of their events' `config` values in
+/// their sysfs `format` directory, and list named events, as assignments to
+/// those fields, in their `events` directory. When `perf_event_open` rejects
+/// an event on such a PMU with `EINVAL`, `ENOENT` or `EOPNOTSUPP`,
+/// [`Builder::build`] returns an [`io::Error`] whose inner error is a
+/// `PmuEventError`. Its message shows the value of each format field the
+/// event's configuration sets, any bits it sets outside every field, and
+/// the named event it matches, if any, which usually makes it clear how the
+/// configuration was put together wrong.
+///
+/// The `io::Error`'s kind is [`io::ErrorKind::Unsupported`] if the kernel
+/// reported `ENOENT` or `EOPNOTSUPP`, and the original error's kind
+/// otherwise. The original operating system error is available as the
+/// error's [`source`][std::error::Error::source].
+///
+/// This is only available when the `"discovery"` feature is enabled;
+/// without it, `build` returns the kernel's error unchanged.
+///
+/// [`Builder::build`]: crate::Builder::build
+#[cfg(feature = "discovery")]
+#[derive(Debug)]
+pub struct PmuEventError {
+    pmu: String,
+    config: [u64; 3],
+    fields: Vec<FormatField>,
+    event: Option<String>,
+    error: io::Error,
+}
+
+#[cfg(feature = "discovery")]
+impl PmuEventError {
+    /// If `attrs` describes an event on a dynamic PMU, and `error` is one
+    /// the PMU's description might explain, wrap it up as a
+    /// `PmuEventError`. Otherwise, return it unchanged.
+    pub(crate) fn check(attrs: &bindings::perf_event_attr, error: io::Error) -> io::Error {
+        if attrs.type_ < bindings::PERF_TYPE_MAX {
+            return error;
+        }
+        let kind = match error.raw_os_error() {
+            Some(libc::ENOENT) | Some(libc::EOPNOTSUPP) => io::ErrorKind::Unsupported,
+            Some(libc::EINVAL) => error.kind(),
+            _ => return error,
+        };
+        let pmu = match pmu_name(attrs.type_) {
+            Some(pmu) => pmu,
+            None => return error,
+        };
+
+        let dir = format!("{}/{}", PMU_DIR, pmu);
+        let config = [attrs.config, attrs.config1(), attrs.config2()];
+        let fields = FormatField::read_all(&dir);
+        let event = matching_event(&dir, &fields, config);
+        let inner = PmuEventError {
+            pmu,
+            config,
+            fields,
+            event,
+            error,
+        };
+        io::Error::new(kind, inner)
+    }
+
+    /// Return the name of the PMU, as listed in
+    /// `/sys/bus/event_source/devices`.
+    pub fn pmu_name(&self) -> &str {
+        &self.pmu
+    }
+
+    /// Return the event's `config`, `config1` and `config2` values.
+    pub fn config(&self) -> [u64; 3] {
+        self.config
+    }
+
+    /// Return the fields the PMU's `format` directory describes, sorted by
+    /// name.
+    pub fn format_fields(&self) -> &[FormatField] {
+        &self.fields
+    }
+
+    /// Return the name of the event in the PMU's `events` directory whose
+    /// definition yields exactly this event's configuration, if there is
+    /// one.
+    pub fn matching_event(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+}
+
+#[cfg(feature = "discovery")]
+impl fmt::Display for PmuEventError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "PMU `{}` rejected the event (config {:#x}, config1 {:#x}, config2 {:#x})",
+            self.pmu, self.config[0], self.config[1], self.config[2]
+        )?;
+        match &self.event {
+            Some(event) => write!(fmt, "; this is its event `{}`", event)?,
+            None => fmt.write_str("; this matches none of its named events")?,
+        }
+
+        let set: Vec<_> = self
+            .fields
+            .iter()
+            .map(|field| (field, field.value(self.config)))
+            .filter(|&(_, value)| value != 0)
+            .collect();
+        if !set.is_empty() {
+            fmt.write_str("; format fields:")?;
+            for (i, (field, value)) in set.into_iter().enumerate() {
+                let sep = if i == 0 { " " } else { ", " };
+                write!(fmt, "{}{}={:#x} ({})", sep, field.name, value, field.spec)?;
+            }
+        }
+
+        for (word, &config) in self.config.iter().enumerate() {
+            let covered = self
+                .fields
+                .iter()
+                .filter(|field| field.word == word)
+                .fold(0, |mask, field| mask | field.mask());
+            let stray = config & !covered;
+            if stray != 0 {
+                write!(
+                    fmt,
+                    "; bits {:#x} of {} belong to no format field",
+                    stray, CONFIG_WORDS[word]
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "discovery")]
+impl std::error::Error for PmuEventError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The names of the `perf_event_attr` fields a PMU's format fields refer to.
+#[cfg(feature = "discovery")]
+const CONFIG_WORDS: [&str; 3] = ["config", "config1", "config2"];
+
+/// A field of a PMU's event configuration, described by a file in the
+/// PMU's sysfs `format` directory.
+///
+/// For example, the `cpu` PMU on x86 has a file `format/umask` containing
+/// `config:8-15`, meaning that its events' unit mask is bits 8 through 15
+/// of `config`.
+#[cfg(feature = "discovery")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FormatField {
+    name: String,
+    spec: String,
+
+    /// Which of `config`, `config1` and `config2` the field is in.
+    word: usize,
+
+    /// The ranges of bits holding the field, least significant first, each
+    /// a pair of the first and last bit numbers.
+    bits: Vec<(u32, u32)>,
+}
+
+#[cfg(feature = "discovery")]
+impl FormatField {
+    /// Parse a format file's contents, like `config:0-7,32-35`.
+    fn parse(name: &str, spec: &str) -> Option<FormatField> {
+        let spec = spec.trim();
+        let (word, ranges) = spec.split_once(':')?;
+        let word = CONFIG_WORDS.iter().position(|&w| w == word)?;
+        let bits = ranges
+            .split(',')
+            .map(|range| {
+                let (first, last) = range.split_once('-').unwrap_or((range, range));
+                let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                (first <= last && last < 64).then_some((first, last))
+            })
+            .collect::<Option<Vec<(u32, u32)>>>()?;
+        Some(FormatField {
+            name: name.to_string(),
+            spec: spec.to_string(),
+            word,
+            bits,
+        })
+    }
+
+    /// Read all the fields in the PMU directory `dir`, sorted by name,
+    /// skipping any we can't parse.
+    fn read_all(dir: &str) -> Vec<FormatField> {
+        let entries = match std::fs::read_dir(format!("{}/format", dir)) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut fields: Vec<FormatField> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                let spec = std::fs::read_to_string(entry.path()).ok()?;
+                FormatField::parse(&name, &spec)
+            })
+            .collect();
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+        fields
+    }
+
+    /// Return the field's name, like `"umask"`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the field's description from sysfs, like `"config:8-15"`.
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    /// Return this field's value in the configuration `config`, given as
+    /// the `config`, `config1` and `config2` values.
+    pub fn value(&self, config: [u64; 3]) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        for &(first, last) in &self.bits {
+            let width = last - first + 1;
+            value |= (config[self.word] >> first & low_bits(width)) << shift;
+            shift += width;
+        }
+        value
+    }
+
+    /// Store `value` in this field of `config`.
+    fn set(&self, config: &mut [u64; 3], mut value: u64) {
+        for &(first, last) in &self.bits {
+            let width = last - first + 1;
+            config[self.word] |= (value & low_bits(width)) << first;
+            value = value.checked_shr(width).unwrap_or(0);
+        }
+    }
+
+    /// Return the bits of its word this field occupies.
+    fn mask(&self) -> u64 {
+        self.bits.iter().fold(0, |mask, &(first, last)| {
+            mask | low_bits(last - first + 1) << first
+        })
+    }
+}
+
+/// Return a mask of the low `width` bits of a `u64`.
+#[cfg(feature = "discovery")]
+fn low_bits(width: u32) -> u64 {
+    u64::MAX.checked_shr(64 - width).unwrap_or(0)
+}
+
+/// Return the name of the PMU whose type number is `type_`, if any.
+#[cfg(feature = "discovery")]
+fn pmu_name(type_: u32) -> Option<String> {
+    std::fs::read_dir(PMU_DIR).ok()?.find_map(|entry| {
+        let entry = entry.ok()?;
+        let contents = std::fs::read_to_string(entry.path().join("type")).ok()?;
+        if contents.trim().parse() == Ok(type_) {
+            entry.file_name().into_string().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Return the name of the event in the PMU directory `dir` whose
+/// definition, assembled using `fields`, is exactly `config`.
+///
+/// Definitions are lists of terms like `event=0x3c,umask=0x01`. A term with
+/// no value sets its field to 1.
+#[cfg(feature = "discovery")]
+fn matching_event(dir: &str, fields: &[FormatField], config: [u64; 3]) -> Option<String> {
+    let mut names: Vec<String> = std::fs::read_dir(format!("{}/events", dir))
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        // Skip `.scale`, `.unit` and other annotations.
+        .filter(|name| !name.contains('.'))
+        .collect();
+    names.sort();
+
+    names.into_iter().find(|name| {
+        let definition = match std::fs::read_to_string(format!("{}/events/{}", dir, name)) {
+            Ok(definition) => definition,
+            Err(_) => return false,
+        };
+        let mut assembled = [0; 3];
+        for term in definition.trim().split(',') {
+            let (field, value) = match term.split_once('=') {
+                Some((field, value)) => match parse_sysfs_number(value) {
+                    Some(value) => (field, value),
+                    None => return false,
+                },
+                None => (term, 1),
+            };
+            match fields.iter().find(|f| f.name == field) {
+                Some(field) => field.set(&mut assembled, value),
+                None => return false,
+            }
+        }
+        assembled == config
+    })
+}
@@ -0,0 +1,203 @@
+//! Watching whether a group's counters keep getting scheduled.
+//!
+//! The kernel only runs a [`Group`] when all its hardware counters fit on the
+//! processor at once. A group that runs fine when a long-lived daemon starts
+//! may be squeezed off the processor later by someone else's counters:
+//! especially by events created with the `exclusive` or `pinned` bits, which
+//! take priority and can leave no room at all. The group's counts then stop
+//! growing, and nothing reports an error.
+//!
+//! A [`GroupHealth`] watches for this. Feed it each [`Counts`] you read, and
+//! it compares the group's `time_running` and `time_enabled` growth since the
+//! previous reading, classifying each interval as a [`Schedule`] state and
+//! reporting whenever that state changes:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::{Builder, Group};
+//!     use perf_event::events::Software;
+//!     use perf_event::health::GroupHealth;
+//!
+//!     let mut group = Group::new()?;
+//!     let clock = Builder::new().group(&mut group).kind(Software::TASK_CLOCK).build()?;
+//!     let mut health = GroupHealth::new();
+//!
+//!     group.enable()?;
+//!     for _ in 0..3 {
+//!         // ... do some work ...
+//!         let counts = group.read()?;
+//!         if let Some(change) = health.observe(&counts) {
+//!             eprintln!("counter group is now {:?} (was {:?})", change.to, change.from);
+//!         }
+//!     }
+//!     # Ok(()) }
+//!
+//! When a group is starved, [`diagnostics::contention`] can help find the
+//! culprit.
+//!
+//! [`Group`]: crate::Group
+//! [`Counts`]: crate::Counts
+//! [`diagnostics::contention`]: crate::diagnostics::contention
+
+use crate::Counts;
+use std::collections::VecDeque;
+
+/// How well a group was scheduled over an interval.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Schedule {
+    /// No readings have covered an interval in which the group was enabled.
+    Unknown,
+
+    /// The group was on the processor the entire time it was enabled.
+    Running,
+
+    /// The group was on the processor for only part of the time it was
+    /// enabled: the kernel was rotating it with other events. Counts need to
+    /// be scaled by `time_enabled / time_running` to estimate the true total.
+    Multiplexed,
+
+    /// The group was enabled, but never on the processor.
+    Starved,
+}
+
+/// A change in a group's [`Schedule`], reported by [`GroupHealth::observe`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    /// The group's state before the latest interval.
+    pub from: Schedule,
+
+    /// The group's state during the latest interval.
+    pub to: Schedule,
+
+    /// The fraction of the latest interval during which the group was
+    /// running, from `0.0` to `1.0`.
+    pub ratio: f64,
+}
+
+/// A tracker of a group's scheduling over time.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug)]
+pub struct GroupHealth {
+    /// The `time_enabled` and `time_running` from the previous reading.
+    last: Option<(u64, u64)>,
+
+    /// The current state.
+    state: Schedule,
+
+    /// Running ratios of recent intervals, oldest first.
+    recent: VecDeque<f64>,
+
+    /// The maximum length of `recent`.
+    window: usize,
+}
+
+impl GroupHealth {
+    /// The number of intervals [`trend`] averages over by default.
+    ///
+    /// [`trend`]: GroupHealth::trend
+    pub const DEFAULT_WINDOW: usize = 16;
+
+    /// Return a new tracker, with no readings yet.
+    pub fn new() -> GroupHealth {
+        GroupHealth::with_window(GroupHealth::DEFAULT_WINDOW)
+    }
+
+    /// Return a new tracker whose [`trend`] averages over the last `window`
+    /// intervals.
+    ///
+    /// [`trend`]: GroupHealth::trend
+    pub fn with_window(window: usize) -> GroupHealth {
+        GroupHealth {
+            last: None,
+            state: Schedule::Unknown,
+            recent: VecDeque::with_capacity(window),
+            window: window.max(1),
+        }
+    }
+
+    /// Record a reading of the group, and return a [`Transition`] if its
+    /// scheduling state changed since the previous reading.
+    ///
+    /// The first reading only establishes a baseline. Intervals during which
+    /// the group was disabled don't affect the state.
+    pub fn observe(&mut self, counts: &Counts) -> Option<Transition> {
+        self.observe_times(counts.time_enabled(), counts.time_running())
+    }
+
+    /// Like [`observe`], but take the group's `time_enabled` and
+    /// `time_running` values directly. This is also useful for tracking an
+    /// individual [`Counter`], using the values from a [`CountAndTime`].
+    ///
+    /// If the times have gone backwards since the last reading, as they
+    /// would if the group were reset, this treats the reading as a new
+    /// baseline.
+    ///
+    /// [`observe`]: GroupHealth::observe
+    /// [`Counter`]: crate::Counter
+    /// [`CountAndTime`]: crate::CountAndTime
+    pub fn observe_times(&mut self, time_enabled: u64, time_running: u64) -> Option<Transition> {
+        let last = self.last.replace((time_enabled, time_running));
+        let (last_enabled, last_running) = last?;
+        if time_enabled < last_enabled || time_running < last_running {
+            return None;
+        }
+
+        let enabled = time_enabled - last_enabled;
+        let running = time_running - last_running;
+        if enabled == 0 {
+            return None;
+        }
+
+        let ratio = running as f64 / enabled as f64;
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ratio);
+
+        let state = if running == 0 {
+            Schedule::Starved
+        } else if running < enabled {
+            Schedule::Multiplexed
+        } else {
+            Schedule::Running
+        };
+
+        let from = std::mem::replace(&mut self.state, state);
+        if from == state {
+            return None;
+        }
+        Some(Transition {
+            from,
+            to: state,
+            ratio,
+        })
+    }
+
+    /// Return the group's scheduling state during the most recent interval
+    /// in which it was enabled.
+    pub fn state(&self) -> Schedule {
+        self.state
+    }
+
+    /// Return the fraction of the most recent interval during which the
+    /// group was running, or `None` if there hasn't been one yet.
+    pub fn ratio(&self) -> Option<f64> {
+        self.recent.back().copied()
+    }
+
+    /// Return the average running fraction over recent intervals, or `None`
+    /// if there haven't been any yet. A falling trend suggests that other
+    /// events are increasingly competing for the counters.
+    pub fn trend(&self) -> Option<f64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        Some(self.recent.iter().sum::<f64>() / self.recent.len() as f64)
+    }
+}
+
+impl Default for GroupHealth {
+    fn default() -> GroupHealth {
+        GroupHealth::new()
+    }
+}
@@ -0,0 +1,399 @@
+//! Histograms for aggregating values without keeping them all.
+//!
+//! When analyzing sample periods, weights, or latencies, you usually want
+//! their distribution, not every individual value. A [`Log2Histogram`]
+//! records values into power-of-two buckets using a fixed amount of memory,
+//! so it can summarize an unbounded stream of values online:
+//!
+//!     use perf_event::histogram::Log2Histogram;
+//!
+//!     let mut latencies = Log2Histogram::new();
+//!     for latency in [3, 5, 17, 250, 6, 4] {
+//!         latencies.record(latency);
+//!     }
+//!
+//!     assert_eq!(latencies.count(), 6);
+//!     assert_eq!(latencies.max(), Some(250));
+//!     for bucket in latencies.buckets() {
+//!         println!("{:>5} ..= {:<5} {}", bucket.start, bucket.end, bucket.count);
+//!     }
+#![cfg_attr(
+    feature = "sampling",
+    doc = "
+With the `\"sampling\"` feature, a [`HistogrammingConsumer`] feeds one field
+of each sample a [`Sampler`] produces into a histogram.
+
+[`Sampler`]: crate::samples::Sampler
+"
+)]
+
+#[cfg(feature = "sampling")]
+use crate::samples::{ParseConfig, Record, RecordEvent, Sample, Sampler};
+use std::fmt;
+
+/// A histogram of `u64` values, with buckets whose bounds are powers of two.
+///
+/// Bucket 0 holds the value zero, and bucket *n* holds values in the range
+/// 2<sup>*n*-1</sup> ..= 2<sup>*n*</sup> - 1. There are 65 buckets in all,
+/// enough to cover every `u64`.
+///
+/// Besides the bucket counts, the histogram tracks the exact count, sum,
+/// minimum, and maximum of the recorded values. Quantiles are only as
+/// precise as the buckets: [`quantile`] returns the upper bound of the
+/// bucket holding the requested quantile.
+///
+/// Histograms can be combined with [`merge`], so separate threads can each
+/// keep their own and combine them at the end.
+///
+/// The `Display` implementation draws a simple text bar chart of the
+/// non-empty buckets.
+///
+/// [`quantile`]: Log2Histogram::quantile
+/// [`merge`]: Log2Histogram::merge
+#[derive(Clone, Eq, PartialEq)]
+pub struct Log2Histogram {
+    buckets: [u64; 65],
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+/// One bucket of a [`Log2Histogram`], as returned by
+/// [`Log2Histogram::buckets`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Bucket {
+    /// The smallest value this bucket holds.
+    pub start: u64,
+
+    /// The largest value this bucket holds.
+    pub end: u64,
+
+    /// The number of values recorded in this bucket.
+    pub count: u64,
+}
+
+impl Log2Histogram {
+    /// Return a new, empty histogram.
+    pub fn new() -> Log2Histogram {
+        Log2Histogram {
+            buckets: [0; 65],
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Record one occurrence of `value`.
+    pub fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+
+    /// Record `n` occurrences of `value`.
+    pub fn record_n(&mut self, value: u64, n: u64) {
+        if n == 0 {
+            return;
+        }
+        self.buckets[bucket_index(value)] += n;
+        self.count += n;
+        self.sum += value as u128 * n as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Add all the values recorded in `other` to this histogram.
+    pub fn merge(&mut self, other: &Log2Histogram) {
+        for (mine, theirs) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *mine += theirs;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Forget all recorded values.
+    pub fn clear(&mut self) {
+        *self = Log2Histogram::new();
+    }
+
+    /// Return the number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Return the sum of all values recorded.
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    /// Return the smallest value recorded, or `None` if the histogram is
+    /// empty.
+    pub fn min(&self) -> Option<u64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.min)
+        }
+    }
+
+    /// Return the largest value recorded, or `None` if the histogram is
+    /// empty.
+    pub fn max(&self) -> Option<u64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+
+    /// Return the mean of the values recorded, or `None` if the histogram is
+    /// empty.
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum as f64 / self.count as f64)
+        }
+    }
+
+    /// Return an upper bound on the `q`'th quantile of the recorded values,
+    /// where `q` is between `0.0` and `1.0`. For example, `quantile(0.99)`
+    /// bounds the 99th percentile.
+    ///
+    /// The result is the upper bound of the bucket holding the quantile,
+    /// clamped to the largest value recorded. Return `None` if the histogram
+    /// is empty.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let q = q.clamp(0.0, 1.0);
+        let rank = ((q * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(bucket_bounds(index).1.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// Return an iterator over the non-empty buckets, from lowest to highest.
+    pub fn buckets(&self) -> impl Iterator<Item = Bucket> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(index, &count)| {
+                let (start, end) = bucket_bounds(index);
+                Bucket { start, end, count }
+            })
+    }
+}
+
+impl Default for Log2Histogram {
+    fn default() -> Log2Histogram {
+        Log2Histogram::new()
+    }
+}
+
+impl Extend<u64> for Log2Histogram {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        for value in iter {
+            self.record(value);
+        }
+    }
+}
+
+impl std::iter::FromIterator<u64> for Log2Histogram {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Log2Histogram {
+        let mut histogram = Log2Histogram::new();
+        histogram.extend(iter);
+        histogram
+    }
+}
+
+impl fmt::Debug for Log2Histogram {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Log2Histogram")
+            .field("count", &self.count)
+            .field("sum", &self.sum)
+            .field("min", &self.min())
+            .field("max", &self.max())
+            .field("buckets", &self.buckets().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl fmt::Display for Log2Histogram {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        const WIDTH: u64 = 40;
+        let tallest = self.buckets.iter().copied().max().unwrap_or(0);
+        let mut first = true;
+        for bucket in self.buckets() {
+            if !first {
+                fmt.write_str("\n")?;
+            }
+            first = false;
+            let bar = (bucket.count * WIDTH).div_ceil(tallest);
+            write!(
+                fmt,
+                "{:>20} ..= {:<20} {:>10} {}",
+                bucket.start,
+                bucket.end,
+                bucket.count,
+                "#".repeat(bar as usize)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the index of the bucket holding `value`.
+fn bucket_index(value: u64) -> usize {
+    (64 - value.leading_zeros()) as usize
+}
+
+/// Return the smallest and largest values held by bucket `index`.
+fn bucket_bounds(index: usize) -> (u64, u64) {
+    match index {
+        0 => (0, 0),
+        64 => (1 << 63, u64::MAX),
+        _ => (1 << (index - 1), (1 << index) - 1),
+    }
+}
+
+/// Which field of each sample a [`HistogrammingConsumer`] records.
+#[cfg(feature = "sampling")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum SampleField {
+    /// The sample's [`period`]: how many events occurred since the last
+    /// sample. This is interesting when sampling by frequency, since the
+    /// kernel adjusts the period to meet it. Request these with
+    /// [`SampleType::PERIOD`].
+    ///
+    /// [`period`]: crate::samples::Sample::period
+    /// [`SampleType::PERIOD`]: crate::samples::SampleType::PERIOD
+    Period,
+
+    /// The sample's [`weight`], whose meaning depends on the event. For
+    /// Intel's load latency events, it is the load's latency in cycles.
+    /// Request these with [`SampleType::WEIGHT`].
+    ///
+    /// [`weight`]: crate::samples::Sample::weight
+    /// [`SampleType::WEIGHT`]: crate::samples::SampleType::WEIGHT
+    Weight,
+}
+
+#[cfg(feature = "sampling")]
+impl SampleField {
+    /// Return this field of `sample`, if it is present.
+    pub fn get(self, sample: &Sample) -> Option<u64> {
+        match self {
+            SampleField::Period => sample.period,
+            SampleField::Weight => sample.weight,
+        }
+    }
+}
+
+/// Records one field of each sample from a [`Sampler`] in a
+/// [`Log2Histogram`]:
+///
+///     # fn main() -> std::io::Result<()> {
+///     use perf_event::Builder;
+///     use perf_event::events::Software;
+///     use perf_event::histogram::{HistogrammingConsumer, SampleField};
+///     use perf_event::samples::SampleType;
+///
+///     let mut sampler = Builder::new()
+///         .kind(Software::PAGE_FAULTS)
+///         .sample_frequency(1000)
+///         .sample(SampleType::PERIOD)
+///         .build_sampler()?;
+///     let mut periods = HistogrammingConsumer::new(SampleField::Period);
+///
+///     sampler.enable()?;
+///     let pages: Vec<Vec<u8>> = (0..100).map(|_| vec![1; 4096]).collect();
+///     sampler.disable()?;
+///     periods.drain(&mut sampler);
+///
+///     println!("{}", periods.histogram());
+///     # drop(pages);
+///     # Ok(()) }
+///
+/// [`Sampler`]: crate::samples::Sampler
+#[cfg(feature = "sampling")]
+#[derive(Clone, Debug)]
+pub struct HistogrammingConsumer {
+    field: SampleField,
+    histogram: Log2Histogram,
+    missing: u64,
+}
+
+#[cfg(feature = "sampling")]
+impl HistogrammingConsumer {
+    /// Return a new consumer that records `field` of each sample.
+    pub fn new(field: SampleField) -> HistogrammingConsumer {
+        HistogrammingConsumer {
+            field,
+            histogram: Log2Histogram::new(),
+            missing: 0,
+        }
+    }
+
+    /// Return the field this consumer records.
+    pub fn field(&self) -> SampleField {
+        self.field
+    }
+
+    /// Record the field of `sample`, if it has one.
+    pub fn consume_sample(&mut self, sample: &Sample) {
+        match self.field.get(sample) {
+            Some(value) => self.histogram.record(value),
+            None => self.missing += 1,
+        }
+    }
+
+    /// If `record` is a sample, record its field. Ignore other records, and
+    /// samples that are malformed.
+    pub fn consume(&mut self, record: &Record, config: &ParseConfig) {
+        if let Ok(RecordEvent::Sample(sample)) = record.try_parse(config) {
+            self.consume_sample(&sample);
+        }
+    }
+
+    /// Read every record currently in `sampler`'s ring buffer, recording the
+    /// field of each sample, and return the number of records read. This
+    /// never blocks.
+    pub fn drain(&mut self, sampler: &mut Sampler) -> usize {
+        let config = *sampler.config();
+        let mut chunks = sampler.iter_chunks();
+        let mut read = 0;
+        while let Some(record) = chunks.next() {
+            if let Ok(RecordEvent::Sample(sample)) = record.try_parse(&config) {
+                self.consume_sample(&sample);
+            }
+            read += 1;
+        }
+        read
+    }
+
+    /// Return the histogram of the values recorded so far.
+    pub fn histogram(&self) -> &Log2Histogram {
+        &self.histogram
+    }
+
+    /// Return the number of samples seen that lacked the field, because
+    /// the sampler wasn't asked to include it.
+    pub fn missing(&self) -> u64 {
+        self.missing
+    }
+
+    /// Return the histogram, consuming this consumer.
+    pub fn into_histogram(self) -> Log2Histogram {
+        self.histogram
+    }
+}
@@ -133,7 +133,7 @@ macro_rules! expand_trait_method {
         /// Wrapper for perf_event ioctl
         #[doc = stringify!($ioctl)]
         /// .
-        #[allow(non_snake_case)]
+        #[allow(non_snake_case, clippy::missing_safety_doc)]
         unsafe fn $name(&mut self, _fd: c_int, _arg: $arg_type) -> c_int {
             panic!(
                 "unimplemented `perf_event::hooks::Hooks` method: {}",
@@ -0,0 +1,218 @@
+//! Safe wrappers for the `perf_event` ioctls.
+//!
+//! The [`Counter`] and [`Group`] types cover the common operations, but the
+//! kernel offers more ioctls on event file descriptors than they expose. This
+//! module provides a typed, safe function for each of them, so you can use
+//! them on a `Counter`, a `Group`, or any other event file descriptor without
+//! depending on [`perf_event_open_sys`][peos] and working with raw pointers
+//! and integer flags.
+//!
+//! Each function takes the event as any type implementing [`AsRawFd`]. All
+//! of them are still subject to the kernel's validation: applying one to a
+//! file descriptor that isn't a perf event, or to an event that doesn't
+//! support it, produces an error.
+//!
+//! See the [`perf_event_open(2)`][man] man page for the details of each ioctl.
+//!
+#![cfg_attr(
+    feature = "hooks",
+    doc = "
+When the `\"hooks\"` feature is enabled, these functions go through the
+[`hooks`] module like the rest of the crate.
+
+[`hooks`]: crate::hooks
+"
+)]
+//!
+//! [`Counter`]: crate::Counter
+//! [`Group`]: crate::Group
+//! [peos]: https://docs.rs/perf-event-open-sys/latest/perf_event_open_sys/
+//! [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
+
+use crate::{check_errno_syscall, sys};
+use std::ffi::CStr;
+use std::io;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::os::unix::io::AsRawFd;
+
+pub use perf_event_open_sys::bindings::perf_event_attr;
+
+/// Whether an ioctl applies to just the given event, or to the entire group
+/// it leads.
+///
+/// [`Group`] methods always use `Scope::Group`. Applying `Scope::Group` to an
+/// event that is not in a group is the same as `Scope::Event`.
+///
+/// [`Group`]: crate::Group
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Scope {
+    /// Apply the operation to this event alone.
+    Event,
+
+    /// Apply the operation to this event, which must be a group leader, and
+    /// all the members of its group, as a single atomic operation. This is
+    /// `PERF_IOC_FLAG_GROUP`.
+    Group,
+}
+
+impl Scope {
+    fn as_arg(self) -> c_uint {
+        match self {
+            Scope::Event => 0,
+            Scope::Group => sys::bindings::PERF_IOC_FLAG_GROUP,
+        }
+    }
+}
+
+/// Enable `event`, via `PERF_EVENT_IOC_ENABLE`.
+pub fn enable<E: AsRawFd + ?Sized>(event: &E, scope: Scope) -> io::Result<()> {
+    let fd = event.as_raw_fd();
+    check_errno_syscall(|| unsafe { sys::ioctls::ENABLE(fd, scope.as_arg()) }).map(|_| ())
+}
+
+/// Disable `event`, via `PERF_EVENT_IOC_DISABLE`.
+pub fn disable<E: AsRawFd + ?Sized>(event: &E, scope: Scope) -> io::Result<()> {
+    let fd = event.as_raw_fd();
+    check_errno_syscall(|| unsafe { sys::ioctls::DISABLE(fd, scope.as_arg()) }).map(|_| ())
+}
+
+/// Reset `event`'s count to zero, via `PERF_EVENT_IOC_RESET`.
+pub fn reset<E: AsRawFd + ?Sized>(event: &E, scope: Scope) -> io::Result<()> {
+    let fd = event.as_raw_fd();
+    check_errno_syscall(|| unsafe { sys::ioctls::RESET(fd, scope.as_arg()) }).map(|_| ())
+}
+
+/// Enable a sampling `event` for `overflows` more overflows, after which the
+/// kernel disables it again, via `PERF_EVENT_IOC_REFRESH`.
+///
+/// This is meant for use with signal-driven sampling. A count of zero is
+/// rejected by the kernel.
+pub fn refresh<E: AsRawFd + ?Sized>(event: &E, overflows: c_int) -> io::Result<()> {
+    let fd = event.as_raw_fd();
+    check_errno_syscall(|| unsafe { sys::ioctls::REFRESH(fd, overflows) }).map(|_| ())
+}
+
+/// Change a sampling `event`'s period (or frequency, if it was created in
+/// frequency mode), via `PERF_EVENT_IOC_PERIOD`.
+pub fn period<E: AsRawFd + ?Sized>(event: &E, period: u64) -> io::Result<()> {
+    let fd = event.as_raw_fd();
+    // Despite the type of the binding's argument, the kernel expects a
+    // pointer to the new period, not the period itself.
+    let arg = &period as *const u64 as u64;
+    check_errno_syscall(|| unsafe { sys::ioctls::PERIOD(fd, arg) }).map(|_| ())
+}
+
+/// Send `event`'s sample records to `target`'s ring buffer, via
+/// `PERF_EVENT_IOC_SET_OUTPUT`. If `target` is `None`, stop redirecting
+/// `event`'s output.
+///
+/// The two events must be on the same CPU, or both observing the same task.
+pub fn set_output<E, T>(event: &E, target: Option<&T>) -> io::Result<()>
+where
+    E: AsRawFd + ?Sized,
+    T: AsRawFd + ?Sized,
+{
+    let fd = event.as_raw_fd();
+    let target = target.map_or(-1, |t| t.as_raw_fd());
+    check_errno_syscall(|| unsafe { sys::ioctls::SET_OUTPUT(fd, target) }).map(|_| ())
+}
+
+/// Set a tracepoint `event`'s filter expression, via
+/// `PERF_EVENT_IOC_SET_FILTER`.
+///
+/// The filter syntax is that of the `filter` files in tracefs; see the
+/// kernel's `Documentation/trace/events.rst`.
+pub fn set_filter<E: AsRawFd + ?Sized>(event: &E, filter: &CStr) -> io::Result<()> {
+    let fd = event.as_raw_fd();
+    // The kernel only reads the string.
+    let filter = filter.as_ptr() as *mut c_char;
+    check_errno_syscall(|| unsafe { sys::ioctls::SET_FILTER(fd, filter) }).map(|_| ())
+}
+
+/// Return the kernel's unique id for `event`, via `PERF_EVENT_IOC_ID`.
+pub fn id<E: AsRawFd + ?Sized>(event: &E) -> io::Result<u64> {
+    let fd = event.as_raw_fd();
+    let mut id = 0_u64;
+    check_errno_syscall(|| unsafe { sys::ioctls::ID(fd, &mut id) })?;
+    Ok(id)
+}
+
+/// Attach the BPF program `program` to a tracing `event`, via
+/// `PERF_EVENT_IOC_SET_BPF`.
+///
+/// The program must be of a type appropriate for the event, and `event` must
+/// be a kprobe, uprobe, or tracepoint event.
+pub fn set_bpf<E, P>(event: &E, program: &P) -> io::Result<()>
+where
+    E: AsRawFd + ?Sized,
+    P: AsRawFd + ?Sized,
+{
+    let fd = event.as_raw_fd();
+    let program = program.as_raw_fd() as u32;
+    check_errno_syscall(|| unsafe { sys::ioctls::SET_BPF(fd, program) }).map(|_| ())
+}
+
+/// Pause or resume writing `event`'s records to its ring buffer, via
+/// `PERF_EVENT_IOC_PAUSE_OUTPUT`.
+///
+/// While paused, the event continues counting, but records that would have
+/// been written are lost.
+pub fn pause_output<E: AsRawFd + ?Sized>(event: &E, pause: bool) -> io::Result<()> {
+    let fd = event.as_raw_fd();
+    check_errno_syscall(|| unsafe { sys::ioctls::PAUSE_OUTPUT(fd, pause as u32) }).map(|_| ())
+}
+
+/// Return the ids of the BPF programs attached to a tracing `event`, via
+/// `PERF_EVENT_IOC_QUERY_BPF`.
+///
+/// The kernel's `perf_event_query_bpf` structure ends with a variable-length
+/// array of ids, whose capacity the caller must choose in advance. This
+/// function takes care of growing the buffer until the kernel's answer fits.
+pub fn query_bpf<E: AsRawFd + ?Sized>(event: &E) -> io::Result<Vec<u32>> {
+    let fd = event.as_raw_fd();
+
+    // The buffer holds `ids_len`, `prog_cnt`, and then the ids themselves.
+    let mut capacity = 16;
+    loop {
+        let mut buf = vec![0_u32; 2 + capacity];
+        buf[0] = capacity as u32;
+        let query = buf.as_mut_ptr() as *mut sys::bindings::perf_event_query_bpf;
+        match check_errno_syscall(|| unsafe { sys::ioctls::QUERY_BPF(fd, query) }) {
+            Ok(_) => {
+                let count = buf[1] as usize;
+                buf.truncate(2 + count);
+                buf.drain(..2);
+                return Ok(buf);
+            }
+            // The kernel still reports the number of attached programs when
+            // the array is too small.
+            Err(e) if e.raw_os_error() == Some(libc::ENOSPC) && buf[1] as usize > capacity => {
+                capacity = buf[1] as usize;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Change a breakpoint `event`'s attributes, via
+/// `PERF_EVENT_IOC_MODIFY_ATTRIBUTES`.
+///
+/// At present the kernel only supports this for breakpoint events, and
+/// `attrs` must be identical to the attributes the event was opened with,
+/// except for the `bp_addr`, `bp_len`, `bp_type`, and `disabled` fields.
+///
+/// If `attrs.size` claims the struct is larger than it actually is, this
+/// returns an `InvalidInput` error without calling the kernel.
+pub fn modify_attributes<E: AsRawFd + ?Sized>(
+    event: &E,
+    attrs: &mut perf_event_attr,
+) -> io::Result<()> {
+    if attrs.size as usize > std::mem::size_of::<perf_event_attr>() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "perf_event_attr size field is larger than the struct",
+        ));
+    }
+    let fd = event.as_raw_fd();
+    check_errno_syscall(|| unsafe { sys::ioctls::MODIFY_ATTRIBUTES(fd, attrs) }).map(|_| ())
+}
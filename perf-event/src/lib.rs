@@ -58,12 +58,11 @@
 //! ### Call for PRs
 //!
 //! Linux's `perf_event_open` API can report all sorts of things this crate
-//! doesn't yet understand: stack traces, logs of executable and shared library
-//! activity, tracepoints, kprobes, uprobes, and so on. And beyond the counters
-//! in the kernel header files, there are others that can only be found at
-//! runtime by consulting `sysfs`, specific to particular processors and
-//! devices. For example, modern Intel processors have counters that measure
-//! power consumption in Joules.
+//! doesn't yet understand: uprobes, most kinds of records in the ring buffer,
+//! and so on. And beyond the counters in the kernel header files, there are
+//! others that can only be found at runtime by consulting `sysfs`, specific to
+//! particular processors and devices. This crate supports only a few of
+//! those, like the energy counters in [`events::Energy`].
 //!
 //! If you find yourself in need of something this crate doesn't support, please
 //! consider submitting a pull request.
@@ -75,16 +74,56 @@
 use events::Event;
 use libc::pid_t;
 use perf_event_open_sys::bindings::perf_event_attr;
+use std::ffi::{CStr, CString};
 use std::fs::File;
 use std::io::{self, Read};
-use std::os::raw::{c_int, c_uint, c_ulong};
-use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
-
+use std::os::raw::{c_int, c_ulong};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+
+pub mod android;
+pub mod batch;
+pub mod cgroup;
+pub mod check;
+#[cfg(feature = "counting_alloc")]
+pub mod counting_alloc;
+#[cfg(feature = "criterion")]
+pub mod criterion;
+pub mod diagnostics;
 pub mod events;
+pub mod health;
+pub mod histogram;
+pub mod ioctl;
+pub mod metrics;
+pub mod paranoid;
+pub mod per_cpu;
+pub mod process;
+pub mod profiler;
+#[cfg(feature = "sampling")]
+pub mod regs;
+#[cfg(feature = "sampler_set")]
+pub mod sampler_set;
+#[cfg(feature = "sampling")]
+pub mod samples;
+pub mod spawn;
+#[cfg(feature = "spool")]
+pub mod spool;
+pub mod stat;
+#[cfg(feature = "discovery")]
+pub mod topology;
+#[cfg(feature = "sampling")]
+pub mod tracepoint;
+pub mod user_read;
+pub mod watchpoints;
+pub mod window;
+pub mod workers;
 
 #[cfg(feature = "hooks")]
 pub mod hooks;
 
+mod error;
+pub use error::Error;
+
 // When the `"hooks"` feature is not enabled, call directly into
 // `perf-event-open-sys`.
 #[cfg(not(feature = "hooks"))]
@@ -144,6 +183,32 @@ pub struct Counter {
 
     /// The unique id assigned to this counter by the kernel.
     id: u64,
+
+    /// Whether this counter was built with `PERF_FORMAT_TOTAL_TIME_ENABLED`
+    /// and `PERF_FORMAT_TOTAL_TIME_RUNNING`, which determines the layout of
+    /// the data we read from `file`.
+    has_times: bool,
+
+    /// Whether this counter was built with `PERF_FORMAT_GROUP`, so that
+    /// reading `file` produces the values of its whole group. Samplers in a
+    /// group whose samples include [`SampleType::READ`] are built this way.
+    ///
+    /// [`SampleType::READ`]: samples::SampleType::READ
+    reads_group: bool,
+
+    /// Whether this counter was built with `PERF_FORMAT_ID`, so that reading
+    /// `file` produces its id after its value and times. Samplers whose
+    /// samples include [`SampleType::READ`] are built this way.
+    ///
+    /// [`SampleType::READ`]: samples::SampleType::READ
+    has_id: bool,
+
+    /// Data the event's attributes pointed to when it was opened, like a
+    /// kprobe's function name, taken over from the `Builder`.
+    event_data: Option<CString>,
+
+    /// The event this counter counts, if it was built by a `Builder`.
+    event: Option<Event>,
 }
 
 /// A builder for [`Counter`]s.
@@ -205,6 +270,36 @@ pub struct Builder<'a> {
     who: EventPid<'a>,
     cpu: Option<usize>,
     group: Option<&'a mut Group>,
+
+    /// The event passed to `kind`, which `attrs` describes.
+    event: Event,
+
+    /// Data that `attrs` points to, like a kprobe's function name, which
+    /// must live until the event is opened.
+    event_data: Option<CString>,
+
+    /// Which of `sample_period` and `sample_frequency` have been called.
+    /// `build` rejects using both.
+    sampling: Sampling,
+
+    /// True if `wakeup_events` or `wakeup_watermark` has been called, so
+    /// `build_sampler` should leave the wakeup settings alone.
+    wakeup: bool,
+
+    /// The sampler whose ring buffer should receive this event's records,
+    /// if set by `output_to`.
+    #[cfg(feature = "sampling")]
+    output: Option<&'a samples::Sampler>,
+
+    /// The size of `build_sampler`'s ring buffer, if set by `buffer_size`.
+    #[cfg(feature = "sampling")]
+    buffer: Option<samples::BufferSize>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Sampling {
+    period: bool,
+    frequency: bool,
 }
 
 #[derive(Debug)]
@@ -215,9 +310,15 @@ enum EventPid<'a> {
     /// Monitor the given pid.
     Other(pid_t),
 
+    /// Monitor the process the given pidfd refers to.
+    PidFd(&'a process::PidFd),
+
     /// Monitor members of the given cgroup.
     CGroup(&'a File),
 
+    /// Monitor members of the cgroup whose directory we opened ourselves.
+    OwnedCGroup(File),
+
     /// Monitor any process on some given CPU.
     Any,
 }
@@ -269,10 +370,11 @@ enum EventPid<'a> {
 /// Subsequent reads from the `Counter` will not reflect activity while the
 /// `Group` was disabled, unless the `Counter` is re-enabled individually.
 ///
-/// A `Group` and its members must all observe the same tasks and cpus; mixing
-/// these makes building the `Counter` return an error. Unfortunately, there is
-/// no way at present to specify a `Group`'s task and cpu, so you can only use
-/// `Group` on the calling task. If this is a problem, please file an issue.
+/// A `Group` and its members must all observe the same tasks and cpus.
+/// [`Group::new`] creates a group observing the calling process on any cpu;
+/// to observe another process, a cgroup, or a particular cpu, use
+/// [`Builder::build_group`]. Counters built into a group observe whatever the
+/// group does.
 ///
 /// Internally, a `Group` is just a wrapper around an event file descriptor.
 ///
@@ -288,11 +390,13 @@ enum EventPid<'a> {
 ///
 /// But since the point of a counter group is that its members all cover exactly
 /// the same period of time, this tactic can't be applied to support large
-/// groups. If the kernel cannot schedule a group, its counters remain zero. I
-/// think you can detect this situation by comparing the group's [`time_enabled`]
-/// and [`time_running`] values. It might also be useful to set the `pinned` bit,
-/// which puts the counter in an error state if it's not able to be put on the
-/// CPU; see [#10].
+/// groups. If the kernel cannot schedule a group, its counters remain zero. You
+/// can detect this situation by calling [`Counts::scheduling_failed`], which
+/// compares the group's [`time_enabled`] and [`time_running`] values, and
+/// [`Counts::scheduling_hint`] will suggest likely causes; for a fuller report,
+/// see [`diagnostics::contention`]. It might also be useful to set the `pinned`
+/// bit, which puts the counter in an error state if it's not able to be put on
+/// the CPU; see [#10].
 ///
 /// According to the `perf_list(1)` man page, you may be able to free up a
 /// hardware counter by disabling the kernel's NMI watchdog, which reserves one
@@ -316,8 +420,9 @@ enum EventPid<'a> {
 /// [`time_running`]: Counts::time_running
 pub struct Group {
     /// The file descriptor for this counter, returned by `perf_event_open`.
-    /// This counter itself is for the dummy software event, so it's not
-    /// interesting.
+    /// This counter is the group's leader. Unless the group was built with
+    /// `Builder::build_group_leader`, it's for the dummy software event, so
+    /// it's not interesting.
     file: File,
 
     /// The unique id assigned to this group by the kernel. We only use this for
@@ -339,8 +444,114 @@ pub struct Group {
     /// just increment, giving us an overestimate, and then correct the count
     /// when we actually do a read.
     ///
-    /// This includes the dummy counter for the group itself.
+    /// This includes the leader, the group's own counter.
     max_members: usize,
+
+    /// True if the leader counts a real event, whose value `Counts` should
+    /// include; false if it's just a placeholder.
+    counts_leader: bool,
+
+    /// The `pid` and `cpu` arguments this group's leader was opened with.
+    /// Members must observe the same process and CPU, so `Builder::build`
+    /// passes these along. If `cgroup` is set, `pid` is ignored.
+    pid: pid_t,
+    cpu: c_int,
+
+    /// If this group observes a cgroup, our own handle on its directory, to
+    /// pass to `perf_event_open` when opening members.
+    cgroup: Option<File>,
+}
+
+/// A likely reason the kernel could not schedule a [`Group`].
+///
+/// See [`SchedulingHint`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SchedulingCause {
+    /// The kernel's NMI watchdog is enabled, and has claimed one of the
+    /// processor's hardware counters for itself. Disabling the watchdog frees
+    /// that counter up:
+    ///
+    /// ```ignore
+    /// $ echo 0 > /proc/sys/kernel/nmi_watchdog
+    /// ```
+    NmiWatchdog,
+
+    /// The group has more hardware counters than the processor can run at
+    /// once. Since all members of a group must be on the processor together,
+    /// the kernel cannot timeshare them; the group simply never runs. Try
+    /// splitting it into smaller groups.
+    TooManyEvents,
+
+    /// Some other event that requested the `pinned` or `exclusive` bits is
+    /// occupying the counters this group needs. This could be another process
+    /// using `perf`, or another `Counter` in this process.
+    PinnedConflict,
+}
+
+/// An explanation of why a [`Group`] might never have been scheduled.
+///
+/// This is returned by [`Counts::scheduling_hint`] when
+/// [`Counts::scheduling_failed`] is true. It lists the most likely causes
+/// first. Its `Display` implementation produces advice suitable for showing
+/// to a user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SchedulingHint {
+    causes: Vec<SchedulingCause>,
+}
+
+impl SchedulingHint {
+    /// Build a hint, consulting the system for anything we can check.
+    fn new() -> SchedulingHint {
+        let contention = diagnostics::contention();
+        let mut causes = vec![];
+        if contention.nmi_watchdog() == Some(true) {
+            causes.push(SchedulingCause::NmiWatchdog);
+        }
+
+        // If other processes are using perf, they're a likelier culprit.
+        let own_pid = std::process::id();
+        if contention.perf_users().iter().any(|u| u.pid != own_pid) {
+            causes.push(SchedulingCause::PinnedConflict);
+            causes.push(SchedulingCause::TooManyEvents);
+        } else {
+            causes.push(SchedulingCause::TooManyEvents);
+            causes.push(SchedulingCause::PinnedConflict);
+        }
+        SchedulingHint { causes }
+    }
+
+    /// Return the likely causes, most likely first.
+    pub fn causes(&self) -> &[SchedulingCause] {
+        &self.causes
+    }
+}
+
+impl std::fmt::Display for SchedulingCause {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(match self {
+            SchedulingCause::NmiWatchdog => {
+                "the NMI watchdog is using a hardware counter; \
+                 try `echo 0 > /proc/sys/kernel/nmi_watchdog`"
+            }
+            SchedulingCause::TooManyEvents => {
+                "the group has more hardware events than the processor \
+                 has counters; try splitting it into smaller groups"
+            }
+            SchedulingCause::PinnedConflict => {
+                "other pinned or exclusive events are occupying the counters"
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for SchedulingHint {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str("the group was never scheduled on the processor. Possible causes:")?;
+        for cause in &self.causes {
+            write!(fmt, "\n- {}", cause)?;
+        }
+        Ok(())
+    }
 }
 
 /// A collection of counts from a [`Group`] of counters.
@@ -398,9 +609,110 @@ pub struct Group {
 ///     # Ok(()) }
 ///
 /// [`read`]: Group::read
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawCounts"))]
 pub struct Counts {
     // Raw results from the `read`.
     data: Vec<u64>,
+
+    // The index of the first entry in `data` to include when iterating or
+    // indexing: 1 to skip a placeholder group leader, or 0 to include a
+    // real one.
+    first: usize,
+}
+
+/// The serialized form of a [`Counts`], before we've checked that its
+/// `data` has the layout the accessors index into.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RawCounts {
+    data: Vec<u64>,
+    first: usize,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<RawCounts> for Counts {
+    type Error = String;
+
+    fn try_from(raw: RawCounts) -> Result<Counts, String> {
+        // `data` is `nr`, `time_enabled`, `time_running`, and then a value
+        // and an id for each of the `nr` counters.
+        let nr = match raw.data.first() {
+            Some(&nr) => nr,
+            None => return Err("Counts data is empty".to_string()),
+        };
+        let expected = usize::try_from(nr)
+            .ok()
+            .and_then(|nr| nr.checked_mul(2))
+            .and_then(|len| len.checked_add(3));
+        if expected != Some(raw.data.len()) {
+            return Err(format!(
+                "Counts data holds {} words, which doesn't fit a group of {} counters",
+                raw.data.len(),
+                nr
+            ));
+        }
+        if raw.first > 1 || raw.first as u64 > nr {
+            return Err(format!("Counts first entry {} is out of range", raw.first));
+        }
+
+        Ok(Counts {
+            data: raw.data,
+            first: raw.first,
+        })
+    }
+}
+
+/// A stored copy of a [`Counts`], with its entries found by label or by id.
+///
+/// A `Counts` can only be indexed by the [`Counter`] it was read for, so it's
+/// awkward to keep around once the counters are gone. A `CountsSnapshot`
+/// holds the same values, but lets you give each entry a label of your own
+/// choosing, and look entries up by label or by kernel-assigned id. It's
+/// cloneable, and (with the `serde` feature) serializable, so it's suitable
+/// for keeping a history of readings:
+///
+///     # fn main() -> std::io::Result<()> {
+///     # use perf_event::{Builder, Group};
+///     # use perf_event::events::Software;
+///     # let mut group = Group::new()?;
+///     # let clock = Builder::new().group(&mut group).kind(Software::TASK_CLOCK).build()?;
+///     # let faults = Builder::new().group(&mut group).kind(Software::PAGE_FAULTS).build()?;
+///     let mut history = vec![];
+///     for _ in 0..3 {
+///         let snapshot = group.read()?.snapshot()
+///             .label(&clock, "task-clock")
+///             .label(&faults, "page-faults");
+///         history.push(snapshot);
+///     }
+///     drop((clock, faults, group));
+///
+///     for snapshot in &history {
+///         println!("{} page faults", snapshot["page-faults"]);
+///     }
+///     # Ok(()) }
+///
+/// Use [`Counts::snapshot`] to make one.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CountsSnapshot {
+    time_enabled: u64,
+    time_running: u64,
+    entries: Vec<SnapshotEntry>,
+}
+
+/// One counter's entry in a [`CountsSnapshot`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotEntry {
+    /// The counter's kernel-assigned id; see [`Counter::id`].
+    pub id: u64,
+
+    /// The label given to this entry with [`CountsSnapshot::label`], if any.
+    pub label: Option<String>,
+
+    /// The counter's value.
+    pub value: u64,
 }
 
 /// The value of a counter, along with timesharing data.
@@ -412,7 +724,13 @@ pub struct Counts {
 ///
 /// This struct holds the value of a counter, together with the time it was
 /// enabled, and the proportion of that for which it was actually running.
+/// Use [`scaled`] to estimate what the count would have been had the counter
+/// run the whole time.
+///
+/// [`scaled`]: CountAndTime::scaled
 #[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CountAndTime {
     /// The counter value.
     ///
@@ -432,20 +750,165 @@ pub struct CountAndTime {
     pub time_running: u64,
 }
 
+/// The values of a [`Group`]'s members, each with its own timesharing data.
+///
+/// This is returned by [`Group::read_with_times`]. Each entry is a
+/// [`MemberCount`], which you can look up by `Counter`, or iterate over:
+///
+///     # fn main() -> std::io::Result<()> {
+///     # use perf_event::{Builder, Group};
+///     # use perf_event::events::Software;
+///     # let mut group = Group::new()?;
+///     # let mut clock = Builder::new().group(&mut group).kind(Software::TASK_CLOCK).build()?;
+///     # let mut faults = Builder::new().group(&mut group).kind(Software::PAGE_FAULTS).build()?;
+///     let data = group.read_with_times(&mut [&mut clock, &mut faults])?;
+///     for member in &data {
+///         match member.scaled() {
+///             Some(value) => println!("counter {}: {}", member.id, value),
+///             None => println!("counter {}: never ran", member.id),
+///         }
+///     }
+///     # Ok(()) }
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GroupData {
+    members: Vec<MemberCount>,
+}
+
+/// One member's entry in a [`GroupData`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemberCount {
+    /// The counter's kernel-assigned id; see [`Counter::id`].
+    pub id: u64,
+
+    /// The counter's value, from the group's atomic read.
+    pub value: u64,
+
+    /// How long this counter was enabled, in nanoseconds.
+    pub time_enabled: u64,
+
+    /// How long the kernel actually ran this counter, in nanoseconds.
+    pub time_running: u64,
+}
+
+/// The result of a time-bounded measurement, along with how long it took.
+///
+/// This is returned by [`Counter::measure_for`] and [`Group::measure_for`].
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Measurement<T> {
+    /// The counts collected: a [`CountAndTime`] for a `Counter`, or a
+    /// [`Counts`] for a `Group`.
+    pub counts: T,
+
+    /// The wall-clock time that passed between enabling and disabling the
+    /// counters. This is at least the duration requested, and may be longer
+    /// if the thread was slow to wake up.
+    pub elapsed: Duration,
+}
+
 impl<'a> EventPid<'a> {
     // Return the `pid` arg and the `flags` bits representing `self`.
-    fn as_args(&self) -> (pid_t, u32) {
-        match self {
+    fn as_args(&self) -> io::Result<(pid_t, u32)> {
+        Ok(match self {
             EventPid::Any => (-1, 0),
             EventPid::ThisProcess => (0, 0),
             EventPid::Other(pid) => (*pid, 0),
+            EventPid::PidFd(pidfd) => (pidfd.live_pid()?, 0),
             EventPid::CGroup(file) => (file.as_raw_fd(), sys::bindings::PERF_FLAG_PID_CGROUP),
+            EventPid::OwnedCGroup(file) => (file.as_raw_fd(), sys::bindings::PERF_FLAG_PID_CGROUP),
+        })
+    }
+
+    // If `self` is a pidfd, check that the process it refers to is still
+    // `pid`, now that we've opened an event on `pid`. If it is, the event
+    // is observing the right process: a pid can't be reused until its
+    // process has been reaped.
+    fn check_pid(&self, pid: pid_t) -> io::Result<()> {
+        match self {
+            EventPid::PidFd(pidfd) if pidfd.live_pid()? != pid => Err(process::PidFd::exited()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The `read_format` bits for `time_enabled` and `time_running`.
+const TIME_FORMAT: u64 = sys::bindings::PERF_FORMAT_TOTAL_TIME_ENABLED as u64
+    | sys::bindings::PERF_FORMAT_TOTAL_TIME_RUNNING as u64;
+
+/// Crate-wide defaults for newly created [`Builder`]s.
+///
+/// A few of `Builder`'s settings are usually chosen once for a whole program:
+/// whether to count kernel and hypervisor activity, and whether counters
+/// should track how long they were enabled and running. Rather than
+/// adjusting every `Builder`, a program can install its preferred defaults
+/// once at startup:
+///
+///     use perf_event::BuilderDefaults;
+///
+///     BuilderDefaults {
+///         include_kernel: true,
+///         ..BuilderDefaults::new()
+///     }
+///     .install();
+///
+/// Installing defaults affects only `Builder`s created afterwards, on any
+/// thread. Methods called on a particular `Builder` still override them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BuilderDefaults {
+    /// Whether to count events in kernel code. The crate's default is
+    /// `false`; see [`Builder::include_kernel`].
+    pub include_kernel: bool,
+
+    /// Whether to count events in hypervisor code. The crate's default is
+    /// `false`; see [`Builder::include_hv`].
+    pub include_hv: bool,
+
+    /// Whether counters track how long they have been enabled and running,
+    /// as reported by [`Counter::read_count_and_time`]. The crate's default
+    /// is `true`.
+    ///
+    /// Turning this off makes each read slightly cheaper, but then
+    /// `read_count_and_time` returns an error, and there's no way to tell
+    /// whether a counter's hardware was timeshared. [`Group`]s always track
+    /// these times, regardless of this setting.
+    pub times: bool,
+}
+
+static DEFAULTS: std::sync::Mutex<BuilderDefaults> = std::sync::Mutex::new(BuilderDefaults::new());
+
+impl BuilderDefaults {
+    /// Return the crate's built-in defaults.
+    pub const fn new() -> BuilderDefaults {
+        BuilderDefaults {
+            include_kernel: false,
+            include_hv: false,
+            times: true,
         }
     }
+
+    /// Return the defaults currently in effect.
+    pub fn current() -> BuilderDefaults {
+        *DEFAULTS.lock().unwrap()
+    }
+
+    /// Make `self` the defaults for all `Builder`s created from now on.
+    /// Return the defaults previously in effect.
+    pub fn install(self) -> BuilderDefaults {
+        std::mem::replace(&mut *DEFAULTS.lock().unwrap(), self)
+    }
+}
+
+impl Default for BuilderDefaults {
+    fn default() -> BuilderDefaults {
+        BuilderDefaults::new()
+    }
 }
 
 impl<'a> Default for Builder<'a> {
     fn default() -> Builder<'a> {
+        let defaults = BuilderDefaults::current();
         let mut attrs = perf_event_attr {
             // Setting `size` accurately will not prevent the code from working
             // on older kernels. The module comments for `perf_event_open_sys`
@@ -455,21 +918,31 @@ impl<'a> Default for Builder<'a> {
         };
 
         attrs.set_disabled(1);
-        attrs.set_exclude_kernel(1); // don't count time in kernel
-        attrs.set_exclude_hv(1); // don't count time in hypervisor
+        // Unless asked, don't count time in the kernel or hypervisor.
+        attrs.set_exclude_kernel((!defaults.include_kernel).into());
+        attrs.set_exclude_hv((!defaults.include_hv).into());
 
         // Request data for `time_enabled` and `time_running`.
-        attrs.read_format |= sys::bindings::PERF_FORMAT_TOTAL_TIME_ENABLED as u64
-            | sys::bindings::PERF_FORMAT_TOTAL_TIME_RUNNING as u64;
+        if defaults.times {
+            attrs.read_format |= TIME_FORMAT;
+        }
 
         let kind = Event::Hardware(events::Hardware::INSTRUCTIONS);
-        kind.update_attrs(&mut attrs);
+        kind.clone().update_attrs(&mut attrs);
 
         Builder {
             attrs,
             who: EventPid::ThisProcess,
             cpu: None,
             group: None,
+            event: kind,
+            event_data: None,
+            sampling: Sampling::default(),
+            wakeup: false,
+            #[cfg(feature = "sampling")]
+            output: None,
+            #[cfg(feature = "sampling")]
+            buffer: None,
         }
     }
 }
@@ -481,17 +954,73 @@ impl<'a> Builder<'a> {
     }
 
     /// Include kernel code.
+    ///
+    /// By default, a `Builder` excludes activity in the kernel, since
+    /// counting it requires a `perf_event_paranoid` level of 1 or less, or
+    /// the `CAP_PERFMON` capability; see [`paranoid::Level::NoCpuEvents`].
+    /// [`BuilderDefaults`] can change the default.
     pub fn include_kernel(mut self) -> Builder<'a> {
         self.attrs.set_exclude_kernel(0);
         self
     }
 
     /// Include hypervisor code.
+    ///
+    /// By default, a `Builder` excludes activity in the hypervisor.
+    /// [`BuilderDefaults`] can change the default. Not all processors can
+    /// tell hypervisor activity apart; on those, this has no effect.
     pub fn include_hv(mut self) -> Builder<'a> {
         self.attrs.set_exclude_hv(0);
         self
     }
 
+    /// Exclude user-space code, counting only what [`include_kernel`] or
+    /// [`include_hv`] add back.
+    ///
+    /// Since a `Builder` excludes the kernel and hypervisor by default, a
+    /// counter that excludes user space too counts nothing unless one of
+    /// those is included, which has its own permission requirements.
+    ///
+    /// [`include_kernel`]: Builder::include_kernel
+    /// [`include_hv`]: Builder::include_hv
+    pub fn exclude_user(mut self) -> Builder<'a> {
+        self.attrs.set_exclude_user(1);
+        self
+    }
+
+    /// Exclude time the processor spends idle.
+    ///
+    /// This only matters for counters that observe a whole CPU (see
+    /// [`system_wide_on_cpu`]), since a particular process is never
+    /// running while its CPU is idle. Not all events and processors support
+    /// it.
+    ///
+    /// [`system_wide_on_cpu`]: Builder::system_wide_on_cpu
+    pub fn exclude_idle(mut self) -> Builder<'a> {
+        self.attrs.set_exclude_idle(1);
+        self
+    }
+
+    /// When running under virtualization support like KVM, exclude activity
+    /// in the host, counting only the guest.
+    ///
+    /// This needs a kernel and processor with guest/host event filtering; on
+    /// others, it has no effect.
+    pub fn exclude_host(mut self) -> Builder<'a> {
+        self.attrs.set_exclude_host(1);
+        self
+    }
+
+    /// When running under virtualization support like KVM, exclude activity
+    /// in guests, counting only the host.
+    ///
+    /// This needs a kernel and processor with guest/host event filtering; on
+    /// others, it has no effect.
+    pub fn exclude_guest(mut self) -> Builder<'a> {
+        self.attrs.set_exclude_guest(1);
+        self
+    }
+
     /// Observe the calling process. (This is the default.)
     pub fn observe_self(mut self) -> Builder<'a> {
         self.who = EventPid::ThisProcess;
@@ -501,18 +1030,146 @@ impl<'a> Builder<'a> {
     /// Observe the process with the given process id. This requires
     /// [`CAP_SYS_PTRACE`][man-capabilities] capabilities.
     ///
+    /// Despite the name, the kernel treats `pid` as a thread id: given a
+    /// multi-threaded process's id, the counter observes only its main
+    /// thread (and, if you set [`inherit`], threads created after the
+    /// counter). This is the same as [`observe_tid`], which says so more
+    /// plainly. To count every thread of a process, use
+    /// [`observe_all_threads_of`].
+    ///
     /// [man-capabilities]: http://man7.org/linux/man-pages/man7/capabilities.7.html
+    /// [`inherit`]: Builder::inherit
+    /// [`observe_tid`]: Builder::observe_tid
+    /// [`observe_all_threads_of`]: Builder::observe_all_threads_of
     pub fn observe_pid(mut self, pid: pid_t) -> Builder<'a> {
         self.who = EventPid::Other(pid);
         self
     }
 
+    /// Observe the process that `pidfd` refers to, without risk of
+    /// observing some other process that has reused its id.
+    ///
+    /// A monitoring program that learns a process's id and then calls
+    /// [`observe_pid`] with it can't be sure the id still refers to the same
+    /// process: if the process exits and is reaped in the meantime, the
+    /// kernel may give its id to a new one. A [`PidFd`] refers to one
+    /// particular process, however long it lives. The kernel can't open
+    /// events on a pidfd directly, so [`build`] opens the event on the
+    /// process's id, and then checks that the pidfd's process still has
+    /// that id, which means it can't have been reused. If the process has
+    /// exited, `build` returns an `ESRCH` error.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     # let pid = std::process::id() as libc::pid_t;
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///     use perf_event::process::PidFd;
+    ///
+    ///     let pidfd = PidFd::open(pid)?;
+    ///     // ... time passes; the process may have exited ...
+    ///     let counter = Builder::new()
+    ///         .observe_pidfd(&pidfd)
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .build()?;
+    ///     # Ok(()) }
+    ///
+    /// As with `observe_pid`, this observes only the process's main
+    /// thread, plus the threads it creates later if you set [`inherit`].
+    ///
+    /// [`observe_pid`]: Builder::observe_pid
+    /// [`PidFd`]: process::PidFd
+    /// [`build`]: Builder::build
+    /// [`inherit`]: Builder::inherit
+    pub fn observe_pidfd(mut self, pidfd: &'a process::PidFd) -> Builder<'a> {
+        self.who = EventPid::PidFd(pidfd);
+        self
+    }
+
+    /// Observe the single thread whose thread id is `tid`. This has the same
+    /// requirements as [`observe_pid`].
+    ///
+    /// [`observe_pid`]: Builder::observe_pid
+    pub fn observe_tid(self, tid: pid_t) -> Builder<'a> {
+        self.observe_pid(tid)
+    }
+
+    /// Build a counter for each thread of the process `pid`, as this
+    /// `Builder` specifies, and return a [`ProcessCounter`] that sums them.
+    ///
+    /// This replaces whatever this `Builder` was set to observe with each
+    /// thread's id in turn. The `ProcessCounter` builds counters the same
+    /// way for threads it finds when refreshed. Counters in a `Group`, or
+    /// whose output goes to another sampler, can't be built this way; if
+    /// this `Builder` has a [`group`] or an `output_to` sampler, this
+    /// returns an `ErrorKind::InvalidInput` error.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///
+    ///     let pid = std::process::id() as libc::pid_t;
+    ///     let mut counter = Builder::new()
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .observe_all_threads_of(pid)?;
+    ///     counter.enable()?;
+    ///     // ...
+    ///     counter.disable()?;
+    ///     println!("{} ns in {} threads", counter.read()?, counter.threads());
+    ///     # Ok(()) }
+    ///
+    /// [`ProcessCounter`]: process::ProcessCounter
+    /// [`group`]: Builder::group
+    pub fn observe_all_threads_of(self, pid: pid_t) -> io::Result<process::ProcessCounter> {
+        if self.group.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "observe_all_threads_of can't build counters in a Group",
+            ));
+        }
+        #[cfg(feature = "sampling")]
+        if self.output.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "observe_all_threads_of can't redirect counters' output",
+            ));
+        }
+        let Builder {
+            attrs,
+            cpu,
+            event,
+            event_data,
+            sampling,
+            wakeup,
+            ..
+        } = self;
+        process::ProcessCounter::observe_process(pid, move |tid| {
+            // `attrs` may point into `event_data`, which the closure owns,
+            // so it stays put for as long as we build counters.
+            Builder {
+                attrs,
+                who: EventPid::Other(tid),
+                cpu,
+                group: None,
+                event: event.clone(),
+                event_data: event_data.clone(),
+                sampling,
+                wakeup,
+                #[cfg(feature = "sampling")]
+                output: None,
+                #[cfg(feature = "sampling")]
+                buffer: None,
+            }
+            .build()
+        })
+    }
+
     /// Observe all processes.
     ///
     /// Linux does not support observing all processes on all CPUs without
     /// restriction, so combining `any_pid` with [`any_cpu`] will cause the
     /// final [`build`] to return an error. This must be used together with
-    /// [`one_cpu`], to select a specific CPU to observe.
+    /// [`one_cpu`], to select a specific CPU to observe; or see
+    /// [`system_wide_on_cpu`], which does both.
     ///
     /// This requires [`CAP_PERFMON`][cap] or [`CAP_SYS_ADMIN`][cap]
     /// capabilities, or a `/proc/sys/kernel/perf_event_paranoid` value of less
@@ -521,6 +1178,7 @@ impl<'a> Builder<'a> {
     /// [`any_cpu`]: Builder::any_cpu
     /// [`build`]: Builder::build
     /// [`one_cpu`]: Builder::one_cpu
+    /// [`system_wide_on_cpu`]: Builder::system_wide_on_cpu
     /// [cap]: http://man7.org/linux/man-pages/man7/capabilities.7.html
     pub fn any_pid(mut self) -> Builder<'a> {
         self.who = EventPid::Any;
@@ -537,12 +1195,81 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Observe code running in the cgroup at `cgroup`, a path within the
+    /// cgroup hierarchy like `"system.slice/cron.service"`.
+    ///
+    /// This finds and opens the cgroup's directory with [`cgroup::open`],
+    /// which explains where it looks, and returns any error it reports. The
+    /// kernel only counts cgroups' events one CPU at a time, so as with
+    /// [`observe_cgroup`], this must be used together with [`one_cpu`]:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///
+    ///     # if perf_event::cgroup::path("system.slice").is_ok() {
+    ///     let mut counter = Builder::new()
+    ///         .observe_cgroup_path("system.slice")?
+    ///         .one_cpu(0)
+    ///         .kind(Software::CPU_CLOCK)
+    ///         .build()?;
+    ///     # }
+    ///     # Ok(()) }
+    ///
+    /// [`observe_cgroup`]: Builder::observe_cgroup
+    /// [`one_cpu`]: Builder::one_cpu
+    pub fn observe_cgroup_path<P: AsRef<std::path::Path>>(
+        mut self,
+        cgroup: P,
+    ) -> io::Result<Builder<'a>> {
+        self.who = EventPid::OwnedCGroup(cgroup::open(cgroup)?);
+        Ok(self)
+    }
+
     /// Observe only code running on the given CPU core.
     pub fn one_cpu(mut self, cpu: usize) -> Builder<'a> {
         self.cpu = Some(cpu);
         self
     }
 
+    /// Observe all processes, but only while they run on the given CPU core.
+    ///
+    /// This is shorthand for [`any_pid`] followed by [`one_cpu`]: the
+    /// combination Linux requires for system-wide monitoring. Opening one
+    /// such counter per CPU covers the whole system. It's well suited to
+    /// software events, like context switches or page faults:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///
+    ///     // Count context switches on CPU 0, in any process.
+    ///     let mut switches = Builder::new()
+    ///         .system_wide_on_cpu(0)
+    ///         .include_kernel()
+    ///         .kind(Software::CONTEXT_SWITCHES)
+    ///         .build()?;
+    ///     # Ok(()) }
+    ///
+    /// Note that events the kernel triggers on its own behalf, like context
+    /// switches, are only counted if you call [`include_kernel`]; by default,
+    /// a `Builder` excludes kernel activity.
+    ///
+    /// This has the same permission requirements as [`any_pid`]. Calling
+    /// [`observe_self`], [`observe_pid`], [`observe_cgroup`], or [`any_cpu`]
+    /// afterwards overrides the corresponding half of this setting.
+    ///
+    /// [`any_pid`]: Builder::any_pid
+    /// [`one_cpu`]: Builder::one_cpu
+    /// [`any_cpu`]: Builder::any_cpu
+    /// [`include_kernel`]: Builder::include_kernel
+    /// [`observe_self`]: Builder::observe_self
+    /// [`observe_pid`]: Builder::observe_pid
+    /// [`observe_cgroup`]: Builder::observe_cgroup
+    pub fn system_wide_on_cpu(self, cpu: usize) -> Builder<'a> {
+        self.any_pid().one_cpu(cpu)
+    }
+
     /// Observe code running on any CPU core. (This is the default.)
     ///
     /// Linux does not support observing all processes on all CPUs without
@@ -571,12 +1298,136 @@ impl<'a> Builder<'a> {
     /// This flag cannot be set if the counter belongs to a `Group`. Doing so
     /// will result in an error when the counter is built. This is a kernel
     /// limitation.
+    ///
+    #[cfg_attr(
+        feature = "sampling",
+        doc = "
+Nor can an inherited event that observes every CPU be used for sampling:
+the kernel won't map its ring buffer, so [`build_sampler`] returns an error
+of kind [`io::ErrorKind::InvalidInput`]. An inherited sampler must observe a
+single CPU, chosen with [`one_cpu`]. To sample a whole process, see
+[`ProcessSampler`], which does this for each CPU, or else follows the
+process's threads as they're created.
+
+[`build_sampler`]: Builder::build_sampler
+[`one_cpu`]: Builder::one_cpu
+[`ProcessSampler`]: process::ProcessSampler
+"
+    )]
+    ///
+    /// See also [`inherit_thread`] and [`inherit_stat`].
+    ///
+    /// [`inherit_thread`]: Builder::inherit_thread
+    /// [`inherit_stat`]: Builder::inherit_stat
     pub fn inherit(mut self, inherit: bool) -> Builder<'a> {
         let flag = if inherit { 1 } else { 0 };
         self.attrs.set_inherit(flag);
         self
     }
 
+    /// Set whether an inherited counter follows only new threads, and not
+    /// new processes.
+    ///
+    /// When this flag is set along with [`inherit`], the counter is
+    /// inherited by threads created with `CLONE_THREAD`, but not by child
+    /// processes created with `fork` and the like. This flag has no effect
+    /// unless [`inherit`] is also set. It requires Linux 5.13 or later; on
+    /// older kernels, [`build`] returns an error.
+    ///
+    /// [`inherit`]: Builder::inherit
+    /// [`build`]: Builder::build
+    pub fn inherit_thread(mut self, inherit_thread: bool) -> Builder<'a> {
+        self.attrs.set_inherit_thread(inherit_thread.into());
+        self
+    }
+
+    /// Set whether an inherited counter's per-task counts are saved across
+    /// context switches.
+    ///
+    /// When this flag is set along with [`inherit`], the kernel saves each
+    /// inherited copy's count when its task is switched out, so that the
+    /// values the copies report on exit are exact. This flag has no effect
+    /// unless [`inherit`] is also set.
+    ///
+    #[cfg_attr(
+        feature = "sampling",
+        doc = "
+For a sampler, this also asks the kernel to report each inherited copy's
+values as its thread exits, in a [`samples::RecordEvent::Read`] record. Not
+all kernels send these.
+"
+    )]
+    ///
+    /// [`inherit`]: Builder::inherit
+    pub fn inherit_stat(mut self, inherit_stat: bool) -> Builder<'a> {
+        self.attrs.set_inherit_stat(inherit_stat.into());
+        self
+    }
+
+    /// Enable this counter automatically when the observed process calls
+    /// `exec`.
+    ///
+    /// The counter starts out disabled, as usual, and the kernel enables it
+    /// when the process it observes next executes a new program. This is
+    /// how `perf stat COMMAND` counts exactly the command's own work. Use
+    /// [`spawn::spawn_counted`] to attach counters like this to a
+    /// [`std::process::Command`] before it runs.
+    pub fn enable_on_exec(mut self) -> Builder<'a> {
+        self.attrs.set_enable_on_exec(1);
+        self
+    }
+
+    /// Set whether the counter is removed from a thread when the thread
+    /// calls `exec`. The default is `false`.
+    ///
+    /// This requires Linux 5.13 or later; on older kernels, [`build`]
+    /// returns an error.
+    ///
+    /// [`build`]: Builder::build
+    pub fn remove_on_exec(mut self, remove_on_exec: bool) -> Builder<'a> {
+        self.attrs.set_remove_on_exec(remove_on_exec.into());
+        self
+    }
+
+    /// Send the observed thread a `SIGTRAP` signal each time the counter
+    /// overflows, instead of, or as well as, writing a sample to a ring
+    /// buffer. Use [`sample_period`] to say how often the counter
+    /// overflows.
+    ///
+    /// The signal's `siginfo_t` has an `si_code` of `TRAP_PERF`, an
+    /// `si_perf_type` giving the event's type, and an `si_perf_data` holding
+    /// `sig_data`, which a handler can use to tell events apart. This lets a
+    /// thread sample itself synchronously, without a ring buffer or a
+    /// separate reader.
+    ///
+    /// Since a program started by `exec` won't have the handler, this also
+    /// sets [`remove_on_exec`], as the kernel requires. The counter must
+    /// observe a particular thread or process, not a whole CPU; and to
+    /// observe another process, you need permission to trace it. This
+    /// requires Linux 5.13 or later; on older kernels, [`build`] returns an
+    /// error.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///
+    ///     // Signal this thread every 10ms of CPU time it uses.
+    ///     let builder = Builder::new()
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .sample_period(10_000_000)
+    ///         .sigtrap(0x1234);
+    ///     # Ok(()) }
+    ///
+    /// [`sample_period`]: Builder::sample_period
+    /// [`remove_on_exec`]: Builder::remove_on_exec
+    /// [`build`]: Builder::build
+    pub fn sigtrap(mut self, sig_data: u64) -> Builder<'a> {
+        self.attrs.set_sigtrap(1);
+        self.attrs.set_remove_on_exec(1);
+        self.attrs.sig_data = sig_data;
+        self
+    }
+
     /// Count events of the given kind. This accepts an [`Event`] value,
     /// or any type that can be converted to one, so you can pass [`Hardware`],
     /// [`Software`] and [`Cache`] values directly.
@@ -608,7 +1459,8 @@ impl<'a> Builder<'a> {
     /// [`Cache`]: events::Cache
     pub fn kind<K: Into<Event>>(mut self, kind: K) -> Builder<'a> {
         let kind = kind.into();
-        kind.update_attrs(&mut self.attrs);
+        self.event_data = kind.clone().update_attrs(&mut self.attrs);
+        self.event = kind;
         self
     }
 
@@ -616,7 +1468,11 @@ impl<'a> Builder<'a> {
     /// to be enabled, disabled, or read as a single atomic operation, so that
     /// the counts can be usefully compared.
     ///
+    /// The counter observes whatever process and CPU the group does; see
+    /// [`build_group`].
+    ///
     /// [`Group`]: struct.Group.html
+    /// [`build_group`]: Builder::build_group
     pub fn group(mut self, group: &'a mut Group) -> Builder<'a> {
         self.group = Some(group);
 
@@ -627,28 +1483,510 @@ impl<'a> Builder<'a> {
         self
     }
 
-    /// Construct a [`Counter`] according to the specifications made on this
-    /// `Builder`.
+    /// Take a sample every `period` events, writing a record to the event's
+    /// ring buffer.
     ///
-    /// A freshly built `Counter` is disabled. To begin counting events, you
-    /// must call [`enable`] on the `Counter` or the `Group` to which it belongs.
+    #[cfg_attr(
+        feature = "sampling",
+        doc = "
+This only has an effect on events built with [`build_sampler`]; a plain
+`Counter` has nowhere to put the samples.
+
+[`build_sampler`]: Builder::build_sampler
+"
+    )]
     ///
-    /// If the `Builder` requests features that the running kernel does not
-    /// support, it returns `Err(e)` where `e.kind() == ErrorKind::Other` and
-    /// `e.raw_os_error() == Some(libc::E2BIG)`.
+    /// For [`Software::TASK_CLOCK`] and [`Software::CPU_CLOCK`] events, the
+    /// period is in nanoseconds.
     ///
-    /// Unfortunately, problems in counter configuration are detected at this
-    /// point, by the kernel, not earlier when the offending request is made on
-    /// the `Builder`. The kernel's returned errors are not always helpful.
+    /// This can't be combined with [`sample_frequency`]; if both are called,
+    /// [`build`] returns an `ErrorKind::InvalidInput` error.
     ///
-    /// [`Counter`]: struct.Counter.html
-    /// [`enable`]: struct.Counter.html#method.enable
-    pub fn build(mut self) -> std::io::Result<Counter> {
-        let cpu = match self.cpu {
-            Some(cpu) => cpu as c_int,
-            None => -1,
+    /// [`Software::TASK_CLOCK`]: events::Software::TASK_CLOCK
+    /// [`Software::CPU_CLOCK`]: events::Software::CPU_CLOCK
+    /// [`sample_frequency`]: Builder::sample_frequency
+    /// [`build`]: Builder::build
+    pub fn sample_period(mut self, period: u64) -> Builder<'a> {
+        self.attrs.set_freq(0);
+        self.attrs.set_sample_period(period);
+        self.sampling.period = true;
+        self
+    }
+
+    /// Take about `frequency` samples per second, writing a record to the
+    /// event's ring buffer for each.
+    ///
+    #[cfg_attr(
+        feature = "sampling",
+        doc = "
+This only has an effect on events built with [`build_sampler`].
+
+[`build_sampler`]: Builder::build_sampler
+"
+    )]
+    ///
+    /// Rather than sampling every so many events, the kernel adjusts the
+    /// period as it goes to hit the requested rate. The kernel limits the
+    /// rate to `/proc/sys/kernel/perf_event_max_sample_rate`; asking for
+    /// more makes [`build`] fail with `EINVAL`.
+    ///
+    /// This can't be combined with [`sample_period`]; if both are called,
+    /// [`build`] returns an `ErrorKind::InvalidInput` error.
+    ///
+    /// [`sample_period`]: Builder::sample_period
+    /// [`build`]: Builder::build
+    pub fn sample_frequency(mut self, frequency: u64) -> Builder<'a> {
+        self.attrs.set_freq(1);
+        self.attrs.set_sample_freq(frequency);
+        self.sampling.frequency = true;
+        self
+    }
+
+    /// Include the values selected by `sample_type` in each sample. This
+    /// adds to any values already requested.
+    ///
+    /// See [`samples::Sample`] for how to retrieve them.
+    #[cfg(feature = "sampling")]
+    pub fn sample(mut self, sample_type: samples::SampleType) -> Builder<'a> {
+        self.attrs.sample_type |= sample_type.bits();
+        self
+    }
+
+    /// Record when the observed threads change their names, producing
+    /// [`samples::RecordEvent::Comm`] records. The default is `false`.
+    ///
+    /// This also asks the kernel to flag names changed by `exec`, which
+    /// requires Linux 3.16 or later. Together with [`task`], this lets a
+    /// sampler keep track of which process each sample came from.
+    ///
+    /// This only has an effect on events built with [`build_sampler`].
+    ///
+    /// [`task`]: Builder::task
+    /// [`build_sampler`]: Builder::build_sampler
+    #[cfg(feature = "sampling")]
+    pub fn comm(mut self, comm: bool) -> Builder<'a> {
+        self.attrs.set_comm(comm.into());
+        self.attrs.set_comm_exec(comm.into());
+        self
+    }
+
+    /// Record when the observed processes and threads are created and exit,
+    /// producing [`samples::RecordEvent::Fork`] and
+    /// [`samples::RecordEvent::Exit`] records. The default is `false`.
+    ///
+    /// This only has an effect on events built with [`build_sampler`]. A
+    /// `Fork` record goes to events observing the parent, but an `Exit`
+    /// record goes only to events observing the thread that exited, so an
+    /// event observing a single thread sees the threads it creates start,
+    /// but not exit. A system-wide sampler (see [`system_wide_on_cpu`])
+    /// sees both.
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    /// [`system_wide_on_cpu`]: Builder::system_wide_on_cpu
+    #[cfg(feature = "sampling")]
+    pub fn task(mut self, task: bool) -> Builder<'a> {
+        self.attrs.set_task(task.into());
+        self
+    }
+
+    /// Record context switches into and out of the observed threads,
+    /// producing [`samples::RecordEvent::Switch`] records. The default is
+    /// `false`.
+    ///
+    /// This only has an effect on events built with [`build_sampler`]. It
+    /// requires Linux 4.3 or later.
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    #[cfg(feature = "sampling")]
+    pub fn context_switch(mut self, context_switch: bool) -> Builder<'a> {
+        self.attrs.set_context_switch(context_switch.into());
+        self
+    }
+
+    /// Include a branch stack in each sample: a list of the most recent
+    /// branches the processor took, selected and described according to
+    /// `flags`.
+    ///
+    /// This sets [`SampleType::BRANCH_STACK`], and decoded samples hold the
+    /// branches in [`Sample::branch_stack`]. Branch stacks come from
+    /// hardware like Intel's Last Branch Record, so only hardware events
+    /// support them; for other events, [`build`] fails with `EOPNOTSUPP`.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Hardware;
+    ///     use perf_event::samples::SampleBranchFlag;
+    ///
+    ///     let builder = Builder::new()
+    ///         .kind(Hardware::BRANCH_INSTRUCTIONS)
+    ///         .sample_period(10_000)
+    ///         .sample_branch_stack(SampleBranchFlag::USER | SampleBranchFlag::ANY);
+    ///     # Ok(()) }
+    ///
+    /// [`SampleType::BRANCH_STACK`]: samples::SampleType::BRANCH_STACK
+    /// [`Sample::branch_stack`]: samples::Sample::branch_stack
+    /// [`build`]: Builder::build
+    #[cfg(feature = "sampling")]
+    pub fn sample_branch_stack(mut self, flags: samples::SampleBranchFlag) -> Builder<'a> {
+        self.attrs.sample_type |= samples::SampleType::BRANCH_STACK.bits();
+        self.attrs.branch_sample_type = flags.bits();
+        self
+    }
+
+    /// Timestamp records with `clock`, so that [`Sample::time`] can be
+    /// compared with times from `clock_gettime(2)`.
+    ///
+    /// By default, the kernel uses its own scheduler clock, which is cheap
+    /// to read but doesn't correspond to any clock user space can read.
+    /// [`Sampler::clock`] reports the clock a sampler uses. This requires
+    /// Linux 4.1 or later.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::samples::{Clock, SampleType};
+    ///
+    ///     let builder = Builder::new()
+    ///         .sample(SampleType::TIME)
+    ///         .clock(Clock::Monotonic);
+    ///     # Ok(()) }
+    ///
+    /// [`Sample::time`]: samples::Sample::time
+    /// [`Sampler::clock`]: samples::Sampler::clock
+    #[cfg(feature = "sampling")]
+    pub fn clock(mut self, clock: samples::Clock) -> Builder<'a> {
+        self.attrs.set_use_clockid(1);
+        self.attrs.clockid = clock.clockid();
+        self
+    }
+
+    /// Include the values of the user-space registers `regs` in each
+    /// sample.
+    ///
+    /// This sets [`SampleType::REGS_USER`], and decoded samples hold the
+    /// values in [`Sample::regs_user`]. The registers available depend on
+    /// the architecture; see the [`regs`] module. Some registers can't be
+    /// sampled in every process: on x86_64, for example, the kernel rejects
+    /// the segment registers `ds` through `gs` with `EINVAL`.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::regs::Register;
+    ///
+    ///     let builder = Builder::new().sample_regs_user(&[Register::SP]);
+    ///     # Ok(()) }
+    ///
+    /// [`SampleType::REGS_USER`]: samples::SampleType::REGS_USER
+    /// [`Sample::regs_user`]: samples::Sample::regs_user
+    #[cfg(feature = "sampling")]
+    pub fn sample_regs_user(mut self, regs: &[regs::Register]) -> Builder<'a> {
+        self.attrs.sample_type |= samples::SampleType::REGS_USER.bits();
+        self.attrs.sample_regs_user = regs.iter().fold(0, |mask, reg| mask | reg.mask());
+        self
+    }
+
+    /// Record when cgroups are created, producing
+    /// [`samples::RecordEvent::Cgroup`] records that give each new cgroup's
+    /// id and path. The default is `false`. This requires Linux 5.7 or
+    /// later.
+    ///
+    /// This only has an effect on events built with [`build_sampler`]. It
+    /// is for attributing samples that include [`SampleType::CGROUP`]; to
+    /// count only events in a particular cgroup, use [`observe_cgroup`].
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    /// [`SampleType::CGROUP`]: samples::SampleType::CGROUP
+    /// [`observe_cgroup`]: Builder::observe_cgroup
+    #[cfg(feature = "sampling")]
+    pub fn cgroup(mut self, cgroup: bool) -> Builder<'a> {
+        self.attrs.set_cgroup(cgroup.into());
+        self
+    }
+
+    /// Record when the kernel modifies its own code, producing
+    /// [`samples::RecordEvent::TextPoke`] records. The default is `false`.
+    ///
+    /// These records describe changes to kernel text system-wide, whatever
+    /// the event is observing. This only has an effect on events built with
+    /// [`build_sampler`]. It requires Linux 5.9 or later, and the same
+    /// privileges as [`include_kernel`].
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    /// [`include_kernel`]: Builder::include_kernel
+    #[cfg(feature = "sampling")]
+    pub fn text_poke(mut self, text_poke: bool) -> Builder<'a> {
+        self.attrs.set_text_poke(text_poke.into());
+        self
+    }
+
+    /// Record when the observed processes and threads are created or change
+    /// namespaces, producing [`samples::RecordEvent::Namespaces`] records.
+    /// The default is `false`. This requires Linux 4.12 or later.
+    ///
+    /// This only has an effect on events built with [`build_sampler`].
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    #[cfg(feature = "sampling")]
+    pub fn namespaces(mut self, namespaces: bool) -> Builder<'a> {
+        self.attrs.set_namespaces(namespaces.into());
+        self
+    }
+
+    /// Record when kernel symbols, like BPF programs' JIT-compiled code,
+    /// are registered or unregistered, producing
+    /// [`samples::RecordEvent::Ksymbol`] records. The default is `false`.
+    /// This requires Linux 5.1 or later.
+    ///
+    /// This only has an effect on events built with [`build_sampler`].
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    #[cfg(feature = "sampling")]
+    pub fn ksymbol(mut self, ksymbol: bool) -> Builder<'a> {
+        self.attrs.set_ksymbol(ksymbol.into());
+        self
+    }
+
+    /// Record when BPF programs are loaded or unloaded, producing
+    /// [`samples::RecordEvent::BpfEvent`] records. The default is `false`.
+    /// This requires Linux 5.1 or later.
+    ///
+    /// This only has an effect on events built with [`build_sampler`].
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    #[cfg(feature = "sampling")]
+    pub fn bpf_event(mut self, bpf_event: bool) -> Builder<'a> {
+        self.attrs.set_bpf_event(bpf_event.into());
+        self
+    }
+
+    /// Record when the observed process maps an executable file into memory,
+    /// producing [`samples::RecordEvent::Mmap`] records. The default is
+    /// `false`.
+    ///
+    /// This only has an effect on events built with [`build_sampler`].
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    #[cfg(feature = "sampling")]
+    pub fn mmap(mut self, mmap: bool) -> Builder<'a> {
+        self.attrs.set_mmap(mmap.into());
+        self
+    }
+
+    /// Record when the observed process maps a file into memory, producing
+    /// [`samples::RecordEvent::Mmap2`] records in place of
+    /// [`samples::RecordEvent::Mmap`] records. The default is `false`.
+    ///
+    /// This only has an effect on events built with [`build_sampler`], and
+    /// only if [`mmap`] is also set.
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    /// [`mmap`]: Builder::mmap
+    #[cfg(feature = "sampling")]
+    pub fn mmap2(mut self, mmap2: bool) -> Builder<'a> {
+        self.attrs.set_mmap2(mmap2.into());
+        self
+    }
+
+    /// Wake up readers waiting for records, like
+    /// [`Sampler::next_blocking`], after every `events` samples.
+    ///
+    /// Waking less often saves system calls and context switches, at the
+    /// cost of latency. [`build_sampler`] sets this to one unless this or
+    /// [`wakeup_watermark`] has been called. This replaces any earlier call
+    /// to `wakeup_watermark`. If `events` is zero, the kernel wakes readers
+    /// only when the ring buffer is half full.
+    ///
+    /// [`Sampler::next_blocking`]: samples::Sampler::next_blocking
+    /// [`build_sampler`]: Builder::build_sampler
+    /// [`wakeup_watermark`]: Builder::wakeup_watermark
+    #[cfg(feature = "sampling")]
+    pub fn wakeup_events(mut self, events: u32) -> Builder<'a> {
+        self.attrs.set_watermark(0);
+        self.attrs.set_wakeup_events(events);
+        self.wakeup = true;
+        self
+    }
+
+    /// Wake up readers waiting for records once at least `bytes` bytes of
+    /// records are waiting in the ring buffer.
+    ///
+    /// This replaces any earlier call to [`wakeup_events`]; see that method
+    /// for details.
+    ///
+    /// [`wakeup_events`]: Builder::wakeup_events
+    #[cfg(feature = "sampling")]
+    pub fn wakeup_watermark(mut self, bytes: u32) -> Builder<'a> {
+        self.attrs.set_watermark(1);
+        self.attrs.set_wakeup_watermark(bytes);
+        self.wakeup = true;
+        self
+    }
+
+    /// Send a [`RecordEvent::Aux`] record, waking up readers, once at least
+    /// `bytes` bytes of data are waiting in an [`AuxSampler`]'s AUX area.
+    ///
+    /// If this is zero, which is the default, the kernel uses half the AUX
+    /// area's size. This requires Linux 4.1 or later.
+    ///
+    /// [`RecordEvent::Aux`]: samples::RecordEvent::Aux
+    /// [`AuxSampler`]: samples::AuxSampler
+    #[cfg(feature = "sampling")]
+    pub fn aux_watermark(mut self, bytes: u32) -> Builder<'a> {
+        self.attrs.aux_watermark = bytes;
+        self
+    }
+
+    /// Write this event's samples into the AUX area of its group's leader,
+    /// a hardware tracing event, rather than into a ring buffer of its own.
+    /// The default is `false`.
+    ///
+    /// This lets the samples be placed precisely within the leader's trace:
+    /// on Intel processors, for example, a PEBS event in a group led by an
+    /// `intel_pt` event can have its samples recorded in the Intel PT
+    /// stream. The leader's ring buffer then receives a
+    /// [`RecordEvent::AuxOutputHwId`] record giving the id that marks this
+    /// event's data in the stream.
+    ///
+    /// The event must belong to a [`Group`] whose leader is a tracing event
+    /// with an AUX area, like `intel_pt`, and the PMUs must support the
+    /// combination; otherwise, the kernel rejects the event with `EINVAL`
+    /// or `EOPNOTSUPP`. This requires Linux 5.4 or later.
+    ///
+    /// [`RecordEvent::AuxOutputHwId`]: samples::RecordEvent::AuxOutputHwId
+    #[cfg(feature = "sampling")]
+    pub fn aux_output(mut self, aux_output: bool) -> Builder<'a> {
+        self.attrs.set_aux_output(aux_output.into());
+        self
+    }
+
+    /// Set the size of the sampler's ring buffer. The default is 16 pages of
+    /// data, plus a page of metadata.
+    ///
+    /// A bigger buffer loses fewer records when they arrive in bursts, or
+    /// when the reader falls behind. If the kernel won't let this process
+    /// lock that much memory, [`build_sampler`] returns an error whose inner
+    /// error is a [`BufferTooLarge`], explaining the limit.
+    ///
+    /// This only has an effect on events built with [`build_sampler`].
+    ///
+    /// [`build_sampler`]: Builder::build_sampler
+    /// [`BufferTooLarge`]: samples::BufferTooLarge
+    #[cfg(feature = "sampling")]
+    pub fn buffer_size(mut self, size: samples::BufferSize) -> Builder<'a> {
+        self.buffer = Some(size);
+        self
+    }
+
+    /// Make the sampler's ring buffer a flight recorder, which always holds
+    /// the most recent records. The default is `false`.
+    ///
+    /// Normally, when a [`Sampler`]'s ring buffer is full, the kernel drops
+    /// new records until you read some. With `overwrite` set, the kernel
+    /// writes records backwards through a read-only buffer, overwriting the
+    /// oldest ones, and you read them with [`Sampler::snapshot`] rather than
+    /// [`Sampler::next`]. This requires Linux 4.7 or later.
+    ///
+    /// This only has an effect on events built with [`build_sampler`].
+    ///
+    /// [`Sampler`]: samples::Sampler
+    /// [`Sampler::snapshot`]: samples::Sampler::snapshot
+    /// [`Sampler::next`]: samples::Sampler::next
+    /// [`build_sampler`]: Builder::build_sampler
+    #[cfg(feature = "sampling")]
+    pub fn overwrite(mut self, overwrite: bool) -> Builder<'a> {
+        self.attrs.set_write_backward(overwrite.into());
+        self
+    }
+
+    /// Write this event's records into `sampler`'s ring buffer, rather than
+    /// a buffer of its own.
+    ///
+    /// Once built, the `Counter` has no buffer to read: its samples appear
+    /// among `sampler`'s records, via `PERF_EVENT_IOC_SET_OUTPUT`. This lets
+    /// a single [`Sampler`] collect samples from many events. The kernel
+    /// requires the two events to be observing the same thread, or to be on
+    /// the same CPU; otherwise, [`build`] fails with `EINVAL`. (The
+    /// `PERF_FLAG_FD_OUTPUT` flag to `perf_event_open` was meant to do the
+    /// same thing, but has been broken since Linux 2.6.35.)
+    ///
+    /// Since the records share `sampler`'s [`ParseConfig`], this event
+    /// should request the same [`SampleType`] values as `sampler`. To tell
+    /// the events' samples apart, request [`SampleType::ID`] in both, and
+    /// compare [`Sample::id`] with each event's [`Counter::id`]:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///     use perf_event::samples::SampleType;
+    ///
+    ///     let mut clock = Builder::new()
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .sample_period(100_000)
+    ///         .sample(SampleType::ID | SampleType::IP)
+    ///         .build_sampler()?;
+    ///     let mut faults = Builder::new()
+    ///         .kind(Software::PAGE_FAULTS)
+    ///         .sample_period(1)
+    ///         .sample(SampleType::ID | SampleType::IP)
+    ///         .output_to(&clock)
+    ///         .build()?;
+    ///     # Ok(()) }
+    ///
+    /// [`Sampler`]: samples::Sampler
+    /// [`ParseConfig`]: samples::ParseConfig
+    /// [`SampleType`]: samples::SampleType
+    /// [`SampleType::ID`]: samples::SampleType::ID
+    /// [`Sample::id`]: samples::Sample::id
+    /// [`build`]: Builder::build
+    #[cfg(feature = "sampling")]
+    pub fn output_to(mut self, sampler: &'a samples::Sampler) -> Builder<'a> {
+        self.output = Some(sampler);
+        self
+    }
+
+    /// Construct a [`Counter`] according to the specifications made on this
+    /// `Builder`.
+    ///
+    /// A freshly built `Counter` is disabled. To begin counting events, you
+    /// must call [`enable`] on the `Counter` or the `Group` to which it belongs.
+    ///
+    /// If the `Builder` requests features that the running kernel does not
+    /// support, it returns `Err(e)` where `e.kind() == ErrorKind::Other` and
+    /// `e.raw_os_error() == Some(libc::E2BIG)`. If the kernel doesn't recognize
+    /// the requested event at all, it returns `Err(e)` where `e.kind() ==
+    /// ErrorKind::Unsupported`, wrapping an [`UnsupportedEvent`].
+    ///
+    /// Unfortunately, problems in counter configuration are detected at this
+    /// point, by the kernel, not earlier when the offending request is made on
+    /// the `Builder`. The kernel's returned errors are not always helpful.
+    /// This checks for a few problems itself, returning an
+    /// `ErrorKind::InvalidInput` error rather than the kernel's bare
+    /// `EINVAL`: [`any_pid`] without [`one_cpu`], breakpoints that fail
+    /// [`Breakpoint::validate`], and both [`sample_period`] and
+    /// [`sample_frequency`].
+    ///
+    /// [`Counter`]: struct.Counter.html
+    /// [`enable`]: struct.Counter.html#method.enable
+    /// [`UnsupportedEvent`]: events::UnsupportedEvent
+    /// [`any_pid`]: Builder::any_pid
+    /// [`one_cpu`]: Builder::one_cpu
+    /// [`Breakpoint::validate`]: events::Breakpoint::validate
+    /// [`sample_period`]: Builder::sample_period
+    /// [`sample_frequency`]: Builder::sample_frequency
+    pub fn build(mut self) -> std::io::Result<Counter> {
+        let (pid, flags, cpu) = match self.group {
+            // Members must observe whatever their group does. They may
+            // narrow a group observing all CPUs down to one, though.
+            Some(ref group) => {
+                let (pid, flags) = group.target();
+                let cpu = self.cpu.map_or(group.cpu, |cpu| cpu as c_int);
+                (pid, flags, cpu)
+            }
+            None => {
+                let (pid, flags) = self.who.as_args()?;
+                (pid, flags, self.target_cpu()?)
+            }
         };
-        let (pid, flags) = self.who.as_args();
+        self.check_attrs()?;
         let group_fd = match self.group {
             Some(ref mut g) => {
                 g.max_members += 1;
@@ -658,18 +1996,430 @@ impl<'a> Builder<'a> {
         };
 
         let file = unsafe {
-            File::from_raw_fd(check_errno_syscall(|| {
-                sys::perf_event_open(&mut self.attrs, pid, cpu, group_fd, flags as c_ulong)
-            })?)
+            File::from_raw_fd(
+                check_errno_syscall(|| {
+                    sys::perf_event_open(&mut self.attrs, pid, cpu, group_fd, flags as c_ulong)
+                })
+                .map_err(|e| self.describe_open_error(e))?,
+            )
         };
+        if self.group.is_none() {
+            self.who.check_pid(pid)?;
+        }
 
         // If we're going to be part of a Group, retrieve the ID the kernel
         // assigned us, so we can find our results in a Counts structure. Even
         // if we're not part of a group, we'll use it in `Debug` output.
-        let mut id = 0_u64;
-        check_errno_syscall(|| unsafe { sys::ioctls::ID(file.as_raw_fd(), &mut id) })?;
+        let id = ioctl::id(&file)?;
+
+        #[cfg(feature = "sampling")]
+        if let Some(sampler) = self.output {
+            ioctl::set_output(&file, Some(sampler.counter()))?;
+        }
 
-        Ok(Counter { file, id })
+        Ok(Counter {
+            file,
+            id,
+            has_times: self.attrs.read_format & TIME_FORMAT == TIME_FORMAT,
+            reads_group: self.attrs.read_format & sys::bindings::PERF_FORMAT_GROUP as u64 != 0,
+            has_id: self.attrs.read_format & sys::bindings::PERF_FORMAT_ID as u64 != 0,
+            event_data: self.event_data.take(),
+            event: Some(self.event),
+        })
+    }
+
+    /// Construct a [`Counter`] as [`build`] does, but classify any error as
+    /// an [`Error`], whose message explains the likely cause.
+    ///
+    /// If the kernel denies permission, the error includes the
+    /// `perf_event_paranoid` level this `Builder` needs; see
+    /// [`paranoid::Level::required_for`].
+    ///
+    /// [`build`]: Builder::build
+    pub fn try_build(self) -> Result<Counter, Error> {
+        let required = paranoid::Level::required_for(&self);
+        self.build()
+            .map_err(|e| Error::from(e).with_required_level(required))
+    }
+
+    /// Construct a [`UserReadCounter`] according to the specifications made
+    /// on this `Builder`: a counter whose value the calling thread can read
+    /// without a system call, when the kernel and processor allow it.
+    ///
+    /// The counter must observe the calling thread on any CPU, as a new
+    /// `Builder` does, and must not [`inherit`]; otherwise this returns an
+    /// `ErrorKind::InvalidInput` error. See the [`user_read`] module for
+    /// details.
+    ///
+    /// Like a freshly built `Counter`, a freshly built `UserReadCounter` is
+    /// disabled.
+    ///
+    /// [`UserReadCounter`]: user_read::UserReadCounter
+    /// [`inherit`]: Builder::inherit
+    pub fn build_user_read(self) -> io::Result<user_read::UserReadCounter> {
+        let observes_self = match self.group {
+            Some(ref group) => group.target() == (0, 0) && self.cpu.is_none() && group.cpu == -1,
+            None => matches!(self.who, EventPid::ThisProcess) && self.cpu.is_none(),
+        };
+        if !observes_self || self.attrs.inherit() != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "build_user_read needs a counter observing only the calling thread, on any CPU",
+            ));
+        }
+        user_read::UserReadCounter::new(self.build()?)
+    }
+
+    /// Construct a [`Sampler`] according to the specifications made on this
+    /// `Builder`: a counter with a ring buffer into which the kernel writes
+    /// records. Use [`sample_period`] or [`sample_frequency`] to say how
+    /// often to take samples.
+    ///
+    /// Unless [`wakeup_events`] or [`wakeup_watermark`] has been called,
+    /// this sets the event's `wakeup_events` to one, so that
+    /// [`Sampler::next_blocking`] wakes up as soon as each record arrives.
+    ///
+    /// If the samples include [`SampleType::READ`] and the event belongs to
+    /// a [`Group`], each sample holds the values of every counter in the
+    /// group. The resulting `Sampler` can't be read on its own; use
+    /// [`Group::read`] instead.
+    ///
+    /// The ring buffer holds 16 pages of records, unless [`buffer_size`]
+    /// says otherwise.
+    ///
+    /// Like a freshly built `Counter`, a freshly built `Sampler` is disabled.
+    ///
+    /// This is only available when the `"sampling"` feature is enabled.
+    ///
+    /// [`Sampler`]: samples::Sampler
+    /// [`Sampler::next_blocking`]: samples::Sampler::next_blocking
+    /// [`buffer_size`]: Builder::buffer_size
+    /// [`sample_period`]: Builder::sample_period
+    /// [`sample_frequency`]: Builder::sample_frequency
+    /// [`wakeup_events`]: Builder::wakeup_events
+    /// [`wakeup_watermark`]: Builder::wakeup_watermark
+    /// [`SampleType::READ`]: samples::SampleType::READ
+    #[cfg(feature = "sampling")]
+    pub fn build_sampler(mut self) -> io::Result<samples::Sampler> {
+        if !self.wakeup {
+            self.attrs.set_wakeup_events(1);
+        }
+        if self.attrs.sample_type & samples::SampleType::READ.bits() != 0 {
+            // Identify each value, so that `ReadValues::to_counts` works.
+            self.attrs.read_format |= sys::bindings::PERF_FORMAT_ID as u64;
+            if self.group.is_some() {
+                self.attrs.read_format |= sys::bindings::PERF_FORMAT_GROUP as u64;
+            }
+        }
+        let counts_leader = self.group.as_ref().is_some_and(|group| group.counts_leader);
+        let config =
+            samples::ParseConfig::from_attrs(&self.attrs).with_counts_leader(counts_leader);
+        let overwrite = self.attrs.write_backward() != 0;
+        let inherit_any_cpu = self.attrs.inherit() != 0 && self.cpu.is_none();
+        let buffer = self.buffer.unwrap_or_default();
+        let counter = self.build()?;
+        samples::Sampler::new(counter, config, buffer, overwrite).map_err(|e| {
+            if inherit_any_cpu && e.raw_os_error() == Some(libc::EINVAL) {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the kernel can't map the ring buffer of an inherited event on every CPU; \
+                     use `one_cpu`, or see `process::ProcessSampler`",
+                )
+            } else {
+                e
+            }
+        })
+    }
+
+    /// Construct a [`Sampler`] for each online CPU, observing every process
+    /// running there, and return them as a [`SamplerSet`], for system-wide
+    /// profiling like `perf record -a`.
+    ///
+    /// Each sampler is built as [`build_sampler`] would, with the
+    /// [`any_pid`] and [`one_cpu`] settings that select a CPU; the process,
+    /// cgroup, and CPU this `Builder` specifies are ignored. The sampler at
+    /// index `i` in the set observes the `i`'th CPU that
+    /// [`per_cpu::online_cpus`] returns. The samplers are disabled.
+    ///
+    /// Observing every process needs `perf_event_paranoid` to be `0` or
+    /// less, or the `CAP_PERFMON` capability. If neither holds, this
+    /// returns an error of kind [`io::ErrorKind::PermissionDenied`] saying
+    /// what to change, without opening anything.
+    ///
+    /// Since an unprivileged process may only lock a limited amount of ring
+    /// buffer per CPU (see [`samples::mlock_limit_kb`]), each sampler's
+    /// buffer is made small enough to fit within that limit, unless
+    /// [`buffer_size`] has been called.
+    ///
+    /// This returns an error of kind [`io::ErrorKind::InvalidInput`] if
+    /// [`group`] or [`output_to`] has been called.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///     use perf_event::samples::SampleType;
+    ///
+    ///     let mut set = Builder::new()
+    ///         .kind(Software::CPU_CLOCK)
+    ///         .sample_frequency(1000)
+    ///         .sample(SampleType::IP | SampleType::TID | SampleType::CPU)
+    ///         .build_sampler_on_cpus()?;
+    ///     set.enable()?;
+    ///     // ...
+    ///     set.disable()?;
+    ///     while let Some((index, record)) = set.next() {
+    ///         println!("{:?}", record.parse(set.samplers()[index].config()));
+    ///     }
+    ///     # Ok(()) }
+    ///
+    /// This is only available when the `"sampler_set"` feature is enabled.
+    ///
+    /// [`Sampler`]: samples::Sampler
+    /// [`SamplerSet`]: sampler_set::SamplerSet
+    /// [`build_sampler`]: Builder::build_sampler
+    /// [`any_pid`]: Builder::any_pid
+    /// [`one_cpu`]: Builder::one_cpu
+    /// [`group`]: Builder::group
+    /// [`output_to`]: Builder::output_to
+    /// [`buffer_size`]: Builder::buffer_size
+    #[cfg(feature = "sampler_set")]
+    pub fn build_sampler_on_cpus(mut self) -> io::Result<sampler_set::SamplerSet> {
+        if self.group.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "build_sampler_on_cpus can't build samplers in a Group",
+            ));
+        }
+        if self.output.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "build_sampler_on_cpus can't redirect samplers' output",
+            ));
+        }
+
+        self.who = EventPid::Any;
+        let required = paranoid::Level::required_for(&self);
+        if let Ok(current) = paranoid::Level::current() {
+            if !current.permits(required) && !paranoid::Level::privileged() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!(
+                        "can't sample every process on each CPU: {}",
+                        required.instructions()
+                    ),
+                ));
+            }
+        }
+
+        let buffer = self.buffer.unwrap_or_else(samples::buffer_size_per_cpu);
+        let Builder {
+            attrs,
+            event,
+            event_data,
+            sampling,
+            wakeup,
+            ..
+        } = self;
+        let mut set = sampler_set::SamplerSet::new();
+        for cpu in per_cpu::online_cpus()? {
+            let sampler = Builder {
+                attrs,
+                who: EventPid::Any,
+                cpu: Some(cpu),
+                group: None,
+                event: event.clone(),
+                event_data: event_data.clone(),
+                sampling,
+                wakeup,
+                output: None,
+                buffer: Some(buffer),
+            }
+            .build_sampler()?;
+            set.push_on_cpu(sampler, cpu);
+        }
+        // `attrs` may point into `event_data`, so keep it until we're done.
+        drop(event_data);
+        Ok(set)
+    }
+
+    /// Construct an [`AuxSampler`]: a [`Sampler`] whose event also writes
+    /// data to an AUX area of `aux_pages` pages, which must be a power of
+    /// two.
+    ///
+    /// This is for hardware tracing PMUs, like `intel_pt` and `cs_etm`,
+    /// which you can select with [`events::Pmu`]. If the event's PMU doesn't
+    /// support AUX areas, this returns an `EOPNOTSUPP` error.
+    ///
+    /// [`AuxSampler`]: samples::AuxSampler
+    /// [`Sampler`]: samples::Sampler
+    #[cfg(feature = "sampling")]
+    pub fn build_aux_sampler(self, aux_pages: usize) -> io::Result<samples::AuxSampler> {
+        samples::AuxSampler::new(self.build_sampler()?, aux_pages)
+    }
+
+    /// Construct an empty [`Group`] that observes the process, cgroup, and
+    /// CPU this `Builder` specifies, rather than [`Group::new`]'s default of
+    /// this process on any CPU. Only those settings matter; the event kind
+    /// and everything else is ignored.
+    ///
+    /// Counters built into the group observe the same process and CPU as
+    /// the group, regardless of their own `Builder`'s settings, except that
+    /// a member of a group observing all CPUs may use [`one_cpu`] to choose
+    /// just one. For example, to count events in another process as a
+    /// group:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     # let pid = std::process::id() as libc::pid_t;
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///
+    ///     let mut group = Builder::new().observe_pid(pid).build_group()?;
+    ///     let clock = Builder::new()
+    ///         .group(&mut group)
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .build()?;
+    ///     let faults = Builder::new()
+    ///         .group(&mut group)
+    ///         .kind(Software::PAGE_FAULTS)
+    ///         .build()?;
+    ///     # Ok(()) }
+    ///
+    /// A system-wide group must choose a CPU, as with [`build`].
+    ///
+    /// [`build`]: Builder::build
+    /// [`one_cpu`]: Builder::one_cpu
+    pub fn build_group(self) -> io::Result<Group> {
+        let cpu = self.target_cpu()?;
+        let (pid, _flags) = self.who.as_args()?;
+        let cgroup = match self.who {
+            EventPid::CGroup(file) => Some(file.try_clone()?),
+            EventPid::OwnedCGroup(ref file) => Some(file.try_clone()?),
+            _ => None,
+        };
+        let group = Group::open_placeholder(pid, cpu, cgroup)?;
+        self.who.check_pid(pid)?;
+        Ok(group)
+    }
+
+    /// Construct a [`Group`] whose leader is the event this `Builder`
+    /// describes, rather than a placeholder, along with a [`Counter`] for
+    /// the leader.
+    ///
+    /// A group built with [`build_group`] or [`Group::new`] has a dummy
+    /// software event as its leader, which counts nothing. That's
+    /// convenient, but it's not how `perf` itself sets up groups, and some
+    /// PMUs give special treatment to the leader. A group built this way
+    /// uses its first event as the leader, and [`Counts`] include its
+    /// value:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///
+    ///     let (mut group, clock) = Builder::new()
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .build_group_leader()?;
+    ///     let faults = Builder::new()
+    ///         .group(&mut group)
+    ///         .kind(Software::PAGE_FAULTS)
+    ///         .build()?;
+    ///
+    ///     group.enable()?;
+    ///     let v: Vec<u8> = vec![1; 1 << 20];
+    ///     group.disable()?;
+    ///
+    ///     let counts = group.read()?;
+    ///     println!("{} page faults in {}ns", counts[&faults], counts[&clock]);
+    ///     # drop(v);
+    ///     # Ok(()) }
+    ///
+    /// The group observes the process and CPU this `Builder` specifies, as
+    /// with [`build_group`]. The returned `Counter` refers to the same event
+    /// as the `Group`, so reading it reads the whole group; use
+    /// [`Group::read`] and index the result with the `Counter` instead.
+    ///
+    /// This checks the event for the same problems [`build`] does, and
+    /// explains the kernel's errors the same way.
+    ///
+    /// [`build_group`]: Builder::build_group
+    /// [`build`]: Builder::build
+    pub fn build_group_leader(self) -> io::Result<(Group, Counter)> {
+        if self.group.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a group's leader can't belong to another group",
+            ));
+        }
+        self.check_attrs()?;
+        let cpu = self.target_cpu()?;
+        let (pid, _flags) = self.who.as_args()?;
+        let cgroup = match self.who {
+            EventPid::CGroup(file) => Some(file.try_clone()?),
+            EventPid::OwnedCGroup(ref file) => Some(file.try_clone()?),
+            _ => None,
+        };
+        let group = Group::open(self.attrs, true, pid, cpu, cgroup)
+            .map_err(|e| self.describe_open_error(e))?;
+        self.who.check_pid(pid)?;
+        let leader = Counter {
+            file: group.file.try_clone()?,
+            id: group.id,
+            has_times: true,
+            reads_group: true,
+            has_id: true,
+            event_data: self.event_data,
+            event: Some(self.event),
+        };
+        Ok((group, leader))
+    }
+
+    /// Return the `cpu` argument for `perf_event_open`, checking that it
+    /// makes sense with the process we're observing.
+    fn target_cpu(&self) -> io::Result<c_int> {
+        match (self.cpu, &self.who) {
+            (Some(cpu), _) => Ok(cpu as c_int),
+            (None, EventPid::Any) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "observing any process requires choosing a CPU; \
+                 see Builder::system_wide_on_cpu",
+            )),
+            (None, _) => Ok(-1),
+        }
+    }
+
+    /// Check for the problems [`build`] promises to report itself, rather
+    /// than leaving them to the kernel's bare `EINVAL`.
+    ///
+    /// [`build`]: Builder::build
+    fn check_attrs(&self) -> io::Result<()> {
+        events::Breakpoint::check_attrs(&self.attrs)?;
+        if self.sampling.period && self.sampling.frequency {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sample_period and sample_frequency can't be used together",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Explain `error`, returned by `perf_event_open` for our event, as well
+    /// as we can.
+    fn describe_open_error(&self, error: io::Error) -> io::Error {
+        let error = self.describe_pmu_error(error);
+        events::UnsupportedEvent::check(&self.attrs, error)
+    }
+
+    /// If `error` is a dynamic PMU's rejection of our event, add the PMU's
+    /// description of its events from sysfs; see [`events::PmuEventError`].
+    #[cfg(feature = "discovery")]
+    fn describe_pmu_error(&self, error: io::Error) -> io::Error {
+        events::PmuEventError::check(&self.attrs, error)
+    }
+
+    /// Without the `discovery` feature, we don't consult sysfs.
+    #[cfg(not(feature = "discovery"))]
+    fn describe_pmu_error(&self, error: io::Error) -> io::Error {
+        error
     }
 }
 
@@ -683,6 +2433,189 @@ impl Counter {
         self.id
     }
 
+    /// Return the event this counter counts, as passed to [`Builder::kind`].
+    ///
+    /// For a sampler, this is the event it samples. Counters made with
+    /// [`from_owned_fd`] don't know their event, and return `None`.
+    ///
+    /// [`from_owned_fd`]: Counter::from_owned_fd
+    pub fn event(&self) -> Option<&Event> {
+        self.event.as_ref()
+    }
+
+    /// Return the data this counter's event pointed to when it was opened,
+    /// if any.
+    ///
+    /// Some events pass the kernel a pointer to a string, like the name of
+    /// the function a [`KProbe`] probes. The `Counter` keeps the string for
+    /// as long as it lives, for debugging output. Counters for other events,
+    /// or made with [`from_owned_fd`], return `None`.
+    ///
+    /// [`KProbe`]: events::KProbe
+    /// [`from_owned_fd`]: Counter::from_owned_fd
+    pub fn event_data(&self) -> Option<&CStr> {
+        self.event_data.as_deref()
+    }
+
+    /// Return a new `Counter` for the same kernel counter as this one, with
+    /// a duplicate of its file descriptor, and the same [`id`].
+    ///
+    /// Since most of `Counter`'s methods take `&mut self`, this is the way
+    /// to share a counter between threads: for example, a reader thread
+    /// that samples the count periodically, and a controller thread that
+    /// enables, disables, and resets it:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///
+    ///     let mut controller = Builder::new().kind(Software::TASK_CLOCK).build()?;
+    ///     let mut reader = controller.try_clone()?;
+    ///     let reading = std::thread::spawn(move || reader.read());
+    ///
+    ///     controller.enable()?;
+    ///     let _spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+    ///     controller.disable()?;
+    ///     let count = reading.join().unwrap()?;
+    ///     # let _ = count;
+    ///     # Ok(()) }
+    ///
+    /// All the clones operate on the same counter, so enabling, disabling,
+    /// or resetting it through one affects them all. Each of those
+    /// operations, and each read, is a single system call that the kernel
+    /// applies atomically, so they are safe to perform concurrently from
+    /// different clones: a read sees the counter's state either before or
+    /// after a concurrent reset, never a mixture.
+    ///
+    /// The kernel counter lives until every clone has been dropped. In
+    /// particular, dropping one clone of a group member, or passing it to
+    /// [`Group::remove`], doesn't remove the member from its group while
+    /// other clones remain.
+    ///
+    /// [`id`]: Counter::id
+    pub fn try_clone(&self) -> io::Result<Counter> {
+        Ok(Counter {
+            file: self.file.try_clone()?,
+            id: self.id,
+            has_times: self.has_times,
+            reads_group: self.reads_group,
+            has_id: self.has_id,
+            event_data: self.event_data.clone(),
+            event: self.event.clone(),
+        })
+    }
+
+    /// Return a `Counter` for the perf event whose file descriptor is `fd`,
+    /// as obtained from [`IntoRawFd`] or `OwnedFd::from` on some other
+    /// `Counter`, or from another program or library.
+    ///
+    /// The kernel can't say what `read_format` the event was opened with,
+    /// and the length of a read doesn't settle it either, so the caller
+    /// must pass it in. For a `Counter` from this crate, that's what
+    /// [`read_format`] returns:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::{Builder, Counter};
+    ///     use perf_event::events::Software;
+    ///     use std::os::unix::io::OwnedFd;
+    ///
+    ///     let counter = Builder::new().kind(Software::TASK_CLOCK).build()?;
+    ///     let read_format = counter.read_format();
+    ///     let fd = OwnedFd::from(counter);
+    ///     // ... pass `fd` and `read_format` somewhere else ...
+    ///     let counter = Counter::from_owned_fd(fd, read_format)?;
+    ///     # Ok(()) }
+    ///
+    /// `read_format` may hold `PERF_FORMAT_TOTAL_TIME_ENABLED` and
+    /// `PERF_FORMAT_TOTAL_TIME_RUNNING` (both or neither),
+    /// `PERF_FORMAT_ID`, and `PERF_FORMAT_GROUP`. For other formats, this
+    /// returns an error of kind [`io::ErrorKind::InvalidInput`].
+    ///
+    /// This asks the kernel for the event's id, and reads the event once to
+    /// check that the data is as long as `read_format` says. If `fd` isn't
+    /// a perf event, or the lengths disagree, this returns an error.
+    ///
+    /// [`read_format`]: Counter::read_format
+    pub fn from_owned_fd(fd: OwnedFd, read_format: u64) -> io::Result<Counter> {
+        use sys::bindings::{PERF_FORMAT_GROUP, PERF_FORMAT_ID};
+
+        let supported = TIME_FORMAT | PERF_FORMAT_ID as u64 | PERF_FORMAT_GROUP as u64;
+        let times = read_format & TIME_FORMAT;
+        if read_format & !supported != 0 || (times != 0 && times != TIME_FORMAT) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("perf event read_format {:#x} is not supported", read_format),
+            ));
+        }
+        let has_times = times != 0;
+        let reads_group = read_format & PERF_FORMAT_GROUP as u64 != 0;
+        let has_id = read_format & PERF_FORMAT_ID as u64 != 0;
+
+        let mut file = File::from(fd);
+        let id = ioctl::id(&file)?;
+
+        // A group's read grows with its membership; make room as needed.
+        let mut buf = vec![0_u64; 8];
+        let len = loop {
+            match file.read(u64::slice_as_bytes_mut(&mut buf)) {
+                Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => {
+                    let longer = buf.len() * 2;
+                    buf.resize(longer, 0);
+                }
+                result => break result?,
+            }
+        };
+
+        // A counter in an error state reads as end-of-file, which tells us
+        // nothing either way.
+        let words = if reads_group {
+            1 + 2 * has_times as usize + buf[0] as usize * (1 + has_id as usize)
+        } else {
+            1 + 2 * has_times as usize + has_id as usize
+        };
+        if len != 0 && len != words * 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "perf event's reads are {} bytes long, not the {} that read_format {:#x} implies",
+                    len,
+                    words * 8,
+                    read_format
+                ),
+            ));
+        }
+
+        Ok(Counter {
+            file,
+            id,
+            has_times,
+            reads_group,
+            has_id,
+            event_data: None,
+            event: None,
+        })
+    }
+
+    /// Return the `read_format` bits this counter was opened with, as
+    /// [`from_owned_fd`] expects them.
+    ///
+    /// [`from_owned_fd`]: Counter::from_owned_fd
+    pub fn read_format(&self) -> u64 {
+        use sys::bindings::{PERF_FORMAT_GROUP, PERF_FORMAT_ID};
+
+        let mut format = 0;
+        if self.has_times {
+            format |= TIME_FORMAT;
+        }
+        if self.has_id {
+            format |= PERF_FORMAT_ID as u64;
+        }
+        if self.reads_group {
+            format |= PERF_FORMAT_GROUP as u64;
+        }
+        format
+    }
+
     /// Allow this `Counter` to begin counting its designated event.
     ///
     /// This does not affect whatever value the `Counter` had previously; new
@@ -695,7 +2628,7 @@ impl Counter {
     /// [`reset`]: #method.reset
     /// [`enable`]: struct.Group.html#method.enable
     pub fn enable(&mut self) -> io::Result<()> {
-        check_errno_syscall(|| unsafe { sys::ioctls::ENABLE(self.file.as_raw_fd(), 0) }).map(|_| ())
+        ioctl::enable(&self.file, ioctl::Scope::Event)
     }
 
     /// Make this `Counter` stop counting its designated event. Its count is
@@ -706,8 +2639,7 @@ impl Counter {
     ///
     /// [`disable`]: struct.Group.html#method.disable
     pub fn disable(&mut self) -> io::Result<()> {
-        check_errno_syscall(|| unsafe { sys::ioctls::DISABLE(self.file.as_raw_fd(), 0) })
-            .map(|_| ())
+        ioctl::disable(&self.file, ioctl::Scope::Event)
     }
 
     /// Reset the value of this `Counter` to zero.
@@ -717,7 +2649,48 @@ impl Counter {
     ///
     /// [`reset`]: struct.Group.html#method.reset
     pub fn reset(&mut self) -> io::Result<()> {
-        check_errno_syscall(|| unsafe { sys::ioctls::RESET(self.file.as_raw_fd(), 0) }).map(|_| ())
+        ioctl::reset(&self.file, ioctl::Scope::Event)
+    }
+
+    /// Change this sampling counter's period to `period`, without
+    /// recreating it.
+    ///
+    /// If the counter was built with [`sample_frequency`], this sets the
+    /// frequency instead. The new value takes effect at the next overflow.
+    /// This is usually called on a sampler, which dereferences to its
+    /// `Counter`.
+    ///
+    /// The kernel rejects a `period` of zero, and counters that weren't
+    /// built to sample, with `EINVAL`.
+    ///
+    /// [`sample_frequency`]: Builder::sample_frequency
+    pub fn set_sample_period(&mut self, period: u64) -> io::Result<()> {
+        ioctl::period(&self.file, period)
+    }
+
+    /// Attach the BPF program `program` to this counter's event.
+    ///
+    /// `program` is the file descriptor of a loaded BPF program, as
+    /// returned by a BPF library like `libbpf-rs` or `aya`. The kernel runs
+    /// a `BPF_PROG_TYPE_KPROBE` or `BPF_PROG_TYPE_TRACEPOINT` program each
+    /// time a kprobe, uprobe, or tracepoint event is hit, and a
+    /// `BPF_PROG_TYPE_PERF_EVENT` program each time a sampling event
+    /// overflows. The kernel rejects other combinations with `EINVAL`.
+    ///
+    /// The program stays attached until the counter is closed. The kernel
+    /// allows attaching only one program to a sampling event, but several to
+    /// a tracing event.
+    pub fn attach_bpf<P: AsFd + ?Sized>(&mut self, program: &P) -> io::Result<()> {
+        ioctl::set_bpf(&self.file, &program.as_fd())
+    }
+
+    /// Return the ids of the BPF programs attached to this counter's event,
+    /// which must be a kprobe, uprobe, or tracepoint event.
+    ///
+    /// The kernel doesn't support this query for other events, and returns
+    /// `EINVAL`. It also requires `CAP_SYS_ADMIN`.
+    pub fn bpf_programs(&self) -> io::Result<Vec<u32>> {
+        ioctl::query_bpf(&self.file)
     }
 
     /// Return this `Counter`'s current value as a `u64`.
@@ -735,7 +2708,15 @@ impl Counter {
     /// [`read`]: Group::read
     /// [`read_count_and_time`]: Counter::read_count_and_time
     pub fn read(&mut self) -> io::Result<u64> {
-        Ok(self.read_count_and_time()?.count)
+        self.check_reads_self()?;
+        if self.has_times {
+            return Ok(self.read_count_and_time()?.count);
+        }
+        let mut buf = [0_u64; 2];
+        let len = 1 + self.has_id as usize;
+        self.file
+            .read_exact(u64::slice_as_bytes_mut(&mut buf[..len]))?;
+        Ok(buf[0])
     }
 
     /// Return this `Counter`'s current value and timesharing data.
@@ -756,26 +2737,35 @@ impl Counter {
     ///     # fn main() -> std::io::Result<()> {
     ///     # let mut counter = Builder::new().build()?;
     ///     let cat = counter.read_count_and_time()?;
-    ///     if cat.time_running == 0 {
-    ///         println!("No data collected.");
-    ///     } else if cat.time_running < cat.time_enabled {
-    ///         // Note: this way of scaling is accurate, but `u128` division
-    ///         // is usually implemented in software, which may be slow.
-    ///         println!("{} instructions (estimated)",
-    ///                  (cat.count as u128 *
-    ///                   cat.time_enabled as u128 / cat.time_running as u128) as u64);
-    ///     } else {
-    ///         println!("{} instructions", cat.count);
+    ///     match cat.scaled() {
+    ///         None => println!("No data collected."),
+    ///         Some(count) if cat.time_running < cat.time_enabled => {
+    ///             println!("{} instructions (estimated)", count)
+    ///         }
+    ///         Some(count) => println!("{} instructions", count),
     ///     }
     ///     # Ok(()) }
     ///
+    /// If this counter was built while [`BuilderDefaults::times`] was turned
+    /// off, this returns an `ErrorKind::InvalidInput` error.
+    ///
     /// Note that `Group` also has a [`read`] method, which reads all
     /// its member `Counter`s' values at once.
     ///
     /// [`read`]: Group::read
     pub fn read_count_and_time(&mut self) -> io::Result<CountAndTime> {
-        let mut buf = [0_u64; 3];
-        self.file.read_exact(u64::slice_as_bytes_mut(&mut buf))?;
+        self.check_reads_self()?;
+        if !self.has_times {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "counter was built without time tracking; see BuilderDefaults::times",
+            ));
+        }
+
+        let mut buf = [0_u64; 4];
+        let len = 3 + self.has_id as usize;
+        self.file
+            .read_exact(u64::slice_as_bytes_mut(&mut buf[..len]))?;
 
         let cat = CountAndTime {
             count: buf[0],
@@ -788,16 +2778,70 @@ impl Counter {
 
         Ok(cat)
     }
+
+    /// Reset this `Counter`, let it count for `duration`, and return its
+    /// final value, along with the time that actually passed.
+    ///
+    /// This blocks the calling thread. It uses [`std::thread::sleep`], which
+    /// resumes sleeping if a signal interrupts it, so the counter is always
+    /// enabled for at least `duration`.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///     use std::time::Duration;
+    ///
+    ///     let mut faults = Builder::new()
+    ///         .kind(Software::PAGE_FAULTS)
+    ///         .build()?;
+    ///     let measured = faults.measure_for(Duration::from_millis(10))?;
+    ///     println!("{} page faults in {:?}",
+    ///              measured.counts.count, measured.elapsed);
+    ///     # Ok(()) }
+    ///
+    /// Note that `Group` also has a [`measure_for`] method.
+    ///
+    /// [`measure_for`]: Group::measure_for
+    pub fn measure_for(&mut self, duration: Duration) -> io::Result<Measurement<CountAndTime>> {
+        self.reset()?;
+        self.enable()?;
+        let start = Instant::now();
+        std::thread::sleep(duration);
+        let elapsed = start.elapsed();
+        self.disable()?;
+        Ok(Measurement {
+            counts: self.read_count_and_time()?,
+            elapsed,
+        })
+    }
+}
+
+impl Counter {
+    /// Return an error if reading this counter would produce its whole
+    /// group's values, rather than its own.
+    fn check_reads_self(&self) -> io::Result<()> {
+        if self.reads_group {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "counter samples its whole group's values; use Group::read instead",
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl std::fmt::Debug for Counter {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             fmt,
-            "Counter {{ fd: {}, id: {} }}",
+            "Counter {{ fd: {}, id: {}",
             self.file.as_raw_fd(),
             self.id
-        )
+        )?;
+        if let Some(data) = &self.event_data {
+            write!(fmt, ", event_data: {:?}", data)?;
+        }
+        fmt.write_str(" }")
     }
 }
 
@@ -807,27 +2851,91 @@ impl AsRawFd for Counter {
     }
 }
 
+impl AsFd for Counter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
 impl IntoRawFd for Counter {
     fn into_raw_fd(self) -> RawFd {
         self.file.into_raw_fd()
     }
 }
 
+impl From<Counter> for OwnedFd {
+    fn from(counter: Counter) -> OwnedFd {
+        counter.file.into()
+    }
+}
+
 impl Group {
-    /// Construct a new, empty `Group`.
-    #[allow(unused_parens)]
+    /// Construct a new, empty `Group`, observing the calling process on any
+    /// CPU. To observe some other process or a particular CPU, use
+    /// [`Builder::build_group`].
     pub fn new() -> io::Result<Group> {
-        // Open a placeholder perf counter that we can add other events to.
+        Group::open_placeholder(0, -1, None)
+    }
+
+    /// Return a new `Group` for the same kernel group as this one, with a
+    /// duplicate of its leader's file descriptor.
+    ///
+    /// As with [`Counter::try_clone`], the clones share the group: enabling,
+    /// disabling, resetting, or reading through one is the same as through
+    /// any other, and each is safe to do concurrently with the others.
+    /// Members built with one clone are members of them all. The group's
+    /// leader lives until every clone has been dropped.
+    pub fn try_clone(&self) -> io::Result<Group> {
+        Ok(Group {
+            file: self.file.try_clone()?,
+            id: self.id,
+            max_members: self.max_members,
+            counts_leader: self.counts_leader,
+            pid: self.pid,
+            cpu: self.cpu,
+            cgroup: match self.cgroup {
+                Some(ref cgroup) => Some(cgroup.try_clone()?),
+                None => None,
+            },
+        })
+    }
+
+    /// Return the attributes for a placeholder group leader, which counts
+    /// nothing, but that we can add other events to.
+    fn placeholder() -> perf_event_attr {
         let mut attrs = perf_event_attr {
             size: std::mem::size_of::<perf_event_attr>() as u32,
             type_: sys::bindings::PERF_TYPE_SOFTWARE,
             config: sys::bindings::PERF_COUNT_SW_DUMMY as u64,
             ..perf_event_attr::default()
         };
-
-        attrs.set_disabled(1);
         attrs.set_exclude_kernel(1);
         attrs.set_exclude_hv(1);
+        attrs
+    }
+
+    /// Open a group with a placeholder leader, as for [`Group::open`].
+    fn open_placeholder(pid: pid_t, cpu: c_int, cgroup: Option<File>) -> io::Result<Group> {
+        let attrs = Group::placeholder();
+        Group::open(attrs, false, pid, cpu, cgroup)
+            .map_err(|e| events::UnsupportedEvent::check(&attrs, e))
+    }
+
+    /// Open a group leader for the event `attrs` with the given `pid` and
+    /// `cpu` arguments. If `cgroup` is given, observe that cgroup instead of
+    /// `pid`. If `counts_leader` is false, the leader is a placeholder, and
+    /// `Counts` should leave it out.
+    ///
+    /// Errors from `perf_event_open` are returned as they are; callers
+    /// explain them in terms of the event they asked for.
+    fn open(
+        mut attrs: perf_event_attr,
+        counts_leader: bool,
+        pid: pid_t,
+        cpu: c_int,
+        cgroup: Option<File>,
+    ) -> io::Result<Group> {
+        attrs.set_disabled(1);
 
         // Arrange to be able to identify the counters we read back.
         attrs.read_format = (sys::bindings::PERF_FORMAT_TOTAL_TIME_ENABLED
@@ -835,23 +2943,39 @@ impl Group {
             | sys::bindings::PERF_FORMAT_ID
             | sys::bindings::PERF_FORMAT_GROUP) as u64;
 
+        let (target, flags) = match cgroup {
+            Some(ref cgroup) => (cgroup.as_raw_fd(), sys::bindings::PERF_FLAG_PID_CGROUP),
+            None => (pid, 0),
+        };
         let file = unsafe {
             File::from_raw_fd(check_errno_syscall(|| {
-                sys::perf_event_open(&mut attrs, 0, -1, -1, 0)
+                sys::perf_event_open(&mut attrs, target, cpu, -1, flags as c_ulong)
             })?)
         };
 
         // Retrieve the ID the kernel assigned us.
-        let mut id = 0_u64;
-        check_errno_syscall(|| unsafe { sys::ioctls::ID(file.as_raw_fd(), &mut id) })?;
+        let id = ioctl::id(&file)?;
 
         Ok(Group {
             file,
             id,
             max_members: 1,
+            counts_leader,
+            pid,
+            cpu,
+            cgroup,
         })
     }
 
+    /// Return the `pid` argument and `flags` bits for opening this group's
+    /// members.
+    fn target(&self) -> (pid_t, u32) {
+        match self.cgroup {
+            Some(ref cgroup) => (cgroup.as_raw_fd(), sys::bindings::PERF_FLAG_PID_CGROUP),
+            None => (self.pid, 0),
+        }
+    }
+
     /// Allow all `Counter`s in this `Group` to begin counting their designated
     /// events, as a single atomic operation.
     ///
@@ -861,28 +2985,18 @@ impl Group {
     ///
     /// [`reset`]: #method.reset
     pub fn enable(&mut self) -> io::Result<()> {
-        self.generic_ioctl(sys::ioctls::ENABLE)
+        ioctl::enable(&self.file, ioctl::Scope::Group)
     }
 
     /// Make all `Counter`s in this `Group` stop counting their designated
     /// events, as a single atomic operation. Their counts are unaffected.
     pub fn disable(&mut self) -> io::Result<()> {
-        self.generic_ioctl(sys::ioctls::DISABLE)
+        ioctl::disable(&self.file, ioctl::Scope::Group)
     }
 
     /// Reset all `Counter`s in this `Group` to zero, as a single atomic operation.
     pub fn reset(&mut self) -> io::Result<()> {
-        self.generic_ioctl(sys::ioctls::RESET)
-    }
-
-    /// Perform some group ioctl.
-    ///
-    /// `f` must be a syscall that sets `errno` and returns `-1` on failure.
-    fn generic_ioctl(&mut self, f: unsafe fn(c_int, c_uint) -> c_int) -> io::Result<()> {
-        check_errno_syscall(|| unsafe {
-            f(self.file.as_raw_fd(), sys::bindings::PERF_IOC_FLAG_GROUP)
-        })
-        .map(|_| ())
+        ioctl::reset(&self.file, ioctl::Scope::Group)
     }
 
     /// Return the values of all the `Counter`s in this `Group` as a [`Counts`]
@@ -917,15 +3031,33 @@ impl Group {
         //             u64 id;        /* if PERF_FORMAT_ID */
         //         } values[nr];
         //     };
-        let mut data = vec![0_u64; 3 + 2 * self.max_members];
-        assert_eq!(
-            self.file.read(u64::slice_as_bytes_mut(&mut data))?,
-            std::mem::size_of_val(&data[..])
-        );
+        //
+        // If members have been dropped since the last read, `max_members`
+        // overestimates `nr`, and the kernel fills only the front of our
+        // buffer. If it underestimates `nr`, the kernel returns `ENOSPC`, and
+        // we try again with a larger buffer.
+        let mut data = loop {
+            let mut data = vec![0_u64; 3 + 2 * self.max_members];
+            match self.file.read(u64::slice_as_bytes_mut(&mut data)) {
+                Ok(len) => {
+                    let nr = data[0] as usize;
+                    assert_eq!(len, std::mem::size_of::<u64>() * (3 + 2 * nr));
+                    break data;
+                }
+                Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => {
+                    self.max_members *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        data.truncate(3 + 2 * data[0] as usize);
 
-        let counts = Counts { data };
+        let counts = Counts {
+            data,
+            first: if self.counts_leader { 0 } else { 1 },
+        };
 
-        // CountsIter assumes that the group's dummy count appears first.
+        // CountsIter assumes that the group's leader appears first.
         assert_eq!(counts.nth_ref(0).0, self.id);
 
         // Does the kernel ever return nonsense?
@@ -936,6 +3068,111 @@ impl Group {
 
         Ok(counts)
     }
+
+    /// Remove `member` from this group, and close it.
+    ///
+    /// The kernel has no way to move an event out of its group; the only
+    /// way to remove it is to close it. So this takes ownership of `member`
+    /// and drops it. Simply dropping a `Counter` has the same effect, but
+    /// this also checks that `member` belongs to the group, and keeps the
+    /// group's estimate of its size exact, so later reads allocate no more
+    /// than they need.
+    ///
+    /// If `member` doesn't belong to this group, or is the group's leader,
+    /// this returns an `ErrorKind::InvalidInput` error, and `member` is
+    /// dropped all the same.
+    pub fn remove(&mut self, member: Counter) -> io::Result<()> {
+        if member.id == self.id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't remove a group's leader",
+            ));
+        }
+
+        // Reading brings `max_members` up to date.
+        if !self.read()?.iter().any(|(id, _)| id == member.id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("counter {} is not a member of this group", member.id),
+            ));
+        }
+
+        drop(member);
+        self.max_members -= 1;
+        Ok(())
+    }
+
+    /// Return the values of all the `Counter`s in this `Group`, each with its
+    /// own `time_enabled` and `time_running`, as a [`GroupData`].
+    ///
+    /// The kernel reports only the group leader's times in a group read.
+    /// Since a group's members go on and off the processor together, that's
+    /// usually enough; but a member added after the group was enabled, or
+    /// enabled and disabled on its own, has been enabled for less time than
+    /// the group, and scaling its value by the group's times overestimates
+    /// it.
+    ///
+    /// So for each counter in `members`, this also reads the counter's own
+    /// times. The values all still come from a single atomic read of the
+    /// group. Members not in `members`, and counters built while
+    /// [`BuilderDefaults::times`] was turned off, get the group's times.
+    ///
+    /// If some counter in `members` doesn't belong to this group, this
+    /// returns an `ErrorKind::InvalidInput` error.
+    pub fn read_with_times(&mut self, members: &mut [&mut Counter]) -> io::Result<GroupData> {
+        let counts = self.read()?;
+        let mut data = GroupData {
+            members: counts
+                .iter()
+                .map(|(id, &value)| MemberCount {
+                    id,
+                    value,
+                    time_enabled: counts.time_enabled(),
+                    time_running: counts.time_running(),
+                })
+                .collect(),
+        };
+
+        for counter in members.iter_mut() {
+            let id = counter.id;
+            let entry = data
+                .members
+                .iter_mut()
+                .find(|member| member.id == id)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("counter {} is not a member of this group", id),
+                    )
+                })?;
+            if counter.has_times {
+                let own = counter.read_count_and_time()?;
+                entry.time_enabled = own.time_enabled;
+                entry.time_running = own.time_running;
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Reset all `Counter`s in this `Group`, let them count for `duration`,
+    /// and return their final values, along with the time that actually
+    /// passed.
+    ///
+    /// This blocks the calling thread. As with [`Counter::measure_for`],
+    /// signals arriving during the measurement don't cut it short.
+    pub fn measure_for(&mut self, duration: Duration) -> io::Result<Measurement<Counts>> {
+        self.reset()?;
+        self.enable()?;
+        let start = Instant::now();
+        std::thread::sleep(duration);
+        let elapsed = start.elapsed();
+        self.disable()?;
+        Ok(Measurement {
+            counts: self.read()?,
+            elapsed,
+        })
+    }
 }
 
 impl std::fmt::Debug for Group {
@@ -955,12 +3192,24 @@ impl AsRawFd for Group {
     }
 }
 
+impl AsFd for Group {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
 impl IntoRawFd for Group {
     fn into_raw_fd(self) -> RawFd {
         self.file.into_raw_fd()
     }
 }
 
+impl From<Group> for OwnedFd {
+    fn from(group: Group) -> OwnedFd {
+        group.file.into()
+    }
+}
+
 impl Counts {
     /// Return the number of counters this `Counts` holds results for.
     #[allow(clippy::len_without_is_empty)] // Groups are never empty.
@@ -980,6 +3229,40 @@ impl Counts {
         self.data[2]
     }
 
+    /// Return true if the `Group` was enabled, but the kernel never managed to
+    /// put it on the processor.
+    ///
+    /// This usually means the group needs more hardware counters than are
+    /// available. In this case, all the counts are zero, and say nothing about
+    /// the code being measured. Use [`scheduling_hint`] for an explanation of
+    /// what might have gone wrong.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     # use perf_event::Group;
+    ///     # let mut group = Group::new()?;
+    ///     # let counts = group.read()?;
+    ///     if let Some(hint) = counts.scheduling_hint() {
+    ///         eprintln!("no results: {}", hint);
+    ///     }
+    ///     # Ok(()) }
+    ///
+    /// [`scheduling_hint`]: Counts::scheduling_hint
+    pub fn scheduling_failed(&self) -> bool {
+        self.time_enabled() > 0 && self.time_running() == 0
+    }
+
+    /// If [`scheduling_failed`] is true, return a [`SchedulingHint`] listing
+    /// likely causes. Otherwise, return `None`.
+    ///
+    /// [`scheduling_failed`]: Counts::scheduling_failed
+    pub fn scheduling_hint(&self) -> Option<SchedulingHint> {
+        if self.scheduling_failed() {
+            Some(SchedulingHint::new())
+        } else {
+            None
+        }
+    }
+
     /// Return a range of indexes covering the count and id of the `n`'th counter.
     fn nth_index(n: usize) -> std::ops::Range<usize> {
         let base = 3 + 2 * n;
@@ -1029,7 +3312,7 @@ impl<'c> IntoIterator for &'c Counts {
     fn into_iter(self) -> CountsIter<'c> {
         CountsIter {
             counts: self,
-            next: 1, // skip the `Group` itself, it's just a dummy.
+            next: self.first,
         }
     }
 }
@@ -1066,7 +3349,7 @@ impl Counts {
     /// Each item is a pair `(id, &value)`, where `id` is the number assigned to
     /// the counter by the kernel (see `Counter::id`), and `value` is that
     /// counter's value.
-    pub fn iter(&self) -> CountsIter {
+    pub fn iter(&self) -> CountsIter<'_> {
         <&Counts as IntoIterator>::into_iter(self)
     }
 }
@@ -1080,10 +3363,190 @@ impl std::ops::Index<&Counter> for Counts {
 
 impl std::fmt::Debug for Counts {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        fmt.debug_map().entries(self.into_iter()).finish()
+        fmt.debug_map().entries(self).finish()
     }
 }
 
+impl Counts {
+    /// Return a [`CountsSnapshot`] holding a copy of these counts, which
+    /// doesn't need the `Counter`s to look up entries.
+    ///
+    /// The snapshot's entries have no labels; use [`CountsSnapshot::label`]
+    /// to add them.
+    pub fn snapshot(&self) -> CountsSnapshot {
+        CountsSnapshot {
+            time_enabled: self.time_enabled(),
+            time_running: self.time_running(),
+            entries: self
+                .iter()
+                .map(|(id, &value)| SnapshotEntry {
+                    id,
+                    label: None,
+                    value,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl CountsSnapshot {
+    /// Give `member`'s entry the label `label`, replacing any label it
+    /// already had, and return the snapshot.
+    ///
+    /// If `member` has no entry in this snapshot, this does nothing.
+    pub fn label<L: Into<String>>(self, member: &Counter, label: L) -> CountsSnapshot {
+        self.label_id(member.id(), label)
+    }
+
+    /// Give the entry for the counter whose id is `id` the label `label`,
+    /// replacing any label it already had, and return the snapshot.
+    ///
+    /// If there's no entry for `id` in this snapshot, this does nothing.
+    pub fn label_id<L: Into<String>>(mut self, id: u64, label: L) -> CountsSnapshot {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) {
+            entry.label = Some(label.into());
+        }
+        self
+    }
+
+    /// Return the number of counters this snapshot holds values for.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Return true if this snapshot holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Return the number of nanoseconds the `Group` was enabled that
+    /// contributed to these counts.
+    pub fn time_enabled(&self) -> u64 {
+        self.time_enabled
+    }
+
+    /// Return the number of nanoseconds the `Group` was actually collecting
+    /// counts that contributed to these counts.
+    pub fn time_running(&self) -> u64 {
+        self.time_running
+    }
+
+    /// Return the value of the entry labeled `label`, or `None` if there is
+    /// no such entry.
+    pub fn get(&self, label: &str) -> Option<u64> {
+        self.entry(label).map(|entry| entry.value)
+    }
+
+    /// Return the value of the counter whose id is `id`, or `None` if there
+    /// is no such entry.
+    pub fn get_id(&self, id: u64) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| entry.value)
+    }
+
+    /// Return the entry labeled `label`, or `None` if there is no such
+    /// entry.
+    pub fn entry(&self, label: &str) -> Option<&SnapshotEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.label.as_deref() == Some(label))
+    }
+
+    /// Return an iterator over the entries, in the order the group returned
+    /// them.
+    pub fn iter(&self) -> std::slice::Iter<'_, SnapshotEntry> {
+        self.entries.iter()
+    }
+}
+
+impl<'s> IntoIterator for &'s CountsSnapshot {
+    type Item = &'s SnapshotEntry;
+    type IntoIter = std::slice::Iter<'s, SnapshotEntry>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+impl std::ops::Index<&str> for CountsSnapshot {
+    type Output = u64;
+    fn index(&self, label: &str) -> &u64 {
+        match self.entry(label) {
+            Some(entry) => &entry.value,
+            None => panic!("no entry labeled {:?} in CountsSnapshot", label),
+        }
+    }
+}
+
+impl GroupData {
+    /// Return the number of members this `GroupData` holds results for.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Return true if the group had no members.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Return the entry for `member`, or `None` if `member` is not present.
+    pub fn get(&self, member: &Counter) -> Option<&MemberCount> {
+        self.members.iter().find(|entry| entry.id == member.id)
+    }
+
+    /// Return an iterator over the members' entries.
+    pub fn iter(&self) -> std::slice::Iter<'_, MemberCount> {
+        self.members.iter()
+    }
+}
+
+impl<'d> IntoIterator for &'d GroupData {
+    type Item = &'d MemberCount;
+    type IntoIter = std::slice::Iter<'d, MemberCount>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.iter()
+    }
+}
+
+impl std::ops::Index<&Counter> for GroupData {
+    type Output = MemberCount;
+    fn index(&self, index: &Counter) -> &MemberCount {
+        self.get(index).unwrap()
+    }
+}
+
+impl CountAndTime {
+    /// Return this count, scaled up to estimate what it would have been had
+    /// the counter run the whole time it was enabled. Return `None` if the
+    /// counter never ran.
+    pub fn scaled(&self) -> Option<u64> {
+        prorate(self.count, self.time_enabled, self.time_running)
+    }
+}
+
+impl MemberCount {
+    /// Return this member's value, scaled up to estimate what it would have
+    /// been had the counter run the whole time it was enabled. Return `None`
+    /// if the counter never ran.
+    pub fn scaled(&self) -> Option<u64> {
+        prorate(self.value, self.time_enabled, self.time_running)
+    }
+}
+
+/// Scale `value`, counted over `time_running` nanoseconds, up to
+/// `time_enabled`.
+fn prorate(value: u64, time_enabled: u64, time_running: u64) -> Option<u64> {
+    if time_running == 0 {
+        return None;
+    }
+    if time_running >= time_enabled {
+        return Some(value);
+    }
+    // `u128` division is usually implemented in software, but it's exact.
+    Some((value as u128 * time_enabled as u128 / time_running as u128) as u64)
+}
+
 /// A type whose values can be safely accessed as a slice of bytes.
 ///
 /// # Safety
@@ -1129,6 +3592,34 @@ fn simple_build() {
         .expect("Couldn't build default Counter");
 }
 
+#[test]
+fn scheduling_failed() {
+    // nr, time_enabled, time_running, then (value, id) for the dummy leader.
+    let never_ran = Counts {
+        data: vec![1, 1000, 0, 0, 1],
+        first: 1,
+    };
+    assert!(never_ran.scheduling_failed());
+    assert!(never_ran
+        .scheduling_hint()
+        .unwrap()
+        .causes()
+        .contains(&SchedulingCause::TooManyEvents));
+
+    let ran = Counts {
+        data: vec![1, 1000, 1000, 0, 1],
+        first: 1,
+    };
+    assert!(!ran.scheduling_failed());
+    assert!(ran.scheduling_hint().is_none());
+
+    let never_enabled = Counts {
+        data: vec![1, 0, 0, 0, 1],
+        first: 1,
+    };
+    assert!(!never_enabled.scheduling_failed());
+}
+
 #[test]
 #[cfg(target_os = "linux")]
 fn test_error_code_is_correct() {
@@ -1145,3 +3636,17 @@ fn test_error_code_is_correct() {
         Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EINVAL)),
     }
 }
+
+#[test]
+fn unsupported_event_error() {
+    let mut attrs = perf_event_attr::default();
+    events::Event::from(events::Software::CGROUP_SWITCHES).update_attrs(&mut attrs);
+
+    let e = events::UnsupportedEvent::check(&attrs, io::Error::from_raw_os_error(libc::ENOENT));
+    assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+    assert!(e.to_string().contains("Linux 5.13"));
+
+    // Other errors pass through untouched.
+    let e = events::UnsupportedEvent::check(&attrs, io::Error::from_raw_os_error(libc::EACCES));
+    assert_eq!(e.raw_os_error(), Some(libc::EACCES));
+}
@@ -0,0 +1,420 @@
+//! Derived metrics, computed from a group's counts by formulas.
+//!
+//! Raw counts are rarely what you want to report: cycles per instruction,
+//! cache miss rates, and the like are more telling. A [`MetricSet`] holds
+//! named formulas over event names, like `perf`'s metric groups, and
+//! computes them from a [`Counts`]:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::{Builder, Group};
+//!     use perf_event::events::Software;
+//!     use perf_event::metrics::MetricSet;
+//!
+//!     let mut group = Group::new()?;
+//!     let clock = Builder::new().group(&mut group).kind(Software::TASK_CLOCK).build()?;
+//!     let faults = Builder::new().group(&mut group).kind(Software::PAGE_FAULTS).build()?;
+//!
+//!     let mut metrics = MetricSet::new();
+//!     metrics.bind("task-clock", &clock);
+//!     metrics.bind("page-faults", &faults);
+//!     metrics.add("faults-per-msec", "page-faults / (task-clock / 1000000)")?;
+//!
+//!     group.enable()?;
+//!     let pages = vec![0_u8; 1 << 20];
+//!     group.disable()?;
+//!
+//!     for (name, value) in metrics.evaluate(&group.read()?) {
+//!         match value {
+//!             Some(value) => println!("{}: {:.2}", name, value),
+//!             None => println!("{}: not available", name),
+//!         }
+//!     }
+//!     # Ok(()) }
+//!
+//! Formulas may use numbers, event names, the operators `+`, `-`, `*`, and
+//! `/`, and parentheses. Event names may contain letters, digits, `_`, `.`,
+//! and `-`; since `-` can appear within a name, write subtraction with
+//! spaces around it, as in `cycles - stalled-cycles-frontend`.
+//!
+//! Counts are scaled up to compensate for multiplexing before they're used,
+//! so formulas that mix counts with fixed quantities come out right. A
+//! metric's value is `None` if one of its events is missing or never ran,
+//! or if it divides by zero.
+//!
+//! [`Counts`]: crate::Counts
+
+use crate::{prorate, Counter, Counts, CountsSnapshot};
+use std::fmt;
+use std::io;
+
+/// A named formula over event names.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metric {
+    name: String,
+    formula: String,
+    expr: Expr,
+}
+
+/// A set of [`Metric`]s, along with the counters their event names refer
+/// to.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricSet {
+    /// Event names and the ids of the counters they refer to.
+    bindings: Vec<(String, u64)>,
+    metrics: Vec<Metric>,
+}
+
+/// A parsed formula.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(f64),
+    Event(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl Metric {
+    /// Parse `formula`, and return a metric named `name` that computes it.
+    ///
+    /// If `formula` isn't well-formed, this returns an error of kind
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn new(name: &str, formula: &str) -> io::Result<Metric> {
+        let invalid = |what: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("bad formula for metric `{}`: {}", name, what),
+            )
+        };
+
+        let tokens = tokenize(formula).map_err(invalid)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            next: 0,
+        };
+        let expr = parser.expr().map_err(invalid)?;
+        if let Some(token) = parser.peek() {
+            return Err(invalid(format!("unexpected {}", token)));
+        }
+
+        Ok(Metric {
+            name: name.to_string(),
+            formula: formula.to_string(),
+            expr,
+        })
+    }
+
+    /// Return this metric's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return this metric's formula, as it was given to [`Metric::new`].
+    pub fn formula(&self) -> &str {
+        &self.formula
+    }
+
+    /// Return the names of the events this metric's formula uses, in the
+    /// order they first appear.
+    pub fn events(&self) -> Vec<&str> {
+        let mut names = vec![];
+        self.expr.events(&mut names);
+        names
+    }
+
+    /// Compute this metric, calling `lookup` to get each event's value.
+    ///
+    /// Return `None` if `lookup` returns `None` for any event the formula
+    /// uses, or if the formula divides by zero. This lets you compute
+    /// metrics from any source of counts; for example, given a
+    /// [`StatReport`]:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     # use perf_event::metrics::Metric;
+    ///     # use perf_event::stat::StatBuilder;
+    ///     # let (_, report) = StatBuilder::new().default_events().run(|| ())?;
+    ///     let ipc = Metric::new("ipc", "instructions / cycles")?;
+    ///     let value = ipc.evaluate_with(|name| Some(report.get(name)? as f64));
+    ///     # Ok(()) }
+    ///
+    /// [`StatReport`]: crate::stat::StatReport
+    pub fn evaluate_with<F>(&self, mut lookup: F) -> Option<f64>
+    where
+        F: FnMut(&str) -> Option<f64>,
+    {
+        self.expr.evaluate(&mut lookup)
+    }
+}
+
+impl MetricSet {
+    /// Return an empty `MetricSet`.
+    pub fn new() -> MetricSet {
+        MetricSet::default()
+    }
+
+    /// Let formulas refer to `counter`'s count as `name`, replacing any
+    /// counter already bound to that name.
+    pub fn bind(&mut self, name: &str, counter: &Counter) -> &mut MetricSet {
+        self.bind_id(name, counter.id())
+    }
+
+    /// Let formulas refer to the count of the counter whose id is `id` as
+    /// `name`, replacing any counter already bound to that name.
+    pub fn bind_id(&mut self, name: &str, id: u64) -> &mut MetricSet {
+        match self.bindings.iter_mut().find(|(bound, _)| bound == name) {
+            Some(binding) => binding.1 = id,
+            None => self.bindings.push((name.to_string(), id)),
+        }
+        self
+    }
+
+    /// Add a metric named `name` that computes `formula`.
+    ///
+    /// If `formula` isn't well-formed, this returns an error of kind
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn add(&mut self, name: &str, formula: &str) -> io::Result<&mut MetricSet> {
+        self.metrics.push(Metric::new(name, formula)?);
+        Ok(self)
+    }
+
+    /// Add `metric` to this set.
+    pub fn add_metric(&mut self, metric: Metric) -> &mut MetricSet {
+        self.metrics.push(metric);
+        self
+    }
+
+    /// Return the metrics in this set, in the order they were added.
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    /// Compute each metric from `counts`, using the counters bound with
+    /// [`bind`], and return the metrics' names and values, in the order
+    /// they were added.
+    ///
+    /// Each count is scaled by the group's time enabled and time running
+    /// before it's used.
+    ///
+    /// [`bind`]: MetricSet::bind
+    pub fn evaluate(&self, counts: &Counts) -> Vec<(&str, Option<f64>)> {
+        let count = |name: &str| {
+            let id = self.bound(name)?;
+            let (_, &value) = counts.iter().find(|&(member, _)| member == id)?;
+            Some(prorate(value, counts.time_enabled(), counts.time_running())? as f64)
+        };
+        self.metrics
+            .iter()
+            .map(|metric| (metric.name(), metric.evaluate_with(count)))
+            .collect()
+    }
+
+    /// Compute each metric from `snapshot`, as for [`evaluate`].
+    ///
+    /// Event names are looked up first among the snapshot's labels, and
+    /// then among the counters bound with [`bind`].
+    ///
+    /// [`evaluate`]: MetricSet::evaluate
+    /// [`bind`]: MetricSet::bind
+    pub fn evaluate_snapshot(&self, snapshot: &CountsSnapshot) -> Vec<(&str, Option<f64>)> {
+        let scale = |value| prorate(value, snapshot.time_enabled(), snapshot.time_running());
+        self.metrics
+            .iter()
+            .map(|metric| {
+                let value = metric.evaluate_with(|name| {
+                    let value = match snapshot.get(name) {
+                        Some(value) => value,
+                        None => snapshot.get_id(self.bound(name)?)?,
+                    };
+                    Some(scale(value)? as f64)
+                });
+                (metric.name(), value)
+            })
+            .collect()
+    }
+
+    /// Return the id of the counter bound to `name`, if any.
+    fn bound(&self, name: &str) -> Option<u64> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| bound == name)
+            .map(|&(_, id)| id)
+    }
+}
+
+impl Expr {
+    fn events<'e>(&'e self, names: &mut Vec<&'e str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Event(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Expr::Neg(operand) => operand.events(names),
+            Expr::Binary(_, left, right) => {
+                left.events(names);
+                right.events(names);
+            }
+        }
+    }
+
+    fn evaluate(&self, lookup: &mut dyn FnMut(&str) -> Option<f64>) -> Option<f64> {
+        match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Event(name) => lookup(name),
+            Expr::Neg(operand) => Some(-operand.evaluate(lookup)?),
+            Expr::Binary(op, left, right) => {
+                let left = left.evaluate(lookup)?;
+                let right = right.evaluate(lookup)?;
+                match op {
+                    Op::Add => Some(left + right),
+                    Op::Sub => Some(left - right),
+                    Op::Mul => Some(left * right),
+                    Op::Div if right == 0.0 => None,
+                    Op::Div => Some(left / right),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "number `{}`", n),
+            Token::Name(name) => write!(f, "event name `{}`", name),
+            Token::Op(Op::Add) => f.write_str("`+`"),
+            Token::Op(Op::Sub) => f.write_str("`-`"),
+            Token::Op(Op::Mul) => f.write_str("`*`"),
+            Token::Op(Op::Div) => f.write_str("`/`"),
+            Token::Open => f.write_str("`(`"),
+            Token::Close => f.write_str("`)`"),
+        }
+    }
+}
+
+/// Split `formula` into tokens.
+fn tokenize(formula: &str) -> Result<Vec<Token>, String> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let chars: Vec<char> = formula.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let token = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '+' => Token::Op(Op::Add),
+            '-' => Token::Op(Op::Sub),
+            '*' => Token::Op(Op::Mul),
+            '/' => Token::Op(Op::Div),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            _ if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let n = text.parse().map_err(|_| format!("bad number `{}`", text))?;
+                tokens.push(Token::Number(n));
+                continue;
+            }
+            _ if is_name_char(c) => {
+                // A `-` continues the name if a name character follows it.
+                let start = i;
+                while i < chars.len()
+                    && (is_name_char(chars[i])
+                        || (chars[i] == '-' && chars.get(i + 1).copied().is_some_and(is_name_char)))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..i].iter().collect()));
+                continue;
+            }
+            _ => return Err(format!("unexpected character `{}`", c)),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// A recursive-descent parser for formulas.
+struct Parser<'t> {
+    tokens: &'t [Token],
+    next: usize,
+}
+
+impl<'t> Parser<'t> {
+    fn peek(&self) -> Option<&'t Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Option<&'t Token> {
+        let token = self.peek()?;
+        self.next += 1;
+        Some(token)
+    }
+
+    /// Parse a sum or difference of terms.
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        while let Some(&Token::Op(op @ (Op::Add | Op::Sub))) = self.peek() {
+            self.next += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    /// Parse a product or quotient of factors.
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.factor()?;
+        while let Some(&Token::Op(op @ (Op::Mul | Op::Div))) = self.peek() {
+            self.next += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+
+    /// Parse a number, an event name, a negation, or a parenthesized
+    /// expression.
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.take() {
+            Some(Token::Number(n)) => Ok(Expr::Number(*n)),
+            Some(Token::Name(name)) => Ok(Expr::Event(name.clone())),
+            Some(Token::Op(Op::Sub)) => Ok(Expr::Neg(Box::new(self.factor()?))),
+            Some(Token::Open) => {
+                let expr = self.expr()?;
+                match self.take() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing `)`".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("unexpected end of formula".to_string()),
+        }
+    }
+}
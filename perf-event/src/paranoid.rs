@@ -0,0 +1,169 @@
+//! Checking `perf_event_paranoid` before opening counters.
+//!
+//! The kernel restricts what unprivileged processes may monitor according
+//! to the value in `/proc/sys/kernel/perf_event_paranoid`. Rather than
+//! letting [`Builder::build`] fail partway through a run with a bare
+//! `EACCES`, an application can compare the current [`Level`] against what
+//! its `Builder`s need, and tell the user exactly what to change:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::paranoid::Level;
+//!
+//!     let builder = Builder::new()
+//!         .system_wide_on_cpu(0)
+//!         .kind(Software::CONTEXT_SWITCHES);
+//!     let required = Level::required_for(&builder);
+//!     if !Level::current()?.permits(required) && !Level::privileged() {
+//!         eprintln!("{}", required.instructions());
+//!     }
+//!     # Ok(()) }
+//!
+//! Processes with the `CAP_PERFMON` or `CAP_SYS_ADMIN` capabilities are
+//! exempt from these restrictions; see [`Level::privileged`].
+//!
+//! [`Builder::build`]: crate::Builder::build
+
+use crate::{Builder, EventPid};
+use std::fmt;
+use std::fs;
+use std::io;
+
+const PARANOID_PATH: &str = "/proc/sys/kernel/perf_event_paranoid";
+
+/// A `perf_event_paranoid` setting.
+///
+/// Each level forbids unprivileged processes everything the levels below it
+/// forbid, and more. The variants are ordered from least to most
+/// restrictive, so you can compare them with `<` and `>`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Level {
+    /// `-1`: No restrictions.
+    Unrestricted,
+
+    /// `0`: Unprivileged processes may not access raw tracepoint data.
+    NoRawTracepoints,
+
+    /// `1`: Unprivileged processes may not monitor CPUs (that is, all
+    /// processes on a CPU), only specific processes.
+    NoCpuEvents,
+
+    /// `2`: Unprivileged processes may not count or sample kernel activity,
+    /// only user-space code. This is the kernel's default.
+    NoKernel,
+
+    /// `3` or more: Unprivileged processes may not use `perf_event_open` at
+    /// all. Upstream kernels treat these values like `2`, but Debian, Ubuntu,
+    /// and Android kernels carry a patch giving them this meaning.
+    Disallowed,
+}
+
+impl Level {
+    /// Read the current level from `/proc/sys/kernel/perf_event_paranoid`.
+    pub fn current() -> io::Result<Level> {
+        let contents = fs::read_to_string(PARANOID_PATH)?;
+        let value: i32 = contents.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected contents in {}: {:?}", PARANOID_PATH, contents),
+            )
+        })?;
+        Ok(Level::from_value(value))
+    }
+
+    /// Return the `Level` corresponding to a `perf_event_paranoid` value.
+    pub fn from_value(value: i32) -> Level {
+        match value {
+            i32::MIN..=-1 => Level::Unrestricted,
+            0 => Level::NoRawTracepoints,
+            1 => Level::NoCpuEvents,
+            2 => Level::NoKernel,
+            _ => Level::Disallowed,
+        }
+    }
+
+    /// Return the `perf_event_paranoid` value for this level.
+    pub fn value(self) -> i32 {
+        match self {
+            Level::Unrestricted => -1,
+            Level::NoRawTracepoints => 0,
+            Level::NoCpuEvents => 1,
+            Level::NoKernel => 2,
+            Level::Disallowed => 3,
+        }
+    }
+
+    /// Return the most restrictive level at which an unprivileged process
+    /// could build a counter from `builder`.
+    ///
+    /// This only accounts for `perf_event_paranoid`. Observing another
+    /// user's process needs `CAP_SYS_PTRACE` as well, whatever the level.
+    pub fn required_for(builder: &Builder) -> Level {
+        if let EventPid::Any = builder.who {
+            Level::NoRawTracepoints
+        } else if builder.attrs.exclude_kernel() == 0 {
+            Level::NoCpuEvents
+        } else {
+            Level::NoKernel
+        }
+    }
+
+    /// Return true if a system at this level lets unprivileged processes do
+    /// what `required` allows.
+    pub fn permits(self, required: Level) -> bool {
+        self <= required
+    }
+
+    /// Return true if the calling process has `CAP_PERFMON` or
+    /// `CAP_SYS_ADMIN` in its effective capability set, exempting it from
+    /// `perf_event_paranoid` altogether.
+    ///
+    /// If the capabilities can't be determined, this returns false.
+    pub fn privileged() -> bool {
+        const CAP_SYS_ADMIN: u32 = 21;
+        const CAP_PERFMON: u32 = 38;
+
+        let status = match fs::read_to_string("/proc/self/status") {
+            Ok(status) => status,
+            Err(_) => return false,
+        };
+        let effective = status
+            .lines()
+            .find_map(|line| line.strip_prefix("CapEff:"))
+            .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+            .unwrap_or(0);
+        effective & (1 << CAP_SYS_ADMIN | 1 << CAP_PERFMON) != 0
+    }
+
+    /// Return instructions for a user on how to reach this level, for use in
+    /// error messages.
+    pub fn instructions(self) -> String {
+        format!(
+            "this requires /proc/sys/kernel/perf_event_paranoid to be {} or less \
+             (it allows: {}); try `sudo sysctl kernel.perf_event_paranoid={}`, \
+             or run with the CAP_PERFMON capability",
+            self.value(),
+            self.description(),
+            self.value()
+        )
+    }
+
+    /// Return a short description of what this level allows unprivileged
+    /// processes to do.
+    pub fn description(self) -> &'static str {
+        match self {
+            Level::Unrestricted => "all perf events, including raw tracepoint data",
+            Level::NoRawTracepoints => "all perf events except raw tracepoint data",
+            Level::NoCpuEvents => "monitoring specific processes, including kernel activity",
+            Level::NoKernel => "monitoring specific processes' user-space code",
+            Level::Disallowed => "no perf events at all",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} ({})", self.value(), self.description())
+    }
+}
@@ -0,0 +1,189 @@
+//! Counting on every CPU at once.
+//!
+//! Linux can't count events in all processes on all CPUs with a single
+//! counter: system-wide counters (see [`Builder::system_wide_on_cpu`]) and
+//! cgroup counters (see [`Builder::observe_cgroup`]) must each be opened on
+//! one particular CPU. To cover the whole machine you need one counter per
+//! CPU, and the total is their sum.
+//!
+//! A [`PerCpuCounter`] manages such a set: it calls a closure you provide to
+//! build a counter for each CPU listed in `/sys/devices/system/cpu/online`,
+//! and then enables, disables, resets and reads them together, reporting
+//! both the total and the per-CPU breakdown:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::per_cpu::PerCpuCounter;
+//!
+//!     let mut switches = PerCpuCounter::on_online_cpus(|cpu| {
+//!         Builder::new()
+//!             .system_wide_on_cpu(cpu)
+//!             .include_kernel()
+//!             .kind(Software::CONTEXT_SWITCHES)
+//!             .build()
+//!     })?;
+//!
+//!     switches.enable()?;
+//!     // ... do things ...
+//!     switches.disable()?;
+//!     println!("{} context switches in all", switches.read()?);
+//!     for (cpu, count) in switches.read_per_cpu()? {
+//!         println!("    cpu {}: {}", cpu, count);
+//!     }
+//!     # Ok(()) }
+//!
+//! CPUs brought online after the `PerCpuCounter` is created aren't counted.
+//!
+//! [`Builder::system_wide_on_cpu`]: crate::Builder::system_wide_on_cpu
+//! [`Builder::observe_cgroup`]: crate::Builder::observe_cgroup
+
+use crate::Counter;
+use std::fmt;
+use std::fs;
+use std::io;
+
+/// Where the kernel lists the CPUs currently online.
+const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
+
+/// Return the numbers of the CPUs currently online, in increasing order.
+pub fn online_cpus() -> io::Result<Vec<usize>> {
+    let list = fs::read_to_string(ONLINE_CPUS)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", ONLINE_CPUS, e)))?;
+    parse_cpu_list(list.trim()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: can't parse CPU list {:?}", ONLINE_CPUS, list),
+        )
+    })
+}
+
+/// Parse a kernel CPU list, like `0-3,6,8-9`.
+pub(crate) fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+    for range in list.split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let first: usize = first.parse().ok()?;
+                let last: usize = last.parse().ok()?;
+                if last < first {
+                    return None;
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// One counter per CPU, managed as a unit.
+///
+/// See the [module documentation](self) for details.
+pub struct PerCpuCounter {
+    /// Each CPU's counter, in increasing order of CPU number.
+    counters: Vec<(usize, Counter)>,
+}
+
+impl PerCpuCounter {
+    /// Build a counter for each CPU in [`online_cpus`].
+    ///
+    /// For each CPU, call `build` with the CPU's number to create a counter
+    /// for it. The `Builder` it uses should call [`one_cpu`] or
+    /// [`system_wide_on_cpu`] with the number it's given. The counters start
+    /// out disabled.
+    ///
+    /// [`one_cpu`]: crate::Builder::one_cpu
+    /// [`system_wide_on_cpu`]: crate::Builder::system_wide_on_cpu
+    pub fn on_online_cpus<F>(build: F) -> io::Result<PerCpuCounter>
+    where
+        F: FnMut(usize) -> io::Result<Counter>,
+    {
+        PerCpuCounter::on_cpus(online_cpus()?, build)
+    }
+
+    /// Build a counter for each CPU in `cpus`, as for [`on_online_cpus`].
+    ///
+    /// [`on_online_cpus`]: PerCpuCounter::on_online_cpus
+    pub fn on_cpus<I, F>(cpus: I, mut build: F) -> io::Result<PerCpuCounter>
+    where
+        I: IntoIterator<Item = usize>,
+        F: FnMut(usize) -> io::Result<Counter>,
+    {
+        let counters = cpus
+            .into_iter()
+            .map(|cpu| Ok((cpu, build(cpu)?)))
+            .collect::<io::Result<_>>()?;
+        Ok(PerCpuCounter { counters })
+    }
+
+    /// Enable all the CPUs' counters.
+    pub fn enable(&mut self) -> io::Result<()> {
+        for (_, counter) in &mut self.counters {
+            counter.enable()?;
+        }
+        Ok(())
+    }
+
+    /// Disable all the CPUs' counters.
+    pub fn disable(&mut self) -> io::Result<()> {
+        for (_, counter) in &mut self.counters {
+            counter.disable()?;
+        }
+        Ok(())
+    }
+
+    /// Reset all the CPUs' counters to zero.
+    pub fn reset(&mut self) -> io::Result<()> {
+        for (_, counter) in &mut self.counters {
+            counter.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Return the sum of all the CPUs' counts.
+    pub fn read(&mut self) -> io::Result<u64> {
+        let mut total = 0;
+        for (_, counter) in &mut self.counters {
+            total += counter.read()?;
+        }
+        Ok(total)
+    }
+
+    /// Return each CPU's count, as pairs `(cpu, count)`, in increasing order
+    /// of CPU number.
+    pub fn read_per_cpu(&mut self) -> io::Result<Vec<(usize, u64)>> {
+        self.counters
+            .iter_mut()
+            .map(|(cpu, counter)| Ok((*cpu, counter.read()?)))
+            .collect()
+    }
+
+    /// Return the numbers of the CPUs being counted, in increasing order.
+    pub fn cpus(&self) -> impl Iterator<Item = usize> + '_ {
+        self.counters.iter().map(|&(cpu, _)| cpu)
+    }
+
+    /// Return the counter for `cpu`, if there is one.
+    pub fn get_mut(&mut self, cpu: usize) -> Option<&mut Counter> {
+        self.counters
+            .iter_mut()
+            .find(|(c, _)| *c == cpu)
+            .map(|(_, counter)| counter)
+    }
+
+    /// Iterate over the CPUs being counted and their counters.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut Counter)> {
+        self.counters
+            .iter_mut()
+            .map(|(cpu, counter)| (*cpu, counter))
+    }
+}
+
+impl fmt::Debug for PerCpuCounter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_map()
+            .entries(self.counters.iter().map(|(cpu, counter)| (cpu, counter)))
+            .finish()
+    }
+}
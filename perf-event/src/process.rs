@@ -0,0 +1,670 @@
+//! Counting and sampling every thread of a process.
+//!
+//! A [`Counter`] observing a process id only counts that one thread. To
+//! count a whole multi-threaded process you can set [`inherit`], but that
+//! only covers threads created after the counter, and inherited counters
+//! can't belong to a [`Group`]. A [`ProcessCounter`] takes the other
+//! approach: it opens a separate counter for each thread listed in
+//! `/proc/PID/task`, and sums them.
+//!
+//! Threads come and go, so a `ProcessCounter` must be kept up to date by
+//! calling its [`refresh`] method periodically. Each refresh opens counters
+//! for any new threads, and folds the final counts of threads that have
+//! exited into a running total, so their work isn't lost. Anything a thread
+//! does between its creation and the next refresh goes uncounted, so
+//! refresh as often as the accuracy you need demands.
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::process::ProcessCounter;
+//!
+//!     let pid = std::process::id() as libc::pid_t;
+//!     let mut counter = ProcessCounter::observe_process(pid, |tid| {
+//!         Builder::new()
+//!             .observe_pid(tid)
+//!             .kind(Software::TASK_CLOCK)
+//!             .build()
+//!     })?;
+//!
+//!     counter.enable()?;
+//!     // ... periodically:
+//!     counter.refresh()?;
+//!     // ... and when done:
+//!     counter.disable()?;
+//!     println!("{} ns of CPU time across {} threads",
+//!              counter.read()?, counter.threads());
+//!     # Ok(()) }
+//!
+#![cfg_attr(
+    feature = "sampling",
+    doc = "
+With the `\"sampling\"` feature, a [`ProcessSampler`] does the same for
+sampling. Since it reads each thread's records as they arrive, it can watch
+for the `FORK` records that announce new threads, and open samplers for them
+without waiting for a refresh. Alternatively, [`ProcessSampler::inherit`]
+samples a thread and all its future descendants using one inherited sampler
+per CPU.
+"
+)]
+//!
+//! [`Counter`]: crate::Counter
+//! [`Group`]: crate::Group
+//! [`inherit`]: crate::Builder::inherit
+//! [`refresh`]: ProcessCounter::refresh
+
+#[cfg(feature = "sampling")]
+use crate::per_cpu::online_cpus;
+#[cfg(feature = "sampling")]
+use crate::samples::{ParseConfig, Record, RecordEvent, SampleType, Sampler};
+#[cfg(feature = "sampling")]
+use crate::Builder;
+use crate::Counter;
+use libc::pid_t;
+#[cfg(feature = "sampling")]
+use perf_event_open_sys::bindings;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+
+/// A counter covering every thread of a process.
+///
+/// See the [module documentation](self) for details.
+pub struct ProcessCounter {
+    pid: pid_t,
+
+    /// The function we call to build each thread's counter.
+    build: Box<dyn FnMut(pid_t) -> io::Result<Counter> + Send>,
+
+    /// Counters for the threads we found on the last refresh, by thread id.
+    threads: HashMap<pid_t, Counter>,
+
+    /// The total final counts of threads that have exited.
+    exited: u64,
+
+    /// Whether new threads' counters should be enabled.
+    enabled: bool,
+}
+
+impl ProcessCounter {
+    /// Count events in all threads of the process `pid`.
+    ///
+    /// For each thread, call `build` with the thread's id to create a counter
+    /// for it. The `Builder` it uses should call [`observe_pid`] with the
+    /// thread id it's given, and should not use [`inherit`].
+    ///
+    /// This performs an initial [`refresh`]. The counters start out
+    /// disabled.
+    ///
+    /// [`observe_pid`]: crate::Builder::observe_pid
+    /// [`inherit`]: crate::Builder::inherit
+    /// [`refresh`]: ProcessCounter::refresh
+    pub fn observe_process<F>(pid: pid_t, build: F) -> io::Result<ProcessCounter>
+    where
+        F: FnMut(pid_t) -> io::Result<Counter> + Send + 'static,
+    {
+        let mut counter = ProcessCounter {
+            pid,
+            build: Box::new(build),
+            threads: HashMap::new(),
+            exited: 0,
+            enabled: false,
+        };
+        counter.refresh()?;
+        Ok(counter)
+    }
+
+    /// Bring the set of per-thread counters up to date with the process's
+    /// current threads.
+    ///
+    /// This opens counters for threads that have appeared since the last
+    /// refresh, enabling them if this `ProcessCounter` is enabled. It reads
+    /// the final counts of threads that have exited, adds them to the
+    /// running total, and closes their counters.
+    ///
+    /// If the process itself has exited, this returns an error.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let current = task_ids(self.pid)?;
+
+        // Fold in threads that have exited. Their counters still hold their
+        // final counts.
+        let gone: Vec<pid_t> = self
+            .threads
+            .keys()
+            .copied()
+            .filter(|tid| !current.contains(tid))
+            .collect();
+        for tid in gone {
+            let mut counter = self.threads.remove(&tid).unwrap();
+            self.exited += counter.read()?;
+        }
+
+        for tid in current {
+            if self.threads.contains_key(&tid) {
+                continue;
+            }
+            let mut counter = match (self.build)(tid) {
+                Ok(counter) => counter,
+                // The thread exited before we could attach to it.
+                Err(e) if e.raw_os_error() == Some(libc::ESRCH) => continue,
+                Err(e) => return Err(e),
+            };
+            if self.enabled {
+                counter.enable()?;
+            }
+            self.threads.insert(tid, counter);
+        }
+
+        Ok(())
+    }
+
+    /// Enable the counters for all threads, and for any threads found by
+    /// later refreshes.
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.enabled = true;
+        for counter in self.threads.values_mut() {
+            counter.enable()?;
+        }
+        Ok(())
+    }
+
+    /// Disable the counters for all threads.
+    ///
+    /// Threads found by later refreshes get disabled counters.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.enabled = false;
+        for counter in self.threads.values_mut() {
+            counter.disable()?;
+        }
+        Ok(())
+    }
+
+    /// Reset the counts of all threads to zero, and forget the counts of
+    /// threads that have exited.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.exited = 0;
+        for counter in self.threads.values_mut() {
+            counter.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Return the total count for the process: the sum of the current
+    /// threads' counts, and the final counts of threads that have exited.
+    ///
+    /// This doesn't refresh the set of threads; call [`refresh`] first if
+    /// you want the latest information.
+    ///
+    /// [`refresh`]: ProcessCounter::refresh
+    pub fn read(&mut self) -> io::Result<u64> {
+        let mut total = self.exited;
+        for counter in self.threads.values_mut() {
+            total += counter.read()?;
+        }
+        Ok(total)
+    }
+
+    /// Return the id of the process being observed.
+    pub fn pid(&self) -> pid_t {
+        self.pid
+    }
+
+    /// Return the number of threads being counted, as of the last refresh.
+    pub fn threads(&self) -> usize {
+        self.threads.len()
+    }
+}
+
+impl fmt::Debug for ProcessCounter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ProcessCounter")
+            .field("pid", &self.pid)
+            .field("threads", &self.threads)
+            .field("exited", &self.exited)
+            .field("enabled", &self.enabled)
+            .finish()
+    }
+}
+
+/// A sampler covering every thread of a process.
+///
+///     # fn main() -> std::io::Result<()> {
+///     use perf_event::events::Software;
+///     use perf_event::process::ProcessSampler;
+///     use perf_event::samples::{RecordEvent, SampleType};
+///
+///     let pid = std::process::id() as libc::pid_t;
+///     let mut sampler = ProcessSampler::observe_process(pid, |builder| {
+///         builder
+///             .kind(Software::TASK_CLOCK)
+///             .sample_period(1_000_000)
+///             .sample(SampleType::TID | SampleType::IP)
+///     })?;
+///
+///     sampler.enable()?;
+///     // ... periodically:
+///     while let Some(record) = sampler.next() {
+///         if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+///             println!("thread {:?} at {:?}", sample.tid, sample.ip);
+///         }
+///     }
+///     # Ok(()) }
+///
+/// See the [module documentation](self) for details.
+#[cfg(feature = "sampling")]
+pub struct ProcessSampler {
+    pid: pid_t,
+
+    /// The function we call to configure each sampler.
+    configure: Box<dyn FnMut(Builder<'static>) -> Builder<'static> + Send>,
+
+    /// True if we open samplers for new threads as they appear; false if
+    /// our samplers are inherited, one per CPU.
+    follow: bool,
+
+    samplers: Vec<Observed>,
+
+    /// The configuration of our samplers' records.
+    config: ParseConfig,
+
+    /// The index of the sampler to check first in `next`.
+    turn: usize,
+
+    /// Whether new threads' samplers should be enabled.
+    enabled: bool,
+
+    /// The number of new threads we failed to open samplers for.
+    missed: usize,
+}
+
+/// One of a [`ProcessSampler`]'s samplers.
+#[cfg(feature = "sampling")]
+struct Observed {
+    /// The thread this sampler observes, or `None` if it's one of a set of
+    /// inherited per-CPU samplers.
+    tid: Option<pid_t>,
+    sampler: Sampler,
+
+    /// True if we've seen this sampler's thread exit. We drop the sampler
+    /// once its buffer is empty.
+    exited: bool,
+}
+
+#[cfg(feature = "sampling")]
+impl ProcessSampler {
+    /// Sample all the threads of the process `pid`, opening a sampler for
+    /// each existing thread, and for each new thread as it appears.
+    ///
+    /// For each thread, call `configure` with a `Builder` that observes it,
+    /// and build a sampler from what it returns. `configure` should set the
+    /// event and what to sample, but not change which thread or CPU the
+    /// `Builder` observes. Each sampler also requests [`task`] records:
+    /// when [`next`] reads a `FORK` record for a new thread of the
+    /// process, it opens a sampler for that thread, and when it reads the
+    /// thread's `EXIT` record, it drops the thread's sampler once its
+    /// records have all been read.
+    ///
+    /// Samplers produce `FORK` records only while they're enabled. Threads
+    /// created while this `ProcessSampler` is disabled are picked up by
+    /// [`enable`] or [`refresh`], which scan `/proc/PID/task` for threads
+    /// that have no sampler. Anything a new thread does before its sampler
+    /// is opened goes unsampled.
+    ///
+    /// The samplers start out disabled.
+    ///
+    /// [`task`]: crate::Builder::task
+    /// [`next`]: ProcessSampler::next
+    /// [`enable`]: ProcessSampler::enable
+    /// [`refresh`]: ProcessSampler::refresh
+    pub fn observe_process<F>(pid: pid_t, configure: F) -> io::Result<ProcessSampler>
+    where
+        F: FnMut(Builder<'static>) -> Builder<'static> + Send + 'static,
+    {
+        let mut sampler = ProcessSampler::new(pid, Box::new(configure), true);
+        for tid in task_ids(pid)? {
+            match sampler.open(tid) {
+                Ok(observed) => sampler.samplers.push(observed),
+                // The thread exited before we could attach to it.
+                Err(e) if e.raw_os_error() == Some(libc::ESRCH) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        match sampler.samplers.first() {
+            Some(first) => sampler.config = *first.sampler.config(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("process {} has no threads", pid),
+                ))
+            }
+        }
+        sampler.refresh()?;
+        Ok(sampler)
+    }
+
+    /// Sample the thread `pid` and all the threads and processes it creates
+    /// from now on, using one inherited sampler per online CPU.
+    ///
+    /// The kernel won't map the ring buffer of an inherited event that
+    /// observes its thread on every CPU, but it will if the event observes
+    /// just one CPU, and then the records of all the event's inherited
+    /// copies on that CPU go to the one buffer. So this opens a sampler
+    /// with [`inherit`] set for each online CPU, calling `configure` with a
+    /// `Builder` that observes `pid` on that CPU. `configure` should set the
+    /// event and what to sample, but not change which thread or CPU the
+    /// `Builder` observes.
+    ///
+    /// Unlike [`observe_process`], this covers new threads from the moment
+    /// they're created, but doesn't cover other threads that already exist.
+    /// It suits a process that's just been spawned, as with
+    /// [`spawn_counted`]. It also uses one file descriptor per CPU, rather
+    /// than one per thread.
+    ///
+    /// The samplers start out disabled.
+    ///
+    /// [`inherit`]: crate::Builder::inherit
+    /// [`observe_process`]: ProcessSampler::observe_process
+    /// [`spawn_counted`]: crate::spawn::spawn_counted
+    pub fn inherit<F>(pid: pid_t, configure: F) -> io::Result<ProcessSampler>
+    where
+        F: FnMut(Builder<'static>) -> Builder<'static> + Send + 'static,
+    {
+        let mut sampler = ProcessSampler::new(pid, Box::new(configure), false);
+        for cpu in online_cpus()? {
+            let builder = (sampler.configure)(Builder::new().observe_pid(pid).one_cpu(cpu));
+            sampler.samplers.push(Observed {
+                tid: None,
+                sampler: builder.inherit(true).build_sampler()?,
+                exited: false,
+            });
+        }
+        if let Some(first) = sampler.samplers.first() {
+            sampler.config = *first.sampler.config();
+        }
+        Ok(sampler)
+    }
+
+    fn new(
+        pid: pid_t,
+        configure: Box<dyn FnMut(Builder<'static>) -> Builder<'static> + Send>,
+        follow: bool,
+    ) -> ProcessSampler {
+        ProcessSampler {
+            pid,
+            configure,
+            follow,
+            samplers: vec![],
+            config: ParseConfig::new(SampleType::empty()),
+            turn: 0,
+            enabled: false,
+            missed: 0,
+        }
+    }
+
+    /// Return the next record from any of the samplers, or `None` if there
+    /// are none available. This never blocks.
+    ///
+    /// This takes turns among the samplers, so records from different
+    /// threads or CPUs may be out of order; request [`SampleType::TIME`] if
+    /// you need to sort them.
+    ///
+    /// If this `ProcessSampler` was created by [`observe_process`], this
+    /// also opens samplers for new threads, and drops those of threads that
+    /// have exited, as their `FORK` and `EXIT` records go by.
+    ///
+    /// [`SampleType::TIME`]: crate::samples::SampleType::TIME
+    /// [`observe_process`]: ProcessSampler::observe_process
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Record> {
+        let (len, turn) = (self.samplers.len(), self.turn);
+        for index in (0..len).map(|i| (turn + i) % len) {
+            if let Some(record) = self.samplers[index].sampler.next() {
+                self.turn = (index + 1) % len;
+                self.follow_record(&record);
+                return Some(record);
+            }
+        }
+
+        // Every buffer is empty, so exited threads' samplers are done.
+        self.samplers.retain(|observed| !observed.exited);
+        self.turn = 0;
+        None
+    }
+
+    /// Open samplers for any threads of the process that don't have one.
+    ///
+    /// This does nothing for a `ProcessSampler` created by [`inherit`]. If
+    /// the process has exited, this returns an error.
+    ///
+    /// [`inherit`]: ProcessSampler::inherit
+    pub fn refresh(&mut self) -> io::Result<()> {
+        if self.follow {
+            for tid in task_ids(self.pid)? {
+                self.attach(tid);
+            }
+        }
+        Ok(())
+    }
+
+    /// Enable all the samplers, and those of any threads found later.
+    ///
+    /// This first does a [`refresh`], to pick up any threads created while
+    /// the samplers were disabled.
+    ///
+    /// [`refresh`]: ProcessSampler::refresh
+    pub fn enable(&mut self) -> io::Result<()> {
+        self.refresh()?;
+        self.enabled = true;
+        for observed in &mut self.samplers {
+            observed.sampler.enable()?;
+        }
+        Ok(())
+    }
+
+    /// Disable all the samplers.
+    pub fn disable(&mut self) -> io::Result<()> {
+        self.enabled = false;
+        for observed in &mut self.samplers {
+            observed.sampler.disable()?;
+        }
+        Ok(())
+    }
+
+    /// Return the configuration needed to [`parse`] this sampler's records.
+    ///
+    /// [`parse`]: crate::samples::Record::parse
+    pub fn config(&self) -> &ParseConfig {
+        &self.config
+    }
+
+    /// Return the id of the process being observed.
+    pub fn pid(&self) -> pid_t {
+        self.pid
+    }
+
+    /// Return the ids of the threads that currently have their own
+    /// samplers, in no particular order. This is empty for a
+    /// `ProcessSampler` created by [`inherit`].
+    ///
+    /// [`inherit`]: ProcessSampler::inherit
+    pub fn threads(&self) -> Vec<pid_t> {
+        self.samplers
+            .iter()
+            .filter_map(|observed| observed.tid)
+            .collect()
+    }
+
+    /// Return the number of new threads whose samplers couldn't be opened
+    /// or enabled, for reasons other than the thread having already exited.
+    pub fn missed_threads(&self) -> usize {
+        self.missed
+    }
+
+    /// If `record` announces a new thread or the end of one, open or retire
+    /// its sampler.
+    fn follow_record(&mut self, record: &Record) {
+        if !self.follow
+            || (record.ty != bindings::PERF_RECORD_FORK && record.ty != bindings::PERF_RECORD_EXIT)
+        {
+            return;
+        }
+        match record.try_parse(&self.config) {
+            Ok(RecordEvent::Fork(task)) if task.pid as pid_t == self.pid => {
+                self.attach(task.tid as pid_t)
+            }
+            Ok(RecordEvent::Exit(task)) => {
+                let tid = Some(task.tid as pid_t);
+                for observed in &mut self.samplers {
+                    if observed.tid == tid {
+                        observed.exited = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Open and add a sampler for `tid`, if it doesn't have one already.
+    fn attach(&mut self, tid: pid_t) {
+        if self
+            .samplers
+            .iter()
+            .any(|observed| observed.tid == Some(tid) && !observed.exited)
+        {
+            return;
+        }
+        let result = self.open(tid).and_then(|mut observed| {
+            if self.enabled {
+                observed.sampler.enable()?;
+            }
+            Ok(observed)
+        });
+        match result {
+            Ok(observed) => self.samplers.push(observed),
+            Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {}
+            Err(_) => self.missed += 1,
+        }
+    }
+
+    /// Open a sampler for `tid`.
+    fn open(&mut self, tid: pid_t) -> io::Result<Observed> {
+        let builder = (self.configure)(Builder::new().observe_pid(tid));
+        Ok(Observed {
+            tid: Some(tid),
+            sampler: builder.task(true).build_sampler()?,
+            exited: false,
+        })
+    }
+}
+
+#[cfg(feature = "sampling")]
+impl fmt::Debug for ProcessSampler {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ProcessSampler")
+            .field("pid", &self.pid)
+            .field("follow", &self.follow)
+            .field("threads", &self.threads())
+            .field("enabled", &self.enabled)
+            .field("missed", &self.missed)
+            .finish()
+    }
+}
+
+/// A file descriptor referring to a particular process.
+///
+/// Process ids get reused: once a process exits and is reaped, the kernel
+/// may give its id to a new process. A `PidFd` refers to one process for as
+/// long as it exists, and knows when it has exited, so it can be used to
+/// observe a process without racing against id reuse. See
+/// [`Builder::observe_pidfd`].
+///
+/// This uses the `pidfd_open` system call, available since Linux 5.3.
+///
+/// [`Builder::observe_pidfd`]: crate::Builder::observe_pidfd
+#[derive(Debug)]
+pub struct PidFd {
+    fd: OwnedFd,
+}
+
+impl PidFd {
+    /// Open a pidfd for the process `pid`.
+    pub fn open(pid: pid_t) -> io::Result<PidFd> {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PidFd {
+            fd: unsafe { OwnedFd::from_raw_fd(fd as RawFd) },
+        })
+    }
+
+    /// Return the id of the process this pidfd refers to, or `None` if it
+    /// has exited and been reaped.
+    ///
+    /// The kernel reports this in the pidfd's `/proc/self/fdinfo` entry.
+    pub fn pid(&self) -> io::Result<Option<pid_t>> {
+        let path = format!("/proc/self/fdinfo/{}", self.fd.as_raw_fd());
+        let info = fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let pid = info
+            .lines()
+            .find_map(|line| line.strip_prefix("Pid:"))
+            .and_then(|pid| pid.trim().parse::<pid_t>().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: no `Pid:` line", path),
+                )
+            })?;
+        Ok(if pid > 0 { Some(pid) } else { None })
+    }
+
+    /// Return the process's id, or an `ESRCH` error if it has exited.
+    pub(crate) fn live_pid(&self) -> io::Result<pid_t> {
+        self.pid()?.ok_or_else(PidFd::exited)
+    }
+
+    /// Return the error for a pidfd whose process has exited.
+    pub(crate) fn exited() -> io::Error {
+        io::Error::from_raw_os_error(libc::ESRCH)
+    }
+}
+
+impl AsFd for PidFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for PidFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for PidFd {
+    /// Treat `fd` as a pidfd, as returned by `pidfd_open`, or by `clone3`
+    /// with `CLONE_PIDFD`.
+    fn from(fd: OwnedFd) -> PidFd {
+        PidFd { fd }
+    }
+}
+
+impl From<PidFd> for OwnedFd {
+    fn from(pidfd: PidFd) -> OwnedFd {
+        pidfd.fd
+    }
+}
+
+/// Return the ids of the threads of process `pid`.
+fn task_ids(pid: pid_t) -> io::Result<Vec<pid_t>> {
+    let mut tids = vec![];
+    for entry in fs::read_dir(format!("/proc/{}/task", pid))? {
+        if let Some(tid) = entry?.file_name().to_str().and_then(|s| s.parse().ok()) {
+            tids.push(tid);
+        }
+    }
+    Ok(tids)
+}
@@ -0,0 +1,98 @@
+//! In-process profiling of named regions of code, on any thread.
+//!
+//! A [`SelfProfiler`] counts events in named *regions* of your program, on
+//! whatever threads they run, and adds up each region's counts across all
+//! of them. Unlike a [`WorkerProfiler`], it needs no setup on each thread:
+//! a thread gets its own counters, observing just itself, the first time it
+//! enters a region.
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::events::Software;
+//!     use perf_event::profiler::SelfProfiler;
+//!
+//!     let profiler = SelfProfiler::new(vec![Software::TASK_CLOCK.into()]);
+//!
+//!     let threads: Vec<_> = (0..4).map(|_| {
+//!         let profiler = profiler.clone();
+//!         std::thread::spawn(move || {
+//!             let text = profiler.region("format", || format!("{:?}", vec![1.5; 1000]));
+//!             profiler.region("parse", || {
+//!                 text.trim_matches(&['[', ']'][..])
+//!                     .split(", ")
+//!                     .map(|s| s.parse::<f64>().unwrap())
+//!                     .sum::<f64>()
+//!             })
+//!         })
+//!     }).collect();
+//!     for thread in threads {
+//!         thread.join().unwrap();
+//!     }
+//!
+//!     for (name, totals) in profiler.regions() {
+//!         println!("{}: {} calls, {} ns of task clock",
+//!                  name, totals.calls, totals.counts[0]);
+//!     }
+//!     # Ok(()) }
+//!
+//! Regions may nest, and a nested region's counts are included in those of
+//! the regions enclosing it. A region on a thread whose counters couldn't
+//! be created or read still runs its closure, but its counts are not
+//! recorded.
+//!
+//! Each thread keeps its counters until it exits, even if the profiler is
+//! dropped first. The events on each thread form a single [`Group`], so the
+//! processor must be able to count them all at once.
+//!
+//! [`WorkerProfiler`]: crate::workers::WorkerProfiler
+//! [`Group`]: crate::Group
+
+use crate::events::Event;
+use crate::workers::{SpanTotals, WorkerProfiler};
+
+/// Counters created on demand for each thread, attributed to named regions.
+///
+/// See the [module documentation](self) for details.
+///
+/// Cloning a `SelfProfiler` produces another handle to the same profiler.
+#[derive(Clone, Debug)]
+pub struct SelfProfiler {
+    workers: WorkerProfiler,
+}
+
+impl SelfProfiler {
+    /// Return a new profiler that will count `events` on each thread that
+    /// enters one of its regions.
+    pub fn new(events: Vec<Event>) -> SelfProfiler {
+        SelfProfiler {
+            workers: WorkerProfiler::with_laziness(events, true),
+        }
+    }
+
+    /// Return the events this profiler counts.
+    pub fn events(&self) -> &[Event] {
+        self.workers.events()
+    }
+
+    /// Call `f`, and add the counts it incurs on this thread to the totals
+    /// for the region named `name`.
+    ///
+    /// If this thread has no counters for this profiler yet, this creates
+    /// them first.
+    pub fn region<F, R>(&self, name: &'static str, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        self.workers.span(name, f)
+    }
+
+    /// Return the totals for every region recorded so far, across all
+    /// threads, sorted by name.
+    pub fn regions(&self) -> Vec<(&'static str, SpanTotals)> {
+        self.workers.spans()
+    }
+
+    /// Forget all regions recorded so far.
+    pub fn clear(&self) {
+        self.workers.clear()
+    }
+}
@@ -0,0 +1,417 @@
+//! Naming the registers a sample can include.
+//!
+//! With [`Builder::sample_regs_user`], each sample includes the values of
+//! the chosen user-space registers at the time it was taken: enough to seed
+//! a frame-pointer or DWARF unwinder. Which registers exist depends on the
+//! processor architecture, so this module's [`Register`] type does too,
+//! following the kernel's `asm/perf_regs.h` for the architecture the crate
+//! is compiled for:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::regs::Register;
+//!     use perf_event::samples::RecordEvent;
+//!
+//!     let mut sampler = Builder::new()
+//!         .kind(Software::TASK_CLOCK)
+//!         .sample_period(100_000)
+//!         .sample_regs_user(&[Register::SP])
+//!         .build_sampler()?;
+//!
+//!     sampler.enable()?;
+//!     let _spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+//!     sampler.disable()?;
+//!
+//!     while let Some(record) = sampler.next() {
+//!         if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+//!             if let Some(regs) = sample.regs_user {
+//!                 println!("sp = {:#x?}", regs.get(Register::SP));
+//!             }
+//!         }
+//!     }
+//!     # Ok(()) }
+//!
+//! [`Builder::sample_regs_user`]: crate::Builder::sample_regs_user
+
+/// Define `Register` for one architecture, with the kernel's index for
+/// each register.
+macro_rules! registers {
+    ( $( $(#[$attr:meta])* $name:ident = $index:literal => $text:literal, )* ) => {
+        /// A register whose value a sample can include, numbered as in the
+        /// kernel's `asm/perf_regs.h` for this architecture.
+        ///
+        /// See the [module documentation](self) for details.
+        #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+        #[allow(missing_docs)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum Register {
+            $( $(#[$attr])* $name = $index, )*
+        }
+
+        impl Register {
+            /// All the registers, in order of their indexes.
+            pub const ALL: &'static [Register] = &[ $( Register::$name, )* ];
+
+            /// Return this register's conventional name, in lower case.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $( Register::$name => $text, )*
+                }
+            }
+
+            /// Return the register with the kernel's index `index`, if
+            /// there is one.
+            pub fn from_index(index: u32) -> Option<Register> {
+                match index {
+                    $( $index => Some(Register::$name), )*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+registers! {
+    AX = 0 => "ax",
+    BX = 1 => "bx",
+    CX = 2 => "cx",
+    DX = 3 => "dx",
+    SI = 4 => "si",
+    DI = 5 => "di",
+    BP = 6 => "bp",
+    SP = 7 => "sp",
+    IP = 8 => "ip",
+    FLAGS = 9 => "flags",
+    CS = 10 => "cs",
+    SS = 11 => "ss",
+    /// The kernel rejects sampling this register in 64-bit processes.
+    DS = 12 => "ds",
+    /// The kernel rejects sampling this register in 64-bit processes.
+    ES = 13 => "es",
+    /// The kernel rejects sampling this register in 64-bit processes.
+    FS = 14 => "fs",
+    /// The kernel rejects sampling this register in 64-bit processes.
+    GS = 15 => "gs",
+    R8 = 16 => "r8",
+    R9 = 17 => "r9",
+    R10 = 18 => "r10",
+    R11 = 19 => "r11",
+    R12 = 20 => "r12",
+    R13 = 21 => "r13",
+    R14 = 22 => "r14",
+    R15 = 23 => "r15",
+}
+
+#[cfg(target_arch = "x86")]
+registers! {
+    AX = 0 => "ax",
+    BX = 1 => "bx",
+    CX = 2 => "cx",
+    DX = 3 => "dx",
+    SI = 4 => "si",
+    DI = 5 => "di",
+    BP = 6 => "bp",
+    SP = 7 => "sp",
+    IP = 8 => "ip",
+    FLAGS = 9 => "flags",
+    CS = 10 => "cs",
+    SS = 11 => "ss",
+    DS = 12 => "ds",
+    ES = 13 => "es",
+    FS = 14 => "fs",
+    GS = 15 => "gs",
+}
+
+#[cfg(target_arch = "arm")]
+registers! {
+    R0 = 0 => "r0",
+    R1 = 1 => "r1",
+    R2 = 2 => "r2",
+    R3 = 3 => "r3",
+    R4 = 4 => "r4",
+    R5 = 5 => "r5",
+    R6 = 6 => "r6",
+    R7 = 7 => "r7",
+    R8 = 8 => "r8",
+    R9 = 9 => "r9",
+    R10 = 10 => "r10",
+    /// The frame pointer, `r11`.
+    FP = 11 => "fp",
+    /// The intra-procedure-call scratch register, `r12`.
+    IP = 12 => "ip",
+    SP = 13 => "sp",
+    /// The link register.
+    LR = 14 => "lr",
+    PC = 15 => "pc",
+}
+
+#[cfg(target_arch = "aarch64")]
+registers! {
+    X0 = 0 => "x0",
+    X1 = 1 => "x1",
+    X2 = 2 => "x2",
+    X3 = 3 => "x3",
+    X4 = 4 => "x4",
+    X5 = 5 => "x5",
+    X6 = 6 => "x6",
+    X7 = 7 => "x7",
+    X8 = 8 => "x8",
+    X9 = 9 => "x9",
+    X10 = 10 => "x10",
+    X11 = 11 => "x11",
+    X12 = 12 => "x12",
+    X13 = 13 => "x13",
+    X14 = 14 => "x14",
+    X15 = 15 => "x15",
+    X16 = 16 => "x16",
+    X17 = 17 => "x17",
+    X18 = 18 => "x18",
+    X19 = 19 => "x19",
+    X20 = 20 => "x20",
+    X21 = 21 => "x21",
+    X22 = 22 => "x22",
+    X23 = 23 => "x23",
+    X24 = 24 => "x24",
+    X25 = 25 => "x25",
+    X26 = 26 => "x26",
+    X27 = 27 => "x27",
+    X28 = 28 => "x28",
+    /// The frame pointer.
+    X29 = 29 => "x29",
+    /// The link register.
+    LR = 30 => "lr",
+    SP = 31 => "sp",
+    PC = 32 => "pc",
+}
+
+#[cfg(target_arch = "riscv64")]
+registers! {
+    PC = 0 => "pc",
+    RA = 1 => "ra",
+    SP = 2 => "sp",
+    GP = 3 => "gp",
+    TP = 4 => "tp",
+    T0 = 5 => "t0",
+    T1 = 6 => "t1",
+    T2 = 7 => "t2",
+    /// The frame pointer.
+    S0 = 8 => "s0",
+    S1 = 9 => "s1",
+    A0 = 10 => "a0",
+    A1 = 11 => "a1",
+    A2 = 12 => "a2",
+    A3 = 13 => "a3",
+    A4 = 14 => "a4",
+    A5 = 15 => "a5",
+    A6 = 16 => "a6",
+    A7 = 17 => "a7",
+    S2 = 18 => "s2",
+    S3 = 19 => "s3",
+    S4 = 20 => "s4",
+    S5 = 21 => "s5",
+    S6 = 22 => "s6",
+    S7 = 23 => "s7",
+    S8 = 24 => "s8",
+    S9 = 25 => "s9",
+    S10 = 26 => "s10",
+    S11 = 27 => "s11",
+    T3 = 28 => "t3",
+    T4 = 29 => "t4",
+    T5 = 30 => "t5",
+    T6 = 31 => "t6",
+}
+
+#[cfg(target_arch = "loongarch64")]
+registers! {
+    PC = 0 => "pc",
+    /// The return address.
+    R1 = 1 => "r1",
+    /// The thread pointer.
+    R2 = 2 => "r2",
+    /// The stack pointer.
+    R3 = 3 => "r3",
+    R4 = 4 => "r4",
+    R5 = 5 => "r5",
+    R6 = 6 => "r6",
+    R7 = 7 => "r7",
+    R8 = 8 => "r8",
+    R9 = 9 => "r9",
+    R10 = 10 => "r10",
+    R11 = 11 => "r11",
+    R12 = 12 => "r12",
+    R13 = 13 => "r13",
+    R14 = 14 => "r14",
+    R15 = 15 => "r15",
+    R16 = 16 => "r16",
+    R17 = 17 => "r17",
+    R18 = 18 => "r18",
+    R19 = 19 => "r19",
+    R20 = 20 => "r20",
+    R21 = 21 => "r21",
+    /// The frame pointer.
+    R22 = 22 => "r22",
+    R23 = 23 => "r23",
+    R24 = 24 => "r24",
+    R25 = 25 => "r25",
+    R26 = 26 => "r26",
+    R27 = 27 => "r27",
+    R28 = 28 => "r28",
+    R29 = 29 => "r29",
+    R30 = 30 => "r30",
+    R31 = 31 => "r31",
+}
+
+#[cfg(target_arch = "loongarch64")]
+impl Register {
+    /// The stack pointer, `r3`, under the name other architectures use.
+    pub const SP: Register = Register::R3;
+}
+
+#[cfg(target_arch = "powerpc64")]
+registers! {
+    R0 = 0 => "r0",
+    /// The stack pointer.
+    R1 = 1 => "r1",
+    /// The TOC pointer.
+    R2 = 2 => "r2",
+    R3 = 3 => "r3",
+    R4 = 4 => "r4",
+    R5 = 5 => "r5",
+    R6 = 6 => "r6",
+    R7 = 7 => "r7",
+    R8 = 8 => "r8",
+    R9 = 9 => "r9",
+    R10 = 10 => "r10",
+    R11 = 11 => "r11",
+    R12 = 12 => "r12",
+    /// The thread pointer.
+    R13 = 13 => "r13",
+    R14 = 14 => "r14",
+    R15 = 15 => "r15",
+    R16 = 16 => "r16",
+    R17 = 17 => "r17",
+    R18 = 18 => "r18",
+    R19 = 19 => "r19",
+    R20 = 20 => "r20",
+    R21 = 21 => "r21",
+    R22 = 22 => "r22",
+    R23 = 23 => "r23",
+    R24 = 24 => "r24",
+    R25 = 25 => "r25",
+    R26 = 26 => "r26",
+    R27 = 27 => "r27",
+    R28 = 28 => "r28",
+    R29 = 29 => "r29",
+    R30 = 30 => "r30",
+    R31 = 31 => "r31",
+    /// The next instruction address: the program counter.
+    NIP = 32 => "nip",
+    MSR = 33 => "msr",
+    /// The value of `r3` on entry to a system call.
+    #[allow(non_camel_case_types)]
+    ORIG_R3 = 34 => "orig_r3",
+    CTR = 35 => "ctr",
+    /// The link register.
+    LINK = 36 => "link",
+    XER = 37 => "xer",
+    CCR = 38 => "ccr",
+    SOFTE = 39 => "softe",
+    TRAP = 40 => "trap",
+    DAR = 41 => "dar",
+    DSISR = 42 => "dsisr",
+    SIER = 43 => "sier",
+    MMCRA = 44 => "mmcra",
+}
+
+#[cfg(target_arch = "powerpc64")]
+impl Register {
+    /// The stack pointer, `r1`, under the name other architectures use.
+    pub const SP: Register = Register::R1;
+}
+
+#[cfg(target_arch = "s390x")]
+registers! {
+    R0 = 0 => "r0",
+    R1 = 1 => "r1",
+    R2 = 2 => "r2",
+    R3 = 3 => "r3",
+    R4 = 4 => "r4",
+    R5 = 5 => "r5",
+    R6 = 6 => "r6",
+    R7 = 7 => "r7",
+    R8 = 8 => "r8",
+    R9 = 9 => "r9",
+    R10 = 10 => "r10",
+    /// The frame pointer, when there is one.
+    R11 = 11 => "r11",
+    R12 = 12 => "r12",
+    R13 = 13 => "r13",
+    /// The return address.
+    R14 = 14 => "r14",
+    /// The stack pointer.
+    R15 = 15 => "r15",
+    FP0 = 16 => "fp0",
+    FP1 = 17 => "fp1",
+    FP2 = 18 => "fp2",
+    FP3 = 19 => "fp3",
+    FP4 = 20 => "fp4",
+    FP5 = 21 => "fp5",
+    FP6 = 22 => "fp6",
+    FP7 = 23 => "fp7",
+    FP8 = 24 => "fp8",
+    FP9 = 25 => "fp9",
+    FP10 = 26 => "fp10",
+    FP11 = 27 => "fp11",
+    FP12 = 28 => "fp12",
+    FP13 = 29 => "fp13",
+    FP14 = 30 => "fp14",
+    FP15 = 31 => "fp15",
+    /// The first half of the program status word.
+    MASK = 32 => "mask",
+    /// The instruction address: the second half of the program status
+    /// word.
+    PC = 33 => "pc",
+}
+
+#[cfg(target_arch = "s390x")]
+impl Register {
+    /// The stack pointer, `r15`, under the name other architectures use.
+    pub const SP: Register = Register::R15;
+}
+
+impl Register {
+    /// Return the mask bit that selects this register in
+    /// `sample_regs_user`.
+    pub(crate) fn mask(self) -> u64 {
+        1 << (self as u32)
+    }
+}
+
+/// Register values from a sample, as requested with
+/// [`Builder::sample_regs_user`].
+///
+/// [`Builder::sample_regs_user`]: crate::Builder::sample_regs_user
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    /// The ABI of the sampled code: one of the kernel's
+    /// `PERF_SAMPLE_REGS_ABI_*` values. If this is
+    /// `PERF_SAMPLE_REGS_ABI_NONE`, the sample was taken in a kernel thread,
+    /// which has no user registers, and `values` is empty.
+    pub abi: u64,
+
+    /// The values of the registers, in order of their indexes.
+    pub values: Vec<(Register, u64)>,
+}
+
+impl Registers {
+    /// Return the value of `register`, if the sample included it.
+    pub fn get(&self, register: Register) -> Option<u64> {
+        self.values
+            .iter()
+            .find(|(r, _)| *r == register)
+            .map(|&(_, value)| value)
+    }
+}
@@ -0,0 +1,624 @@
+//! Reading records from many samplers at once.
+//!
+//! Sampling a whole machine takes one [`Sampler`] per CPU, since
+//! system-wide events must each be opened on a particular CPU (see
+//! [`Builder::system_wide_on_cpu`], or [`Builder::build_sampler_on_cpus`],
+//! which builds them all). Sampling several events takes a
+//! `Sampler` for each one, too. Either way, a consumer has to watch all the
+//! samplers' ring buffers, and usually wants their records merged into a
+//! single stream in the order they happened.
+//!
+//! A [`SamplerSet`] does that job. It owns a collection of samplers,
+//! enables and disables them together, and returns their records one at a
+//! time, each with the index of the sampler it came from:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::sampler_set::SamplerSet;
+//!     use perf_event::samples::SampleType;
+//!     use std::time::Duration;
+//!
+//!     let mut set = SamplerSet::sorted(Duration::from_millis(10));
+//!     for kind in [Software::TASK_CLOCK, Software::PAGE_FAULTS] {
+//!         set.push(
+//!             Builder::new()
+//!                 .kind(kind)
+//!                 .sample_period(1000)
+//!                 .sample(SampleType::TIME | SampleType::IP)
+//!                 .build_sampler()?,
+//!         );
+//!     }
+//!
+//!     set.enable()?;
+//!     let pages: Vec<Vec<u8>> = (0..100).map(|_| vec![1; 4096]).collect();
+//!     set.disable()?;
+//!
+//!     set.flush();
+//!     while let Some((index, record)) = set.next() {
+//!         let config = set.samplers()[index].config();
+//!         println!("{:?}: {:?}", record.time(config), record.parse(config));
+//!     }
+//!     # drop(pages);
+//!     # Ok(()) }
+//!
+//! ## Ordering
+//!
+//! The kernel writes each sampler's records in order, but records in
+//! different samplers' buffers can interleave arbitrarily. A set created
+//! with [`SamplerSet::new`] returns records in whatever order it finds
+//! them, taking turns among the samplers so that a busy one can't starve
+//! the others.
+//!
+//! A set created with [`SamplerSet::sorted`] returns records in order of
+//! their timestamps, as `perf report` does. Since a record on one CPU may
+//! turn up after later records on another, the set holds each record back
+//! until it has seen a record at least a given *reordering window* newer,
+//! and only then returns it. A wider window tolerates more delay between
+//! the CPUs' buffers, at the cost of holding more records in memory.
+//! Records still buffered when sampling stops come out after a call to
+//! [`flush`].
+//!
+//! Only samples that include [`SampleType::TIME`] have timestamps. Other
+//! records are given the time of the latest timestamp from the same
+//! sampler, so they stay in place relative to that sampler's samples.
+//!
+//! ## Consuming records on each CPU
+//!
+//! When sampling every CPU of a busy machine, one thread may not keep up
+//! with all the buffers. [`SamplerSet::spawn_pinned`] hands each sampler to
+//! a thread of its own, pinned with `sched_setaffinity` to the CPU the
+//! sampler watches, so that each buffer is read on the CPU that writes it.
+//! The threads pass records to a consumer function as they arrive, until
+//! [`PinnedConsumers::stop`] stops them. Records from different samplers
+//! are not merged or sorted.
+//!
+//! Samplers built by [`Builder::build_sampler_on_cpus`] know their CPUs;
+//! add others with [`SamplerSet::push_on_cpu`].
+//!
+//! ## Shutting down
+//!
+//! When you're done sampling, [`SamplerSet::shutdown`] disables all the
+//! samplers, collects whatever records are left in their buffers, sorted
+//! or not as the set would have returned them, and closes the samplers,
+//! returning the records and each sampler's count of lost records in a
+//! [`Shutdown`] summary.
+//!
+//! [`Sampler`]: crate::samples::Sampler
+//! [`Builder::system_wide_on_cpu`]: crate::Builder::system_wide_on_cpu
+//! [`Builder::build_sampler_on_cpus`]: crate::Builder::build_sampler_on_cpus
+//! [`SampleType::TIME`]: crate::samples::SampleType::TIME
+//! [`flush`]: SamplerSet::flush
+
+use crate::samples::{ParseConfig, Record, Sampler};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// A collection of [`Sampler`]s whose records are read as one stream.
+///
+/// See the [module documentation](self) for details.
+///
+/// [`Sampler`]: crate::samples::Sampler
+pub struct SamplerSet {
+    samplers: Vec<Sampler>,
+
+    /// The CPU each sampler watches, if known.
+    cpus: Vec<Option<usize>>,
+
+    /// The reordering window, in nanoseconds, if this set sorts its
+    /// records.
+    window: Option<u64>,
+
+    /// When unsorted, the index of the sampler to check first in `next`.
+    turn: usize,
+
+    /// When sorted, the records we've read but not yet returned.
+    pending: BinaryHeap<Reverse<Pending>>,
+
+    /// When sorted, the latest timestamp we've seen from each sampler.
+    latest: Vec<u64>,
+
+    /// When sorted, the latest timestamp we've seen from any sampler.
+    newest: u64,
+
+    /// When sorted, the time up to which `flush` has released records.
+    flushed: u64,
+
+    /// The number of records pushed onto `pending` so far, used to keep
+    /// records with equal timestamps in the order we read them.
+    serial: u64,
+}
+
+/// What was left in a [`SamplerSet`] when it was shut down.
+///
+/// This is returned by [`SamplerSet::shutdown`].
+#[derive(Debug)]
+pub struct Shutdown {
+    /// The records that were still in the samplers' ring buffers, in the
+    /// order [`SamplerSet::next`] would have returned them, each with the
+    /// index of the sampler it came from.
+    pub records: Vec<(usize, Record)>,
+
+    /// Each sampler's total number of lost records and samples, by index,
+    /// as [`Sampler::lost_records`] would report it after reading
+    /// `records`.
+    pub lost: Vec<u64>,
+
+    /// Each sampler's parsing configuration, by index, for parsing
+    /// `records` now that the samplers themselves are gone.
+    pub configs: Vec<ParseConfig>,
+}
+
+impl Shutdown {
+    /// Return the total number of lost records and samples, over all the
+    /// samplers.
+    pub fn total_lost(&self) -> u64 {
+        self.lost.iter().sum()
+    }
+}
+
+/// A record waiting in a sorted [`SamplerSet`]'s reordering buffer.
+struct Pending {
+    time: u64,
+    serial: u64,
+    index: usize,
+    record: Record,
+}
+
+impl SamplerSet {
+    /// Return a new, empty set that returns records in the order it finds
+    /// them.
+    pub fn new() -> SamplerSet {
+        SamplerSet {
+            samplers: vec![],
+            cpus: vec![],
+            window: None,
+            turn: 0,
+            pending: BinaryHeap::new(),
+            latest: vec![],
+            newest: 0,
+            flushed: 0,
+            serial: 0,
+        }
+    }
+
+    /// Return a new, empty set that returns records in order of their
+    /// timestamps, holding each record back until it has seen one at least
+    /// `window` newer.
+    pub fn sorted(window: Duration) -> SamplerSet {
+        SamplerSet {
+            window: Some(window.as_nanos().min(u64::MAX as u128) as u64),
+            ..SamplerSet::new()
+        }
+    }
+
+    /// Add `sampler` to this set, and return its index, which [`next`]
+    /// uses to identify the records it produces.
+    ///
+    /// [`next`]: SamplerSet::next
+    pub fn push(&mut self, sampler: Sampler) -> usize {
+        self.samplers.push(sampler);
+        self.cpus.push(None);
+        self.latest.push(0);
+        self.samplers.len() - 1
+    }
+
+    /// Add `sampler`, which watches the CPU numbered `cpu`, to this set, and
+    /// return its index.
+    ///
+    /// This is like [`push`], but also tells [`spawn_pinned`] which CPU to
+    /// read the sampler's records on.
+    ///
+    /// [`push`]: SamplerSet::push
+    /// [`spawn_pinned`]: SamplerSet::spawn_pinned
+    pub fn push_on_cpu(&mut self, sampler: Sampler, cpu: usize) -> usize {
+        let index = self.push(sampler);
+        self.cpus[index] = Some(cpu);
+        index
+    }
+
+    /// Return the CPU the sampler at `index` watches, if it was added with
+    /// [`push_on_cpu`] or built by [`Builder::build_sampler_on_cpus`].
+    ///
+    /// [`push_on_cpu`]: SamplerSet::push_on_cpu
+    /// [`Builder::build_sampler_on_cpus`]: crate::Builder::build_sampler_on_cpus
+    pub fn cpu(&self, index: usize) -> Option<usize> {
+        self.cpus.get(index).copied().flatten()
+    }
+
+    /// Return the samplers in this set, in order of their indexes.
+    pub fn samplers(&self) -> &[Sampler] {
+        &self.samplers
+    }
+
+    /// Return the samplers in this set mutably, in order of their indexes.
+    pub fn samplers_mut(&mut self) -> &mut [Sampler] {
+        &mut self.samplers
+    }
+
+    /// Enable all the samplers.
+    pub fn enable(&mut self) -> io::Result<()> {
+        for sampler in &mut self.samplers {
+            sampler.enable()?;
+        }
+        Ok(())
+    }
+
+    /// Disable all the samplers.
+    pub fn disable(&mut self) -> io::Result<()> {
+        for sampler in &mut self.samplers {
+            sampler.disable()?;
+        }
+        Ok(())
+    }
+
+    /// Stop sampling, and return everything the samplers had left.
+    ///
+    /// This disables all the samplers, reads all the records remaining in
+    /// their ring buffers, including any held back in a sorted set's
+    /// reordering window, and then closes the samplers, unmapping their
+    /// buffers. The returned [`Shutdown`] holds the records, in the order
+    /// [`next`] would have returned them, along with each sampler's lost
+    /// record count and parsing configuration.
+    ///
+    /// If disabling a sampler fails, this returns the error, and the
+    /// samplers are closed without being drained.
+    ///
+    /// [`next`]: SamplerSet::next
+    pub fn shutdown(mut self) -> io::Result<Shutdown> {
+        self.disable()?;
+        self.flush();
+        let mut records = vec![];
+        while let Some(next) = self.next() {
+            records.push(next);
+        }
+        Ok(Shutdown {
+            records,
+            lost: self.samplers.iter().map(Sampler::lost_records).collect(),
+            configs: self
+                .samplers
+                .iter()
+                .map(|sampler| *sampler.config())
+                .collect(),
+        })
+    }
+
+    /// Read each sampler's records on a thread of its own, pinned to the
+    /// sampler's CPU, passing them to `consumer`.
+    ///
+    /// This consumes the set, and starts one thread per sampler. Each thread
+    /// pins itself to the CPU given by [`cpu`], if any, and then calls
+    /// `consumer(index, sampler, record)` for each record as it arrives,
+    /// where `index` is the sampler's index in this set. The threads run
+    /// until [`PinnedConsumers::stop`] is called, or the sampler's event
+    /// can produce no more records. Sorted sets' reordering windows have no
+    /// effect here.
+    ///
+    /// This doesn't enable the samplers; call [`enable`] first.
+    ///
+    /// If pinning a thread fails, it stops, and `stop` returns the error.
+    ///
+    /// [`cpu`]: SamplerSet::cpu
+    /// [`enable`]: SamplerSet::enable
+    pub fn spawn_pinned<F>(self, consumer: F) -> io::Result<PinnedConsumers>
+    where
+        F: Fn(usize, &Sampler, Record) + Send + Sync + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let consumer = Arc::new(consumer);
+        let mut threads = vec![];
+        for (index, (sampler, cpu)) in self.samplers.into_iter().zip(self.cpus).enumerate() {
+            let thread_stop = stop.clone();
+            let consumer = consumer.clone();
+            let thread = std::thread::Builder::new()
+                .name(match cpu {
+                    Some(cpu) => format!("perf-cpu{}", cpu),
+                    None => format!("perf-sampler{}", index),
+                })
+                .spawn(move || consume_pinned(index, sampler, cpu, &thread_stop, &*consumer));
+            match thread {
+                Ok(thread) => threads.push(thread),
+                Err(error) => {
+                    // Stop the threads we did start before giving up.
+                    PinnedConsumers { stop, threads }.stop()?;
+                    return Err(error);
+                }
+            }
+        }
+        Ok(PinnedConsumers { stop, threads })
+    }
+
+    /// Return the next record from any of the samplers, along with the
+    /// index of the sampler it came from. If there are no records
+    /// available, return `None`. This never blocks.
+    ///
+    /// In a sorted set, this also returns `None` while the records on hand
+    /// are all within the reordering window of the newest; see the [module
+    /// documentation](self).
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(usize, Record)> {
+        let window = match self.window {
+            Some(window) => window,
+            None => return self.next_unsorted(),
+        };
+
+        self.fill();
+        let limit = self.newest.saturating_sub(window).max(self.flushed);
+        match self.pending.peek() {
+            Some(Reverse(pending)) if pending.time <= limit => {
+                let Reverse(pending) = self.pending.pop().unwrap();
+                Some((pending.index, pending.record))
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the next record, waiting for one to arrive if necessary.
+    ///
+    /// This uses `poll(2)` to sleep on all the samplers' file descriptors at
+    /// once. If `timeout` is `Some`, give up and return `Ok(None)` if no
+    /// record becomes available within that time; if `timeout` is `None`,
+    /// wait indefinitely. Once every sampler's event can produce no more
+    /// records, because the processes they observe have exited, this
+    /// flushes a sorted set's buffered records, and returns `Ok(None)` when
+    /// they're gone. An empty set returns `Ok(None)` immediately, since no
+    /// record could ever arrive.
+    ///
+    /// A sorted set holds back the newest records until newer ones arrive,
+    /// so after sampling stops, call [`flush`] to release them.
+    ///
+    /// [`flush`]: SamplerSet::flush
+    pub fn next_blocking(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> io::Result<Option<(usize, Record)>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(next) = self.next() {
+                return Ok(Some(next));
+            }
+            if self.samplers.is_empty() {
+                return Ok(None);
+            }
+
+            let wait = match deadline {
+                None => -1,
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(None);
+                    }
+                    // Round up, so we don't spin when less than a
+                    // millisecond remains.
+                    remaining
+                        .as_nanos()
+                        .div_ceil(1_000_000)
+                        .min(i32::MAX as u128) as i32
+                }
+            };
+
+            let mut pollfds: Vec<libc::pollfd> = self
+                .samplers
+                .iter()
+                .map(|sampler| libc::pollfd {
+                    fd: sampler.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                })
+                .collect();
+            match unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, wait) } {
+                -1 => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+                _ if pollfds
+                    .iter()
+                    .all(|pollfd| pollfd.revents & libc::POLLHUP != 0) =>
+                {
+                    self.flush();
+                    return Ok(self.next());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Let [`next`] return all the records available so far, even those
+    /// within the reordering window. This has no effect on an unsorted
+    /// set.
+    ///
+    /// Call this after disabling the samplers, to retrieve the records the
+    /// set would otherwise hold back waiting for newer ones. Records that
+    /// arrive later are still sorted among themselves, but may be older
+    /// than records this lets through.
+    ///
+    /// [`next`]: SamplerSet::next
+    pub fn flush(&mut self) {
+        if self.window.is_some() {
+            self.fill();
+            self.flushed = self.newest;
+        }
+    }
+
+    /// Return the next available record, taking turns among the samplers.
+    fn next_unsorted(&mut self) -> Option<(usize, Record)> {
+        let len = self.samplers.len();
+        let turn = self.turn;
+        for i in (0..len).map(|i| (turn + i) % len) {
+            if let Some(record) = self.samplers[i].next() {
+                self.turn = (i + 1) % len;
+                return Some((i, record));
+            }
+        }
+        None
+    }
+
+    /// Move all the records available from the samplers into `pending`.
+    fn fill(&mut self) {
+        for (index, sampler) in self.samplers.iter_mut().enumerate() {
+            while let Some(record) = sampler.next() {
+                let time = match record.time(sampler.config()) {
+                    Some(time) => {
+                        self.latest[index] = self.latest[index].max(time);
+                        self.newest = self.newest.max(time);
+                        time
+                    }
+                    None => self.latest[index],
+                };
+                self.pending.push(Reverse(Pending {
+                    time,
+                    serial: self.serial,
+                    index,
+                    record,
+                }));
+                self.serial += 1;
+            }
+        }
+    }
+}
+
+/// How long each of `spawn_pinned`'s threads waits for records before
+/// checking whether it has been asked to stop.
+const PINNED_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The body of each of `spawn_pinned`'s threads.
+fn consume_pinned<F>(
+    index: usize,
+    mut sampler: Sampler,
+    cpu: Option<usize>,
+    stop: &AtomicBool,
+    consumer: &F,
+) -> io::Result<Sampler>
+where
+    F: Fn(usize, &Sampler, Record),
+{
+    if let Some(cpu) = cpu {
+        pin_to_cpu(cpu)?;
+    }
+    while !stop.load(AtomicOrdering::Relaxed) {
+        match sampler.next_blocking(Some(PINNED_POLL_INTERVAL))? {
+            Some(record) => consumer(index, &sampler, record),
+            // The event can produce no more records.
+            None if hung_up(&sampler) => break,
+            None => {}
+        }
+    }
+    sampler.disable()?;
+    while let Some(record) = sampler.next() {
+        consumer(index, &sampler, record);
+    }
+    Ok(sampler)
+}
+
+/// Restrict the calling thread to run only on `cpu`.
+fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) != 0 {
+            let error = io::Error::last_os_error();
+            return Err(io::Error::new(
+                error.kind(),
+                format!("couldn't pin sampler thread to CPU {}: {}", cpu, error),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Return true if `sampler`'s event can produce no more records.
+fn hung_up(sampler: &Sampler) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd: sampler.as_raw_fd(),
+        events: 0,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut pollfd, 1, 0) == 1 && pollfd.revents & libc::POLLHUP != 0 }
+}
+
+/// Threads reading a [`SamplerSet`]'s samplers, each pinned to its
+/// sampler's CPU.
+///
+/// This is returned by [`SamplerSet::spawn_pinned`]. Dropping it without
+/// calling [`stop`] leaves the threads running until their events can
+/// produce no more records.
+///
+/// [`stop`]: PinnedConsumers::stop
+#[derive(Debug)]
+pub struct PinnedConsumers {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<io::Result<Sampler>>>,
+}
+
+impl PinnedConsumers {
+    /// Stop the threads, and return the samplers, in order of their
+    /// indexes.
+    ///
+    /// Each thread disables its sampler, and passes any records left in
+    /// the sampler's buffer to the consumer before it exits. If any thread
+    /// failed, this returns the first error. If the consumer panicked, this
+    /// panics too.
+    pub fn stop(self) -> io::Result<Vec<Sampler>> {
+        self.stop.store(true, AtomicOrdering::Relaxed);
+        let mut samplers = vec![];
+        let mut first_error = None;
+        for thread in self.threads {
+            match thread.join() {
+                Ok(Ok(sampler)) => samplers.push(sampler),
+                Ok(Err(error)) => {
+                    first_error.get_or_insert(error);
+                }
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(samplers),
+        }
+    }
+}
+
+impl Default for SamplerSet {
+    fn default() -> SamplerSet {
+        SamplerSet::new()
+    }
+}
+
+impl fmt::Debug for SamplerSet {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SamplerSet")
+            .field("samplers", &self.samplers)
+            .field("window", &self.window)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Pending) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Pending) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Pending) -> Ordering {
+        (self.time, self.serial).cmp(&(other.time, other.serial))
+    }
+}
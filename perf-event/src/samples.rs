@@ -0,0 +1,3861 @@
+//! Sampling: reading records from an event's ring buffer.
+//!
+//! A [`Counter`] only counts events. If you configure an event to take a
+//! sample every so many occurrences, with [`Builder::sample_period`], or so
+//! many times a second, with [`Builder::sample_frequency`], the kernel writes
+//! a record describing each sample into a ring buffer shared with user
+//! space. [`Builder::build_sampler`] creates such an event and maps its ring
+//! buffer, returning a [`Sampler`], from which you can read [`Record`]s:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use std::time::Duration;
+//!
+//!     // Take a sample every 100µs of CPU time.
+//!     let mut sampler = Builder::new()
+//!         .kind(Software::TASK_CLOCK)
+//!         .sample_period(100_000)
+//!         .build_sampler()?;
+//!
+//!     sampler.enable()?;
+//!     let spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+//!     sampler.disable()?;
+//!
+//!     while let Some(record) = sampler.next_blocking(Some(Duration::ZERO))? {
+//!         println!("record of type {}, {} bytes", record.ty, record.data.len());
+//!     }
+//!     # Ok(()) }
+//!
+//! A `Sampler` dereferences to its [`Counter`], so you can enable, disable,
+//! and read it directly.
+//!
+//! Each [`Record`] `next` returns is copied out of the ring buffer. To read
+//! records in place instead, use [`Sampler::iter_chunks`].
+//!
+//! ## Parsing records
+//!
+//! A [`Record`] holds the raw bytes the kernel wrote. To decode it, call
+//! [`Record::parse`] with the sampler's [`ParseConfig`], which records how
+//! the event was configured, and thus how its records are laid out. This
+//! produces a [`RecordEvent`]: for example, a [`Sample`], whose fields hold
+//! whatever [`SampleType`] values were requested with [`Builder::sample`]:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::samples::{RecordEvent, SampleType};
+//!
+//!     let mut sampler = Builder::new()
+//!         .kind(Software::TASK_CLOCK)
+//!         .sample_period(100_000)
+//!         .sample(SampleType::IP | SampleType::TID)
+//!         .build_sampler()?;
+//!
+//!     sampler.enable()?;
+//!     let spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+//!     sampler.disable()?;
+//!
+//!     while let Some(record) = sampler.next() {
+//!         if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+//!             println!("thread {:?} at {:#x?}", sample.tid, sample.ip);
+//!         }
+//!     }
+//!     # Ok(()) }
+//!
+//! A record too short to hold what its type and the `ParseConfig` call for
+//! makes [`Record::parse`] and [`Sampler::parse`] panic. Programs that must
+//! keep running whatever the kernel sends, like long-lived daemons, should
+//! use [`Record::try_parse`] or [`Sampler::try_parse`], which return a
+//! [`ParseError`] instead:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     # use perf_event::Builder;
+//!     # use perf_event::events::Software;
+//!     # use perf_event::samples::{RecordEvent, SampleType};
+//!     # let mut sampler = Builder::new()
+//!     #     .kind(Software::TASK_CLOCK)
+//!     #     .sample_period(100_000)
+//!     #     .sample(SampleType::IP | SampleType::TID)
+//!     #     .build_sampler()?;
+//!     while let Some(record) = sampler.next() {
+//!         match sampler.try_parse(&record) {
+//!             Ok(RecordEvent::Sample(sample)) => println!("{:#x?}", sample.ip),
+//!             Ok(_) => {}
+//!             Err(error) => eprintln!("skipping bad record: {}", error),
+//!         }
+//!     }
+//!     # Ok(()) }
+//!
+//! ## Sharing a ring buffer
+//!
+//! Each `Sampler` maps its own ring buffer. To merge the samples from several
+//! events into one buffer, build the others as plain counters with
+//! [`Builder::output_to`], or pass them to [`Sampler::redirect_output`].
+//!
+//! ## Ring buffer size
+//!
+//! By default, a `Sampler`'s ring buffer holds 16 pages of records, plus a
+//! page of metadata. If records arrive faster than you read them, the
+//! kernel drops them and reports the loss with a [`RecordEvent::Lost`]
+//! record; a bigger buffer helps. Pass a [`BufferSize`] to
+//! [`Builder::buffer_size`] to choose another size:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::samples::BufferSize;
+//!
+//!     let mut sampler = Builder::new()
+//!         .kind(Software::TASK_CLOCK)
+//!         .sample_period(100_000)
+//!         .buffer_size(BufferSize::bytes_at_least(1 << 20))
+//!         .build_sampler()?;
+//!     # Ok(()) }
+//!
+//! Unprivileged processes may lock only [`mlock_limit_kb`] kibibytes of
+//! ring buffer per CPU, beyond which they are charged against their
+//! `RLIMIT_MEMLOCK`. If a buffer doesn't fit, [`Builder::build_sampler`]
+//! returns a [`BufferTooLarge`] error saying so.
+//!
+//! ## Flight recorders
+//!
+//! Normally the kernel stops writing records when the ring buffer fills,
+//! until you read some. To keep just the most recent records instead, call
+//! [`Builder::overwrite`]: the kernel then overwrites the oldest records as
+//! it goes, and [`Sampler::snapshot`] returns what's currently in the buffer,
+//! newest first. This is useful for capturing what happened just before
+//! something went wrong:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::samples::SampleType;
+//!
+//!     let mut recorder = Builder::new()
+//!         .kind(Software::TASK_CLOCK)
+//!         .sample_period(100_000)
+//!         .sample(SampleType::IP)
+//!         .overwrite(true)
+//!         .build_sampler()?;
+//!
+//!     recorder.enable()?;
+//!     // ... run until something goes wrong ...
+//!     for record in recorder.snapshot()? {
+//!         println!("{:?}", record.parse(recorder.config()));
+//!     }
+//!     # Ok(()) }
+//!
+//! ## Hardware tracing
+//!
+//! Tracing PMUs like Intel PT write their trace data to a second buffer,
+//! the AUX area, rather than to records. [`Builder::build_aux_sampler`]
+//! maps one alongside the usual ring buffer, returning an [`AuxSampler`].
+//!
+//! When several events send output to one AUX area, as with PEBS via PT,
+//! [`PERF_RECORD_AUX_OUTPUT_HW_ID`][hwid] records say which hardware
+//! produced the data that follows. [`AuxSampler::register_decoder`] attaches
+//! a decoder for each hardware ID, and [`AuxSampler::dispatch`] hands each
+//! stretch of AUX data to the right one.
+//!
+//! ## Event loops
+//!
+//! A `Sampler`'s file descriptor becomes readable when the kernel wakes
+//! readers, so you can register it with `epoll`, `mio`, or any other event
+//! loop alongside your program's other file descriptors; `Sampler` and
+//! `AuxSampler` implement `AsRawFd` and `AsFd` for this. When the loop
+//! reports the descriptor readable, [`Sampler::poll_events`] says which of
+//! the buffers has data, without making a system call. If the loop reports
+//! a hangup, the observed process has exited, and once you've drained the
+//! buffers, no more records will arrive.
+//!
+//! [`Counter`]: crate::Counter
+//! [hwid]: RecordEvent::AuxOutputHwId
+//! [`Builder::build_aux_sampler`]: crate::Builder::build_aux_sampler
+//! [`Builder::overwrite`]: crate::Builder::overwrite
+//! [`Builder::output_to`]: crate::Builder::output_to
+//! [`Builder::sample_period`]: crate::Builder::sample_period
+//! [`Builder::sample_frequency`]: crate::Builder::sample_frequency
+//! [`Builder::build_sampler`]: crate::Builder::build_sampler
+//! [`Builder::sample`]: crate::Builder::sample
+//! [`Builder::buffer_size`]: crate::Builder::buffer_size
+
+#[cfg(feature = "sampler_set")]
+use crate::paranoid::Level;
+use crate::regs::{Register, Registers};
+use crate::{sys, Counter};
+use bitflags::bitflags;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd};
+use std::ptr::NonNull;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use sys::bindings::{self, perf_event_attr, perf_event_header, perf_event_mmap_page};
+
+/// The number of data pages in a sampler's ring buffer, unless the
+/// `Builder` says otherwise. This must be a power of two.
+pub(crate) const DATA_PAGES: usize = 16;
+
+/// Where the kernel says how much ring buffer each user may lock per CPU.
+const MLOCK_KB_PATH: &str = "/proc/sys/kernel/perf_event_mlock_kb";
+
+/// The size of a [`Sampler`]'s ring buffer.
+///
+/// The kernel requires a ring buffer's data area to be a power of two
+/// pages long; it is mapped together with one more page of metadata, for
+/// a total of `1 + 2^n` pages. A `BufferSize` can only hold a size the
+/// kernel accepts. Pass it to [`Builder::buffer_size`].
+///
+/// The default is 16 pages of data.
+///
+/// [`Builder::buffer_size`]: crate::Builder::buffer_size
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BufferSize {
+    data_pages: usize,
+}
+
+impl BufferSize {
+    /// Return a size of `data_pages` pages of data.
+    ///
+    /// If `data_pages` is not a power of two, return an error of kind
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn pages(data_pages: usize) -> io::Result<BufferSize> {
+        if !data_pages.is_power_of_two() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "ring buffer size must be a power of two pages, not {}",
+                    data_pages
+                ),
+            ));
+        }
+        Ok(BufferSize { data_pages })
+    }
+
+    /// Return the smallest size whose data area holds at least `bytes`
+    /// bytes, and at least one page.
+    pub fn bytes_at_least(bytes: usize) -> BufferSize {
+        let pages = bytes.div_ceil(page_size());
+        BufferSize {
+            data_pages: pages.max(1).next_power_of_two(),
+        }
+    }
+
+    /// Return the number of pages of data.
+    pub fn data_pages(self) -> usize {
+        self.data_pages
+    }
+
+    /// Return the size of the data area, in bytes.
+    pub fn data_bytes(self) -> usize {
+        self.data_pages * page_size()
+    }
+
+    /// Return the size of the whole mapping, in bytes: the data area plus
+    /// the metadata page.
+    pub fn mmap_len(self) -> usize {
+        (1 + self.data_pages) * page_size()
+    }
+}
+
+impl Default for BufferSize {
+    fn default() -> BufferSize {
+        BufferSize {
+            data_pages: DATA_PAGES,
+        }
+    }
+}
+
+/// Return how many kibibytes of ring buffer each user may lock per online
+/// CPU, from `/proc/sys/kernel/perf_event_mlock_kb`.
+///
+/// Processes with `CAP_IPC_LOCK`, or any process when
+/// `perf_event_paranoid` is `-1`, are exempt. Others may lock more only as
+/// far as their `RLIMIT_MEMLOCK` allows.
+pub fn mlock_limit_kb() -> io::Result<usize> {
+    let contents = fs::read_to_string(MLOCK_KB_PATH)?;
+    contents.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected contents in {}: {:?}", MLOCK_KB_PATH, contents),
+        )
+    })
+}
+
+/// An error mapping a [`Sampler`]'s ring buffer: the kernel refused to lock
+/// that much memory for this user.
+///
+/// When mapping a ring buffer fails with `EPERM`, [`Builder::build_sampler`]
+/// returns an [`io::Error`] of kind [`io::ErrorKind::PermissionDenied`]
+/// whose inner error is a `BufferTooLarge`, explaining the limit:
+///
+///     # fn main() -> std::io::Result<()> {
+///     use perf_event::Builder;
+///     use perf_event::events::Software;
+///     use perf_event::samples::{BufferSize, BufferTooLarge};
+///
+///     let built = Builder::new()
+///         .kind(Software::TASK_CLOCK)
+///         .sample_period(100_000)
+///         .buffer_size(BufferSize::pages(1024)?)
+///         .build_sampler();
+///     match built {
+///         Ok(sampler) => { /* ... */ }
+///         Err(e) => match e.get_ref().and_then(|e| e.downcast_ref::<BufferTooLarge>()) {
+///             Some(too_large) => println!("{}", too_large),
+///             None => return Err(e),
+///         },
+///     }
+///     # Ok(()) }
+///
+/// The original operating system error is available as the error's
+/// [`source`][std::error::Error::source].
+///
+/// [`Builder::build_sampler`]: crate::Builder::build_sampler
+#[derive(Debug)]
+pub struct BufferTooLarge {
+    mmap_len: usize,
+    limit_kb: Option<usize>,
+    error: io::Error,
+}
+
+impl BufferTooLarge {
+    /// If `error`, from mapping `mmap_len` bytes of ring buffer, is the
+    /// kernel's `EPERM`, wrap it up as a `BufferTooLarge`. Otherwise,
+    /// return it unchanged.
+    fn check(mmap_len: usize, error: io::Error) -> io::Error {
+        if error.raw_os_error() != Some(libc::EPERM) {
+            return error;
+        }
+        let too_large = BufferTooLarge {
+            mmap_len,
+            limit_kb: mlock_limit_kb().ok(),
+            error,
+        };
+        io::Error::new(io::ErrorKind::PermissionDenied, too_large)
+    }
+
+    /// Return the size of the mapping that failed, in bytes, including the
+    /// metadata page.
+    pub fn mmap_len(&self) -> usize {
+        self.mmap_len
+    }
+
+    /// Return the per-CPU limit from `/proc/sys/kernel/perf_event_mlock_kb`
+    /// when the mapping failed, if it could be read.
+    pub fn limit_kb(&self) -> Option<usize> {
+        self.limit_kb
+    }
+}
+
+impl fmt::Display for BufferTooLarge {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "can't lock {} KiB for a ring buffer: ",
+            self.mmap_len / 1024
+        )?;
+        if let Some(limit_kb) = self.limit_kb {
+            write!(
+                fmt,
+                "unprivileged processes may lock {} KiB per CPU, for all their \
+                 ring buffers together, ",
+                limit_kb
+            )?;
+        }
+        write!(
+            fmt,
+            "beyond which RLIMIT_MEMLOCK applies; use a smaller BufferSize, \
+             raise the limit with `sudo sysctl kernel.perf_event_mlock_kb=N`, \
+             or run with the CAP_IPC_LOCK capability"
+        )
+    }
+}
+
+impl std::error::Error for BufferTooLarge {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The size of a record's header, which precedes its `data`.
+const HEADER_SIZE: usize = std::mem::size_of::<perf_event_header>();
+
+/// A record read from a [`Sampler`]'s ring buffer.
+///
+/// The record's `ty` says what sort of record it is: one of the kernel's
+/// `PERF_RECORD_*` constants, like `PERF_RECORD_SAMPLE`. The layout of
+/// `data` depends on the type, and on how the event was configured; see the
+/// [`perf_event_open(2)`][man] man page.
+///
+/// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Record {
+    /// The record type, a `PERF_RECORD_*` value.
+    pub ty: u32,
+
+    /// The `misc` field of the record's header. This holds flags like
+    /// `PERF_RECORD_MISC_USER` and `PERF_RECORD_MISC_KERNEL`.
+    pub misc: u16,
+
+    /// The record's contents, following its header.
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    /// If this is a `LOST` or `LOST_SAMPLES` record, return its count;
+    /// otherwise, return zero. This doesn't need the `ParseConfig`.
+    fn lost_count(&self) -> u64 {
+        RecordRef::from(self).lost_count()
+    }
+
+    /// Decode this record, given the configuration of the event that
+    /// produced it.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the record is malformed; see [`RecordRef::parse`].
+    /// Use [`try_parse`] to get an error instead.
+    ///
+    /// [`try_parse`]: Record::try_parse
+    pub fn parse(&self, config: &ParseConfig) -> RecordEvent {
+        RecordRef::from(self).parse(config)
+    }
+
+    /// Decode this record, given the configuration of the event that
+    /// produced it, or return a [`ParseError`] if it is malformed.
+    pub fn try_parse(&self, config: &ParseConfig) -> Result<RecordEvent, ParseError> {
+        RecordRef::from(self).try_parse(config)
+    }
+
+    /// Return this record's timestamp, if it is a sample that includes one,
+    /// given the configuration of the event that produced it.
+    ///
+    /// This finds the time without decoding the rest of the sample.
+    pub fn time(&self, config: &ParseConfig) -> Option<u64> {
+        RecordRef::from(self).time(config)
+    }
+}
+
+/// A record borrowed from a [`Sampler`]'s ring buffer, as returned by
+/// [`RecordChunks::next`].
+///
+/// This is a [`Record`] whose `data` refers directly to the ring buffer's
+/// pages, rather than a copy of them. The only exception is a record that
+/// wraps around the end of the buffer, whose data is copied out to make it
+/// contiguous.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordRef<'a> {
+    /// The record type, a `PERF_RECORD_*` value.
+    pub ty: u32,
+
+    /// The `misc` field of the record's header.
+    pub misc: u16,
+
+    /// The record's contents, following its header.
+    pub data: Cow<'a, [u8]>,
+}
+
+impl RecordRef<'_> {
+    /// If this is a `LOST` or `LOST_SAMPLES` record, return its count;
+    /// otherwise, return zero. This doesn't need the `ParseConfig`.
+    fn lost_count(&self) -> u64 {
+        let mut parser = Parser::new(self.ty, &self.data);
+        let lost = match self.ty {
+            bindings::PERF_RECORD_LOST => Lost::parse(&mut parser).map(|lost| lost.lost),
+            bindings::PERF_RECORD_LOST_SAMPLES => parser.u64(),
+            _ => Ok(0),
+        };
+        lost.unwrap_or(0)
+    }
+
+    /// Decode this record, given the configuration of the event that
+    /// produced it.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the record is too short for what `config` says it
+    /// holds. That shouldn't happen if `config` came from the sampler that
+    /// produced the record, but programs that must not panic, or that parse
+    /// records from elsewhere, should use [`try_parse`] instead.
+    ///
+    /// [`try_parse`]: RecordRef::try_parse
+    pub fn parse(&self, config: &ParseConfig) -> RecordEvent {
+        self.try_parse(config)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Decode this record, given the configuration of the event that
+    /// produced it, or return a [`ParseError`] if it is malformed.
+    pub fn try_parse(&self, config: &ParseConfig) -> Result<RecordEvent, ParseError> {
+        let mut parser = Parser::new(self.ty, &self.data);
+        let event = match self.ty {
+            bindings::PERF_RECORD_MMAP => RecordEvent::Mmap(Mmap::parse(&mut parser)?),
+            bindings::PERF_RECORD_MMAP2 => {
+                RecordEvent::Mmap2(Mmap2::parse(&mut parser, self.misc)?)
+            }
+            bindings::PERF_RECORD_COMM => RecordEvent::Comm(Comm::parse(&mut parser, self.misc)?),
+            bindings::PERF_RECORD_FORK => RecordEvent::Fork(Task::parse(&mut parser)?),
+            bindings::PERF_RECORD_EXIT => RecordEvent::Exit(Task::parse(&mut parser)?),
+            bindings::PERF_RECORD_SWITCH => RecordEvent::Switch(Switch::from_misc(self.misc)),
+            bindings::PERF_RECORD_SWITCH_CPU_WIDE => {
+                RecordEvent::Switch(Switch::parse_cpu_wide(&mut parser, self.misc)?)
+            }
+            bindings::PERF_RECORD_READ => RecordEvent::Read(Read::parse(&mut parser, config)?),
+            bindings::PERF_RECORD_LOST => RecordEvent::Lost(Lost::parse(&mut parser)?),
+            bindings::PERF_RECORD_LOST_SAMPLES => RecordEvent::LostSamples(parser.u64()?),
+            bindings::PERF_RECORD_THROTTLE => RecordEvent::Throttle(Throttle::parse(&mut parser)?),
+            bindings::PERF_RECORD_UNTHROTTLE => {
+                RecordEvent::Unthrottle(Throttle::parse(&mut parser)?)
+            }
+            bindings::PERF_RECORD_CGROUP => RecordEvent::Cgroup(Cgroup::parse(&mut parser)?),
+            bindings::PERF_RECORD_TEXT_POKE => RecordEvent::TextPoke(TextPoke::parse(&mut parser)?),
+            bindings::PERF_RECORD_AUX => RecordEvent::Aux(Aux::parse(&mut parser)?),
+            bindings::PERF_RECORD_ITRACE_START => {
+                RecordEvent::ItraceStart(ItraceStart::parse(&mut parser)?)
+            }
+            bindings::PERF_RECORD_AUX_OUTPUT_HW_ID => RecordEvent::AuxOutputHwId(parser.u64()?),
+            bindings::PERF_RECORD_NAMESPACES => {
+                RecordEvent::Namespaces(Namespaces::parse(&mut parser)?)
+            }
+            bindings::PERF_RECORD_KSYMBOL => RecordEvent::Ksymbol(Ksymbol::parse(&mut parser)?),
+            bindings::PERF_RECORD_BPF_EVENT => RecordEvent::BpfEvent(BpfEvent::parse(&mut parser)?),
+            bindings::PERF_RECORD_SAMPLE => {
+                RecordEvent::Sample(Sample::parse(&mut parser, config)?)
+            }
+            _ => RecordEvent::Unknown,
+        };
+        Ok(event)
+    }
+
+    /// Return this record's timestamp, if it is a sample that includes one,
+    /// given the configuration of the event that produced it.
+    ///
+    /// This finds the time without decoding the rest of the sample.
+    pub fn time(&self, config: &ParseConfig) -> Option<u64> {
+        let ty = config.sample_type;
+        if self.ty != bindings::PERF_RECORD_SAMPLE || !ty.contains(SampleType::TIME) {
+            return None;
+        }
+        // Only these fields come before the time, and each takes 8 bytes.
+        let before = [SampleType::IDENTIFIER, SampleType::IP, SampleType::TID];
+        let offset = before.iter().filter(|&&flag| ty.contains(flag)).count() * 8;
+        let mut parser = Parser::new(self.ty, &self.data);
+        parser.bytes(offset).ok()?;
+        parser.u64().ok()
+    }
+
+    /// Copy this record out of the ring buffer.
+    pub fn into_record(self) -> Record {
+        Record {
+            ty: self.ty,
+            misc: self.misc,
+            data: self.data.into_owned(),
+        }
+    }
+}
+
+impl<'a> From<&'a Record> for RecordRef<'a> {
+    fn from(record: &'a Record) -> RecordRef<'a> {
+        RecordRef {
+            ty: record.ty,
+            misc: record.misc,
+            data: Cow::Borrowed(&record.data),
+        }
+    }
+}
+
+/// A decoded [`Record`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[allow(clippy::large_enum_variant)] // Most records are samples.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RecordEvent {
+    /// A `PERF_RECORD_MMAP` record: the observed process mapped an
+    /// executable file. Request these with [`Builder::mmap`].
+    ///
+    /// [`Builder::mmap`]: crate::Builder::mmap
+    Mmap(Mmap),
+
+    /// A `PERF_RECORD_MMAP2` record: the observed process mapped a file,
+    /// described in more detail than an [`Mmap`] record. Request these
+    /// with [`Builder::mmap2`].
+    ///
+    /// [`Builder::mmap2`]: crate::Builder::mmap2
+    Mmap2(Mmap2),
+
+    /// A `PERF_RECORD_SAMPLE` record.
+    Sample(Sample),
+
+    /// A `PERF_RECORD_COMM` record: a thread's name changed. Request these
+    /// with [`Builder::comm`].
+    ///
+    /// [`Builder::comm`]: crate::Builder::comm
+    Comm(Comm),
+
+    /// A `PERF_RECORD_FORK` record: a process or thread was created. Request
+    /// these with [`Builder::task`].
+    ///
+    /// [`Builder::task`]: crate::Builder::task
+    Fork(Task),
+
+    /// A `PERF_RECORD_EXIT` record: a process or thread exited. Request
+    /// these with [`Builder::task`].
+    ///
+    /// [`Builder::task`]: crate::Builder::task
+    Exit(Task),
+
+    /// A `PERF_RECORD_SWITCH` or `PERF_RECORD_SWITCH_CPU_WIDE` record: a
+    /// context switch. Request these with [`Builder::context_switch`].
+    ///
+    /// [`Builder::context_switch`]: crate::Builder::context_switch
+    Switch(Switch),
+
+    /// A `PERF_RECORD_READ` record: an inherited counter's values, reported
+    /// as the thread it was counting exited. Request these with
+    /// [`Builder::inherit_stat`].
+    ///
+    /// [`Builder::inherit_stat`]: crate::Builder::inherit_stat
+    Read(Read),
+
+    /// A `PERF_RECORD_LOST` record: the ring buffer was full, so the kernel
+    /// dropped some records.
+    Lost(Lost),
+
+    /// A `PERF_RECORD_LOST_SAMPLES` record, holding the number of samples
+    /// the PMU dropped without the kernel seeing them. Only some hardware
+    /// events, like Intel PEBS events, produce these.
+    LostSamples(u64),
+
+    /// A `PERF_RECORD_THROTTLE` record: the event was sampling so often
+    /// that the kernel stopped it for a while. See
+    /// `/proc/sys/kernel/perf_cpu_time_max_percent`.
+    Throttle(Throttle),
+
+    /// A `PERF_RECORD_UNTHROTTLE` record: the kernel resumed sampling an
+    /// event it had throttled.
+    Unthrottle(Throttle),
+
+    /// A `PERF_RECORD_CGROUP` record: a cgroup was created. Request these
+    /// with [`Builder::cgroup`].
+    ///
+    /// [`Builder::cgroup`]: crate::Builder::cgroup
+    Cgroup(Cgroup),
+
+    /// A `PERF_RECORD_TEXT_POKE` record: the kernel modified its own code.
+    /// Request these with [`Builder::text_poke`].
+    ///
+    /// [`Builder::text_poke`]: crate::Builder::text_poke
+    TextPoke(TextPoke),
+
+    /// A `PERF_RECORD_AUX` record: new data is available in an
+    /// [`AuxSampler`]'s AUX area.
+    Aux(Aux),
+
+    /// A `PERF_RECORD_ITRACE_START` record: a hardware tracing event has
+    /// started tracing a thread.
+    ItraceStart(ItraceStart),
+
+    /// A `PERF_RECORD_AUX_OUTPUT_HW_ID` record, giving the hardware id
+    /// that an event built with [`Builder::aux_output`] uses to mark its
+    /// data in the AUX trace stream. On Intel processors, this identifies
+    /// the PEBS counter whose records appear in the Intel PT trace. This
+    /// requires Linux 5.16 or later.
+    ///
+    /// [`Builder::aux_output`]: crate::Builder::aux_output
+    AuxOutputHwId(u64),
+
+    /// A `PERF_RECORD_NAMESPACES` record: a process or thread was created,
+    /// or changed namespaces. Request these with [`Builder::namespaces`].
+    ///
+    /// [`Builder::namespaces`]: crate::Builder::namespaces
+    Namespaces(Namespaces),
+
+    /// A `PERF_RECORD_KSYMBOL` record: a kernel symbol, like a BPF
+    /// program's JIT-compiled code, was registered or unregistered.
+    /// Request these with [`Builder::ksymbol`].
+    ///
+    /// [`Builder::ksymbol`]: crate::Builder::ksymbol
+    Ksymbol(Ksymbol),
+
+    /// A `PERF_RECORD_BPF_EVENT` record: a BPF program was loaded or
+    /// unloaded. Request these with [`Builder::bpf_event`].
+    ///
+    /// [`Builder::bpf_event`]: crate::Builder::bpf_event
+    BpfEvent(BpfEvent),
+
+    /// A record of a type the kernel headers this crate was built with
+    /// define, but which it doesn't decode. This holds the record's type;
+    /// the [`Record`]'s `data` field is still available.
+    ///
+    /// This crate currently decodes every type its headers define, so this
+    /// is reserved for types those headers add in the future.
+    UnsupportedKnown(u32),
+
+    /// A record of a type this crate doesn't know, probably from a newer
+    /// kernel. The [`Record`]'s `ty` and `data` fields are still available.
+    Unknown,
+
+    /// A record decoded by a parser registered with
+    /// [`Sampler::register_parser`].
+    ///
+    /// These can't be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(CustomRecord),
+}
+
+/// A record decoded by a parser registered with
+/// [`Sampler::register_parser`].
+///
+/// This holds whatever value the parser returned. Use [`downcast_ref`] to
+/// get it back.
+///
+/// Cloning a `CustomRecord` shares its value. Two `CustomRecord`s are equal
+/// only if they share the same value.
+///
+/// [`downcast_ref`]: CustomRecord::downcast_ref
+#[derive(Clone, Debug)]
+pub struct CustomRecord {
+    ty: u32,
+    value: Arc<dyn CustomValue>,
+}
+
+/// A value a custom parser can return.
+trait CustomValue: Any + fmt::Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any + fmt::Debug + Send + Sync> CustomValue for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl CustomRecord {
+    /// Return the type of the record this was decoded from, a
+    /// `PERF_RECORD_*` value.
+    pub fn record_type(&self) -> u32 {
+        self.ty
+    }
+
+    /// Return the parser's value, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        // Call `as_any` on the value, not on the `Arc`, which is also `Any`.
+        (*self.value).as_any().downcast_ref()
+    }
+}
+
+impl PartialEq for CustomRecord {
+    fn eq(&self, other: &CustomRecord) -> bool {
+        self.ty == other.ty && Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+impl Eq for CustomRecord {}
+
+/// A parser registered with [`Sampler::register_parser`].
+type CustomParser = Box<dyn Fn(&RecordRef, &ParseConfig) -> CustomRecord + Send>;
+
+/// A decoder registered with [`AuxSampler::register_decoder`].
+type AuxDecoder = Box<dyn FnMut(&[u8]) + Send>;
+
+/// A `PERF_RECORD_MMAP` record, describing a new executable mapping.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmap {
+    /// The process and thread that created the mapping.
+    pub pid: u32,
+
+    /// The thread that created the mapping.
+    pub tid: u32,
+
+    /// The address at which the file was mapped.
+    pub addr: u64,
+
+    /// The length of the mapping, in bytes.
+    pub len: u64,
+
+    /// The offset within the file at which the mapping starts.
+    pub pgoff: u64,
+
+    /// The name of the mapped file.
+    pub filename: Vec<u8>,
+}
+
+impl Mmap {
+    fn parse(parser: &mut Parser) -> Result<Mmap, ParseError> {
+        Ok(Mmap {
+            pid: parser.u32()?,
+            tid: parser.u32()?,
+            addr: parser.u64()?,
+            len: parser.u64()?,
+            pgoff: parser.u64()?,
+            filename: parser.c_string()?,
+        })
+    }
+}
+
+/// A `PERF_RECORD_MMAP2` record, describing a new mapping.
+///
+/// Unlike [`Mmap`], this identifies the mapped file either by its device and
+/// inode or by its ELF build id, and gives the mapping's protection and
+/// flags.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mmap2 {
+    /// The process that created the mapping.
+    pub pid: u32,
+
+    /// The thread that created the mapping.
+    pub tid: u32,
+
+    /// The address at which the file was mapped.
+    pub addr: u64,
+
+    /// The length of the mapping, in bytes.
+    pub len: u64,
+
+    /// The offset within the file at which the mapping starts.
+    pub pgoff: u64,
+
+    /// Which file was mapped.
+    pub file: Mmap2File,
+
+    /// The mapping's protection, as `PROT_*` flags.
+    pub prot: u32,
+
+    /// The mapping's flags, as `MAP_*` flags.
+    pub flags: u32,
+
+    /// The name of the mapped file.
+    pub filename: Vec<u8>,
+}
+
+/// How an [`Mmap2`] record identifies the mapped file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mmap2File {
+    /// The file's device and inode.
+    Inode {
+        /// The device's major number.
+        maj: u32,
+
+        /// The device's minor number.
+        min: u32,
+
+        /// The file's inode number.
+        ino: u64,
+
+        /// The inode's generation number.
+        ino_generation: u64,
+    },
+
+    /// The ELF build id of the mapped file, when the event was opened with
+    /// `perf_event_attr::build_id` set and the file has one. This requires
+    /// Linux 5.12 or later.
+    BuildId(Vec<u8>),
+}
+
+impl Mmap2 {
+    fn parse(parser: &mut Parser, misc: u16) -> Result<Mmap2, ParseError> {
+        let pid = parser.u32()?;
+        let tid = parser.u32()?;
+        let addr = parser.u64()?;
+        let len = parser.u64()?;
+        let pgoff = parser.u64()?;
+        let file = if misc as u32 & bindings::PERF_RECORD_MISC_MMAP_BUILD_ID != 0 {
+            // A size byte, three reserved bytes, and twenty bytes of id.
+            let bytes = parser.bytes(24)?;
+            let size = (bytes[0] as usize).min(20);
+            Mmap2File::BuildId(bytes[4..4 + size].to_vec())
+        } else {
+            Mmap2File::Inode {
+                maj: parser.u32()?,
+                min: parser.u32()?,
+                ino: parser.u64()?,
+                ino_generation: parser.u64()?,
+            }
+        };
+        Ok(Mmap2 {
+            pid,
+            tid,
+            addr,
+            len,
+            pgoff,
+            file,
+            prot: parser.u32()?,
+            flags: parser.u32()?,
+            filename: parser.c_string()?,
+        })
+    }
+}
+
+/// A `PERF_RECORD_THROTTLE` or `PERF_RECORD_UNTHROTTLE` record, reporting
+/// that the kernel stopped or resumed sampling an event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Throttle {
+    /// When the event was throttled or unthrottled, in the same units as
+    /// [`Sample::time`].
+    pub time: u64,
+
+    /// The id of the event, as for [`SampleType::ID`].
+    pub id: u64,
+
+    /// The id of the group leader, or of the event itself if it has none,
+    /// as for [`SampleType::STREAM_ID`].
+    pub stream_id: u64,
+}
+
+impl Throttle {
+    fn parse(parser: &mut Parser) -> Result<Throttle, ParseError> {
+        Ok(Throttle {
+            time: parser.u64()?,
+            id: parser.u64()?,
+            stream_id: parser.u64()?,
+        })
+    }
+}
+
+/// A `PERF_RECORD_COMM` record, giving a thread's new name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comm {
+    /// The process whose thread was renamed.
+    pub pid: u32,
+
+    /// The thread that was renamed.
+    pub tid: u32,
+
+    /// The thread's new name, without the trailing NUL. The kernel truncates
+    /// names to 15 bytes.
+    pub comm: Vec<u8>,
+
+    /// True if the name changed because the process called `exec`, rather
+    /// than by `prctl(PR_SET_NAME)` or the like.
+    pub exec: bool,
+}
+
+impl Comm {
+    fn parse(parser: &mut Parser, misc: u16) -> Result<Comm, ParseError> {
+        Ok(Comm {
+            pid: parser.u32()?,
+            tid: parser.u32()?,
+            comm: parser.c_string()?,
+            exec: misc as u32 & bindings::PERF_RECORD_MISC_COMM_EXEC != 0,
+        })
+    }
+}
+
+/// A `PERF_RECORD_FORK` or `PERF_RECORD_EXIT` record, describing a process
+/// or thread's creation or exit.
+///
+/// When a new thread is created, `pid` and `ppid` are equal, and `ptid` is
+/// the thread that created it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Task {
+    /// The process.
+    pub pid: u32,
+
+    /// The parent process.
+    pub ppid: u32,
+
+    /// The thread.
+    pub tid: u32,
+
+    /// The parent thread.
+    pub ptid: u32,
+
+    /// When the process or thread was created or exited, in the same units
+    /// as [`Sample::time`].
+    pub time: u64,
+}
+
+impl Task {
+    fn parse(parser: &mut Parser) -> Result<Task, ParseError> {
+        Ok(Task {
+            pid: parser.u32()?,
+            ppid: parser.u32()?,
+            tid: parser.u32()?,
+            ptid: parser.u32()?,
+            time: parser.u64()?,
+        })
+    }
+}
+
+/// A `PERF_RECORD_SWITCH` or `PERF_RECORD_SWITCH_CPU_WIDE` record,
+/// describing a context switch.
+///
+/// Each switch produces two records: one as the old thread is switched out,
+/// and one as the new thread is switched in. An event observing particular
+/// threads gets `PERF_RECORD_SWITCH` records, which say only which way the
+/// switch went; a system-wide event gets `PERF_RECORD_SWITCH_CPU_WIDE`
+/// records, which also identify the thread on the other side.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Switch {
+    /// True if the thread is being switched out, false if it is being
+    /// switched in.
+    pub out: bool,
+
+    /// True if the thread was switched out while still runnable, because it
+    /// was preempted, rather than because it blocked.
+    pub preempted: bool,
+
+    /// For `PERF_RECORD_SWITCH_CPU_WIDE` records, the process and thread
+    /// ids of the next thread, when switching out, or of the previous
+    /// thread, when switching in.
+    pub next_prev: Option<(u32, u32)>,
+}
+
+impl Switch {
+    fn from_misc(misc: u16) -> Switch {
+        let misc = misc as u32;
+        Switch {
+            out: misc & bindings::PERF_RECORD_MISC_SWITCH_OUT != 0,
+            preempted: misc & bindings::PERF_RECORD_MISC_SWITCH_OUT_PREEMPT != 0,
+            next_prev: None,
+        }
+    }
+
+    fn parse_cpu_wide(parser: &mut Parser, misc: u16) -> Result<Switch, ParseError> {
+        Ok(Switch {
+            next_prev: Some((parser.u32()?, parser.u32()?)),
+            ..Switch::from_misc(misc)
+        })
+    }
+}
+
+/// A `PERF_RECORD_READ` record, giving a thread's counter values as it
+/// exits.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Read {
+    /// The process that exited.
+    pub pid: u32,
+
+    /// The thread that exited.
+    pub tid: u32,
+
+    /// The thread's counter values, laid out according to the event's
+    /// [`ReadFormat`].
+    pub values: ReadValues,
+}
+
+impl Read {
+    fn parse(parser: &mut Parser, config: &ParseConfig) -> Result<Read, ParseError> {
+        Ok(Read {
+            pid: parser.u32()?,
+            tid: parser.u32()?,
+            values: ReadValues::parse(parser, config.read_format)?,
+        })
+    }
+}
+
+/// A `PERF_RECORD_LOST` record, reporting records dropped because the ring
+/// buffer was full.
+///
+/// [`Sampler::lost_records`] keeps a running total of these.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lost {
+    /// The id of the event whose records were lost.
+    pub id: u64,
+
+    /// The number of records lost.
+    pub lost: u64,
+}
+
+impl Lost {
+    fn parse(parser: &mut Parser) -> Result<Lost, ParseError> {
+        Ok(Lost {
+            id: parser.u64()?,
+            lost: parser.u64()?,
+        })
+    }
+}
+
+/// A `PERF_RECORD_CGROUP` record, giving the path of a new cgroup.
+///
+/// Samples that include [`SampleType::CGROUP`] identify the cgroup only by
+/// id; these records let you map the ids to paths.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cgroup {
+    /// The cgroup's id, as it appears in [`Sample::cgroup`].
+    pub id: u64,
+
+    /// The cgroup's path, relative to the root of the cgroup v2 hierarchy,
+    /// like `/system.slice/sshd.service`.
+    pub path: Vec<u8>,
+}
+
+impl Cgroup {
+    fn parse(parser: &mut Parser) -> Result<Cgroup, ParseError> {
+        Ok(Cgroup {
+            id: parser.u64()?,
+            path: parser.c_string()?,
+        })
+    }
+}
+
+/// A `PERF_RECORD_TEXT_POKE` record, describing a change the kernel made to
+/// its own code.
+///
+/// The kernel patches its text at run time to flip static keys, enable
+/// ftrace and kprobes, and so on. A profiler that disassembles kernel code
+/// can apply these changes to its copy to keep it accurate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextPoke {
+    /// The address of the change.
+    pub addr: u64,
+
+    /// The bytes at `addr` before the change.
+    pub old_bytes: Vec<u8>,
+
+    /// The bytes at `addr` after the change.
+    pub new_bytes: Vec<u8>,
+}
+
+impl TextPoke {
+    fn parse(parser: &mut Parser) -> Result<TextPoke, ParseError> {
+        let addr = parser.u64()?;
+        let old_len = parser.u16()? as usize;
+        let new_len = parser.u16()? as usize;
+        Ok(TextPoke {
+            addr,
+            old_bytes: parser.bytes(old_len)?.to_vec(),
+            new_bytes: parser.bytes(new_len)?.to_vec(),
+        })
+    }
+}
+
+/// A `PERF_RECORD_NAMESPACES` record, giving the namespaces of a process
+/// or thread.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Namespaces {
+    /// The process.
+    pub pid: u32,
+
+    /// The thread.
+    pub tid: u32,
+
+    /// The thread's namespaces, indexed by the kernel's namespace index
+    /// constants, like `NET_NS_INDEX` and `PID_NS_INDEX`. Kernels may add
+    /// kinds of namespace in the future, so this may be longer than the
+    /// constants this crate's bindings define.
+    pub namespaces: Vec<NamespaceId>,
+}
+
+impl Namespaces {
+    fn parse(parser: &mut Parser) -> Result<Namespaces, ParseError> {
+        let pid = parser.u32()?;
+        let tid = parser.u32()?;
+        let count = parser.u64()?;
+        let namespaces = (0..count)
+            .map(|_| {
+                Ok(NamespaceId {
+                    dev: parser.u64()?,
+                    inode: parser.u64()?,
+                })
+            })
+            .collect::<Result<_, ParseError>>()?;
+        Ok(Namespaces {
+            pid,
+            tid,
+            namespaces,
+        })
+    }
+}
+
+/// A namespace in a [`Namespaces`] record, identified as the kernel
+/// identifies its `/proc/PID/ns` files.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamespaceId {
+    /// The device number of the namespace filesystem.
+    pub dev: u64,
+
+    /// The namespace's inode number.
+    pub inode: u64,
+}
+
+/// A `PERF_RECORD_KSYMBOL` record, announcing that a kernel symbol was
+/// registered or unregistered.
+///
+/// The kernel generates code at run time for BPF programs, and for
+/// out-of-line code like ftrace trampolines. These records let a profiler
+/// symbolize samples that land there.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ksymbol {
+    /// The symbol's address.
+    pub addr: u64,
+
+    /// The symbol's length, in bytes.
+    pub len: u32,
+
+    /// What sort of symbol this is: `PERF_RECORD_KSYMBOL_TYPE_BPF`,
+    /// `PERF_RECORD_KSYMBOL_TYPE_OOL`, and so on.
+    pub ksym_type: u16,
+
+    /// Flags: `PERF_RECORD_KSYMBOL_FLAGS_UNREGISTER` if the symbol is going
+    /// away.
+    pub flags: u16,
+
+    /// The symbol's name.
+    pub name: Vec<u8>,
+}
+
+impl Ksymbol {
+    fn parse(parser: &mut Parser) -> Result<Ksymbol, ParseError> {
+        Ok(Ksymbol {
+            addr: parser.u64()?,
+            len: parser.u32()?,
+            ksym_type: parser.u16()?,
+            flags: parser.u16()?,
+            name: parser.c_string()?,
+        })
+    }
+
+    /// Return true if the symbol is being unregistered, rather than
+    /// registered.
+    pub fn unregistered(&self) -> bool {
+        self.flags as u32 & bindings::PERF_RECORD_KSYMBOL_FLAGS_UNREGISTER != 0
+    }
+}
+
+/// A `PERF_RECORD_BPF_EVENT` record, announcing that a BPF program was
+/// loaded or unloaded.
+///
+/// The program's JIT-compiled code also gets [`Ksymbol`] records. Use the
+/// `id` with the `bpf` system call to find out more about the program.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BpfEvent {
+    /// What happened: `PERF_BPF_EVENT_PROG_LOAD` or
+    /// `PERF_BPF_EVENT_PROG_UNLOAD`.
+    pub event_type: u16,
+
+    /// Flags, currently always zero.
+    pub flags: u16,
+
+    /// The program's id.
+    pub id: u32,
+
+    /// The program's tag, a hash of its instructions.
+    pub tag: [u8; 8],
+}
+
+impl BpfEvent {
+    fn parse(parser: &mut Parser) -> Result<BpfEvent, ParseError> {
+        Ok(BpfEvent {
+            event_type: parser.u16()?,
+            flags: parser.u16()?,
+            id: parser.u32()?,
+            tag: parser.bytes(8)?.try_into().unwrap(),
+        })
+    }
+}
+
+/// A `PERF_RECORD_AUX` record, announcing new data in the AUX area.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Aux {
+    /// The offset of the new data within the AUX data stream. This counts
+    /// all the data ever written, so it is not yet reduced modulo the AUX
+    /// area's size.
+    pub aux_offset: u64,
+
+    /// The number of bytes of new data.
+    pub aux_size: u64,
+
+    /// Flags describing the data: `PERF_AUX_FLAG_TRUNCATED` if the AUX area
+    /// filled up and data was lost, `PERF_AUX_FLAG_PARTIAL` if the record
+    /// covers only part of a hardware snapshot, and so on.
+    pub flags: u64,
+}
+
+impl Aux {
+    fn parse(parser: &mut Parser) -> Result<Aux, ParseError> {
+        Ok(Aux {
+            aux_offset: parser.u64()?,
+            aux_size: parser.u64()?,
+            flags: parser.u64()?,
+        })
+    }
+
+    /// Return true if the kernel had to drop trace data because the AUX
+    /// area was full.
+    pub fn truncated(&self) -> bool {
+        self.flags & bindings::PERF_AUX_FLAG_TRUNCATED as u64 != 0
+    }
+}
+
+/// A `PERF_RECORD_ITRACE_START` record, sent when a hardware tracing event
+/// starts tracing a thread.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItraceStart {
+    /// The process being traced.
+    pub pid: u32,
+
+    /// The thread being traced.
+    pub tid: u32,
+}
+
+impl ItraceStart {
+    fn parse(parser: &mut Parser) -> Result<ItraceStart, ParseError> {
+        Ok(ItraceStart {
+            pid: parser.u32()?,
+            tid: parser.u32()?,
+        })
+    }
+}
+
+bitflags! {
+    /// Values to include in each sample, for [`Builder::sample`].
+    ///
+    /// Each flag selects the corresponding field of [`Sample`]; see the
+    /// `PERF_SAMPLE_*` values in the [`perf_event_open(2)`][man] man page
+    /// for details.
+    ///
+    /// [`Builder::sample`]: crate::Builder::sample
+    /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct SampleType: u64 {
+        /// The instruction pointer.
+        const IP = bindings::PERF_SAMPLE_IP;
+
+        /// The process and thread ids.
+        const TID = bindings::PERF_SAMPLE_TID;
+
+        /// A timestamp.
+        const TIME = bindings::PERF_SAMPLE_TIME;
+
+        /// An address, for events that have one, like page faults or
+        /// breakpoints.
+        const ADDR = bindings::PERF_SAMPLE_ADDR;
+
+        /// The event's id. If the event is in a group, this is the id of the
+        /// member that took the sample.
+        const ID = bindings::PERF_SAMPLE_ID;
+
+        /// The event's id, at a fixed position at the start of the sample.
+        /// This fills in the same [`Sample::id`] field as `ID`.
+        const IDENTIFIER = bindings::PERF_SAMPLE_IDENTIFIER;
+
+        /// The id of the event that was originally opened, even if this
+        /// sample came from an inherited copy.
+        const STREAM_ID = bindings::PERF_SAMPLE_STREAM_ID;
+
+        /// The CPU the sample was taken on.
+        const CPU = bindings::PERF_SAMPLE_CPU;
+
+        /// The sampling period in effect when the sample was taken.
+        const PERIOD = bindings::PERF_SAMPLE_PERIOD;
+
+        /// The event's value when the sample was taken. If the event belongs
+        /// to a [`Group`], this holds the values of every counter in the
+        /// group, which lets you compute ratios like instructions per cycle
+        /// for each sample. See [`Builder::build_sampler`].
+        ///
+        /// [`Group`]: crate::Group
+        /// [`Builder::build_sampler`]: crate::Builder::build_sampler
+        const READ = bindings::PERF_SAMPLE_READ;
+
+        /// The call chain: the return addresses on the stack. See
+        /// [`Callchain`].
+        const CALLCHAIN = bindings::PERF_SAMPLE_CALLCHAIN;
+
+        /// Raw data whose format depends on the event. For a tracepoint,
+        /// this is the tracepoint's record, whose layout `tracefs` describes
+        /// in the tracepoint's `format` file.
+        const RAW = bindings::PERF_SAMPLE_RAW;
+
+        /// A record of the most recent branches taken, from the processor's
+        /// last branch record facility. Use [`Builder::sample_branch_stack`]
+        /// to request this, rather than requesting it directly. See
+        /// [`BranchEntry`].
+        ///
+        /// [`Builder::sample_branch_stack`]: crate::Builder::sample_branch_stack
+        const BRANCH_STACK = bindings::PERF_SAMPLE_BRANCH_STACK;
+
+        /// The values of user-space registers. Use
+        /// [`Builder::sample_regs_user`] to request this, rather than
+        /// requesting it directly, so that the event knows which registers
+        /// to include.
+        ///
+        /// [`Builder::sample_regs_user`]: crate::Builder::sample_regs_user
+        const REGS_USER = bindings::PERF_SAMPLE_REGS_USER;
+
+        /// A hardware-provided weight, indicating how costly the sampled
+        /// event was, like a memory access's latency.
+        const WEIGHT = bindings::PERF_SAMPLE_WEIGHT;
+
+        /// Where a sampled memory access found its data. See
+        /// [`DataSource`].
+        const DATA_SRC = bindings::PERF_SAMPLE_DATA_SRC;
+
+        /// Why a hardware transaction aborted, for events that count
+        /// transaction aborts. See [`Transaction`].
+        const TRANSACTION = bindings::PERF_SAMPLE_TRANSACTION;
+
+        /// The physical address of `ADDR`.
+        const PHYS_ADDR = bindings::PERF_SAMPLE_PHYS_ADDR;
+
+        /// The id of the sampled thread's cgroup, in the cgroup v2
+        /// hierarchy. This is the inode number of the cgroup's directory;
+        /// [`RecordEvent::Cgroup`] records give the path for each id.
+        const CGROUP = bindings::PERF_SAMPLE_CGROUP;
+
+        /// The size of the page containing `ADDR`.
+        const DATA_PAGE_SIZE = bindings::PERF_SAMPLE_DATA_PAGE_SIZE;
+
+        /// The size of the page containing `IP`.
+        const CODE_PAGE_SIZE = bindings::PERF_SAMPLE_CODE_PAGE_SIZE;
+    }
+}
+
+/// A decoded `PERF_RECORD_SAMPLE` record.
+///
+/// Each field is `Some` if the corresponding [`SampleType`] flag was
+/// requested.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample {
+    /// The instruction pointer.
+    pub ip: Option<u64>,
+
+    /// The process id.
+    pub pid: Option<u32>,
+
+    /// The thread id.
+    pub tid: Option<u32>,
+
+    /// The timestamp, in nanoseconds.
+    pub time: Option<u64>,
+
+    /// The address associated with the event.
+    pub addr: Option<u64>,
+
+    /// The id of the event that took the sample.
+    pub id: Option<u64>,
+
+    /// The id of the event as originally opened.
+    pub stream_id: Option<u64>,
+
+    /// The CPU the sample was taken on.
+    pub cpu: Option<u32>,
+
+    /// The sampling period.
+    pub period: Option<u64>,
+
+    /// The values of the event, or of its group, when the sample was taken.
+    /// Use [`ReadValues::to_counts`] to look up group members' values by
+    /// their counters' ids.
+    pub read: Option<ReadValues>,
+
+    /// The call chain.
+    pub callchain: Option<Callchain>,
+
+    /// The raw data. For a tracepoint, this is the tracepoint's record,
+    /// which a [`tracepoint::Format`] can decode.
+    ///
+    /// [`tracepoint::Format`]: crate::tracepoint::Format
+    pub raw: Option<Vec<u8>>,
+
+    /// The branch stack, most recent branch first.
+    pub branch_stack: Option<Vec<BranchEntry>>,
+
+    /// The index in the processor's branch record buffer of the most
+    /// recent branch, if [`SampleBranchFlag::HW_INDEX`] was requested.
+    pub branch_hw_index: Option<u64>,
+
+    /// The values of the user-space registers requested with
+    /// [`Builder::sample_regs_user`].
+    ///
+    /// [`Builder::sample_regs_user`]: crate::Builder::sample_regs_user
+    pub regs_user: Option<Registers>,
+
+    /// The hardware-provided weight.
+    pub weight: Option<u64>,
+
+    /// Where a sampled memory access found its data.
+    pub data_src: Option<DataSource>,
+
+    /// Why a hardware transaction aborted.
+    pub transaction: Option<Transaction>,
+
+    /// The physical address.
+    pub phys_addr: Option<u64>,
+
+    /// The cgroup id.
+    pub cgroup: Option<u64>,
+
+    /// The size of the data page.
+    pub data_page_size: Option<u64>,
+
+    /// The size of the code page.
+    pub code_page_size: Option<u64>,
+}
+
+impl Sample {
+    /// Decode a sample, following the field order given in
+    /// `perf_event_open(2)`.
+    fn parse(parser: &mut Parser, config: &ParseConfig) -> Result<Sample, ParseError> {
+        let ty = config.sample_type;
+        let mut sample = Sample::default();
+        let field = |flag: SampleType, parser: &mut Parser| {
+            if ty.contains(flag) {
+                parser.u64().map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+
+        sample.id = field(SampleType::IDENTIFIER, parser)?;
+        sample.ip = field(SampleType::IP, parser)?;
+        if ty.contains(SampleType::TID) {
+            sample.pid = Some(parser.u32()?);
+            sample.tid = Some(parser.u32()?);
+        }
+        sample.time = field(SampleType::TIME, parser)?;
+        sample.addr = field(SampleType::ADDR, parser)?;
+        if let Some(id) = field(SampleType::ID, parser)? {
+            sample.id = Some(id);
+        }
+        sample.stream_id = field(SampleType::STREAM_ID, parser)?;
+        if ty.contains(SampleType::CPU) {
+            sample.cpu = Some(parser.u32()?);
+            let _reserved = parser.u32()?;
+        }
+        sample.period = field(SampleType::PERIOD, parser)?;
+        if ty.contains(SampleType::READ) {
+            sample.read = Some(ReadValues::parse(parser, config.read_format)?);
+        }
+        if ty.contains(SampleType::CALLCHAIN) {
+            let len = parser.u64()? as usize;
+            let ips = (0..len)
+                .map(|_| parser.u64())
+                .collect::<Result<Vec<_>, _>>()?;
+            sample.callchain = Some(Callchain::new(ips));
+        }
+        if ty.contains(SampleType::RAW) {
+            // The kernel pads the size to keep what follows aligned.
+            let len = parser.u32()? as usize;
+            sample.raw = Some(parser.bytes(len)?.to_vec());
+        }
+        if ty.contains(SampleType::BRANCH_STACK) {
+            let len = parser.u64()? as usize;
+            if config
+                .branch_sample_type
+                .contains(SampleBranchFlag::HW_INDEX)
+            {
+                sample.branch_hw_index = Some(parser.u64()?);
+            }
+            let entries = (0..len).map(|_| BranchEntry::parse(parser));
+            sample.branch_stack = Some(entries.collect::<Result<_, _>>()?);
+        }
+        if ty.contains(SampleType::REGS_USER) {
+            sample.regs_user = Some(parse_registers(parser, config.sample_regs_user)?);
+        }
+        sample.weight = field(SampleType::WEIGHT, parser)?;
+        sample.data_src = field(SampleType::DATA_SRC, parser)?.map(DataSource::from);
+        sample.transaction =
+            field(SampleType::TRANSACTION, parser)?.map(Transaction::from_bits_truncate);
+        sample.phys_addr = field(SampleType::PHYS_ADDR, parser)?;
+        sample.cgroup = field(SampleType::CGROUP, parser)?;
+        sample.data_page_size = field(SampleType::DATA_PAGE_SIZE, parser)?;
+        sample.code_page_size = field(SampleType::CODE_PAGE_SIZE, parser)?;
+        Ok(sample)
+    }
+}
+
+/// Decode a sample's register values, given the mask of registers it
+/// includes. The kernel omits the values if the ABI is
+/// `PERF_SAMPLE_REGS_ABI_NONE`.
+fn parse_registers(parser: &mut Parser, mask: u64) -> Result<Registers, ParseError> {
+    let abi = parser.u64()?;
+    let mut values = vec![];
+    if abi != bindings::PERF_SAMPLE_REGS_ABI_NONE as u64 {
+        for index in (0..64).filter(|index| mask & (1 << index) != 0) {
+            let value = parser.u64()?;
+            if let Some(register) = Register::from_index(index) {
+                values.push((register, value));
+            }
+        }
+    }
+    Ok(Registers { abi, values })
+}
+
+/// A sample's call chain, divided into sections by execution context.
+///
+/// The kernel reports a call chain as a single list of addresses, innermost
+/// first, with special marker values (`PERF_CONTEXT_KERNEL`,
+/// `PERF_CONTEXT_USER`, and so on) introducing each run of addresses from a
+/// given context. A sample taken in a system call, for example, has a kernel
+/// section describing the kernel's stack, followed by a user section
+/// describing the stack of the user code that made the call.
+///
+/// The [`sections`] method returns the runs with the markers removed; the
+/// [`kernel`] and [`user`] methods select the addresses from one context.
+///
+/// Use [`Builder::include_kernel`] to get kernel sections; otherwise, the
+/// kernel omits them.
+///
+/// [`sections`]: Callchain::sections
+/// [`kernel`]: Callchain::kernel
+/// [`user`]: Callchain::user
+/// [`Builder::include_kernel`]: crate::Builder::include_kernel
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Callchain {
+    sections: Vec<CallchainSection>,
+}
+
+/// A run of addresses in a [`Callchain`] from a single context.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallchainSection {
+    /// The context the addresses belong to.
+    pub context: CallchainContext,
+
+    /// The addresses, innermost first.
+    pub frames: Vec<u64>,
+}
+
+/// The execution context of a [`CallchainSection`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallchainContext {
+    /// `PERF_CONTEXT_HV`: the hypervisor.
+    Hypervisor,
+
+    /// `PERF_CONTEXT_KERNEL`: the kernel.
+    Kernel,
+
+    /// `PERF_CONTEXT_USER`: user space.
+    User,
+
+    /// `PERF_CONTEXT_GUEST`: a virtual machine guest, in an unspecified
+    /// mode.
+    Guest,
+
+    /// `PERF_CONTEXT_GUEST_KERNEL`: a guest's kernel.
+    GuestKernel,
+
+    /// `PERF_CONTEXT_GUEST_USER`: a guest's user space.
+    GuestUser,
+
+    /// Addresses that appeared before any context marker, or after a marker
+    /// this crate doesn't recognize. This holds the marker, or zero if there
+    /// was none.
+    Unknown(u64),
+}
+
+impl CallchainContext {
+    /// If `value` is a context marker, return the context it introduces.
+    fn from_marker(value: u64) -> Option<CallchainContext> {
+        if value < bindings::PERF_CONTEXT_MAX {
+            return None;
+        }
+        Some(match value {
+            bindings::PERF_CONTEXT_HV => CallchainContext::Hypervisor,
+            bindings::PERF_CONTEXT_KERNEL => CallchainContext::Kernel,
+            bindings::PERF_CONTEXT_USER => CallchainContext::User,
+            bindings::PERF_CONTEXT_GUEST => CallchainContext::Guest,
+            bindings::PERF_CONTEXT_GUEST_KERNEL => CallchainContext::GuestKernel,
+            bindings::PERF_CONTEXT_GUEST_USER => CallchainContext::GuestUser,
+            other => CallchainContext::Unknown(other),
+        })
+    }
+}
+
+impl Callchain {
+    /// Split the raw call chain `ips` into sections.
+    fn new(ips: impl IntoIterator<Item = u64>) -> Callchain {
+        let mut sections: Vec<CallchainSection> = vec![];
+        for ip in ips {
+            if let Some(context) = CallchainContext::from_marker(ip) {
+                sections.push(CallchainSection {
+                    context,
+                    frames: vec![],
+                });
+                continue;
+            }
+            match sections.last_mut() {
+                Some(section) => section.frames.push(ip),
+                None => sections.push(CallchainSection {
+                    context: CallchainContext::Unknown(0),
+                    frames: vec![ip],
+                }),
+            }
+        }
+        Callchain { sections }
+    }
+
+    /// Return the call chain's sections, innermost first.
+    pub fn sections(&self) -> &[CallchainSection] {
+        &self.sections
+    }
+
+    /// Return the kernel addresses in the call chain, innermost first.
+    pub fn kernel(&self) -> impl Iterator<Item = u64> + '_ {
+        self.frames_in(CallchainContext::Kernel)
+    }
+
+    /// Return the user-space addresses in the call chain, innermost first.
+    pub fn user(&self) -> impl Iterator<Item = u64> + '_ {
+        self.frames_in(CallchainContext::User)
+    }
+
+    fn frames_in(&self, context: CallchainContext) -> impl Iterator<Item = u64> + '_ {
+        self.sections
+            .iter()
+            .filter(move |section| section.context == context)
+            .flat_map(|section| section.frames.iter().copied())
+    }
+}
+
+bitflags! {
+    /// The values the kernel reports when an event's count is read: the
+    /// event's `read_format`.
+    ///
+    /// This determines the layout of [`ReadValues`], in [`RecordEvent::Read`]
+    /// records. See the `PERF_FORMAT_*` values in the
+    /// [`perf_event_open(2)`][man] man page for details.
+    ///
+    /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct ReadFormat: u64 {
+        /// How long the event was enabled.
+        const TOTAL_TIME_ENABLED = bindings::PERF_FORMAT_TOTAL_TIME_ENABLED as u64;
+
+        /// How long the event was actually running.
+        const TOTAL_TIME_RUNNING = bindings::PERF_FORMAT_TOTAL_TIME_RUNNING as u64;
+
+        /// Each event's id.
+        const ID = bindings::PERF_FORMAT_ID as u64;
+
+        /// The values of all the members of the event's group.
+        const GROUP = bindings::PERF_FORMAT_GROUP as u64;
+    }
+}
+
+/// Counter values, as the kernel reports them in `PERF_RECORD_READ` records
+/// and in samples that include [`SampleType::READ`].
+///
+/// Which fields are present depends on the event's [`ReadFormat`]. Each
+/// field is `Some` if the corresponding flag was set.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReadValues {
+    /// How long the event, or the group, was enabled, in nanoseconds.
+    pub time_enabled: Option<u64>,
+
+    /// How long the event, or the group, was actually running, in
+    /// nanoseconds.
+    pub time_running: Option<u64>,
+
+    /// The events' values: one for an event read on its own, or one per
+    /// group member if the format includes [`ReadFormat::GROUP`].
+    pub values: Vec<ReadValue>,
+}
+
+/// One event's value in a [`ReadValues`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReadValue {
+    /// The event's count.
+    pub value: u64,
+
+    /// The event's id, if the format includes [`ReadFormat::ID`].
+    pub id: Option<u64>,
+}
+
+impl ReadValues {
+    fn parse(parser: &mut Parser, format: ReadFormat) -> Result<ReadValues, ParseError> {
+        let field = |flag, parser: &mut Parser| {
+            if format.contains(flag) {
+                parser.u64().map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+
+        // A group read puts the count of values first; a single event's
+        // value comes before its times.
+        if format.contains(ReadFormat::GROUP) {
+            let nr = parser.u64()?;
+            let time_enabled = field(ReadFormat::TOTAL_TIME_ENABLED, parser)?;
+            let time_running = field(ReadFormat::TOTAL_TIME_RUNNING, parser)?;
+            let values = (0..nr)
+                .map(|_| {
+                    Ok(ReadValue {
+                        value: parser.u64()?,
+                        id: field(ReadFormat::ID, parser)?,
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(ReadValues {
+                time_enabled,
+                time_running,
+                values,
+            })
+        } else {
+            let value = parser.u64()?;
+            let time_enabled = field(ReadFormat::TOTAL_TIME_ENABLED, parser)?;
+            let time_running = field(ReadFormat::TOTAL_TIME_RUNNING, parser)?;
+            let id = field(ReadFormat::ID, parser)?;
+            Ok(ReadValues {
+                time_enabled,
+                time_running,
+                values: vec![ReadValue { value, id }],
+            })
+        }
+    }
+
+    /// Return these values as a [`Counts`], as [`Group::read`] would.
+    /// `config` should be the configuration of the event that produced
+    /// them.
+    ///
+    /// This requires every value to have an id, so the format must include
+    /// [`ReadFormat::ID`]; otherwise, this returns `None`. Missing times are
+    /// reported as zero. For a group, the first value is the group's leader.
+    /// Unless [`config.counts_leader()`] is true, the leader is taken to be
+    /// the placeholder [`Group::new`] uses, so like the results of such a
+    /// group's `read`, the `Counts` leaves it out when iterating or
+    /// indexing. [`Sampler::config`] reflects how the group was built.
+    ///
+    /// [`Counts`]: crate::Counts
+    /// [`Group::read`]: crate::Group::read
+    /// [`Group::new`]: crate::Group::new
+    /// [`config.counts_leader()`]: ParseConfig::counts_leader
+    pub fn to_counts(&self, config: &ParseConfig) -> Option<crate::Counts> {
+        let mut data = vec![
+            self.values.len() as u64,
+            self.time_enabled.unwrap_or(0),
+            self.time_running.unwrap_or(0),
+        ];
+        for value in &self.values {
+            data.push(value.value);
+            data.push(value.id?);
+        }
+        let skip_leader = config.read_format.contains(ReadFormat::GROUP) && !config.counts_leader;
+        Some(crate::Counts {
+            data,
+            first: skip_leader as usize,
+        })
+    }
+}
+
+bitflags! {
+    /// Which branches to record in a sample's branch stack, and what to
+    /// record about them, for [`Builder::sample_branch_stack`].
+    ///
+    /// The flags are the kernel's `PERF_SAMPLE_BRANCH_*` values; see the
+    /// [`perf_event_open(2)`][man] man page for details. If none of `USER`,
+    /// `KERNEL` and `HV` is set, the kernel uses the event's own exclusion
+    /// settings.
+    ///
+    /// [`Builder::sample_branch_stack`]: crate::Builder::sample_branch_stack
+    /// [man]: http://man7.org/linux/man-pages/man2/perf_event_open.2.html
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct SampleBranchFlag: u64 {
+        /// Branch target is in user space.
+        const USER = bindings::PERF_SAMPLE_BRANCH_USER as u64;
+
+        /// Branch target is in kernel space.
+        const KERNEL = bindings::PERF_SAMPLE_BRANCH_KERNEL as u64;
+
+        /// Branch target is in the hypervisor.
+        const HV = bindings::PERF_SAMPLE_BRANCH_HV as u64;
+
+        /// Any branch type.
+        const ANY = bindings::PERF_SAMPLE_BRANCH_ANY as u64;
+
+        /// Any call branch, including direct and indirect calls and far
+        /// jumps.
+        const ANY_CALL = bindings::PERF_SAMPLE_BRANCH_ANY_CALL as u64;
+
+        /// Any return branch.
+        const ANY_RETURN = bindings::PERF_SAMPLE_BRANCH_ANY_RETURN as u64;
+
+        /// Indirect calls.
+        const IND_CALL = bindings::PERF_SAMPLE_BRANCH_IND_CALL as u64;
+
+        /// Transactional memory aborts.
+        const ABORT_TX = bindings::PERF_SAMPLE_BRANCH_ABORT_TX as u64;
+
+        /// Branches within a transaction.
+        const IN_TX = bindings::PERF_SAMPLE_BRANCH_IN_TX as u64;
+
+        /// Branches outside a transaction.
+        const NO_TX = bindings::PERF_SAMPLE_BRANCH_NO_TX as u64;
+
+        /// Conditional branches.
+        const COND = bindings::PERF_SAMPLE_BRANCH_COND as u64;
+
+        /// The call stack, maintained by the hardware.
+        const CALL_STACK = bindings::PERF_SAMPLE_BRANCH_CALL_STACK as u64;
+
+        /// Indirect jumps.
+        const IND_JUMP = bindings::PERF_SAMPLE_BRANCH_IND_JUMP as u64;
+
+        /// Direct calls.
+        const CALL = bindings::PERF_SAMPLE_BRANCH_CALL as u64;
+
+        /// Don't record the mispredicted, predicted, transaction and abort
+        /// flags.
+        const NO_FLAGS = bindings::PERF_SAMPLE_BRANCH_NO_FLAGS as u64;
+
+        /// Don't record cycle counts.
+        const NO_CYCLES = bindings::PERF_SAMPLE_BRANCH_NO_CYCLES as u64;
+
+        /// Record each branch's type in [`BranchEntry::branch_type`].
+        const TYPE_SAVE = bindings::PERF_SAMPLE_BRANCH_TYPE_SAVE as u64;
+
+        /// Record the hardware index of the most recent branch, in
+        /// [`Sample::branch_hw_index`].
+        const HW_INDEX = bindings::PERF_SAMPLE_BRANCH_HW_INDEX as u64;
+    }
+}
+
+/// One branch in a sample's branch stack: a `perf_branch_entry`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BranchEntry {
+    /// The address of the branch instruction.
+    pub from: u64,
+
+    /// The address the branch went to.
+    pub to: u64,
+
+    /// True if the processor mispredicted the branch.
+    pub mispredicted: bool,
+
+    /// True if the processor predicted the branch correctly.
+    pub predicted: bool,
+
+    /// True if the branch was within a transaction.
+    pub in_tx: bool,
+
+    /// True if the branch was a transaction abort.
+    pub abort: bool,
+
+    /// The number of cycles since the previous branch, or zero if the
+    /// hardware doesn't record this.
+    pub cycles: u16,
+
+    /// The branch's type, one of the kernel's `PERF_BR_*` values, like
+    /// `PERF_BR_COND` or `PERF_BR_CALL`. This is only recorded if
+    /// [`SampleBranchFlag::TYPE_SAVE`] was requested; otherwise it is
+    /// `PERF_BR_UNKNOWN`, zero.
+    pub branch_type: u8,
+}
+
+impl BranchEntry {
+    fn parse(parser: &mut Parser) -> Result<BranchEntry, ParseError> {
+        let from = parser.u64()?;
+        let to = parser.u64()?;
+
+        // The rest is a bitfield:
+        //
+        //     u64 mispred:1, predicted:1, in_tx:1, abort:1,
+        //         cycles:16, type:4, ...
+        let flags = parser.u64()?;
+        Ok(BranchEntry {
+            from,
+            to,
+            mispredicted: flags & 1 != 0,
+            predicted: flags & 2 != 0,
+            in_tx: flags & 4 != 0,
+            abort: flags & 8 != 0,
+            cycles: (flags >> 4) as u16,
+            branch_type: ((flags >> 20) & 0xf) as u8,
+        })
+    }
+}
+
+/// Where a sampled memory access found its data: a decoded
+/// `perf_mem_data_src` value, from samples that include
+/// [`SampleType::DATA_SRC`].
+///
+/// Only hardware that can attribute samples to particular memory accesses,
+/// like Intel PEBS or AMD IBS, fills this in, and then only for the events
+/// that support it. Fields the hardware didn't report are `None` or
+/// `false`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DataSource {
+    /// What sort of access this was.
+    pub op: Option<MemOp>,
+
+    /// The level of the memory hierarchy that the access reached.
+    pub level: Option<MemLevel>,
+
+    /// True if the access hit at `level`, false if it missed there.
+    pub hit: Option<bool>,
+
+    /// True if `level` belongs to another node.
+    pub remote: bool,
+
+    /// How many hops away the remote `level` was, if the hardware says.
+    pub hops: Option<Hops>,
+
+    /// How the access interacted with other processors' caches.
+    pub snoop: Option<Snoop>,
+
+    /// True if the access was part of a locked transaction.
+    pub locked: bool,
+
+    /// Which parts of the TLB the access's translation involved.
+    pub tlb: TlbLevel,
+
+    /// True if the translation hit in `tlb`, false if it missed.
+    pub tlb_hit: Option<bool>,
+
+    bits: u64,
+}
+
+/// What sort of memory access a [`DataSource`] describes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemOp {
+    /// A load.
+    Load,
+
+    /// A store.
+    Store,
+
+    /// A prefetch.
+    Prefetch,
+
+    /// An instruction fetch.
+    Exec,
+}
+
+/// A level of the memory hierarchy, for [`DataSource::level`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemLevel {
+    /// The level 1 cache.
+    L1,
+
+    /// The level 2 cache.
+    L2,
+
+    /// The level 3 cache.
+    L3,
+
+    /// The level 4 cache.
+    L4,
+
+    /// Some cache, but the hardware doesn't say which.
+    AnyCache,
+
+    /// The line fill buffer, or miss address buffer: the access found a
+    /// request for its line already outstanding.
+    Lfb,
+
+    /// Main memory.
+    Ram,
+
+    /// Persistent memory.
+    Pmem,
+
+    /// Memory-mapped I/O.
+    Io,
+
+    /// Uncached memory.
+    Uncached,
+}
+
+/// How far away a remote [`DataSource::level`] was.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Hops {
+    /// Another core on the same node.
+    Core,
+
+    /// Another node on the same socket.
+    Node,
+
+    /// Another socket on the same board.
+    Socket,
+
+    /// Another board.
+    Board,
+}
+
+/// What a memory access's snoop of other processors' caches found, for
+/// [`DataSource::snoop`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Snoop {
+    /// The access didn't need a snoop.
+    NotNeeded,
+
+    /// Another cache had the line, unmodified.
+    Hit,
+
+    /// No other cache had the line.
+    Miss,
+
+    /// Another cache had the line, modified.
+    HitModified,
+
+    /// Another cache forwarded the line.
+    Forward,
+}
+
+bitflags! {
+    /// Parts of the TLB involved in translating a memory access's address,
+    /// for [`DataSource::tlb`].
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct TlbLevel: u8 {
+        /// The level 1 TLB.
+        const L1 = bindings::PERF_MEM_TLB_L1 as u8;
+
+        /// The level 2 TLB.
+        const L2 = bindings::PERF_MEM_TLB_L2 as u8;
+
+        /// The hardware page table walker.
+        const WALKER = bindings::PERF_MEM_TLB_WK as u8;
+
+        /// The operating system's page fault handler.
+        const OS = bindings::PERF_MEM_TLB_OS as u8;
+    }
+}
+
+impl DataSource {
+    /// Return the raw `perf_mem_data_src` value this was decoded from.
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+}
+
+impl From<u64> for DataSource {
+    fn from(bits: u64) -> DataSource {
+        use bindings::*;
+
+        // Extract the bitfield starting at bit `shift` that is `width` bits
+        // wide.
+        let field = |shift: u32, width: u32| ((bits >> shift) & ((1 << width) - 1)) as u32;
+        let op = field(PERF_MEM_OP_SHIFT, 5);
+        let lvl = field(PERF_MEM_LVL_SHIFT, 14);
+        let snoop = field(PERF_MEM_SNOOP_SHIFT, 5);
+        let snoopx = field(PERF_MEM_SNOOPX_SHIFT, 2);
+        let lock = field(PERF_MEM_LOCK_SHIFT, 2);
+        let tlb = field(PERF_MEM_TLB_SHIFT, 7);
+        let lvl_num = field(PERF_MEM_LVLNUM_SHIFT, 4);
+        let remote = field(PERF_MEM_REMOTE_SHIFT, 1);
+        let hops = field(PERF_MEM_HOPS_SHIFT, 3);
+
+        let op = if op & PERF_MEM_OP_LOAD != 0 {
+            Some(MemOp::Load)
+        } else if op & PERF_MEM_OP_STORE != 0 {
+            Some(MemOp::Store)
+        } else if op & PERF_MEM_OP_PFETCH != 0 {
+            Some(MemOp::Prefetch)
+        } else if op & PERF_MEM_OP_EXEC != 0 {
+            Some(MemOp::Exec)
+        } else {
+            None
+        };
+
+        // Newer kernels give the level as a number, which can say more than
+        // the older bitmask can. Use that if it's present and something we
+        // recognize; otherwise, fall back to the bitmask.
+        let mut remote = remote == PERF_MEM_REMOTE_REMOTE;
+        let level = match lvl_num {
+            PERF_MEM_LVLNUM_L1 => Some(MemLevel::L1),
+            PERF_MEM_LVLNUM_L2 => Some(MemLevel::L2),
+            PERF_MEM_LVLNUM_L3 => Some(MemLevel::L3),
+            PERF_MEM_LVLNUM_L4 => Some(MemLevel::L4),
+            PERF_MEM_LVLNUM_ANY_CACHE => Some(MemLevel::AnyCache),
+            PERF_MEM_LVLNUM_LFB => Some(MemLevel::Lfb),
+            PERF_MEM_LVLNUM_RAM => Some(MemLevel::Ram),
+            PERF_MEM_LVLNUM_PMEM => Some(MemLevel::Pmem),
+            _ => None,
+        };
+        let level = level.or_else(|| {
+            if lvl & PERF_MEM_LVL_L1 != 0 {
+                Some(MemLevel::L1)
+            } else if lvl & PERF_MEM_LVL_LFB != 0 {
+                Some(MemLevel::Lfb)
+            } else if lvl & PERF_MEM_LVL_L2 != 0 {
+                Some(MemLevel::L2)
+            } else if lvl & PERF_MEM_LVL_L3 != 0 {
+                Some(MemLevel::L3)
+            } else if lvl & PERF_MEM_LVL_LOC_RAM != 0 {
+                Some(MemLevel::Ram)
+            } else if lvl & (PERF_MEM_LVL_REM_RAM1 | PERF_MEM_LVL_REM_RAM2) != 0 {
+                remote = true;
+                Some(MemLevel::Ram)
+            } else if lvl & (PERF_MEM_LVL_REM_CCE1 | PERF_MEM_LVL_REM_CCE2) != 0 {
+                remote = true;
+                Some(MemLevel::AnyCache)
+            } else if lvl & PERF_MEM_LVL_IO != 0 {
+                Some(MemLevel::Io)
+            } else if lvl & PERF_MEM_LVL_UNC != 0 {
+                Some(MemLevel::Uncached)
+            } else {
+                None
+            }
+        });
+
+        let hops = match hops {
+            PERF_MEM_HOPS_0 => Some(Hops::Core),
+            PERF_MEM_HOPS_1 => Some(Hops::Node),
+            PERF_MEM_HOPS_2 => Some(Hops::Socket),
+            PERF_MEM_HOPS_3 => Some(Hops::Board),
+            _ => None,
+        };
+
+        let snoop = if snoopx & PERF_MEM_SNOOPX_FWD != 0 {
+            Some(Snoop::Forward)
+        } else if snoop & PERF_MEM_SNOOP_HITM != 0 {
+            Some(Snoop::HitModified)
+        } else if snoop & PERF_MEM_SNOOP_HIT != 0 {
+            Some(Snoop::Hit)
+        } else if snoop & PERF_MEM_SNOOP_MISS != 0 {
+            Some(Snoop::Miss)
+        } else if snoop & PERF_MEM_SNOOP_NONE != 0 {
+            Some(Snoop::NotNeeded)
+        } else {
+            None
+        };
+
+        // The kernel marks hits and misses the same way for cache levels
+        // and for the TLB.
+        let hit_or_miss = |flags: u32, hit: u32, miss: u32| {
+            if flags & hit != 0 {
+                Some(true)
+            } else if flags & miss != 0 {
+                Some(false)
+            } else {
+                None
+            }
+        };
+
+        DataSource {
+            op,
+            level,
+            hit: hit_or_miss(lvl, PERF_MEM_LVL_HIT, PERF_MEM_LVL_MISS),
+            remote,
+            hops,
+            snoop,
+            locked: lock & PERF_MEM_LOCK_LOCKED != 0,
+            tlb: TlbLevel::from_bits_truncate(tlb as u8),
+            tlb_hit: hit_or_miss(tlb, PERF_MEM_TLB_HIT, PERF_MEM_TLB_MISS),
+            bits,
+        }
+    }
+}
+
+bitflags! {
+    /// Why a hardware transaction aborted, from samples that include
+    /// [`SampleType::TRANSACTION`].
+    ///
+    /// This describes aborts of transactions begun with Intel TSX or a
+    /// similar hardware transactional memory facility. Besides the flags
+    /// below, the high 32 bits hold an abort code supplied by the program,
+    /// which [`abort_code`] returns.
+    ///
+    /// [`abort_code`]: Transaction::abort_code
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Transaction: u64 {
+        /// The transaction was begun by lock elision, rather than
+        /// explicitly.
+        const ELISION = bindings::PERF_TXN_ELISION;
+
+        /// The transaction was begun explicitly.
+        const TRANSACTION = bindings::PERF_TXN_TRANSACTION;
+
+        /// The abort was caused by the sampled instruction.
+        const SYNC = bindings::PERF_TXN_SYNC;
+
+        /// The abort was caused by something other than the sampled
+        /// instruction.
+        const ASYNC = bindings::PERF_TXN_ASYNC;
+
+        /// Retrying the transaction might succeed.
+        const RETRY = bindings::PERF_TXN_RETRY;
+
+        /// The transaction conflicted with another thread's accesses.
+        const CONFLICT = bindings::PERF_TXN_CONFLICT;
+
+        /// The transaction wrote more data than the hardware can track.
+        const CAPACITY_WRITE = bindings::PERF_TXN_CAPACITY_WRITE;
+
+        /// The transaction read more data than the hardware can track.
+        const CAPACITY_READ = bindings::PERF_TXN_CAPACITY_READ;
+
+        /// The bits holding the program's abort code.
+        const ABORT_MASK = bindings::PERF_TXN_ABORT_MASK;
+    }
+}
+
+impl Transaction {
+    /// Return the abort code the program passed to the instruction that
+    /// aborted the transaction, like the operand of Intel's `XABORT`.
+    pub fn abort_code(&self) -> u32 {
+        ((self.bits & Transaction::ABORT_MASK.bits) >> bindings::PERF_TXN_ABORT_SHIFT) as u32
+    }
+}
+
+/// Conversions between the processor's cycle counter and perf timestamps,
+/// as returned by [`Sampler::time_converter`].
+///
+/// Hardware tracers like Intel PT timestamp their trace data with the
+/// processor's time stamp counter, rather than with the kernel's clock.
+/// The kernel publishes the parameters for converting between the two in
+/// the header of each ring buffer, so a decoder can line up trace
+/// timestamps with the [`Sample::time`] values of ordinary samples.
+///
+/// This only applies to events using the kernel's default clock; see
+/// [`Builder::clock`].
+///
+/// [`Builder::clock`]: crate::Builder::clock
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimeConverter {
+    time_zero: u64,
+    time_mult: u32,
+    time_shift: u16,
+
+    /// If the cycle counter is narrower than 64 bits, the kernel's recent
+    /// reading of it, and a mask covering its width.
+    short: Option<(u64, u64)>,
+}
+
+impl TimeConverter {
+    /// Convert a reading of the processor's cycle counter to a perf
+    /// timestamp, in nanoseconds.
+    pub fn cycles_to_time(&self, cycles: u64) -> u64 {
+        // This follows the formula given in `<linux/perf_event.h>`, which
+        // splits the multiplication to avoid overflow.
+        let cycles = match self.short {
+            Some((time_cycles, time_mask)) => {
+                time_cycles.wrapping_add(cycles.wrapping_sub(time_cycles) & time_mask)
+            }
+            None => cycles,
+        };
+        let shift = self.time_shift as u32;
+        let mult = self.time_mult as u64;
+        let quot = cycles >> shift;
+        let rem = cycles & ((1 << shift) - 1);
+        self.time_zero
+            .wrapping_add(quot.wrapping_mul(mult))
+            .wrapping_add((rem * mult) >> shift)
+    }
+
+    /// Convert a perf timestamp, in nanoseconds, to the corresponding
+    /// reading of the processor's cycle counter.
+    pub fn time_to_cycles(&self, time: u64) -> u64 {
+        let shift = self.time_shift as u32;
+        let mult = self.time_mult as u64;
+        let time = time.wrapping_sub(self.time_zero);
+        let quot = time / mult;
+        let rem = time % mult;
+        (quot << shift).wrapping_add((rem << shift) / mult)
+    }
+}
+
+/// A clock for sample timestamps, selected with [`Builder::clock`].
+///
+/// By default, the kernel timestamps records with its own scheduler clock,
+/// which no user-space clock matches. Choosing one of these clocks instead
+/// puts [`Sample::time`] in the same terms as `clock_gettime(2)` with the
+/// corresponding clock id, so samples can be lined up with timestamps from
+/// other sources, like an application's own trace events.
+///
+/// [`Builder::clock`]: crate::Builder::clock
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Clock {
+    /// `CLOCK_REALTIME`: wall-clock time, which can jump when the system
+    /// time is set.
+    Realtime,
+
+    /// `CLOCK_MONOTONIC`: time since some unspecified starting point, which
+    /// never jumps, but which NTP may speed up or slow down slightly.
+    Monotonic,
+
+    /// `CLOCK_MONOTONIC_RAW`: like `Monotonic`, but without NTP's
+    /// adjustments.
+    MonotonicRaw,
+
+    /// `CLOCK_BOOTTIME`: like `Monotonic`, but including time the system
+    /// spent suspended.
+    Boottime,
+
+    /// `CLOCK_TAI`: International Atomic Time.
+    Tai,
+}
+
+impl Clock {
+    /// Return this clock's id, for `clock_gettime(2)`.
+    pub fn clockid(self) -> libc::clockid_t {
+        match self {
+            Clock::Realtime => libc::CLOCK_REALTIME,
+            Clock::Monotonic => libc::CLOCK_MONOTONIC,
+            Clock::MonotonicRaw => libc::CLOCK_MONOTONIC_RAW,
+            Clock::Boottime => libc::CLOCK_BOOTTIME,
+            Clock::Tai => libc::CLOCK_TAI,
+        }
+    }
+
+    /// Return the clock with the id `clockid`, if it's one of these.
+    pub fn from_clockid(clockid: libc::clockid_t) -> Option<Clock> {
+        [
+            Clock::Realtime,
+            Clock::Monotonic,
+            Clock::MonotonicRaw,
+            Clock::Boottime,
+            Clock::Tai,
+        ]
+        .iter()
+        .copied()
+        .find(|clock| clock.clockid() == clockid)
+    }
+
+    /// Return this clock's current time, in nanoseconds: the same units as
+    /// the timestamps of samples taken with this clock.
+    pub fn now(self) -> u64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe {
+            libc::clock_gettime(self.clockid(), &mut ts);
+        }
+        ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+    }
+}
+
+/// What [`Record::parse`] needs to know about how an event was configured.
+///
+/// Use [`Sampler::config`] to get the configuration for a sampler's records.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseConfig {
+    sample_type: SampleType,
+    read_format: ReadFormat,
+    branch_sample_type: SampleBranchFlag,
+    sample_regs_user: u64,
+    clock: Option<Clock>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    counts_leader: bool,
+}
+
+impl ParseConfig {
+    /// Return a configuration for an event whose samples include the values
+    /// selected by `sample_type`, and whose `read_format` is empty.
+    pub fn new(sample_type: SampleType) -> ParseConfig {
+        ParseConfig {
+            sample_type,
+            read_format: ReadFormat::empty(),
+            branch_sample_type: SampleBranchFlag::empty(),
+            sample_regs_user: 0,
+            clock: None,
+            counts_leader: false,
+        }
+    }
+
+    /// Return a copy of this configuration, for an event whose `read_format`
+    /// is `read_format`.
+    pub fn with_read_format(self, read_format: ReadFormat) -> ParseConfig {
+        ParseConfig {
+            read_format,
+            ..self
+        }
+    }
+
+    /// Return a copy of this configuration, for an event whose
+    /// `branch_sample_type` is `branch_sample_type`.
+    pub fn with_branch_sample_type(self, branch_sample_type: SampleBranchFlag) -> ParseConfig {
+        ParseConfig {
+            branch_sample_type,
+            ..self
+        }
+    }
+
+    /// Return a copy of this configuration, for an event whose
+    /// `sample_regs_user` mask is `sample_regs_user`: bit `n` is set if
+    /// samples include the register whose index is `n`.
+    pub fn with_sample_regs_user(self, sample_regs_user: u64) -> ParseConfig {
+        ParseConfig {
+            sample_regs_user,
+            ..self
+        }
+    }
+
+    /// Return a copy of this configuration, for an event whose timestamps
+    /// come from `clock`, or from the kernel's default clock if `clock` is
+    /// `None`.
+    pub fn with_clock(self, clock: Option<Clock>) -> ParseConfig {
+        ParseConfig { clock, ..self }
+    }
+
+    /// Return a copy of this configuration, for an event in a group whose
+    /// leader is a real event if `counts_leader` is true, or a placeholder
+    /// that counts nothing if it is false, as for a group built with
+    /// [`Group::new`]. This affects only [`ReadValues::to_counts`].
+    ///
+    /// [`Group::new`]: crate::Group::new
+    pub fn with_counts_leader(self, counts_leader: bool) -> ParseConfig {
+        ParseConfig {
+            counts_leader,
+            ..self
+        }
+    }
+
+    /// Return the configuration for events built from `attrs`.
+    pub(crate) fn from_attrs(attrs: &perf_event_attr) -> ParseConfig {
+        ParseConfig::new(SampleType::from_bits_truncate(attrs.sample_type))
+            .with_read_format(ReadFormat::from_bits_truncate(attrs.read_format))
+            .with_branch_sample_type(SampleBranchFlag::from_bits_truncate(
+                attrs.branch_sample_type,
+            ))
+            .with_sample_regs_user(attrs.sample_regs_user)
+            .with_clock(if attrs.use_clockid() != 0 {
+                Clock::from_clockid(attrs.clockid)
+            } else {
+                None
+            })
+    }
+
+    /// Return the values included in each sample.
+    pub fn sample_type(&self) -> SampleType {
+        self.sample_type
+    }
+
+    /// Return the values included when the event is read.
+    pub fn read_format(&self) -> ReadFormat {
+        self.read_format
+    }
+
+    /// Return which branches the event's branch stacks record.
+    pub fn branch_sample_type(&self) -> SampleBranchFlag {
+        self.branch_sample_type
+    }
+
+    /// Return the mask of user-space registers included in each sample.
+    pub fn sample_regs_user(&self) -> u64 {
+        self.sample_regs_user
+    }
+
+    /// Return the clock the event's timestamps come from, or `None` if they
+    /// come from the kernel's default clock.
+    pub fn clock(&self) -> Option<Clock> {
+        self.clock
+    }
+
+    /// Return true if the event's group leader is a real event, rather than
+    /// a placeholder.
+    pub fn counts_leader(&self) -> bool {
+        self.counts_leader
+    }
+}
+
+/// A cursor over a record's data, in native byte order.
+struct Parser<'a> {
+    /// The type of the record being parsed, for errors.
+    ty: u32,
+
+    /// The record's whole contents, for errors.
+    record: &'a [u8],
+
+    /// The part of the contents not yet consumed.
+    data: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn new(ty: u32, record: &'a [u8]) -> Parser<'a> {
+        Parser {
+            ty,
+            record,
+            data: record,
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        if len > self.data.len() {
+            return Err(ParseError {
+                ty: self.ty,
+                offset: self.record.len() - self.data.len(),
+                needed: len,
+                len: self.record.len(),
+            });
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseError> {
+        Ok(u16::from_ne_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        Ok(u32::from_ne_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ParseError> {
+        Ok(u64::from_ne_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Consume a NUL-terminated string, padded to a multiple of eight bytes.
+    fn c_string(&mut self) -> Result<Vec<u8>, ParseError> {
+        let len = self
+            .data
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.data.len());
+        let string = self.bytes(len)?.to_vec();
+        let padded = (len + 1).div_ceil(8) * 8;
+        self.bytes((padded - len).min(self.data.len()))?;
+        Ok(string)
+    }
+}
+
+/// An error decoding a [`Record`]: it was too short to hold the fields that
+/// its type and the event's [`ParseConfig`] call for.
+///
+/// This is returned by [`Record::try_parse`] and [`RecordRef::try_parse`].
+/// It usually means that the `ParseConfig` doesn't match the event that
+/// produced the record, or that the kernel laid the record out in a way
+/// this crate doesn't expect.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    ty: u32,
+    offset: usize,
+    needed: usize,
+    len: usize,
+}
+
+impl ParseError {
+    /// Return the type of the malformed record, a `PERF_RECORD_*` value.
+    pub fn record_type(&self) -> u32 {
+        self.ty
+    }
+
+    /// Return the offset within the record's `data` of the field that
+    /// couldn't be read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Return the number of bytes the field needed.
+    pub fn needed(&self) -> usize {
+        self.needed
+    }
+
+    /// Return the length of the record's `data`.
+    pub fn record_len(&self) -> usize {
+        self.len
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "malformed perf record of type {}: needed {} bytes at offset {}, \
+             but the record holds only {}",
+            self.ty, self.needed, self.offset, self.len
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Which of a [`Sampler`]'s buffers have data waiting, as returned by
+/// [`Sampler::poll_events`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PollEvents {
+    /// True if the ring buffer holds records for [`Sampler::next`].
+    pub data: bool,
+
+    /// True if the AUX area holds data for [`AuxSampler::read_aux`].
+    pub aux: bool,
+}
+
+/// An event with a ring buffer of records.
+///
+/// This is created by [`Builder::build_sampler`]. See the [module
+/// documentation](self) for details.
+///
+/// [`Builder::build_sampler`]: crate::Builder::build_sampler
+pub struct Sampler {
+    counter: Counter,
+
+    /// How to parse this sampler's records.
+    config: ParseConfig,
+
+    /// The start of the mapping: the `perf_event_mmap_page` header page,
+    /// followed by the data area.
+    mmap: NonNull<u8>,
+
+    /// The length of the whole mapping, in bytes.
+    mmap_len: usize,
+
+    /// The offset and size of the data area within the mapping.
+    data_offset: usize,
+    data_size: usize,
+
+    /// True if this is a flight recorder: the kernel writes records
+    /// backwards, overwriting the oldest, and we never consume them.
+    overwrite: bool,
+
+    /// The total of the counts in the `LOST` and `LOST_SAMPLES` records
+    /// `next` has returned.
+    lost: u64,
+
+    /// Parsers registered with `register_parser`, by record type.
+    parsers: HashMap<u32, CustomParser>,
+}
+
+// The mapping is only accessed through `&mut self`, and the kernel's side of
+// it doesn't care which thread we're on.
+unsafe impl Send for Sampler {}
+
+impl Sampler {
+    /// Map `counter`'s ring buffer, of size `size`.
+    ///
+    /// If `overwrite` is true, map the buffer read-only, which tells the
+    /// kernel to overwrite old records instead of waiting for us to consume
+    /// them.
+    pub(crate) fn new(
+        counter: Counter,
+        config: ParseConfig,
+        size: BufferSize,
+        overwrite: bool,
+    ) -> io::Result<Sampler> {
+        let page_size = page_size();
+        let mmap_len = size.mmap_len();
+        let prot = if overwrite {
+            libc::PROT_READ
+        } else {
+            libc::PROT_READ | libc::PROT_WRITE
+        };
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                mmap_len,
+                prot,
+                libc::MAP_SHARED,
+                counter.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(BufferTooLarge::check(mmap_len, io::Error::last_os_error()));
+        }
+        let mmap = NonNull::new(base as *mut u8).unwrap();
+
+        // Kernels before 4.1 don't fill in `data_offset` and `data_size`, but
+        // they always put the data right after the header page.
+        let page = unsafe { &*(mmap.as_ptr() as *const perf_event_mmap_page) };
+        let (data_offset, data_size) = if page.data_size != 0 {
+            (page.data_offset as usize, page.data_size as usize)
+        } else {
+            (page_size, size.data_bytes())
+        };
+
+        Ok(Sampler {
+            counter,
+            config,
+            mmap,
+            mmap_len,
+            data_offset,
+            data_size,
+            overwrite,
+            lost: 0,
+            parsers: HashMap::new(),
+        })
+    }
+
+    /// Return the next record from the ring buffer, or `None` if there isn't
+    /// one. This never blocks.
+    ///
+    /// A flight recorder's records are never consumed, so this always
+    /// returns `None` for one; use [`snapshot`] instead.
+    ///
+    /// If a record's header claims it is too short to hold even the header,
+    /// the buffer is corrupt, and there's no telling where the next record
+    /// starts. In that case, this discards everything in the buffer and
+    /// returns `None`.
+    ///
+    /// [`snapshot`]: Sampler::snapshot
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Record> {
+        if self.overwrite {
+            return None;
+        }
+        let head = self.head().load(Ordering::Acquire);
+        let tail = self.tail().load(Ordering::Relaxed);
+        if head == tail {
+            return None;
+        }
+
+        let header = self.header_at(tail);
+        if (header.size as usize) < HEADER_SIZE {
+            self.tail().store(head, Ordering::Release);
+            return None;
+        }
+        let record = self.record_at(tail, &header);
+
+        // Make sure we've finished reading the record before the kernel sees
+        // that it may overwrite it.
+        fence(Ordering::SeqCst);
+        self.tail()
+            .store(tail + header.size as u64, Ordering::Release);
+
+        self.lost += record.lost_count();
+        Some(record)
+    }
+
+    /// Append every record currently in the ring buffer to `records`, and
+    /// return how many there were. This never blocks.
+    ///
+    /// This is like calling [`next`] until it returns `None`, but it tells
+    /// the kernel it may reuse the records' space just once, at the end,
+    /// rather than after each record. The kernel and the reader then pass
+    /// the cache line holding the buffer's position back and forth less
+    /// often, which helps when draining many records at a time.
+    ///
+    /// Like `next`, this reads nothing from a flight recorder.
+    ///
+    /// If a record's header claims it is too short to hold even the header,
+    /// the buffer is corrupt, and there's no telling where the next record
+    /// starts. In that case, this consumes the records before the bad one,
+    /// leaving them in `records`, and returns an error of kind
+    /// [`io::ErrorKind::InvalidData`].
+    ///
+    /// [`next`]: Sampler::next
+    pub fn drain_into(&mut self, records: &mut Vec<Record>) -> io::Result<usize> {
+        if self.overwrite {
+            return Ok(0);
+        }
+        let head = self.head().load(Ordering::Acquire);
+        let mut position = self.tail().load(Ordering::Relaxed);
+        let start = records.len();
+        let mut lost = 0;
+        let mut result = Ok(());
+        while position != head {
+            let header = self.header_at(position);
+            if (header.size as usize) < HEADER_SIZE {
+                result = Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "record at ring buffer position {} has invalid size {}",
+                        position, header.size
+                    ),
+                ));
+                break;
+            }
+            let record = self.record_at(position, &header);
+            lost += record.lost_count();
+            records.push(record);
+            position += header.size as u64;
+        }
+
+        // Make sure we've finished reading the records before the kernel
+        // sees that it may overwrite them.
+        fence(Ordering::SeqCst);
+        self.tail().store(position, Ordering::Release);
+
+        self.lost += lost;
+        result.map(|()| records.len() - start)
+    }
+
+    /// Return a [`RecordChunks`] that reads the records in the ring buffer
+    /// in place, without copying them.
+    ///
+    /// [`next`] copies each record out of the ring buffer into a freshly
+    /// allocated `Vec`. At high sampling rates, those allocations can cost
+    /// more than the sampling itself. `RecordChunks::next` instead returns
+    /// a [`RecordRef`] that borrows the record's bytes from the buffer,
+    /// copying only a record that wraps around the end of the buffer. The
+    /// kernel may not reuse a record's space until you're done with it, so
+    /// each `RecordRef` must be dropped before asking for the next:
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///     use perf_event::samples::{RecordEvent, SampleType};
+    ///
+    ///     let mut sampler = Builder::new()
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .sample_period(100_000)
+    ///         .sample(SampleType::IP)
+    ///         .build_sampler()?;
+    ///
+    ///     sampler.enable()?;
+    ///     let spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+    ///     sampler.disable()?;
+    ///
+    ///     let config = *sampler.config();
+    ///     let mut chunks = sampler.iter_chunks();
+    ///     while let Some(record) = chunks.next() {
+    ///         if let RecordEvent::Sample(sample) = record.parse(&config) {
+    ///             println!("{:#x?}", sample.ip);
+    ///         }
+    ///     }
+    ///     # Ok(()) }
+    ///
+    /// Like `next`, this returns nothing for a flight recorder.
+    ///
+    /// [`next`]: Sampler::next
+    pub fn iter_chunks(&mut self) -> RecordChunks<'_> {
+        let position = self.tail().load(Ordering::Relaxed);
+        RecordChunks {
+            sampler: self,
+            position,
+            lost: 0,
+        }
+    }
+
+    /// Return which of this sampler's buffers have data waiting to be read.
+    ///
+    /// This checks the buffers' positions in memory shared with the kernel,
+    /// so it never blocks and makes no system calls. It's meant for event
+    /// loops: after `epoll` or the like reports the sampler's file
+    /// descriptor readable, this says whether to call [`next`], or, for an
+    /// [`AuxSampler`], [`read_aux`]. See the [module documentation](self)
+    /// for details.
+    ///
+    /// A flight recorder never has data for `next`.
+    ///
+    /// [`next`]: Sampler::next
+    /// [`read_aux`]: AuxSampler::read_aux
+    pub fn poll_events(&self) -> PollEvents {
+        let pending = |head: &AtomicU64, tail: &AtomicU64| {
+            head.load(Ordering::Acquire) != tail.load(Ordering::Relaxed)
+        };
+        PollEvents {
+            data: !self.overwrite && pending(self.head(), self.tail()),
+            aux: pending(self.aux_head(), self.aux_tail()),
+        }
+    }
+
+    /// Return the number of records and samples lost so far, according to
+    /// the [`RecordEvent::Lost`] and [`RecordEvent::LostSamples`] records
+    /// that [`next`] and [`next_blocking`] have returned.
+    ///
+    /// The kernel reports lost records only once it has room in the ring
+    /// buffer to do so, so this catches up after you read some records.
+    ///
+    /// [`next`]: Sampler::next
+    /// [`next_blocking`]: Sampler::next_blocking
+    pub fn lost_records(&self) -> u64 {
+        self.lost
+    }
+
+    /// Return the records in a flight recorder's ring buffer, from newest to
+    /// oldest.
+    ///
+    /// This pauses the kernel's output while it copies the records out, so
+    /// that they aren't overwritten in the middle; records the event would
+    /// have written meanwhile are lost. The records stay in the buffer, so
+    /// a later snapshot will include them again, unless they've been
+    /// overwritten by then.
+    ///
+    /// If this sampler isn't a flight recorder, this returns an
+    /// `ErrorKind::InvalidInput` error; see [`Builder::overwrite`].
+    ///
+    /// [`Builder::overwrite`]: crate::Builder::overwrite
+    pub fn snapshot(&mut self) -> io::Result<Vec<Record>> {
+        if !self.overwrite {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only a flight recorder's records can be snapshotted; \
+                 see Builder::overwrite",
+            ));
+        }
+
+        self.pause()?;
+
+        // Writing backwards, the kernel moves `data_head` down as it adds
+        // records, so it always points at the newest one, followed by older
+        // records up to a full buffer's worth. Parts of the buffer that
+        // have never been written are zero.
+        let head = self.head().load(Ordering::Acquire);
+        let mut records = vec![];
+        let mut position = head;
+        loop {
+            let consumed = position.wrapping_sub(head) as usize;
+            if consumed + HEADER_SIZE > self.data_size {
+                break;
+            }
+            let header = self.header_at(position);
+            let size = header.size as usize;
+            if size < HEADER_SIZE || consumed + size > self.data_size {
+                break;
+            }
+            records.push(self.record_at(position, &header));
+            position = position.wrapping_add(size as u64);
+        }
+
+        self.resume()?;
+        Ok(records)
+    }
+
+    /// Return the next record from the ring buffer, waiting for one to
+    /// arrive if necessary.
+    ///
+    /// This uses `poll(2)` to sleep on the event's file descriptor, so it
+    /// doesn't busy-wait. If `timeout` is `Some`, give up and return
+    /// `Ok(None)` if no record arrives within that time; `Some(Duration::ZERO)`
+    /// is the same as calling [`next`]. If `timeout` is `None`, wait
+    /// indefinitely.
+    ///
+    /// This also returns `Ok(None)` once the buffer is empty if the event can
+    /// produce no more records, because the process it observes has exited,
+    /// and always returns `Ok(None)` immediately for a flight recorder.
+    ///
+    /// The kernel wakes up waiting readers according to the event's
+    /// `wakeup_events` setting; [`Builder::build_sampler`] sets it to wake
+    /// for every sample, unless told otherwise with
+    /// [`Builder::wakeup_events`] or [`Builder::wakeup_watermark`].
+    ///
+    /// [`next`]: Sampler::next
+    /// [`Builder::build_sampler`]: crate::Builder::build_sampler
+    /// [`Builder::wakeup_events`]: crate::Builder::wakeup_events
+    /// [`Builder::wakeup_watermark`]: crate::Builder::wakeup_watermark
+    pub fn next_blocking(&mut self, timeout: Option<Duration>) -> io::Result<Option<Record>> {
+        if self.overwrite {
+            return Ok(None);
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(record) = self.next() {
+                return Ok(Some(record));
+            }
+
+            let wait = match deadline {
+                None => -1,
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(None);
+                    }
+                    // Round up, so we don't spin when less than a
+                    // millisecond remains.
+                    remaining
+                        .as_nanos()
+                        .div_ceil(1_000_000)
+                        .min(i32::MAX as u128) as i32
+                }
+            };
+
+            let mut pollfd = libc::pollfd {
+                fd: self.counter.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut pollfd, 1, wait) } {
+                -1 => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+                _ if pollfd.revents & libc::POLLHUP != 0 => return Ok(self.next()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Return the next record from the ring buffer, waiting asynchronously
+    /// for one to arrive if necessary.
+    ///
+    /// This is like [`next_blocking`] with no timeout, but rather than
+    /// blocking the calling thread, it registers the event's file
+    /// descriptor with the current tokio runtime, so a task can await
+    /// records without a dedicated reader thread. The runtime must have its
+    /// I/O driver enabled. To give up after a while, wrap the future in
+    /// `tokio::time::timeout`.
+    ///
+    /// This returns `Ok(None)` once the buffer is empty if the event can
+    /// produce no more records, and always returns `Ok(None)` immediately
+    /// for a flight recorder.
+    ///
+    /// This is only available when the `"async"` feature is enabled.
+    ///
+    /// [`next_blocking`]: Sampler::next_blocking
+    #[cfg(feature = "async")]
+    pub async fn next_async(&mut self) -> io::Result<Option<Record>> {
+        use tokio::io::unix::AsyncFd;
+        use tokio::io::Interest;
+
+        if self.overwrite {
+            return Ok(None);
+        }
+        let fd = AsyncFd::with_interest(self.counter.as_raw_fd(), Interest::READABLE)?;
+        loop {
+            if let Some(record) = self.next() {
+                return Ok(Some(record));
+            }
+
+            let mut guard = fd.readable().await?;
+            if guard.ready().is_read_closed() {
+                return Ok(self.next());
+            }
+            guard.clear_ready();
+        }
+    }
+
+    /// Return the configuration needed to parse this sampler's records.
+    pub fn config(&self) -> &ParseConfig {
+        &self.config
+    }
+
+    /// Decode records of type `ty` with `parser`, when they're passed to
+    /// this sampler's [`parse`] or [`try_parse`] methods.
+    ///
+    /// This lets you decode records this crate doesn't understand yet, like
+    /// those added by newer kernels, or specific to some PMU, without
+    /// waiting for the crate to catch up. `parser` receives the record and
+    /// this sampler's [`ParseConfig`], and whatever it returns is wrapped up
+    /// as a [`RecordEvent::Custom`]. It replaces any parser previously
+    /// registered for `ty`, and takes precedence over the crate's own
+    /// decoding of that type.
+    ///
+    ///     # fn main() -> std::io::Result<()> {
+    ///     use perf_event::Builder;
+    ///     use perf_event::events::Software;
+    ///     use perf_event::samples::RecordEvent;
+    ///     use std::convert::TryInto;
+    ///
+    ///     /// A record type from some future kernel.
+    ///     const PERF_RECORD_FUTURE: u32 = 99;
+    ///
+    ///     #[derive(Debug)]
+    ///     struct Future {
+    ///         flags: u64,
+    ///     }
+    ///
+    ///     let mut sampler = Builder::new()
+    ///         .kind(Software::TASK_CLOCK)
+    ///         .sample_period(100_000)
+    ///         .build_sampler()?;
+    ///     sampler.register_parser(PERF_RECORD_FUTURE, |record, _config| {
+    ///         let flags = record.data.get(..8)?.try_into().ok()?;
+    ///         Some(Future { flags: u64::from_ne_bytes(flags) })
+    ///     });
+    ///
+    ///     // ...
+    ///     while let Some(record) = sampler.next() {
+    ///         if let RecordEvent::Custom(custom) = sampler.parse(&record) {
+    ///             if let Some(Some(future)) = custom.downcast_ref::<Option<Future>>() {
+    ///                 println!("{:?}", future);
+    ///             }
+    ///         }
+    ///     }
+    ///     # Ok(()) }
+    ///
+    /// [`parse`]: Sampler::parse
+    /// [`try_parse`]: Sampler::try_parse
+    pub fn register_parser<F, T>(&mut self, ty: u32, parser: F)
+    where
+        F: Fn(&RecordRef, &ParseConfig) -> T + Send + 'static,
+        T: Any + fmt::Debug + Send + Sync,
+    {
+        let parser: CustomParser = Box::new(move |record, config| CustomRecord {
+            ty: record.ty,
+            value: Arc::new(parser(record, config)),
+        });
+        self.parsers.insert(ty, parser);
+    }
+
+    /// Decode `record`, which this sampler produced, using the parser
+    /// registered for its type with [`register_parser`], if any, or else as
+    /// [`Record::parse`] would.
+    ///
+    /// # Panics
+    ///
+    /// Like [`Record::parse`], this panics if the crate's own decoding finds
+    /// the record malformed; use [`try_parse`] to get an error instead.
+    ///
+    /// [`register_parser`]: Sampler::register_parser
+    /// [`try_parse`]: Sampler::try_parse
+    pub fn parse(&self, record: &Record) -> RecordEvent {
+        self.try_parse(record)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Decode `record` as [`parse`] does, but return a [`ParseError`] if it
+    /// is malformed.
+    ///
+    /// [`parse`]: Sampler::parse
+    pub fn try_parse(&self, record: &Record) -> Result<RecordEvent, ParseError> {
+        let record = RecordRef::from(record);
+        match self.parsers.get(&record.ty) {
+            Some(parser) => Ok(RecordEvent::Custom(parser(&record, &self.config))),
+            None => record.try_parse(&self.config),
+        }
+    }
+
+    /// Return the size of this sampler's ring buffer.
+    pub fn buffer_size(&self) -> BufferSize {
+        BufferSize {
+            data_pages: self.data_size / page_size(),
+        }
+    }
+
+    /// Return the parameters for converting between the processor's cycle
+    /// counter and this sampler's timestamps, or `None` if the kernel
+    /// doesn't provide them.
+    ///
+    /// The kernel provides these only when the processor's cycle counter is
+    /// stable enough to use as a clock, as with an invariant TSC on x86.
+    /// The parameters can change, for example when the system resumes from
+    /// suspend, so long-running consumers should fetch them again from time
+    /// to time.
+    pub fn time_converter(&self) -> Option<TimeConverter> {
+        let page = self.page();
+        let lock = unsafe { &*(std::ptr::addr_of_mut!((*page).lock) as *const AtomicU32) };
+
+        // The kernel updates the header under a sequence lock: retry until
+        // we read the fields without the lock changing underneath us.
+        loop {
+            let seq = lock.load(Ordering::Acquire);
+            let (converter, usable) = unsafe {
+                let capabilities = std::ptr::read_volatile(&(*page).__bindgen_anon_1);
+                let capabilities = capabilities.__bindgen_anon_1;
+                let short = if capabilities.cap_user_time_short() != 0 {
+                    Some((
+                        std::ptr::read_volatile(&(*page).time_cycles),
+                        std::ptr::read_volatile(&(*page).time_mask),
+                    ))
+                } else {
+                    None
+                };
+                let converter = TimeConverter {
+                    time_zero: std::ptr::read_volatile(&(*page).time_zero),
+                    time_mult: std::ptr::read_volatile(&(*page).time_mult),
+                    time_shift: std::ptr::read_volatile(&(*page).time_shift),
+                    short,
+                };
+                (converter, capabilities.cap_user_time_zero() != 0)
+            };
+            fence(Ordering::Acquire);
+            if lock.load(Ordering::Relaxed) == seq {
+                return if usable { Some(converter) } else { None };
+            }
+        }
+    }
+
+    /// Return the clock this sampler's timestamps come from, or `None` if
+    /// they come from the kernel's default clock. See [`Builder::clock`].
+    ///
+    /// [`Builder::clock`]: crate::Builder::clock
+    pub fn clock(&self) -> Option<Clock> {
+        self.config.clock
+    }
+
+    /// Return a reference to this sampler's `Counter`.
+    pub fn counter(&self) -> &Counter {
+        &self.counter
+    }
+
+    /// Return a mutable reference to this sampler's `Counter`.
+    pub fn counter_mut(&mut self) -> &mut Counter {
+        &mut self.counter
+    }
+
+    /// Unmap this sampler's ring buffer, and return its `Counter`.
+    ///
+    /// The kernel stops writing records once the buffer is unmapped, but
+    /// the counter goes on counting.
+    pub fn into_counter(self) -> Counter {
+        let this = std::mem::ManuallyDrop::new(self);
+        unsafe {
+            libc::munmap(this.mmap.as_ptr() as *mut libc::c_void, this.mmap_len);
+            drop(std::ptr::read(&this.parsers));
+            // None of our other fields own anything.
+            std::ptr::read(&this.counter)
+        }
+    }
+
+    /// Stop the kernel from writing records to the ring buffer, via
+    /// `PERF_EVENT_IOC_PAUSE_OUTPUT`.
+    ///
+    /// This leaves the buffer's contents alone while you read them, without
+    /// disabling the event: it goes on counting, but the records it would
+    /// have written while paused are lost. Call [`resume`] to start writing
+    /// records again. This requires Linux 4.7 or later.
+    ///
+    /// [`resume`]: Sampler::resume
+    pub fn pause(&mut self) -> io::Result<()> {
+        crate::ioctl::pause_output(&self.counter, true)
+    }
+
+    /// Let the kernel write records to the ring buffer again, after a call
+    /// to [`pause`].
+    ///
+    /// [`pause`]: Sampler::pause
+    pub fn resume(&mut self) -> io::Result<()> {
+        crate::ioctl::pause_output(&self.counter, false)
+    }
+
+    /// Send `counter`'s records to this sampler's ring buffer, via
+    /// `PERF_EVENT_IOC_SET_OUTPUT`.
+    ///
+    /// This is [`Builder::output_to`] for a counter that has already been
+    /// built; see that method for the requirements. `counter` must not
+    /// have a ring buffer of its own, so it can't be another `Sampler`'s
+    /// counter.
+    ///
+    /// [`Builder::output_to`]: crate::Builder::output_to
+    pub fn redirect_output(&self, counter: &Counter) -> io::Result<()> {
+        crate::ioctl::set_output(counter, Some(&self.counter))
+    }
+
+    fn page(&self) -> *mut perf_event_mmap_page {
+        self.mmap.as_ptr() as *mut perf_event_mmap_page
+    }
+
+    /// The kernel advances `data_head` as it writes records.
+    fn head(&self) -> &AtomicU64 {
+        unsafe { &*(std::ptr::addr_of_mut!((*self.page()).data_head) as *const AtomicU64) }
+    }
+
+    /// We advance `data_tail` as we consume them.
+    fn tail(&self) -> &AtomicU64 {
+        unsafe { &*(std::ptr::addr_of_mut!((*self.page()).data_tail) as *const AtomicU64) }
+    }
+
+    /// The kernel advances `aux_head` as it writes AUX data. These are
+    /// zero unless an `AuxSampler` has mapped an AUX area.
+    fn aux_head(&self) -> &AtomicU64 {
+        unsafe { &*(std::ptr::addr_of_mut!((*self.page()).aux_head) as *const AtomicU64) }
+    }
+
+    /// We advance `aux_tail` as we consume AUX data.
+    fn aux_tail(&self) -> &AtomicU64 {
+        unsafe { &*(std::ptr::addr_of_mut!((*self.page()).aux_tail) as *const AtomicU64) }
+    }
+
+    /// Read the header of the record at `position`.
+    fn header_at(&self, position: u64) -> perf_event_header {
+        let mut header = [0_u8; HEADER_SIZE];
+        self.copy_out(position, &mut header);
+        unsafe { std::ptr::read_unaligned(header.as_ptr() as *const perf_event_header) }
+    }
+
+    /// Copy out the record at `position`, whose header is `header`.
+    fn record_at(&self, position: u64, header: &perf_event_header) -> Record {
+        let mut data = vec![0; (header.size as usize).saturating_sub(HEADER_SIZE)];
+        self.copy_out(position.wrapping_add(HEADER_SIZE as u64), &mut data);
+        Record {
+            ty: header.type_,
+            misc: header.misc,
+            data,
+        }
+    }
+
+    /// Borrow the record at `position`, whose header is `header`, copying
+    /// it out only if it wraps around the end of the data area.
+    fn record_ref_at(&self, position: u64, header: &perf_event_header) -> RecordRef<'_> {
+        let len = (header.size as usize).saturating_sub(HEADER_SIZE);
+        let position = position.wrapping_add(HEADER_SIZE as u64);
+        let start = (position % self.data_size as u64) as usize;
+        let data = if start + len <= self.data_size {
+            Cow::Borrowed(unsafe {
+                std::slice::from_raw_parts(self.mmap.as_ptr().add(self.data_offset + start), len)
+            })
+        } else {
+            let mut data = vec![0; len];
+            self.copy_out(position, &mut data);
+            Cow::Owned(data)
+        };
+        RecordRef {
+            ty: header.type_,
+            misc: header.misc,
+            data,
+        }
+    }
+
+    /// Copy bytes out of the data area, starting at `position` (a
+    /// `data_head`-style offset, not yet reduced modulo the buffer size), and
+    /// wrapping around the end of the buffer as needed.
+    fn copy_out(&self, position: u64, out: &mut [u8]) {
+        let start = (position % self.data_size as u64) as usize;
+        let first = out.len().min(self.data_size - start);
+        unsafe {
+            let data = self.mmap.as_ptr().add(self.data_offset);
+            std::ptr::copy_nonoverlapping(data.add(start), out.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(data, out.as_mut_ptr().add(first), out.len() - first);
+        }
+    }
+}
+
+/// Records read in place from a [`Sampler`]'s ring buffer.
+///
+/// This is created by [`Sampler::iter_chunks`]; see its documentation for
+/// details. This isn't an [`Iterator`], since each record it returns
+/// borrows the buffer only until the next call to [`next`]; use `while
+/// let` to loop over the records.
+///
+/// The space of the records read is released to the kernel as you go, and
+/// when this is dropped.
+///
+/// [`next`]: RecordChunks::next
+pub struct RecordChunks<'a> {
+    sampler: &'a mut Sampler,
+
+    /// The position of the next record to read.
+    position: u64,
+
+    /// The count from `LOST` records read, not yet added to the sampler's.
+    lost: u64,
+}
+
+impl RecordChunks<'_> {
+    /// Return the next record from the ring buffer, or `None` if there
+    /// isn't one. This never blocks.
+    ///
+    /// The record borrows its data from the ring buffer, if it can. Calling
+    /// this again releases the previous record's space to the kernel.
+    ///
+    /// Like [`Sampler::next`], this discards everything in the buffer and
+    /// returns `None` if it finds a record whose header is too short.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<RecordRef<'_>> {
+        if self.sampler.overwrite {
+            return None;
+        }
+        self.release();
+
+        let head = self.sampler.head().load(Ordering::Acquire);
+        if head == self.position {
+            return None;
+        }
+        let header = self.sampler.header_at(self.position);
+        if (header.size as usize) < HEADER_SIZE {
+            self.position = head;
+            self.release();
+            return None;
+        }
+        let sampler: &Sampler = self.sampler;
+        let record = sampler.record_ref_at(self.position, &header);
+        self.position += header.size as u64;
+        self.lost += record.lost_count();
+        Some(record)
+    }
+
+    /// Let the kernel reuse the space of the records read so far.
+    fn release(&mut self) {
+        // Make sure we've finished reading the records before the kernel
+        // sees that it may overwrite them.
+        fence(Ordering::SeqCst);
+        self.sampler.tail().store(self.position, Ordering::Release);
+        self.sampler.lost += std::mem::take(&mut self.lost);
+    }
+}
+
+impl Drop for RecordChunks<'_> {
+    fn drop(&mut self) {
+        if !self.sampler.overwrite {
+            self.release();
+        }
+    }
+}
+
+impl fmt::Debug for RecordChunks<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("RecordChunks")
+            .field("sampler", &self.sampler)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl Deref for Sampler {
+    type Target = Counter;
+
+    fn deref(&self) -> &Counter {
+        &self.counter
+    }
+}
+
+impl DerefMut for Sampler {
+    fn deref_mut(&mut self) -> &mut Counter {
+        &mut self.counter
+    }
+}
+
+impl AsRawFd for Sampler {
+    fn as_raw_fd(&self) -> RawFd {
+        self.counter.as_raw_fd()
+    }
+}
+
+impl AsFd for Sampler {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.counter.as_fd()
+    }
+}
+
+/// Unmap the sampler's ring buffer, and return its file descriptor.
+impl IntoRawFd for Sampler {
+    fn into_raw_fd(self) -> RawFd {
+        self.into_counter().into_raw_fd()
+    }
+}
+
+/// Unmap the sampler's ring buffer, and return its file descriptor.
+impl From<Sampler> for OwnedFd {
+    fn from(sampler: Sampler) -> OwnedFd {
+        sampler.into_counter().into()
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.mmap.as_ptr() as *mut libc::c_void, self.mmap_len);
+        }
+    }
+}
+
+impl fmt::Debug for Sampler {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Sampler")
+            .field("counter", &self.counter)
+            .field("data_size", &self.data_size)
+            .finish()
+    }
+}
+
+/// A [`Sampler`] with an AUX area, for hardware tracing PMUs like Intel PT
+/// or Arm CoreSight.
+///
+/// Hardware tracing PMUs produce far more data than fits in records, so
+/// they write it into a second ring buffer, the AUX area, as an unstructured
+/// stream of bytes in a PMU-specific format. They still use the ordinary
+/// ring buffer for records, including [`RecordEvent::Aux`] records saying
+/// when new AUX data has arrived.
+///
+/// This is created by [`Builder::build_aux_sampler`]. It dereferences to
+/// its `Sampler`, so you can read records and enable or disable the event
+/// as usual; call [`read_aux`] to collect the AUX data:
+///
+///     # fn main() -> std::io::Result<()> {
+///     use perf_event::Builder;
+///     use perf_event::events::Pmu;
+///
+///     if let Ok(pt) = Pmu::named("intel_pt") {
+///         let mut tracer = Builder::new()
+///             .kind(pt)
+///             .build_aux_sampler(64)?;
+///
+///         tracer.enable()?;
+///         // ... do things ...
+///         tracer.disable()?;
+///
+///         let mut trace = vec![];
+///         while let Some(data) = tracer.read_aux() {
+///             trace.extend(data);
+///         }
+///         // Hand `trace` to a decoder, like libipt.
+///     }
+///     # Ok(()) }
+///
+/// If the AUX area fills up, the kernel stops tracing until you read some
+/// data, and marks the next `Aux` record [`truncated`].
+///
+/// Alternatively, register a decoder for each hardware ID that may write
+/// to the AUX area with [`register_decoder`], and call [`dispatch`] instead
+/// of `read_aux`.
+///
+/// [`Builder::build_aux_sampler`]: crate::Builder::build_aux_sampler
+/// [`read_aux`]: AuxSampler::read_aux
+/// [`truncated`]: Aux::truncated
+/// [`register_decoder`]: AuxSampler::register_decoder
+/// [`dispatch`]: AuxSampler::dispatch
+pub struct AuxSampler {
+    sampler: Sampler,
+
+    /// The start of the AUX area's mapping.
+    aux: NonNull<u8>,
+
+    /// The size of the AUX area, in bytes.
+    aux_size: usize,
+
+    /// Decoders registered with `register_decoder`, by hardware ID.
+    decoders: HashMap<u64, AuxDecoder>,
+
+    /// The hardware ID from the last `AUX_OUTPUT_HW_ID` record `dispatch`
+    /// read.
+    hw_id: Option<u64>,
+}
+
+// As for `Sampler`, the AUX area is only accessed through `&mut self`.
+unsafe impl Send for AuxSampler {}
+
+impl AuxSampler {
+    /// Map an AUX area of `aux_pages` pages for `sampler`'s event.
+    pub(crate) fn new(sampler: Sampler, aux_pages: usize) -> io::Result<AuxSampler> {
+        if !aux_pages.is_power_of_two() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "AUX area size must be a power of two pages, not {}",
+                    aux_pages
+                ),
+            ));
+        }
+        if sampler.overwrite {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a flight recorder can't have an AUX area",
+            ));
+        }
+
+        // The AUX area goes right after the data area. The kernel learns
+        // where from the header page, which we must fill in before mapping.
+        let aux_size = page_size() * aux_pages;
+        let aux_offset = sampler.mmap_len;
+        unsafe {
+            let page = sampler.page();
+            (*page).aux_offset = aux_offset as u64;
+            (*page).aux_size = aux_size as u64;
+        }
+
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                aux_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                sampler.counter.as_raw_fd(),
+                aux_offset as libc::off_t,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(AuxSampler {
+            sampler,
+            aux: NonNull::new(base as *mut u8).unwrap(),
+            aux_size,
+            decoders: HashMap::new(),
+            hw_id: None,
+        })
+    }
+
+    /// Return the AUX data written since the last call, or `None` if there
+    /// isn't any. This never blocks.
+    ///
+    /// The data is a contiguous stretch of the stream, in the PMU's own
+    /// format. Reading it frees the space in the AUX area for the kernel to
+    /// reuse.
+    pub fn read_aux(&mut self) -> Option<Vec<u8>> {
+        let head = self.aux_head().load(Ordering::Acquire);
+        let tail = self.aux_tail().load(Ordering::Relaxed);
+        if head == tail {
+            return None;
+        }
+
+        let data = self.aux_data(tail, (head - tail) as usize);
+        fence(Ordering::SeqCst);
+        self.aux_tail().store(head, Ordering::Release);
+        Some(data)
+    }
+
+    /// Call `decoder` with the AUX data written by the hardware whose ID is
+    /// `hw_id`, whenever [`dispatch`] finds some.
+    ///
+    /// The kernel reports hardware IDs in [`RecordEvent::AuxOutputHwId`]
+    /// records, whose values are specific to the PMU. This replaces any
+    /// decoder already registered for `hw_id`.
+    ///
+    /// [`dispatch`]: AuxSampler::dispatch
+    pub fn register_decoder<F>(&mut self, hw_id: u64, decoder: F)
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        self.decoders.insert(hw_id, Box::new(decoder));
+    }
+
+    /// Read all the records in the ring buffer, passing the AUX data each
+    /// [`RecordEvent::Aux`] record announces to the decoder registered for
+    /// the current hardware ID, and return the records. This never blocks.
+    ///
+    /// The current hardware ID is the one given by the most recent
+    /// [`RecordEvent::AuxOutputHwId`] record. AUX data that arrives before
+    /// any such record, or for a hardware ID with no decoder registered, is
+    /// discarded. Either way, the data's space in the AUX area is freed for
+    /// the kernel to reuse.
+    ///
+    /// Don't mix calls to this with calls to [`read_aux`], which takes AUX
+    /// data without regard to which hardware wrote it.
+    ///
+    /// [`read_aux`]: AuxSampler::read_aux
+    pub fn dispatch(&mut self) -> Vec<Record> {
+        let mut records = vec![];
+        while let Some(record) = self.sampler.next() {
+            match record.try_parse(self.sampler.config()) {
+                Ok(RecordEvent::AuxOutputHwId(hw_id)) => self.hw_id = Some(hw_id),
+                Ok(RecordEvent::Aux(aux)) => self.dispatch_aux(&aux),
+                _ => {}
+            }
+            records.push(record);
+        }
+        records
+    }
+
+    /// Return the hardware ID from the most recent
+    /// [`RecordEvent::AuxOutputHwId`] record that [`dispatch`] has read,
+    /// if any.
+    ///
+    /// [`dispatch`]: AuxSampler::dispatch
+    pub fn hw_id(&self) -> Option<u64> {
+        self.hw_id
+    }
+
+    /// Return the size of the AUX area, in bytes.
+    pub fn aux_size(&self) -> usize {
+        self.aux_size
+    }
+
+    /// Hand the data `aux` announces to the current hardware ID's decoder,
+    /// and free its space in the AUX area.
+    fn dispatch_aux(&mut self, aux: &Aux) {
+        let len = aux.aux_size as usize;
+        // If the kernel reports more than the AUX area holds, the start of
+        // it has already been overwritten.
+        if len > 0 && len <= self.aux_size {
+            if let Some(hw_id) = self.hw_id.filter(|id| self.decoders.contains_key(id)) {
+                let data = self.aux_data(aux.aux_offset, len);
+                (self.decoders.get_mut(&hw_id).unwrap())(&data);
+            }
+        }
+        let end = aux.aux_offset + aux.aux_size;
+        let tail = self.aux_tail().load(Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        self.aux_tail().store(tail.max(end), Ordering::Release);
+    }
+
+    /// Copy `len` bytes of the AUX data stream out of the AUX area,
+    /// starting at `offset`, which needn't be reduced modulo its size.
+    fn aux_data(&self, offset: u64, len: usize) -> Vec<u8> {
+        let start = (offset % self.aux_size as u64) as usize;
+        let first = len.min(self.aux_size - start);
+        let mut data = vec![0; len];
+        unsafe {
+            let aux = self.aux.as_ptr();
+            std::ptr::copy_nonoverlapping(aux.add(start), data.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(aux, data.as_mut_ptr().add(first), len - first);
+        }
+        data
+    }
+
+    /// Return a reference to this `AuxSampler`'s `Sampler`.
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    /// Return a mutable reference to this `AuxSampler`'s `Sampler`.
+    pub fn sampler_mut(&mut self) -> &mut Sampler {
+        &mut self.sampler
+    }
+}
+
+impl Deref for AuxSampler {
+    type Target = Sampler;
+
+    fn deref(&self) -> &Sampler {
+        &self.sampler
+    }
+}
+
+impl DerefMut for AuxSampler {
+    fn deref_mut(&mut self) -> &mut Sampler {
+        &mut self.sampler
+    }
+}
+
+impl AsRawFd for AuxSampler {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sampler.as_raw_fd()
+    }
+}
+
+impl AsFd for AuxSampler {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.sampler.as_fd()
+    }
+}
+
+impl Drop for AuxSampler {
+    fn drop(&mut self) {
+        // Unmap the AUX area before `self.sampler` unmaps the rest of the
+        // buffer.
+        unsafe {
+            libc::munmap(self.aux.as_ptr() as *mut libc::c_void, self.aux_size);
+        }
+    }
+}
+
+impl fmt::Debug for AuxSampler {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut hw_ids: Vec<&u64> = self.decoders.keys().collect();
+        hw_ids.sort();
+        fmt.debug_struct("AuxSampler")
+            .field("sampler", &self.sampler)
+            .field("aux_size", &self.aux_size)
+            .field("decoders", &hw_ids)
+            .field("hw_id", &self.hw_id)
+            .finish()
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Return the ring buffer size to give each sampler in a set with one per
+/// CPU.
+///
+/// Unprivileged processes may lock [`mlock_limit_kb`] of ring buffer per
+/// online CPU, and beyond that are charged against `RLIMIT_MEMLOCK`, which
+/// is often tiny. So this returns the largest size no greater than the
+/// default whose mapping fits within that limit. Privileged processes, or
+/// systems where `perf_event_paranoid` is `-1`, get the default.
+#[cfg(feature = "sampler_set")]
+pub(crate) fn buffer_size_per_cpu() -> BufferSize {
+    let unrestricted = matches!(Level::current(), Ok(Level::Unrestricted));
+    if unrestricted || Level::privileged() {
+        return BufferSize::default();
+    }
+    let mlock_kb = match mlock_limit_kb() {
+        Ok(kb) => kb,
+        Err(_) => return BufferSize::default(),
+    };
+    let fit = (mlock_kb * 1024 / page_size()).saturating_sub(1);
+    let mut data_pages = 1;
+    while data_pages * 2 <= fit && data_pages * 2 <= DATA_PAGES {
+        data_pages *= 2;
+    }
+    BufferSize { data_pages }
+}
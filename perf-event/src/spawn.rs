@@ -0,0 +1,220 @@
+//! Counting a command from the moment it starts.
+//!
+//! To measure a program the way `perf stat ./prog` does, the counters must
+//! observe the child process, but begin counting exactly when it calls
+//! `exec`, so that neither the parent's work nor the child's setup between
+//! `fork` and `exec` is included. The kernel supports this with the
+//! `enable_on_exec` flag, which [`Builder::enable_on_exec`] sets. But the
+//! counters must be attached to the child after it has been forked, and
+//! before it has called `exec`, and [`std::process::Command`] offers no
+//! moment like that.
+//!
+//! The [`spawn_counted`] function provides one: the child pauses just before
+//! `exec` while your closure attaches counters to it.
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::spawn::spawn_counted;
+//!     use std::process::Command;
+//!
+//!     let (mut child, mut counter) = spawn_counted(&mut Command::new("true"), |pid| {
+//!         Builder::new()
+//!             .observe_pid(pid)
+//!             .enable_on_exec()
+//!             .kind(Software::TASK_CLOCK)
+//!             .build()
+//!     })?;
+//!     child.wait()?;
+//!     println!("child ran for {} ns", counter.read()?);
+//!     # Ok(()) }
+//!
+//! To learn what the child cost beyond what the counters measure, like its
+//! peak memory use and how often it was switched out, wait for it with
+//! [`wait_with_usage`] instead of [`Child::wait`].
+//!
+//! [`Builder::enable_on_exec`]: crate::Builder::enable_on_exec
+
+use libc::pid_t;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::time::Duration;
+
+/// The resources a child process used, as `wait4(2)` reports them.
+///
+/// The figures cover the child and any of its own children that it waited
+/// for.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ResourceUsage {
+    /// Time spent running in user mode.
+    pub user_time: Duration,
+
+    /// Time spent running in the kernel.
+    pub system_time: Duration,
+
+    /// The peak resident set size, in kilobytes.
+    pub max_rss_kb: u64,
+
+    /// Page faults serviced without any I/O.
+    pub minor_faults: u64,
+
+    /// Page faults that required I/O.
+    pub major_faults: u64,
+
+    /// Context switches because the process gave up the processor, usually
+    /// to wait for a resource.
+    pub voluntary_context_switches: u64,
+
+    /// Context switches because the process's time slice ran out, or a
+    /// higher priority process became runnable.
+    pub involuntary_context_switches: u64,
+}
+
+impl From<&libc::rusage> for ResourceUsage {
+    fn from(usage: &libc::rusage) -> ResourceUsage {
+        let duration =
+            |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+        ResourceUsage {
+            user_time: duration(usage.ru_utime),
+            system_time: duration(usage.ru_stime),
+            max_rss_kb: usage.ru_maxrss as u64,
+            minor_faults: usage.ru_minflt as u64,
+            major_faults: usage.ru_majflt as u64,
+            voluntary_context_switches: usage.ru_nvcsw as u64,
+            involuntary_context_switches: usage.ru_nivcsw as u64,
+        }
+    }
+}
+
+/// Wait for `child` to exit, and return its exit status together with the
+/// resources it used.
+///
+/// [`Child::wait`] reaps the child with `waitpid`, which discards its
+/// resource usage; this uses `wait4` instead. Like `Child::wait`, this
+/// closes the child's standard input first, if it was piped, so that a
+/// child reading it doesn't wait forever.
+pub fn wait_with_usage(mut child: Child) -> io::Result<(ExitStatus, ResourceUsage)> {
+    drop(child.stdin.take());
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        if unsafe { libc::wait4(child.id() as pid_t, &mut status, 0, &mut usage) } != -1 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok((ExitStatus::from_raw(status), ResourceUsage::from(&usage)))
+}
+
+/// Spawn `command`, calling `attach` with the child's process id before it
+/// runs, and return the child along with whatever `attach` returned.
+///
+/// The child process is forked, and then waits for `attach` to return before
+/// calling `exec`. `attach` should build counters that [`observe_pid`] the
+/// given process id, and set [`enable_on_exec`], so that they start counting
+/// when the child's program does. Since `enable_on_exec` counters are only
+/// enabled by the `exec` itself, this returns them ready to read once the
+/// child has exited.
+///
+/// If `attach` returns an error, the child exits without running the
+/// program, and this returns `attach`'s error. If the child can't be spawned
+/// at all, this returns the error from [`Command::spawn`].
+///
+/// This replaces any previous [`pre_exec`] closure on `command`'s behalf.
+///
+/// [`observe_pid`]: crate::Builder::observe_pid
+/// [`enable_on_exec`]: crate::Builder::enable_on_exec
+/// [`pre_exec`]: std::os::unix::process::CommandExt::pre_exec
+pub fn spawn_counted<T, F>(command: &mut Command, attach: F) -> io::Result<(Child, T)>
+where
+    F: FnOnce(pid_t) -> io::Result<T>,
+{
+    // The child sends its pid through `ready`, and then waits for a byte on
+    // `go` before calling `exec`.
+    let (ready_read, ready_write) = pipe()?;
+    let (go_read, go_write) = pipe()?;
+    let (ready_write_fd, go_read_fd, go_write_fd) =
+        (raw_fd(&ready_write), raw_fd(&go_read), raw_fd(&go_write));
+
+    // This closure runs in the child, between `fork` and `exec`, so it may
+    // only make async-signal-safe system calls.
+    unsafe {
+        command.pre_exec(move || {
+            // Close our copy of the `go` pipe's write end, so that if the
+            // parent closes its own, our read sees end-of-file.
+            libc::close(go_write_fd);
+            let pid = libc::getpid().to_ne_bytes();
+            if libc::write(ready_write_fd, pid.as_ptr() as *const _, pid.len())
+                != pid.len() as isize
+            {
+                return Err(io::Error::last_os_error());
+            }
+            let mut go = 0_u8;
+            if libc::read(go_read_fd, &mut go as *mut u8 as *mut _, 1) != 1 {
+                return Err(io::Error::from_raw_os_error(libc::ECANCELED));
+            }
+            Ok(())
+        });
+    }
+
+    std::thread::scope(|scope| {
+        // `Command::spawn` doesn't return until the child has called `exec`,
+        // so it needs a thread of its own.
+        let spawner = scope.spawn(move || {
+            let child = command.spawn();
+            // Once the child has been forked, the parent's copies of these
+            // must close, so that `ready_read` sees end-of-file if the
+            // spawn failed.
+            drop(ready_write);
+            drop(go_read);
+            child
+        });
+
+        // If reading the pid fails, the child was never forked, and the
+        // spawning thread has the real error.
+        let attached = read_pid(ready_read).ok().map(attach);
+        let mut go_write = go_write;
+        if let Some(Ok(_)) = attached {
+            go_write.write_all(&[1])?;
+        }
+        // If `attach` failed, this makes the child's read fail, so it exits
+        // without running the program.
+        drop(go_write);
+
+        let spawned = spawner.join().expect("spawning thread panicked");
+        match (spawned, attached) {
+            (Ok(child), Some(Ok(value))) => Ok((child, value)),
+            // If `attach` failed, the spawn did too; report the cause.
+            (_, Some(Err(e))) | (Err(e), _) => Err(e),
+            (Ok(_), None) => unreachable!("child ran without reporting its pid"),
+        }
+    })
+}
+
+/// Read the child's pid from `ready`.
+fn read_pid(mut ready: File) -> io::Result<pid_t> {
+    let mut pid = [0_u8; std::mem::size_of::<pid_t>()];
+    ready.read_exact(&mut pid)?;
+    Ok(pid_t::from_ne_bytes(pid))
+}
+
+/// Create a pipe whose ends are closed on `exec`, returning the read and
+/// write ends.
+fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]))) }
+}
+
+fn raw_fd(file: &File) -> RawFd {
+    use std::os::unix::io::AsRawFd;
+    file.as_raw_fd()
+}
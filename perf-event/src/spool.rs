@@ -0,0 +1,379 @@
+//! Saving records to a file, and reading them back.
+//!
+//! A [`RecordWriter`] writes records as JSON Lines: the first line is the
+//! [`ParseConfig`] needed to decode them, and each line after that is one
+//! raw [`Record`], as the crate's `serde` support serializes it. This is the
+//! format `perf-ev-record` writes. A [`RecordReader`] reads it back:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Software;
+//!     use perf_event::spool::{RecordReader, RecordWriter};
+//!
+//!     let mut sampler = Builder::new()
+//!         .kind(Software::TASK_CLOCK)
+//!         .sample_period(100_000)
+//!         .build_sampler()?;
+//!     sampler.enable()?;
+//!     let _spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+//!     sampler.disable()?;
+//!
+//!     let mut writer = RecordWriter::new(Vec::new(), sampler.config())?;
+//!     while let Some(record) = sampler.next() {
+//!         writer.write(&record)?;
+//!     }
+//!     let file = writer.finish()?;
+//!
+//!     let reader = RecordReader::new(&file[..])?;
+//!     let config = *reader.config();
+//!     for record in reader {
+//!         println!("{:?}", record?.parse(&config));
+//!     }
+//!     # Ok(()) }
+//!
+//! Records sampled at a high frequency add up quickly, so with the `"lz4"`
+//! or `"zstd"` feature, a `RecordWriter` can compress its output; see
+//! [`Compression`]. It compresses the records in batches, each in a frame of
+//! its own that ends at a record boundary, so a file whose writer was cut
+//! short loses at most the records of its last frame. The output is an
+//! ordinary `.lz4` or `.zst` stream, which `lz4 -d` or `zstd -d` turns back
+//! into JSON Lines. `RecordReader` recognizes compressed files by their
+//! magic numbers.
+//!
+//! This module is only available when the `"spool"` feature is enabled.
+//!
+//! [`ParseConfig`]: crate::samples::ParseConfig
+//! [`Record`]: crate::samples::Record
+
+use crate::samples::{ParseConfig, Record};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// The first bytes of an LZ4 frame.
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+/// The first bytes of a Zstandard frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How a [`RecordWriter`] compresses its output.
+///
+/// Which variants exist depends on the crate's features: `Lz4` needs the
+/// `"lz4"` feature, and `Zstd` needs the `"zstd"` feature.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Write plain JSON Lines.
+    #[default]
+    None,
+
+    /// Write an LZ4 frame stream. This is fast enough to keep up with most
+    /// samplers, at a modest compression ratio.
+    #[cfg(feature = "lz4")]
+    Lz4,
+
+    /// Write a Zstandard frame stream, compressed at the given level: `1`
+    /// is fastest, `19` is smallest, and `0` selects the library's default.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+/// Writes records to a file, or any other [`Write`] implementation, for a
+/// [`RecordReader`] to read back.
+///
+/// See the [module documentation](self) for the format.
+///
+/// A `RecordWriter` holds records until it has a frame's worth, and then
+/// writes them out in one go, compressed if requested; use
+/// [`set_frame_size`] to change how much it holds. Call [`flush`] or
+/// [`finish`] to write out the records it's holding. Dropping a
+/// `RecordWriter` writes them out too, but ignores any errors.
+///
+/// [`set_frame_size`]: RecordWriter::set_frame_size
+/// [`flush`]: RecordWriter::flush
+/// [`finish`]: RecordWriter::finish
+#[derive(Debug)]
+pub struct RecordWriter<W: Write> {
+    // This is only `None` once `finish` has taken it.
+    inner: Option<W>,
+    compression: Compression,
+
+    /// JSON Lines not yet written to `inner`, all complete.
+    frame: Vec<u8>,
+
+    /// Write out `frame` once it holds at least this many bytes.
+    frame_size: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// The default for [`set_frame_size`].
+    ///
+    /// [`set_frame_size`]: RecordWriter::set_frame_size
+    pub const DEFAULT_FRAME_SIZE: usize = 256 * 1024;
+
+    /// Return a `RecordWriter` that writes uncompressed records to `inner`,
+    /// for an event whose configuration is `config`.
+    ///
+    /// This writes `config` to `inner` right away.
+    pub fn new(inner: W, config: &ParseConfig) -> io::Result<RecordWriter<W>> {
+        RecordWriter::with_compression(inner, config, Compression::None)
+    }
+
+    /// Return a `RecordWriter` that writes records to `inner`, compressed as
+    /// `compression` says, for an event whose configuration is `config`.
+    ///
+    /// This writes `config` to `inner` right away, in a frame of its own.
+    pub fn with_compression(
+        inner: W,
+        config: &ParseConfig,
+        compression: Compression,
+    ) -> io::Result<RecordWriter<W>> {
+        let mut writer = RecordWriter {
+            inner: Some(inner),
+            compression,
+            frame: vec![],
+            frame_size: Self::DEFAULT_FRAME_SIZE,
+        };
+        serde_json::to_writer(&mut writer.frame, config)?;
+        writer.frame.push(b'\n');
+        writer.end_frame()?;
+        Ok(writer)
+    }
+
+    /// Write out a frame once at least `bytes` bytes of records are waiting,
+    /// counted before compression. The default is
+    /// [`DEFAULT_FRAME_SIZE`].
+    ///
+    /// Bigger frames compress better; smaller frames lose fewer records if
+    /// the writer is interrupted.
+    ///
+    /// [`DEFAULT_FRAME_SIZE`]: RecordWriter::DEFAULT_FRAME_SIZE
+    pub fn set_frame_size(&mut self, bytes: usize) {
+        self.frame_size = bytes;
+    }
+
+    /// Write `record`.
+    ///
+    /// This holds the record until the current frame is full; see
+    /// [`set_frame_size`].
+    ///
+    /// [`set_frame_size`]: RecordWriter::set_frame_size
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        serde_json::to_writer(&mut self.frame, record)?;
+        self.frame.push(b'\n');
+        if self.frame.len() >= self.frame_size {
+            self.end_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Write out the records this `RecordWriter` is holding as a frame, and
+    /// flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.end_frame()?;
+        self.inner_mut().flush()
+    }
+
+    /// Write out the records this `RecordWriter` is holding, and return
+    /// the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    /// Return a reference to the underlying writer.
+    ///
+    /// Records this `RecordWriter` is holding haven't been written to it
+    /// yet; see [`flush`].
+    ///
+    /// [`flush`]: RecordWriter::flush
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    fn inner_mut(&mut self) -> &mut W {
+        self.inner.as_mut().expect("RecordWriter used after finish")
+    }
+
+    /// Write the records in `self.frame` to the underlying writer, as a
+    /// frame of their own.
+    fn end_frame(&mut self) -> io::Result<()> {
+        if self.frame.is_empty() {
+            return Ok(());
+        }
+        let inner = self.inner.as_mut().expect("RecordWriter used after finish");
+        match self.compression {
+            Compression::None => inner.write_all(&self.frame)?,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(inner);
+                encoder.write_all(&self.frame)?;
+                encoder.finish()?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => zstd::stream::copy_encode(&self.frame[..], inner, level)?,
+        }
+        self.frame.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for RecordWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.flush();
+        }
+    }
+}
+
+/// Reads records written by a [`RecordWriter`], or by `perf-ev-record`.
+///
+/// This is an iterator over the file's records. Use [`config`] to decode
+/// them. If reading the file fails, as when a compressed file has been cut
+/// short, the iterator produces the error and then ends; a line that
+/// doesn't hold a record produces an error, but the iterator goes on to
+/// the next line.
+///
+/// [`config`]: RecordReader::config
+pub struct RecordReader<'a> {
+    lines: io::Lines<Box<dyn BufRead + 'a>>,
+    config: ParseConfig,
+
+    /// True once reading has failed. Decompressors can keep failing on a
+    /// damaged stream, so we stop there.
+    failed: bool,
+}
+
+impl<'a> RecordReader<'a> {
+    /// Return a `RecordReader` for the records in `reader`, and read their
+    /// configuration.
+    ///
+    /// If `reader` holds an LZ4 or Zstandard stream, this decompresses it.
+    /// If this crate was built without the feature for that format, this
+    /// returns an error of kind [`io::ErrorKind::Unsupported`].
+    pub fn new<R: Read + 'a>(mut reader: R) -> io::Result<RecordReader<'a>> {
+        let mut magic = [0; 4];
+        let mut len = 0;
+        while len < magic.len() {
+            match reader.read(&mut magic[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let reader = io::Cursor::new(magic).take(len as u64).chain(reader);
+
+        let reader: Box<dyn BufRead + 'a> = if magic[..len] == LZ4_MAGIC {
+            lz4_reader(reader)?
+        } else if magic[..len] == ZSTD_MAGIC {
+            zstd_reader(reader)?
+        } else {
+            Box::new(BufReader::new(reader))
+        };
+
+        let mut lines = reader.lines();
+        let config = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "record file is empty",
+                ))
+            }
+        };
+        Ok(RecordReader {
+            lines,
+            config,
+            failed: false,
+        })
+    }
+
+    /// Return the configuration of the event that produced these records,
+    /// for use with [`Record::parse`].
+    ///
+    /// [`Record::parse`]: crate::samples::Record::parse
+    pub fn config(&self) -> &ParseConfig {
+        &self.config
+    }
+}
+
+#[cfg(feature = "lz4")]
+fn lz4_reader<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let decoder = lz4_flex::frame::FrameDecoder::new(reader);
+    Ok(Box::new(BufReader::new(Lz4Frames(decoder))))
+}
+
+/// A reader for a stream of LZ4 frames.
+///
+/// `FrameDecoder` reports the end of each frame as end-of-file, but starts
+/// on the next frame if read again.
+#[cfg(feature = "lz4")]
+struct Lz4Frames<R: Read>(lz4_flex::frame::FrameDecoder<R>);
+
+#[cfg(feature = "lz4")]
+impl<R: Read> Read for Lz4Frames<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf)? {
+            // `RecordWriter` never writes empty frames, so a second zero is
+            // the real end of the stream.
+            0 if !buf.is_empty() => self.0.read(buf),
+            n => Ok(n),
+        }
+    }
+}
+
+#[cfg(not(feature = "lz4"))]
+fn lz4_reader<'a, R: Read + 'a>(_reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    Err(unsupported("lz4"))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_reader<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let decoder = zstd::Decoder::new(reader)?;
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_reader<'a, R: Read + 'a>(_reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    Err(unsupported("zstd"))
+}
+
+/// Return an error for a file compressed with `format`, whose feature
+/// this crate was built without.
+#[cfg(not(all(feature = "lz4", feature = "zstd")))]
+fn unsupported(format: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "record file is {0}-compressed, but perf-event was built without \
+             the \"{0}\" feature",
+            format
+        ),
+    )
+}
+
+impl Iterator for RecordReader<'_> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        if self.failed {
+            return None;
+        }
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+        Some(serde_json::from_str(&line).map_err(io::Error::from))
+    }
+}
+
+impl fmt::Debug for RecordReader<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("RecordReader")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
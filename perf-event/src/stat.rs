@@ -0,0 +1,356 @@
+//! Counting events over a piece of work, like `perf stat`.
+//!
+//! Most programs that use this crate to measure something do the same
+//! dance: build a counter for each event, reset and enable them, run the
+//! work, disable them, read them, scale up any that were multiplexed, and
+//! work out ratios like instructions per cycle. A [`StatBuilder`] does all
+//! that, and returns a [`StatReport`]:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use perf_event::stat::StatBuilder;
+//!
+//! let (sum, report) = StatBuilder::new()
+//!     .default_events()
+//!     .run(|| (0..1_000_000_u64).sum::<u64>())?;
+//!
+//! println!("sum: {}", sum);
+//! if let Some(ipc) = report.ipc() {
+//!     println!("{:.2} instructions per cycle", ipc);
+//! }
+//! print!("{}", report);
+//! # Ok(()) }
+//! ```
+//!
+//! [`StatBuilder::run_command`] measures a child process instead, from the
+//! moment it calls `exec`, and includes the child's [`ResourceUsage`] in
+//! the report.
+//!
+//! Events the system doesn't support don't stop the others from being
+//! counted; their entries in the report just hold the error.
+
+#[cfg(feature = "discovery")]
+use crate::events::ParseEventError;
+use crate::events::{Event, Hardware, Software};
+use crate::spawn::{spawn_counted, wait_with_usage, ResourceUsage};
+use crate::{batch, Builder, CountAndTime, Counter};
+use std::fmt;
+use std::io;
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+/// A set of events to count over a closure or a command.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug, Default)]
+pub struct StatBuilder {
+    events: Vec<(String, Event)>,
+}
+
+/// The results of a [`StatBuilder`] run.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug)]
+pub struct StatReport {
+    entries: Vec<StatEntry>,
+    elapsed: Duration,
+    usage: Option<ResourceUsage>,
+}
+
+/// One event's entry in a [`StatReport`].
+#[derive(Debug)]
+pub struct StatEntry {
+    /// The name the event was given when it was added to the
+    /// [`StatBuilder`].
+    pub name: String,
+
+    /// The event counted.
+    pub event: Event,
+
+    /// The event's count and timesharing data, or the error that prevented
+    /// it from being counted.
+    pub result: io::Result<CountAndTime>,
+}
+
+impl StatBuilder {
+    /// Return a `StatBuilder` with no events.
+    pub fn new() -> StatBuilder {
+        StatBuilder::default()
+    }
+
+    /// Add `event` to the set of events to count, under the name `name`.
+    pub fn event<N, E>(mut self, name: N, event: E) -> StatBuilder
+    where
+        N: Into<String>,
+        E: Into<Event>,
+    {
+        self.events.push((name.into(), event.into()));
+        self
+    }
+
+    /// Add the events named in the comma-separated list `names`, as
+    /// [`Event::parse`] understands them, like `"cycles,instructions"`.
+    ///
+    /// This is only available when the `"discovery"` feature is enabled.
+    #[cfg(feature = "discovery")]
+    pub fn parse_events(mut self, names: &str) -> Result<StatBuilder, ParseEventError> {
+        for name in names.split(',').filter(|name| !name.is_empty()) {
+            self.events.push((name.to_string(), Event::parse(name)?));
+        }
+        Ok(self)
+    }
+
+    /// Add the events `perf stat` counts by default: task clock, context
+    /// switches, CPU migrations, page faults, cycles, instructions, branches,
+    /// and branch misses.
+    pub fn default_events(self) -> StatBuilder {
+        self.event("task-clock", Software::TASK_CLOCK)
+            .event("context-switches", Software::CONTEXT_SWITCHES)
+            .event("cpu-migrations", Software::CPU_MIGRATIONS)
+            .event("page-faults", Software::PAGE_FAULTS)
+            .event("cycles", Hardware::CPU_CYCLES)
+            .event("instructions", Hardware::INSTRUCTIONS)
+            .event("branches", Hardware::BRANCH_INSTRUCTIONS)
+            .event("branch-misses", Hardware::BRANCH_MISSES)
+    }
+
+    /// Return the names and events added so far, in order.
+    pub fn events(&self) -> impl Iterator<Item = (&str, &Event)> + '_ {
+        self.events
+            .iter()
+            .map(|(name, event)| (name.as_str(), event))
+    }
+
+    /// Count the events while calling `work`, and return its result
+    /// together with a report.
+    ///
+    /// The counters observe the calling thread, and are inherited by any
+    /// threads or processes it creates while they're enabled, so work that
+    /// `work` hands off to new threads is counted too. Work in other threads
+    /// that already existed is not.
+    ///
+    /// This returns an error only if enabling or disabling the counters
+    /// fails. Events that can't be counted at all are reported in the
+    /// report's entries.
+    pub fn run<T, F: FnOnce() -> T>(&self, work: F) -> io::Result<(T, StatReport)> {
+        let mut built = self.build(|| Builder::new().observe_self().inherit(true));
+
+        let mut counters: Vec<&mut Counter> =
+            built.iter_mut().filter_map(|c| c.as_mut().ok()).collect();
+        batch::reset_all(&mut counters)?;
+        let start = Instant::now();
+        batch::enable_all(&mut counters)?;
+        let value = work();
+        batch::disable_all(&mut counters)?;
+        let elapsed = start.elapsed();
+
+        Ok((value, self.report(built, elapsed, None)))
+    }
+
+    /// Spawn `command`, count the events in it and its descendants until it
+    /// exits, and return its exit status together with a report.
+    ///
+    /// The counters start counting when the child calls `exec`, as with
+    /// [`spawn_counted`]. The report's elapsed time runs from spawning the
+    /// child to its exit, and its [`usage`] holds the resources the child
+    /// used, as [`wait_with_usage`] reports them.
+    ///
+    /// [`usage`]: StatReport::usage
+    pub fn run_command(&self, command: &mut Command) -> io::Result<(ExitStatus, StatReport)> {
+        let start = Instant::now();
+        let (child, built) = spawn_counted(command, |pid| {
+            Ok(self.build(|| {
+                Builder::new()
+                    .observe_pid(pid)
+                    .inherit(true)
+                    .enable_on_exec()
+            }))
+        })?;
+        let (status, usage) = wait_with_usage(child)?;
+        let elapsed = start.elapsed();
+
+        Ok((status, self.report(built, elapsed, Some(usage))))
+    }
+
+    /// Build a counter for each event, starting from the `Builder`s that
+    /// `builder` returns.
+    fn build<'a, B>(&self, builder: B) -> Vec<io::Result<Counter>>
+    where
+        B: Fn() -> Builder<'a>,
+    {
+        self.events
+            .iter()
+            .map(|(_, event)| builder().kind(event.clone()).build())
+            .collect()
+    }
+
+    /// Read each counter in `built`, and pair its result with its event.
+    fn report(
+        &self,
+        built: Vec<io::Result<Counter>>,
+        elapsed: Duration,
+        usage: Option<ResourceUsage>,
+    ) -> StatReport {
+        let entries = self
+            .events
+            .iter()
+            .zip(built)
+            .map(|((name, event), counter)| StatEntry {
+                name: name.clone(),
+                event: event.clone(),
+                result: counter.and_then(|mut counter| counter.read_count_and_time()),
+            })
+            .collect();
+        StatReport {
+            entries,
+            elapsed,
+            usage,
+        }
+    }
+}
+
+impl StatReport {
+    /// Return the entries for each event, in the order they were added to
+    /// the [`StatBuilder`].
+    pub fn entries(&self) -> &[StatEntry] {
+        &self.entries
+    }
+
+    /// Return the wall-clock time the counters were enabled.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Return the resources the measured child process used, if this
+    /// report came from [`StatBuilder::run_command`].
+    pub fn usage(&self) -> Option<ResourceUsage> {
+        self.usage
+    }
+
+    /// Return the count for the event named `name`, scaled up if it was
+    /// multiplexed, or `None` if there is no such event, or it couldn't be
+    /// counted, or it never ran.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .and_then(StatEntry::scaled)
+    }
+
+    /// Return the scaled count for `event`, as for [`get`].
+    ///
+    /// [`get`]: StatReport::get
+    pub fn value<E: Into<Event>>(&self, event: E) -> Option<u64> {
+        let event = event.into();
+        self.entries
+            .iter()
+            .find(|entry| entry.event == event)
+            .and_then(StatEntry::scaled)
+    }
+
+    /// Return the instructions retired per CPU cycle, if both
+    /// [`Hardware::INSTRUCTIONS`] and [`Hardware::CPU_CYCLES`] were counted.
+    pub fn ipc(&self) -> Option<f64> {
+        self.ratio(Hardware::INSTRUCTIONS, Hardware::CPU_CYCLES)
+    }
+
+    /// Return the fraction of cache references that missed, if both
+    /// [`Hardware::CACHE_MISSES`] and [`Hardware::CACHE_REFERENCES`] were
+    /// counted.
+    pub fn cache_miss_rate(&self) -> Option<f64> {
+        self.ratio(Hardware::CACHE_MISSES, Hardware::CACHE_REFERENCES)
+    }
+
+    /// Return the fraction of branches that were mispredicted, if both
+    /// [`Hardware::BRANCH_MISSES`] and [`Hardware::BRANCH_INSTRUCTIONS`]
+    /// were counted.
+    pub fn branch_miss_rate(&self) -> Option<f64> {
+        self.ratio(Hardware::BRANCH_MISSES, Hardware::BRANCH_INSTRUCTIONS)
+    }
+
+    /// Return the ratio of the counts of `numerator` and `denominator`, if
+    /// both were counted and the denominator isn't zero.
+    fn ratio(&self, numerator: Hardware, denominator: Hardware) -> Option<f64> {
+        let denominator = self.value(denominator)?;
+        if denominator == 0 {
+            return None;
+        }
+        Some(self.value(numerator)? as f64 / denominator as f64)
+    }
+
+    /// Return a note on `entry` giving a derived metric, as `perf stat`
+    /// shows beside its count.
+    fn metric(&self, entry: &StatEntry) -> Option<String> {
+        match entry.event {
+            Event::Hardware(Hardware::INSTRUCTIONS) => {
+                Some(format!("{:.2} insn per cycle", self.ipc()?))
+            }
+            Event::Hardware(Hardware::CACHE_MISSES) => Some(format!(
+                "{:.2}% of all cache refs",
+                self.cache_miss_rate()? * 100.0
+            )),
+            Event::Hardware(Hardware::BRANCH_MISSES) => Some(format!(
+                "{:.2}% of all branches",
+                self.branch_miss_rate()? * 100.0
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl StatEntry {
+    /// Return this entry's count, scaled up to estimate what it would have
+    /// been had the counter run the whole time it was enabled. Return `None`
+    /// if the event couldn't be counted, or never ran.
+    pub fn scaled(&self) -> Option<u64> {
+        self.result.as_ref().ok()?.scaled()
+    }
+}
+
+/// Format the report the way `perf stat` does: one line per event, with its
+/// count, name, derived metric, and how much of the time it ran if it was
+/// multiplexed, followed by the elapsed time, and for a command, its user
+/// and system time.
+impl fmt::Display for StatReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            let reading = match &entry.result {
+                Ok(reading) => reading,
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                    writeln!(f, "{:>20}      {}", "<not supported>", entry.name)?;
+                    continue;
+                }
+                Err(_) => {
+                    writeln!(f, "{:>20}      {}", "<not counted>", entry.name)?;
+                    continue;
+                }
+            };
+            match entry.scaled() {
+                Some(value) => write!(f, "{:>20}      {}", value, entry.name)?,
+                None => write!(f, "{:>20}      {}", "<not counted>", entry.name)?,
+            }
+            if let Some(metric) = self.metric(entry) {
+                write!(f, "    # {}", metric)?;
+            }
+            if reading.time_running > 0 && reading.time_running < reading.time_enabled {
+                let ratio = reading.time_running as f64 / reading.time_enabled as f64;
+                write!(f, "  ({:.2}% of the time)", ratio * 100.0)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(
+            f,
+            "\n{:>20.9} seconds time elapsed",
+            self.elapsed.as_secs_f64()
+        )?;
+        if let Some(usage) = &self.usage {
+            writeln!(
+                f,
+                "\n{:>20.9} seconds user\n{:>20.9} seconds sys",
+                usage.user_time.as_secs_f64(),
+                usage.system_time.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! Hybrid processors, with more than one kind of core.
+//!
+//! Some processors mix cores of different designs: Intel's Alder Lake and
+//! later pair performance cores with efficiency cores. On these hybrid
+//! processors, each kind of core has its own PMU, `cpu_core` or `cpu_atom`,
+//! and a generic
+//! [`Hardware`] or [`Cache`] event opened the usual way counts on only one
+//! of them. A thread that migrates between the two kinds of core then seems
+//! to stop running whenever it's on the other kind.
+//!
+//! [`core_types`] describes the kinds of core this machine has, and which
+//! CPUs are of each kind. A [`HybridCounter`] opens one counter for each
+//! kind, and reports their total:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use perf_event::Builder;
+//! use perf_event::events::Hardware;
+//! use perf_event::topology::HybridCounter;
+//!
+//! let mut instructions = HybridCounter::new(|core| {
+//!     Builder::new()
+//!         .kind(core.hardware(Hardware::INSTRUCTIONS))
+//!         .build()
+//! })?;
+//!
+//! instructions.enable()?;
+//! // ... do things ...
+//! instructions.disable()?;
+//! println!("{} instructions in all", instructions.read()?);
+//! for (core, count) in instructions.read_per_core_type()? {
+//!     println!("    on {}: {}", core.name(), count);
+//! }
+//! # Ok(()) }
+//! ```
+//!
+//! On a processor with only one kind of core, `core_types` returns a single
+//! entry, and `HybridCounter` holds a single ordinary counter, so code
+//! written this way works everywhere.
+//!
+//! [`Hardware`]: crate::events::Hardware
+//! [`Cache`]: crate::events::Cache
+
+use crate::events::{Cache, Event, Hardware, Pmu, PMU_DIR};
+use crate::per_cpu::{online_cpus, parse_cpu_list};
+use crate::Counter;
+use std::fmt;
+use std::fs;
+use std::io;
+
+/// The PMUs the kernel registers for the kinds of core in Intel's hybrid
+/// processors.
+const HYBRID_PMUS: &[&str] = &["cpu_core", "cpu_atom"];
+
+/// One kind of core in this machine's processors.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CoreType {
+    name: String,
+    pmu_type: Option<u32>,
+    cpus: Vec<usize>,
+}
+
+/// Return the kinds of core this machine has.
+///
+/// On an Intel hybrid processor, this returns an entry for each of the
+/// `cpu_core` and `cpu_atom` PMUs the kernel has registered, with the CPUs
+/// that sysfs lists for each. Otherwise, this returns a single entry, named
+/// `"cpu"`, covering all the online CPUs, whose events are the ordinary
+/// generic ones.
+pub fn core_types() -> io::Result<Vec<CoreType>> {
+    let mut types = vec![];
+    for &name in HYBRID_PMUS {
+        let pmu = match Pmu::named(name) {
+            Ok(pmu) => pmu,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let path = format!("{}/{}/cpus", PMU_DIR, name);
+        let list = fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let cpus = parse_cpu_list(list.trim()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: can't parse CPU list {:?}", path, list),
+            )
+        })?;
+        types.push(CoreType {
+            name: name.to_string(),
+            pmu_type: Some(pmu.pmu_type()),
+            cpus,
+        });
+    }
+
+    if types.is_empty() {
+        types.push(CoreType {
+            name: "cpu".to_string(),
+            pmu_type: None,
+            cpus: online_cpus()?,
+        });
+    }
+    Ok(types)
+}
+
+/// Return true if this machine has more than one kind of core.
+pub fn is_hybrid() -> io::Result<bool> {
+    Ok(core_types()?.len() > 1)
+}
+
+impl CoreType {
+    /// Return the name of this kind of core's PMU, like `"cpu_core"`, or
+    /// `"cpu"` on a processor with only one kind of core.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the type number of this kind of core's PMU, or `None` on a
+    /// processor with only one kind of core.
+    pub fn pmu_type(&self) -> Option<u32> {
+        self.pmu_type
+    }
+
+    /// Return the CPUs of this kind, in increasing order.
+    pub fn cpus(&self) -> &[usize] {
+        &self.cpus
+    }
+
+    /// Return the event that counts `hw` on this kind of core.
+    ///
+    /// On a hybrid processor, this is an [`OnPmu`] event for this kind of
+    /// core's PMU. Otherwise, it's just `hw`.
+    ///
+    /// [`OnPmu`]: crate::events::OnPmu
+    pub fn hardware(&self, hw: Hardware) -> Event {
+        match self.pmu_type {
+            Some(pmu_type) => hw.on_pmu(pmu_type).into(),
+            None => hw.into(),
+        }
+    }
+
+    /// Return the event that counts `cache` on this kind of core, as for
+    /// [`hardware`].
+    ///
+    /// [`hardware`]: CoreType::hardware
+    pub fn cache(&self, cache: &Cache) -> Event {
+        match self.pmu_type {
+            Some(pmu_type) => cache.on_pmu(pmu_type).into(),
+            None => cache.clone().into(),
+        }
+    }
+}
+
+/// One counter per kind of core, managed as a unit.
+///
+/// See the [module documentation](self) for details.
+pub struct HybridCounter {
+    /// Each kind of core's counter, in the order `core_types` returned
+    /// them.
+    counters: Vec<(CoreType, Counter)>,
+}
+
+impl HybridCounter {
+    /// Build a counter for each kind of core in [`core_types`].
+    ///
+    /// For each kind of core, call `build` with its [`CoreType`] to create a
+    /// counter for it. The `Builder` it uses should choose its event with
+    /// [`CoreType::hardware`] or [`CoreType::cache`]. The counters start out
+    /// disabled.
+    pub fn new<F>(mut build: F) -> io::Result<HybridCounter>
+    where
+        F: FnMut(&CoreType) -> io::Result<Counter>,
+    {
+        let counters = core_types()?
+            .into_iter()
+            .map(|core| {
+                let counter = build(&core)?;
+                Ok((core, counter))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(HybridCounter { counters })
+    }
+
+    /// Enable all the counters.
+    pub fn enable(&mut self) -> io::Result<()> {
+        for (_, counter) in &mut self.counters {
+            counter.enable()?;
+        }
+        Ok(())
+    }
+
+    /// Disable all the counters.
+    pub fn disable(&mut self) -> io::Result<()> {
+        for (_, counter) in &mut self.counters {
+            counter.disable()?;
+        }
+        Ok(())
+    }
+
+    /// Reset all the counters to zero.
+    pub fn reset(&mut self) -> io::Result<()> {
+        for (_, counter) in &mut self.counters {
+            counter.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Return the sum of all the counters' counts.
+    pub fn read(&mut self) -> io::Result<u64> {
+        let mut total = 0;
+        for (_, counter) in &mut self.counters {
+            total += counter.read()?;
+        }
+        Ok(total)
+    }
+
+    /// Return each kind of core's count, in the order [`core_types`]
+    /// returned them.
+    pub fn read_per_core_type(&mut self) -> io::Result<Vec<(&CoreType, u64)>> {
+        self.counters
+            .iter_mut()
+            .map(|(core, counter)| Ok((&*core, counter.read()?)))
+            .collect()
+    }
+
+    /// Iterate over the kinds of core and their counters.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&CoreType, &mut Counter)> {
+        self.counters
+            .iter_mut()
+            .map(|(core, counter)| (&*core, counter))
+    }
+}
+
+impl fmt::Debug for HybridCounter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_map()
+            .entries(
+                self.counters
+                    .iter()
+                    .map(|(core, counter)| (core.name(), counter)),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,281 @@
+//! Decoding tracepoint data.
+//!
+//! When a [`Sampler`] samples a [`Tracepoint`] with [`SampleType::RAW`],
+//! each sample's [`raw`] field holds the record the tracepoint wrote: a C
+//! structure whose layout the kernel describes in the tracepoint's `format`
+//! file in `tracefs`. [`Tracepoint::format`] reads that description into a
+//! [`Format`], which can pick a sample's raw bytes apart into named, typed
+//! fields:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use perf_event::Builder;
+//! use perf_event::events::Tracepoint;
+//! use perf_event::samples::{RecordEvent, SampleType};
+//!
+//! let switch = Tracepoint::new("sched:sched_switch")?;
+//! let format = switch.format()?;
+//! let mut sampler = Builder::new()
+//!     .kind(switch)
+//!     .sample_period(1)
+//!     .sample(SampleType::RAW)
+//!     .build_sampler()?;
+//!
+//! sampler.enable()?;
+//! std::thread::sleep(std::time::Duration::from_millis(10));
+//! sampler.disable()?;
+//!
+//! while let Some(record) = sampler.next() {
+//!     if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+//!         let raw = sample.raw.unwrap();
+//!         println!("{:?} -> {:?}",
+//!                  format.value("prev_comm", &raw),
+//!                  format.value("next_comm", &raw));
+//!     }
+//! }
+//! # Ok(()) }
+//! ```
+//!
+//! [`Sampler`]: crate::samples::Sampler
+//! [`Tracepoint`]: crate::events::Tracepoint
+//! [`Tracepoint::format`]: crate::events::Tracepoint::format
+//! [`SampleType::RAW`]: crate::samples::SampleType::RAW
+//! [`raw`]: crate::samples::Sample::raw
+
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::io;
+
+/// The layout of a tracepoint's records, as described by its `format` file.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Format {
+    name: String,
+    id: u64,
+    fields: Vec<Field>,
+}
+
+/// One field of a tracepoint's records.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Field {
+    /// The field's name, like `"prev_pid"`. The fields every tracepoint
+    /// shares have names starting with `common_`.
+    pub name: String,
+
+    /// The field's C type, like `"pid_t"` or `"char[16]"`. Dynamic arrays
+    /// have types like `"__data_loc char[]"`.
+    pub type_name: String,
+
+    /// The offset of the field from the start of the record, in bytes.
+    pub offset: usize,
+
+    /// The size of the field, in bytes. For dynamic arrays, this is the size
+    /// of the location word that points to the data.
+    pub size: usize,
+
+    /// True if the field's type is signed.
+    pub signed: bool,
+}
+
+/// The value of a tracepoint record's field, decoded according to its
+/// [`Field`] description.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value<'a> {
+    /// A signed integer of up to eight bytes.
+    Signed(i64),
+
+    /// An unsigned integer of up to eight bytes, or a pointer.
+    Unsigned(u64),
+
+    /// A `char` array, up to its first NUL byte. Bytes that aren't valid
+    /// UTF-8 are replaced with `U+FFFD`.
+    Str(Cow<'a, str>),
+
+    /// Anything else, as raw bytes.
+    Bytes(&'a [u8]),
+}
+
+impl Format {
+    /// Parse the contents of a tracepoint's `format` file.
+    ///
+    /// If `text` doesn't look like a format file, this returns an error of
+    /// kind [`io::ErrorKind::InvalidData`].
+    pub fn parse(text: &str) -> io::Result<Format> {
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected tracepoint format: {}", what),
+            )
+        };
+
+        let mut name = None;
+        let mut id = None;
+        let mut fields = vec![];
+        for line in text.lines().map(str::trim) {
+            if let Some(rest) = line.strip_prefix("name:") {
+                name = Some(rest.trim().to_string());
+            } else if let Some(rest) = line.strip_prefix("ID:") {
+                id = Some(rest.trim().parse().map_err(|_| invalid(line))?);
+            } else if line.starts_with("field:") {
+                fields.push(Field::parse(line).ok_or_else(|| invalid(line))?);
+            }
+        }
+
+        Ok(Format {
+            name: name.ok_or_else(|| invalid("no name"))?,
+            id: id.ok_or_else(|| invalid("no ID"))?,
+            fields,
+        })
+    }
+
+    /// Return the tracepoint's name within its system, like
+    /// `"sched_switch"`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the tracepoint's id.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Return the descriptions of the record's fields, in order of their
+    /// offsets.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Return the description of the field called `name`, if there is one.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Return the value of the field called `name` in the record `raw`.
+    ///
+    /// Return `None` if there's no such field, or if `raw` is too short to
+    /// hold it.
+    pub fn value<'a>(&self, name: &str, raw: &'a [u8]) -> Option<Value<'a>> {
+        self.field(name)?.value(raw)
+    }
+
+    /// Return the names and values of all the fields in the record `raw`,
+    /// leaving out the `common_` fields every tracepoint shares, and any
+    /// that `raw` is too short to hold.
+    pub fn values<'f, 'a>(&'f self, raw: &'a [u8]) -> Vec<(&'f str, Value<'a>)> {
+        self.fields
+            .iter()
+            .filter(|field| !field.name.starts_with("common_"))
+            .filter_map(|field| Some((field.name.as_str(), field.value(raw)?)))
+            .collect()
+    }
+}
+
+impl Field {
+    /// Parse a line like
+    /// `field:char prev_comm[16]; offset:8; size:16; signed:0;`, whose
+    /// parts are separated by tabs.
+    fn parse(line: &str) -> Option<Field> {
+        let mut decl = None;
+        let mut offset = None;
+        let mut size = None;
+        let mut signed = None;
+        for part in line.split(';').map(str::trim) {
+            let (key, value) = match part.split_once(':') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            match key {
+                "field" => decl = Some(value.trim()),
+                "offset" => offset = value.parse().ok(),
+                "size" => size = value.parse().ok(),
+                "signed" => signed = Some(value == "1"),
+                _ => {}
+            }
+        }
+
+        // Split `char prev_comm[16]` into the type `char[16]` and the name
+        // `prev_comm`.
+        let decl = decl?;
+        let (type_name, name) = decl.rsplit_once(' ')?;
+        let (name, type_name) = match name.find('[') {
+            Some(bracket) => (
+                &name[..bracket],
+                format!("{}{}", type_name, &name[bracket..]),
+            ),
+            None => (name, type_name.to_string()),
+        };
+
+        Some(Field {
+            name: name.to_string(),
+            type_name,
+            offset: offset?,
+            size: size?,
+            signed: signed.unwrap_or(false),
+        })
+    }
+
+    /// Return this field's value in the record `raw`, or `None` if `raw` is
+    /// too short to hold it.
+    pub fn value<'a>(&self, raw: &'a [u8]) -> Option<Value<'a>> {
+        let bytes = raw.get(self.offset..self.offset.checked_add(self.size)?)?;
+
+        // Dynamic arrays store a 32-bit word holding the data's length in
+        // the upper half and its offset in the lower half. For `__data_loc`,
+        // the offset is from the start of the record; for `__rel_loc`, from
+        // the end of the word itself.
+        let dynamic = if self.type_name.starts_with("__data_loc") {
+            Some(0)
+        } else if self.type_name.starts_with("__rel_loc") {
+            Some(self.offset + self.size)
+        } else {
+            None
+        };
+        if let Some(base) = dynamic {
+            let loc = u32::from_ne_bytes(bytes.get(..4)?.try_into().ok()?);
+            let start = base + (loc & 0xffff) as usize;
+            let data = raw.get(start..start + (loc >> 16) as usize)?;
+            return Some(if self.type_name.ends_with("char[]") {
+                Value::Str(c_string(data))
+            } else {
+                Value::Bytes(data)
+            });
+        }
+
+        if self.type_name.ends_with(']') {
+            return Some(if self.type_name.starts_with("char[") {
+                Value::Str(c_string(bytes))
+            } else {
+                Value::Bytes(bytes)
+            });
+        }
+
+        let mut buf = [0_u8; 8];
+        let value = match self.size {
+            1 | 2 | 4 | 8 => {
+                if cfg!(target_endian = "little") {
+                    buf[..self.size].copy_from_slice(bytes);
+                } else {
+                    buf[8 - self.size..].copy_from_slice(bytes);
+                }
+                u64::from_ne_bytes(buf)
+            }
+            _ => return Some(Value::Bytes(bytes)),
+        };
+        Some(if self.signed {
+            // Sign-extend from the field's width.
+            let shift = 64 - 8 * self.size as u32;
+            Value::Signed(((value << shift) as i64) >> shift)
+        } else {
+            Value::Unsigned(value)
+        })
+    }
+}
+
+/// Return the text in `bytes` up to the first NUL.
+fn c_string(bytes: &[u8]) -> Cow<'_, str> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len])
+}
@@ -0,0 +1,249 @@
+//! Reading a counter's value without a system call.
+//!
+//! Reading a [`Counter`] normally takes a `read` system call, which costs a
+//! microsecond or so: fine for measuring a whole program phase, but far too
+//! slow to wrap around something as small as a single allocation. On x86,
+//! the kernel lets a thread read its own hardware counters directly, with
+//! the `rdpmc` instruction, if it has mapped the counter's
+//! `perf_event_mmap_page`. That page says which hardware counter the event
+//! is currently using, and what to add to it to get the event's count.
+//!
+//! A [`UserReadCounter`], built by [`Builder::build_user_read`], maps that
+//! page and reads the counter that way whenever it can:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use perf_event::Builder;
+//! use perf_event::events::Hardware;
+//!
+//! let mut insns = Builder::new()
+//!     .kind(Hardware::INSTRUCTIONS)
+//!     .build_user_read()?;
+//! insns.enable()?;
+//!
+//! let before = insns.read()?;
+//! let v = vec![0_u8; 1000];
+//! let after = insns.read()?;
+//! println!("{} instructions to allocate {} bytes", after - before, v.len());
+//! # Ok(()) }
+//! ```
+//!
+//! The page is only meaningful to the thread the counter observes, so a
+//! `UserReadCounter` must observe the thread that builds it, on any CPU,
+//! without [`inherit`]; and it can't be sent to another thread.
+//!
+//! [`UserReadCounter::read`] falls back to the `read` system call when the
+//! kernel doesn't offer a direct read: for software events, on processors
+//! other than x86, when the counter is disabled or not scheduled, or when
+//! `/sys/bus/event_source/devices/cpu/rdpmc` is zero.
+//! [`read_user`] never makes a system call, and returns `None` instead.
+//!
+//! Like [`Counter::read`], these return the raw count, without scaling for
+//! timesharing.
+//!
+//! [`Builder::build_user_read`]: crate::Builder::build_user_read
+//! [`Counter`]: crate::Counter
+//! [`Counter::read`]: crate::Counter::read
+//! [`inherit`]: crate::Builder::inherit
+//! [`read_user`]: UserReadCounter::read_user
+
+use crate::Counter;
+use perf_event_open_sys::bindings::perf_event_mmap_page;
+use std::fmt;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::ptr::{addr_of, NonNull};
+use std::sync::atomic::{compiler_fence, Ordering};
+
+/// The `cap_user_rdpmc` bit of `perf_event_mmap_page::capabilities`.
+const CAP_USER_RDPMC: u64 = 1 << 2;
+
+/// A [`Counter`] that the calling thread can read without a system call.
+///
+/// This is created by [`Builder::build_user_read`]. See the [module
+/// documentation](self) for details.
+///
+/// A `UserReadCounter` dereferences to its `Counter`, so you can enable,
+/// disable, and reset it as usual.
+///
+/// [`Builder::build_user_read`]: crate::Builder::build_user_read
+pub struct UserReadCounter {
+    counter: Counter,
+
+    /// The counter's `perf_event_mmap_page`, mapped read-only. This pointer
+    /// also keeps `UserReadCounter` from being `Send` or `Sync`.
+    page: NonNull<perf_event_mmap_page>,
+
+    /// The length of the mapping: one page.
+    page_len: usize,
+}
+
+impl UserReadCounter {
+    /// Map `counter`'s `perf_event_mmap_page`.
+    pub(crate) fn new(counter: Counter) -> io::Result<UserReadCounter> {
+        let page_len = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                page_len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                counter.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(UserReadCounter {
+            counter,
+            page: NonNull::new(base as *mut perf_event_mmap_page).unwrap(),
+            page_len,
+        })
+    }
+
+    /// Return this counter's current value, reading it directly if the
+    /// kernel allows, or with the `read` system call if not.
+    pub fn read(&mut self) -> io::Result<u64> {
+        match self.read_user() {
+            Some(count) => Ok(count),
+            None => self.counter.read(),
+        }
+    }
+
+    /// Return this counter's current value if it can be read without a
+    /// system call, or `None` if it can't.
+    ///
+    /// This follows the protocol described in `<linux/perf_event.h>`: read
+    /// the page's `offset`, add the hardware counter's value, and retry if
+    /// the kernel updated the page meanwhile, say, because this thread was
+    /// migrated to another CPU.
+    pub fn read_user(&self) -> Option<u64> {
+        let page = self.page.as_ptr();
+        loop {
+            // The kernel increments `lock` before and after each update.
+            let seq = unsafe { addr_of!((*page).lock).read_volatile() };
+            compiler_fence(Ordering::SeqCst);
+
+            let (index, offset, caps, width) = unsafe {
+                (
+                    addr_of!((*page).index).read_volatile(),
+                    addr_of!((*page).offset).read_volatile(),
+                    addr_of!((*page).__bindgen_anon_1.capabilities).read_volatile(),
+                    addr_of!((*page).pmc_width).read_volatile(),
+                )
+            };
+            // An index of zero means the event isn't on a hardware counter
+            // at the moment, and the kernel only updates `offset` for
+            // software events now and then.
+            let count = if index == 0 || caps & CAP_USER_RDPMC == 0 || !(1..=64).contains(&width) {
+                None
+            } else {
+                // The counter is only `width` bits wide; sign-extend it,
+                // since `offset` may be negative to account for the value
+                // the counter started at.
+                let shift = 64 - u32::from(width);
+                rdpmc(index - 1).map(|pmc| {
+                    let pmc = ((pmc << shift) as i64 >> shift) as u64;
+                    (offset as u64).wrapping_add(pmc)
+                })
+            };
+
+            compiler_fence(Ordering::SeqCst);
+            if unsafe { addr_of!((*page).lock).read_volatile() } == seq {
+                return count;
+            }
+        }
+    }
+
+    /// Return a reference to this counter's `Counter`.
+    pub fn counter(&self) -> &Counter {
+        &self.counter
+    }
+
+    /// Return a mutable reference to this counter's `Counter`.
+    pub fn counter_mut(&mut self) -> &mut Counter {
+        &mut self.counter
+    }
+
+    /// Unmap this counter's `perf_event_mmap_page`, and return its
+    /// `Counter`.
+    pub fn into_counter(self) -> Counter {
+        let this = std::mem::ManuallyDrop::new(self);
+        unsafe {
+            libc::munmap(this.page.as_ptr() as *mut libc::c_void, this.page_len);
+            // None of our other fields own anything.
+            std::ptr::read(&this.counter)
+        }
+    }
+}
+
+/// Read hardware performance counter `counter` with the `rdpmc`
+/// instruction.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn rdpmc(counter: u32) -> Option<u64> {
+    let (low, high): (u32, u32);
+    // This is only called when the kernel has said `rdpmc` is allowed. It
+    // doesn't touch memory, but leaving out `nomem` keeps the compiler from
+    // moving it out from between the reads of `lock`.
+    unsafe {
+        std::arch::asm!(
+            "rdpmc",
+            in("ecx") counter,
+            out("eax") low,
+            out("edx") high,
+            options(nostack, preserves_flags),
+        );
+    }
+    Some(u64::from(high) << 32 | u64::from(low))
+}
+
+/// Other processors have their own ways to read counters from user space,
+/// which we don't support yet.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn rdpmc(_counter: u32) -> Option<u64> {
+    None
+}
+
+impl Deref for UserReadCounter {
+    type Target = Counter;
+
+    fn deref(&self) -> &Counter {
+        &self.counter
+    }
+}
+
+impl DerefMut for UserReadCounter {
+    fn deref_mut(&mut self) -> &mut Counter {
+        &mut self.counter
+    }
+}
+
+impl AsRawFd for UserReadCounter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.counter.as_raw_fd()
+    }
+}
+
+impl AsFd for UserReadCounter {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.counter.as_fd()
+    }
+}
+
+impl Drop for UserReadCounter {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.page.as_ptr() as *mut libc::c_void, self.page_len);
+        }
+    }
+}
+
+impl fmt::Debug for UserReadCounter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("UserReadCounter")
+            .field("counter", &self.counter)
+            .finish()
+    }
+}
@@ -0,0 +1,247 @@
+//! Managing several hardware breakpoints within the debug-register budget.
+//!
+//! Hardware breakpoints are implemented with the processor's debug
+//! registers, and there are very few of those: four on x86_64, shared between
+//! code and data breakpoints; on aarch64, separate sets for each, with as few
+//! as two apiece. When they run out, `perf_event_open` fails with `ENOSPC`.
+//!
+//! A [`Watchpoints`] set builds breakpoint counters for you, checking each
+//! one with [`Breakpoint::validate`] and counting the debug registers in use,
+//! so that it can refuse a breakpoint that won't fit before asking the
+//! kernel, and report how much room is left:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::Breakpoint;
+//!     use perf_event::watchpoints::Watchpoints;
+//!
+//!     let data = [0_u64; 4];
+//!     let mut watches = Watchpoints::new();
+//!     let first = watches.add(Builder::new(), Breakpoint::write(&data[0] as *const _ as u64, 8))?;
+//!     let last = watches.add(Builder::new(), Breakpoint::write(&data[3] as *const _ as u64, 8))?;
+//!
+//!     watches.enable_all()?;
+//!     // ... code that might write to `data` ...
+//!     watches.disable_all()?;
+//!     println!("first element written {} times", watches.get_mut(first).unwrap().read()?);
+//!     # Ok(()) }
+//!
+//! The budget is only an estimate of what this process can use: debug
+//! registers are per thread, and a debugger attached to the thread, or other
+//! perf users watching it, take registers from the same pool. So [`add`] can
+//! still fail with `ENOSPC` from the kernel even when the budget says there's
+//! room.
+//!
+//! [`Breakpoint::validate`]: crate::events::Breakpoint::validate
+//! [`add`]: Watchpoints::add
+
+use crate::batch::{self, BatchError};
+use crate::events::Breakpoint;
+use crate::{Builder, Counter};
+use std::io;
+
+/// The number of debug registers available for breakpoints.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DebugRegisters {
+    /// A single pool of registers, used by both code and data breakpoints.
+    Shared(usize),
+
+    /// Separate pools for code and data breakpoints.
+    Separate {
+        /// The number of code breakpoints available.
+        code: usize,
+
+        /// The number of data breakpoints available.
+        data: usize,
+    },
+}
+
+impl DebugRegisters {
+    /// Return the debug registers available on this architecture.
+    ///
+    /// On x86 and x86_64, there are four registers, shared. On arm and
+    /// aarch64, the count varies by processor and can't be read from user
+    /// space, so this returns the architectural minimum of two of each. On
+    /// s390x, which has no hardware breakpoints, there are none. Elsewhere,
+    /// this assumes a single register.
+    pub fn for_this_arch() -> DebugRegisters {
+        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            DebugRegisters::Shared(4)
+        } else if cfg!(any(target_arch = "arm", target_arch = "aarch64")) {
+            DebugRegisters::Separate { code: 2, data: 2 }
+        } else if cfg!(target_arch = "s390x") {
+            DebugRegisters::Shared(0)
+        } else {
+            DebugRegisters::Shared(1)
+        }
+    }
+}
+
+/// Identifies a breakpoint in a [`Watchpoints`] set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct WatchpointId(usize);
+
+/// A set of breakpoint counters, limited to the available debug registers.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug)]
+pub struct Watchpoints {
+    budget: DebugRegisters,
+
+    /// The breakpoints, indexed by `WatchpointId`. Removed entries become
+    /// `None`, so ids stay stable.
+    entries: Vec<Option<(Breakpoint, Counter)>>,
+}
+
+impl Watchpoints {
+    /// Return an empty set, limited to [`DebugRegisters::for_this_arch`].
+    pub fn new() -> Watchpoints {
+        Watchpoints::with_budget(DebugRegisters::for_this_arch())
+    }
+
+    /// Return an empty set, limited to `budget`. Use this if you know better
+    /// than [`DebugRegisters::for_this_arch`], or want to leave some
+    /// registers free for other uses.
+    pub fn with_budget(budget: DebugRegisters) -> Watchpoints {
+        Watchpoints {
+            budget,
+            entries: vec![],
+        }
+    }
+
+    /// Return this set's budget.
+    pub fn budget(&self) -> DebugRegisters {
+        self.budget
+    }
+
+    /// Build a counter for `breakpoint` from `builder`, and add it to this
+    /// set. The counter starts out disabled.
+    ///
+    /// If `breakpoint` fails [`Breakpoint::validate`], return that error. If
+    /// the set has no room for it, return an error whose `raw_os_error` is
+    /// `ENOSPC`, as the kernel would.
+    ///
+    /// [`Breakpoint::validate`]: crate::events::Breakpoint::validate
+    pub fn add(&mut self, builder: Builder, breakpoint: Breakpoint) -> io::Result<WatchpointId> {
+        breakpoint.validate()?;
+        if !self.has_room_for(&breakpoint) {
+            return Err(io::Error::from_raw_os_error(libc::ENOSPC));
+        }
+        let counter = builder.kind(breakpoint).build()?;
+        let id = match self.entries.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => {
+                self.entries.push(None);
+                self.entries.len() - 1
+            }
+        };
+        self.entries[id] = Some((breakpoint, counter));
+        Ok(WatchpointId(id))
+    }
+
+    /// Remove the breakpoint `id` from this set, freeing its debug register,
+    /// and return its counter. Return `None` if there is no such breakpoint.
+    pub fn remove(&mut self, id: WatchpointId) -> Option<Counter> {
+        self.entries
+            .get_mut(id.0)
+            .and_then(Option::take)
+            .map(|(_, counter)| counter)
+    }
+
+    /// Return the breakpoint `id` watches.
+    pub fn breakpoint(&self, id: WatchpointId) -> Option<Breakpoint> {
+        match self.entries.get(id.0) {
+            Some(Some((breakpoint, _))) => Some(*breakpoint),
+            _ => None,
+        }
+    }
+
+    /// Return the counter for the breakpoint `id`.
+    pub fn get_mut(&mut self, id: WatchpointId) -> Option<&mut Counter> {
+        match self.entries.get_mut(id.0) {
+            Some(Some((_, counter))) => Some(counter),
+            _ => None,
+        }
+    }
+
+    /// Return true if this set could accept `breakpoint` without exceeding
+    /// its budget.
+    pub fn has_room_for(&self, breakpoint: &Breakpoint) -> bool {
+        let (code, data) = self.in_use();
+        match self.budget {
+            DebugRegisters::Shared(total) => code + data < total,
+            DebugRegisters::Separate {
+                code: code_total,
+                data: data_total,
+            } => {
+                if breakpoint.is_code() {
+                    code < code_total
+                } else {
+                    data < data_total
+                }
+            }
+        }
+    }
+
+    /// Return the number of breakpoints in this set.
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    /// Return true if this set has no breakpoints.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the breakpoints in this set, with their ids and
+    /// counters.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (WatchpointId, Breakpoint, &mut Counter)> {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(id, entry)| {
+                let (breakpoint, counter) = entry.as_mut()?;
+                Some((WatchpointId(id), *breakpoint, counter))
+            })
+    }
+
+    /// Enable all the breakpoints' counters, as with [`batch::enable_all`].
+    /// Indices in the error are positions in [`iter_mut`]'s order.
+    ///
+    /// [`iter_mut`]: Watchpoints::iter_mut
+    pub fn enable_all(&mut self) -> Result<(), BatchError> {
+        batch::enable_all(&mut self.counters())
+    }
+
+    /// Disable all the breakpoints' counters, as with
+    /// [`batch::disable_all`].
+    pub fn disable_all(&mut self) -> Result<(), BatchError> {
+        batch::disable_all(&mut self.counters())
+    }
+
+    /// Reset all the breakpoints' counters, as with [`batch::reset_all`].
+    pub fn reset_all(&mut self) -> Result<(), BatchError> {
+        batch::reset_all(&mut self.counters())
+    }
+
+    fn counters(&mut self) -> Vec<&mut Counter> {
+        self.iter_mut().map(|(_, _, counter)| counter).collect()
+    }
+
+    /// Return the number of code and data breakpoints in use.
+    fn in_use(&self) -> (usize, usize) {
+        let code = self
+            .entries
+            .iter()
+            .flatten()
+            .filter(|(breakpoint, _)| breakpoint.is_code())
+            .count();
+        (code, self.len() - code)
+    }
+}
+
+impl Default for Watchpoints {
+    fn default() -> Watchpoints {
+        Watchpoints::new()
+    }
+}
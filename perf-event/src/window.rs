@@ -0,0 +1,342 @@
+//! Counting only while the program is inside a particular region of code.
+//!
+//! A [`TriggeredWindow`] watches two code addresses with hardware breakpoints:
+//! when execution reaches the `start` address, it enables a [`Counter`] or
+//! [`Group`]; when execution reaches the `stop` address, it disables it again.
+//! This lets you measure one phase of a large program, say, everything that
+//! happens between entering `parse` and entering `codegen`, without modifying
+//! the program to call [`enable`] and [`disable`] itself.
+//!
+//! For example, this counts context switches only during calls to
+//! `interesting`, ignoring the calls to `boring`:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::Builder;
+//!     use perf_event::events::{Breakpoint, Software};
+//!     use perf_event::window::TriggeredWindow;
+//!
+//!     #[inline(never)]
+//!     fn interesting() { std::thread::yield_now(); done(); }
+//!     #[inline(never)]
+//!     fn done() { std::hint::black_box(()); }
+//!     #[inline(never)]
+//!     fn boring() { std::thread::yield_now(); }
+//!
+//!     let switches = Builder::new().kind(Software::CONTEXT_SWITCHES).build()?;
+//!     let mut window = TriggeredWindow::new(
+//!         switches,
+//!         Breakpoint::execute(interesting as fn() as usize as u64),
+//!         Breakpoint::execute(done as fn() as usize as u64),
+//!     )?;
+//!
+//!     window.arm()?;
+//!     boring();
+//!     interesting();
+//!     boring();
+//!     window.disarm()?;
+//!
+//!     println!("{} context switches in {} calls to `interesting`",
+//!              window.target_mut().read()?, window.entries()?);
+//!     # Ok(()) }
+//!
+//! ## How it works
+//!
+//! The `start` and `stop` breakpoints are sampling events with a period of
+//! one, whose file descriptors are set up to deliver `SIGIO` on every hit.
+//! The first `TriggeredWindow` created installs a `SIGIO` handler that looks
+//! up which window the signal came from and issues the `ENABLE` or `DISABLE`
+//! ioctl on its target. If the process already had a `SIGIO` handler, signals
+//! that don't belong to a window are passed along to it.
+//!
+//! The breakpoints observe only the thread that created the window, and the
+//! signals are directed at that thread, so the target is enabled and disabled
+//! before the thread executes any further user code. Each window consumes two
+//! of the processor's debug registers (x86_64 has four).
+//!
+//! The ioctls issued from the signal handler go directly to the kernel; they
+//! are not routed through the `hooks` module, if the `"hooks"` feature is
+//! enabled.
+//!
+//! [`Counter`]: crate::Counter
+//! [`Group`]: crate::Group
+//! [`enable`]: crate::Counter::enable
+//! [`disable`]: crate::Counter::disable
+
+use crate::events::Breakpoint;
+use crate::{check_errno_syscall, Builder, Counter};
+use perf_event_open_sys as raw;
+use std::io;
+use std::os::raw::{c_int, c_long, c_uint, c_void};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Once;
+
+/// The maximum number of `TriggeredWindow`s that can exist at once. Each
+/// window needs two hardware breakpoints, so the processor will run out well
+/// before this does.
+const MAX_WINDOWS: usize = 8;
+
+// `fcntl` definitions the `libc` crate doesn't provide for glibc targets.
+
+/// Choose the signal sent for `O_ASYNC` notifications, and request that the
+/// handler receive a `siginfo_t` with `si_fd` filled in.
+const F_SETSIG: c_int = 10;
+
+/// Direct `O_ASYNC` notifications to a specific thread or process.
+const F_SETOWN_EX: c_int = 15;
+
+/// `f_owner_ex::type_` value meaning `pid` is a thread id.
+const F_OWNER_TID: c_int = 0;
+
+#[repr(C)]
+struct f_owner_ex {
+    type_: c_int,
+    pid: libc::pid_t,
+}
+
+/// A code region whose entry and exit enable and disable a counter.
+///
+/// See the [module documentation](self) for details.
+///
+/// The target `T` is typically a [`Counter`] or a [`Group`]. Whichever it is,
+/// it should be built disabled, as `Builder` does by default. The window
+/// takes ownership of the target; use [`target_mut`] to read it, and
+/// [`into_inner`] to get it back.
+///
+/// [`Counter`]: crate::Counter
+/// [`Group`]: crate::Group
+/// [`target_mut`]: TriggeredWindow::target_mut
+/// [`into_inner`]: TriggeredWindow::into_inner
+pub struct TriggeredWindow<T: AsRawFd> {
+    // This must be dropped before `target`, so that the signal handler has
+    // stopped using `target`'s file descriptor before it is closed.
+    triggers: Triggers,
+    target: T,
+}
+
+/// The breakpoints that drive a window, and the slot they occupy in `SLOTS`.
+struct Triggers {
+    start: Counter,
+    stop: Counter,
+    slot: usize,
+}
+
+/// What the signal handler knows about a live window. All fields are `-1`
+/// when the slot is free.
+struct Slot {
+    start: AtomicI32,
+    stop: AtomicI32,
+    target: AtomicI32,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const FREE_SLOT: Slot = Slot {
+    start: AtomicI32::new(-1),
+    stop: AtomicI32::new(-1),
+    target: AtomicI32::new(-1),
+};
+
+static SLOTS: [Slot; MAX_WINDOWS] = [FREE_SLOT; MAX_WINDOWS];
+
+static INSTALL_HANDLER: Once = Once::new();
+
+/// The `SIGIO` disposition in effect before we installed ours. This is
+/// written once, under `INSTALL_HANDLER`, before our handler can run.
+static mut PREVIOUS_ACTION: Option<libc::sigaction> = None;
+
+impl<T: AsRawFd> TriggeredWindow<T> {
+    /// Return a new window that enables `target` when the calling thread
+    /// reaches `start`, and disables it when the calling thread reaches
+    /// `stop`.
+    ///
+    /// The window is created unarmed: the breakpoints don't fire until you
+    /// call [`arm`]. Both `start` and `stop` are usually
+    /// [`Breakpoint::execute`] values, but data breakpoints work too.
+    ///
+    /// [`arm`]: TriggeredWindow::arm
+    pub fn new(target: T, start: Breakpoint, stop: Breakpoint) -> io::Result<TriggeredWindow<T>> {
+        INSTALL_HANDLER.call_once(install_handler);
+
+        let start = trigger_counter(start)?;
+        let stop = trigger_counter(stop)?;
+
+        let slot = SLOTS
+            .iter()
+            .position(|slot| {
+                slot.target
+                    .compare_exchange(-1, target.as_raw_fd(), Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or_else(|| io::Error::other("too many TriggeredWindows"))?;
+        SLOTS[slot]
+            .start
+            .store(start.as_raw_fd(), Ordering::Release);
+        SLOTS[slot].stop.store(stop.as_raw_fd(), Ordering::Release);
+
+        Ok(TriggeredWindow {
+            triggers: Triggers { start, stop, slot },
+            target,
+        })
+    }
+
+    /// Start watching for the `start` and `stop` addresses.
+    pub fn arm(&mut self) -> io::Result<()> {
+        self.triggers.start.enable()?;
+        self.triggers.stop.enable()
+    }
+
+    /// Stop watching for the `start` and `stop` addresses.
+    ///
+    /// This leaves the target in whatever state it is in. If the thread is
+    /// between `start` and `stop`, the target stays enabled.
+    pub fn disarm(&mut self) -> io::Result<()> {
+        self.triggers.start.disable()?;
+        self.triggers.stop.disable()
+    }
+
+    /// Return the number of times execution has reached `start` while the
+    /// window was armed.
+    pub fn entries(&mut self) -> io::Result<u64> {
+        self.triggers.start.read()
+    }
+
+    /// Return a reference to the target.
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    /// Return a mutable reference to the target, so you can read or reset
+    /// it.
+    ///
+    /// Don't replace the target through this reference: the window will
+    /// continue to operate on the old target's file descriptor.
+    pub fn target_mut(&mut self) -> &mut T {
+        &mut self.target
+    }
+
+    /// Tear down the window, and return the target.
+    pub fn into_inner(self) -> T {
+        let TriggeredWindow { triggers, target } = self;
+        drop(triggers);
+        target
+    }
+}
+
+impl<T: AsRawFd + std::fmt::Debug> std::fmt::Debug for TriggeredWindow<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("TriggeredWindow")
+            .field("target", &self.target)
+            .field("start", &self.triggers.start)
+            .field("stop", &self.triggers.stop)
+            .finish()
+    }
+}
+
+impl Drop for Triggers {
+    fn drop(&mut self) {
+        // Make sure no further signals are generated, and then make the slot
+        // unrecognizable to the handler before anyone's fd gets closed.
+        let _ = self.start.disable();
+        let _ = self.stop.disable();
+        let slot = &SLOTS[self.slot];
+        slot.start.store(-1, Ordering::Release);
+        slot.stop.store(-1, Ordering::Release);
+        slot.target.store(-1, Ordering::Release);
+    }
+}
+
+/// Build a disabled counter for `bp` that raises `SIGIO` every time it's hit.
+fn trigger_counter(bp: Breakpoint) -> io::Result<Counter> {
+    let mut builder = Builder::new().kind(bp);
+    builder.attrs.set_sample_period(1);
+    builder.attrs.set_wakeup_events(1);
+    let counter = builder.build()?;
+
+    let fd = counter.as_raw_fd();
+    unsafe {
+        // Send the signal to this thread specifically. If we let it go to the
+        // process as a whole, it may be handled by some other thread well after
+        // the breakpoint was hit, or merged with a later one.
+        let owner = f_owner_ex {
+            type_: F_OWNER_TID,
+            pid: libc::syscall(libc::SYS_gettid) as libc::pid_t,
+        };
+        check_errno_syscall(|| libc::fcntl(fd, F_SETOWN_EX, &owner as *const f_owner_ex))?;
+        check_errno_syscall(|| libc::fcntl(fd, F_SETSIG, libc::SIGIO))?;
+        let flags = check_errno_syscall(|| libc::fcntl(fd, libc::F_GETFL))?;
+        check_errno_syscall(|| libc::fcntl(fd, libc::F_SETFL, flags | libc::O_ASYNC))?;
+    }
+
+    Ok(counter)
+}
+
+fn install_handler() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_sigio as extern "C" fn(_, _, _) as usize;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(libc::SIGIO, &action, &mut previous) == 0 {
+            PREVIOUS_ACTION = Some(previous);
+        }
+    }
+}
+
+/// The prefix of a `siginfo_t` for a signal generated by `O_ASYNC` I/O, when
+/// `F_SETSIG` has been used.
+#[repr(C)]
+struct SigPollInfo {
+    signo: c_int,
+    errno: c_int,
+    code: c_int,
+    band: c_long,
+    fd: c_int,
+}
+
+extern "C" fn handle_sigio(signo: c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    // Only async-signal-safe operations here: atomics and ioctls.
+    let fd = unsafe { (*(info as *const SigPollInfo)).fd };
+
+    for slot in &SLOTS {
+        let request: unsafe fn(c_int, c_uint) -> c_int = if slot.start.load(Ordering::Acquire) == fd
+        {
+            raw::ioctls::ENABLE
+        } else if slot.stop.load(Ordering::Acquire) == fd {
+            raw::ioctls::DISABLE
+        } else {
+            continue;
+        };
+
+        let target: RawFd = slot.target.load(Ordering::Acquire);
+        unsafe {
+            let errno = libc::__errno_location();
+            let saved = *errno;
+            request(target, raw::bindings::PERF_IOC_FLAG_GROUP);
+            *errno = saved;
+        }
+        return;
+    }
+
+    // Not one of ours. Pass it along to whoever was handling SIGIO before.
+    let previous = match unsafe { PREVIOUS_ACTION } {
+        Some(previous) => previous,
+        None => return,
+    };
+    if previous.sa_sigaction == libc::SIG_DFL || previous.sa_sigaction == libc::SIG_IGN {
+        // SIGIO's default action is to terminate the process, which is
+        // surely not what anyone wants.
+        return;
+    }
+    unsafe {
+        if previous.sa_flags & libc::SA_SIGINFO != 0 {
+            let handler: extern "C" fn(c_int, *mut libc::siginfo_t, *mut c_void) =
+                std::mem::transmute(previous.sa_sigaction);
+            handler(signo, info, context);
+        } else {
+            let handler: extern "C" fn(c_int) = std::mem::transmute(previous.sa_sigaction);
+            handler(signo);
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! Per-worker counters for thread pools, with counts attributed to named spans.
+//!
+//! In a thread pool or async runtime, the code you care about runs on
+//! whichever worker thread picks it up, interleaved with unrelated tasks. A
+//! [`WorkerProfiler`] gives each worker thread its own [`Group`] of counters,
+//! enables it only while the thread is inside a named *span* of work, and adds
+//! each span's counts to a shared table. You get totals per kind of task,
+//! regardless of which threads ran them.
+//!
+//! To use it:
+//!
+//! -   Call [`start_worker`] on each worker thread as it starts, and
+//!     [`stop_worker`] as it exits. With the `"rayon"` or `"tokio"` features
+//!     enabled, the `rayon_pool` and `tokio_runtime` methods install these
+//!     as the pool's thread start and exit handlers for you.
+//!
+//! -   Wrap units of work in [`span`], or wrap futures with [`instrument`].
+//!
+//! -   Call [`spans`] to retrieve the totals.
+//!
+//! For example:
+//!
+//!     # fn main() -> std::io::Result<()> {
+//!     use perf_event::events::Software;
+//!     use perf_event::workers::WorkerProfiler;
+//!
+//!     let profiler = WorkerProfiler::new(vec![Software::TASK_CLOCK.into()]);
+//!
+//!     let workers: Vec<_> = (0..4).map(|_| {
+//!         let profiler = profiler.clone();
+//!         std::thread::spawn(move || {
+//!             profiler.start_worker().unwrap();
+//!             for _ in 0..10 {
+//!                 profiler.span("sort", || {
+//!                     let mut v: Vec<u32> = (0..10000).rev().collect();
+//!                     v.sort();
+//!                 });
+//!             }
+//!             profiler.stop_worker();
+//!         })
+//!     }).collect();
+//!     for worker in workers {
+//!         worker.join().unwrap();
+//!     }
+//!
+//!     for (name, totals) in profiler.spans() {
+//!         println!("{}: {} calls, {} ns of task clock",
+//!                  name, totals.calls, totals.counts[0]);
+//!     }
+//!     # Ok(()) }
+//!
+//! Spans may nest. A nested span's counts are included in those of the spans
+//! enclosing it.
+//!
+//! A span on a thread that hasn't called `start_worker`, or whose counters
+//! couldn't be created or read, still runs its closure, but its counts are not
+//! recorded.
+//!
+//! [`Group`]: crate::Group
+//! [`start_worker`]: WorkerProfiler::start_worker
+//! [`stop_worker`]: WorkerProfiler::stop_worker
+//! [`span`]: WorkerProfiler::span
+//! [`instrument`]: WorkerProfiler::instrument
+//! [`spans`]: WorkerProfiler::spans
+
+use crate::events::Event;
+use crate::{Builder, Counter, Counts, Group};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+
+/// Counters for each worker thread of a pool, attributed to named spans.
+///
+/// See the [module documentation](self) for details.
+///
+/// Cloning a `WorkerProfiler` produces another handle to the same profiler.
+#[derive(Clone)]
+pub struct WorkerProfiler {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    events: Vec<Event>,
+    spans: Mutex<HashMap<&'static str, SpanTotals>>,
+
+    /// If true, threads get counters when they first enter a span, without
+    /// calling `start_worker`.
+    lazy: bool,
+}
+
+/// The accumulated counts for one named span, across all worker threads.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SpanTotals {
+    /// The number of times the span was entered.
+    pub calls: u64,
+
+    /// The total count for each event, in the order the events were passed
+    /// to [`WorkerProfiler::new`].
+    pub counts: Vec<u64>,
+
+    /// The total time the worker counters were enabled during this span, in
+    /// nanoseconds.
+    pub time_enabled: u64,
+
+    /// The total time the worker counters were actually running during this
+    /// span, in nanoseconds. If this is less than `time_enabled`, the
+    /// counters were multiplexed, and `counts` should be scaled accordingly.
+    pub time_running: u64,
+}
+
+/// A worker thread's counters for one profiler.
+struct Worker {
+    /// The `Shared` this worker reports to, for identification only.
+    owner: Weak<Shared>,
+    group: Group,
+    counters: Vec<Counter>,
+    /// How many spans this thread is currently inside.
+    depth: usize,
+}
+
+/// A span in progress on this thread, exited when dropped.
+///
+/// Exiting in `drop` keeps the worker's depth and enabled state right even
+/// if the span's closure panics.
+struct SpanGuard<'a> {
+    profiler: &'a WorkerProfiler,
+    name: &'static str,
+    entry: Entry,
+}
+
+/// A reading taken on entry to a span.
+struct Entry {
+    counts: Vec<u64>,
+    time_enabled: u64,
+    time_running: u64,
+}
+
+std::thread_local! {
+    /// This thread's counters, one `Worker` for each profiler it has
+    /// started.
+    static WORKERS: RefCell<Vec<Worker>> = const { RefCell::new(Vec::new()) };
+}
+
+impl WorkerProfiler {
+    /// Return a new profiler that will count `events` on each worker thread.
+    ///
+    /// The events are placed in a single `Group` on each thread, so the
+    /// processor must be able to count them all at once.
+    pub fn new(events: Vec<Event>) -> WorkerProfiler {
+        WorkerProfiler::with_laziness(events, false)
+    }
+
+    /// Return a new profiler that creates each thread's counters when it
+    /// first enters a span, if `lazy` is true.
+    pub(crate) fn with_laziness(events: Vec<Event>, lazy: bool) -> WorkerProfiler {
+        WorkerProfiler {
+            shared: Arc::new(Shared {
+                events,
+                spans: Mutex::new(HashMap::new()),
+                lazy,
+            }),
+        }
+    }
+
+    /// Return the events this profiler counts.
+    pub fn events(&self) -> &[Event] {
+        &self.shared.events
+    }
+
+    /// Create counters for the calling thread.
+    ///
+    /// This replaces any counters the thread already had for this
+    /// `WorkerProfiler`. A thread may have counters for several profilers at
+    /// once.
+    pub fn start_worker(&self) -> io::Result<()> {
+        let mut group = Group::new()?;
+        let counters = self
+            .shared
+            .events
+            .iter()
+            .map(|event| Builder::new().group(&mut group).kind(event.clone()).build())
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let worker = Worker {
+            owner: Arc::downgrade(&self.shared),
+            group,
+            counters,
+            depth: 0,
+        };
+        WORKERS.with(|w| {
+            let mut workers = w.borrow_mut();
+            // Drop our old counters, and any left behind by profilers that
+            // no longer exist.
+            workers.retain(|w| w.owner.strong_count() > 0 && !self.owns(w));
+            workers.push(worker);
+        });
+        Ok(())
+    }
+
+    /// Drop the calling thread's counters for this profiler, if it has any.
+    pub fn stop_worker(&self) {
+        WORKERS.with(|w| w.borrow_mut().retain(|w| !self.owns(w)))
+    }
+
+    /// Call `f`, and add the counts it incurs on this thread to the totals
+    /// for the span named `name`.
+    ///
+    /// If `f` panics, the span is not recorded, but the thread's counters
+    /// are left as they were before the span began.
+    pub fn span<F, R>(&self, name: &'static str, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        if self.shared.lazy && self.with_worker(|_| ()).is_none() {
+            // If this fails, the span just goes unrecorded.
+            let _ = self.start_worker();
+        }
+
+        // Don't hold the `RefCell` borrowed while `f` runs: it may enter
+        // spans of its own.
+        let _guard = match self.with_worker(|w| w.enter()) {
+            Some(Ok(entry)) => Some(SpanGuard {
+                profiler: self,
+                name,
+                entry,
+            }),
+            _ => None,
+        };
+        f()
+    }
+
+    /// Return a future that runs `future`, treating each call to its `poll`
+    /// method as a span named `name`.
+    ///
+    /// An async task can move from one worker thread to another whenever it
+    /// is suspended, so this attributes each poll to whichever worker
+    /// thread performed it.
+    pub fn instrument<F: Future>(&self, name: &'static str, future: F) -> Instrumented<F> {
+        Instrumented {
+            profiler: self.clone(),
+            name,
+            future,
+        }
+    }
+
+    /// Return the totals for every span recorded so far, sorted by name.
+    pub fn spans(&self) -> Vec<(&'static str, SpanTotals)> {
+        let spans = self.shared.spans.lock().unwrap();
+        let mut spans: Vec<_> = spans
+            .iter()
+            .map(|(&name, totals)| (name, totals.clone()))
+            .collect();
+        spans.sort_by_key(|&(name, _)| name);
+        spans
+    }
+
+    /// Forget all spans recorded so far.
+    pub fn clear(&self) {
+        self.shared.spans.lock().unwrap().clear();
+    }
+
+    /// Set the handlers on `builder` so that each of the pool's threads
+    /// calls [`start_worker`] when it starts and [`stop_worker`] when it
+    /// exits.
+    ///
+    /// If a thread's counters can't be created, its spans go unrecorded.
+    ///
+    /// This is only available when the `"rayon"` feature is enabled.
+    ///
+    /// [`start_worker`]: WorkerProfiler::start_worker
+    /// [`stop_worker`]: WorkerProfiler::stop_worker
+    #[cfg(feature = "rayon")]
+    pub fn rayon_pool(&self, builder: rayon::ThreadPoolBuilder) -> rayon::ThreadPoolBuilder {
+        let start = self.clone();
+        let exit = self.clone();
+        builder
+            .start_handler(move |_| {
+                let _ = start.start_worker();
+            })
+            .exit_handler(move |_| exit.stop_worker())
+    }
+
+    /// Set the hooks on `builder` so that each of the runtime's threads
+    /// calls [`start_worker`] when it starts and [`stop_worker`] when it
+    /// exits.
+    ///
+    /// If a thread's counters can't be created, its spans go unrecorded.
+    ///
+    /// This is only available when the `"tokio"` feature is enabled.
+    ///
+    /// [`start_worker`]: WorkerProfiler::start_worker
+    /// [`stop_worker`]: WorkerProfiler::stop_worker
+    #[cfg(feature = "tokio")]
+    pub fn tokio_runtime<'b>(
+        &self,
+        builder: &'b mut tokio::runtime::Builder,
+    ) -> &'b mut tokio::runtime::Builder {
+        let start = self.clone();
+        let stop = self.clone();
+        builder
+            .on_thread_start(move || {
+                let _ = start.start_worker();
+            })
+            .on_thread_stop(move || stop.stop_worker())
+    }
+
+    /// Apply `f` to this thread's `Worker` belonging to us, if it has one.
+    fn with_worker<T>(&self, f: impl FnOnce(&mut Worker) -> T) -> Option<T> {
+        WORKERS.with(|w| {
+            let mut workers = w.borrow_mut();
+            workers.iter_mut().find(|w| self.owns(w)).map(f)
+        })
+    }
+
+    /// Return true if `worker` reports to this profiler.
+    fn owns(&self, worker: &Worker) -> bool {
+        Weak::as_ptr(&worker.owner) == Arc::as_ptr(&self.shared)
+    }
+
+    fn record(&self, name: &'static str, delta: SpanTotals) {
+        let mut spans = self.shared.spans.lock().unwrap();
+        let totals = spans.entry(name).or_insert_with(|| SpanTotals {
+            counts: vec![0; delta.counts.len()],
+            ..SpanTotals::default()
+        });
+        totals.calls += 1;
+        for (total, count) in totals.counts.iter_mut().zip(delta.counts) {
+            *total += count;
+        }
+        totals.time_enabled += delta.time_enabled;
+        totals.time_running += delta.time_running;
+    }
+}
+
+impl std::fmt::Debug for WorkerProfiler {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("WorkerProfiler")
+            .field("events", &self.shared.events)
+            .finish()
+    }
+}
+
+impl Worker {
+    fn enter(&mut self) -> io::Result<Entry> {
+        let entry = self.reading()?;
+        if self.depth == 0 {
+            self.group.enable()?;
+        }
+        self.depth += 1;
+        Ok(entry)
+    }
+
+    fn exit(&mut self, entry: &Entry) -> io::Result<SpanTotals> {
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.group.disable()?;
+        }
+        let exit = self.reading()?;
+        Ok(SpanTotals {
+            calls: 1,
+            counts: exit
+                .counts
+                .iter()
+                .zip(&entry.counts)
+                .map(|(exit, entry)| exit - entry)
+                .collect(),
+            time_enabled: exit.time_enabled - entry.time_enabled,
+            time_running: exit.time_running - entry.time_running,
+        })
+    }
+
+    fn reading(&mut self) -> io::Result<Entry> {
+        let counts: Counts = self.group.read()?;
+        Ok(Entry {
+            counts: self.counters.iter().map(|c| counts[c]).collect(),
+            time_enabled: counts.time_enabled(),
+            time_running: counts.time_running(),
+        })
+    }
+}
+
+impl Drop for SpanGuard<'_> {
+    fn drop(&mut self) {
+        let totals = self.profiler.with_worker(|w| w.exit(&self.entry));
+        // Don't record a span whose closure panicked partway through.
+        if let Some(Ok(totals)) = totals {
+            if !std::thread::panicking() {
+                self.profiler.record(self.name, totals);
+            }
+        }
+    }
+}
+
+/// A future whose polls are counted as spans of a [`WorkerProfiler`].
+///
+/// This is returned by [`WorkerProfiler::instrument`].
+#[derive(Debug)]
+pub struct Instrumented<F> {
+    profiler: WorkerProfiler,
+    name: &'static str,
+    future: F,
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // Safety: we never move `future` out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        this.profiler.span(this.name, || future.poll(cx))
+    }
+}
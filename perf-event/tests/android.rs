@@ -0,0 +1,64 @@
+use perf_event::android::{Conditions, Restriction};
+use perf_event::paranoid::Level;
+
+fn conditions(paranoid: Level, perf_harden: bool, context: &str) -> Conditions {
+    let mut conditions = Conditions::default();
+    conditions.paranoid = Some(paranoid);
+    conditions.perf_harden = Some(perf_harden);
+    conditions.selinux_enforcing = Some(true);
+    conditions.selinux_context = Some(context.to_string());
+    conditions
+}
+
+#[test]
+fn perf_harden() {
+    let hardened = conditions(Level::Disallowed, true, "u:r:shell:s0");
+    assert_eq!(
+        hardened.restriction(Level::NoKernel),
+        Some(Restriction::PerfHarden)
+    );
+
+    // Without `perf_harden`, the paranoia level itself is to blame.
+    let paranoid = conditions(Level::Disallowed, false, "u:r:shell:s0");
+    assert_eq!(
+        paranoid.restriction(Level::NoKernel),
+        Some(Restriction::Paranoid {
+            current: Level::Disallowed,
+            required: Level::NoKernel,
+        })
+    );
+}
+
+#[test]
+fn selinux() {
+    let app = conditions(Level::NoKernel, false, "u:r:untrusted_app:s0:c512,c768");
+    assert_eq!(app.selinux_domain(), Some("untrusted_app"));
+    assert!(app.is_app());
+    assert_eq!(
+        app.restriction(Level::NoKernel),
+        Some(Restriction::SeLinux {
+            domain: "untrusted_app".to_string()
+        })
+    );
+    assert!(app
+        .restriction(Level::NoKernel)
+        .unwrap()
+        .instructions()
+        .contains("run-as"));
+
+    // Profilers started with `run-as` may use perf events.
+    let run_as = conditions(Level::NoKernel, false, "u:r:runas_app:s0:c512,c768");
+    assert!(!run_as.is_app());
+    assert_eq!(run_as.restriction(Level::NoKernel), None);
+
+    let mut permissive = app.clone();
+    permissive.selinux_enforcing = Some(false);
+    assert_eq!(permissive.restriction(Level::NoKernel), None);
+}
+
+#[test]
+#[cfg(not(target_os = "android"))]
+fn not_android() {
+    assert_eq!(Conditions::current().perf_harden, None);
+    assert!(!perf_event::android::disable_perf_harden().unwrap());
+}
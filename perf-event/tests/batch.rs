@@ -0,0 +1,70 @@
+use perf_event::events::Software;
+use perf_event::{batch, Builder};
+
+fn spin() {
+    std::hint::black_box((0..100_000).sum::<u64>());
+}
+
+#[test]
+fn enable_disable_reset() {
+    let mut clock = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    let mut cpu = Builder::new().kind(Software::CPU_CLOCK).build().unwrap();
+
+    batch::enable_all(&mut [&mut clock, &mut cpu]).unwrap();
+    spin();
+    batch::disable_all(&mut [&mut clock, &mut cpu]).unwrap();
+    assert!(clock.read().unwrap() > 0);
+    assert!(cpu.read().unwrap() > 0);
+
+    batch::reset_all(&mut [&mut clock, &mut cpu]).unwrap();
+    assert_eq!(clock.read().unwrap(), 0);
+    assert_eq!(cpu.read().unwrap(), 0);
+}
+
+#[cfg(feature = "hooks")]
+#[test]
+fn failures_do_not_stop_batch() {
+    use perf_event::hooks::{self, Hooks, RealHooks};
+    use std::os::raw::{c_int, c_uint, c_ulong};
+    use std::os::unix::io::AsRawFd;
+
+    // Make `ENABLE` fail on one particular file descriptor.
+    struct FailOn(c_int);
+    impl Hooks for FailOn {
+        unsafe fn perf_event_open(
+            &mut self,
+            attrs: *mut hooks::sys::bindings::perf_event_attr,
+            pid: libc::pid_t,
+            cpu: c_int,
+            group_fd: c_int,
+            flags: c_ulong,
+        ) -> c_int {
+            RealHooks.perf_event_open(attrs, pid, cpu, group_fd, flags)
+        }
+
+        unsafe fn ENABLE(&mut self, fd: c_int, arg: c_uint) -> c_int {
+            if fd == self.0 {
+                *libc::__errno_location() = libc::EBUSY;
+                return -1;
+            }
+            RealHooks.ENABLE(fd, arg)
+        }
+    }
+
+    let mut first = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    let mut second = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+
+    unsafe { hooks::set_thread_hooks(Box::new(FailOn(first.as_raw_fd()))) };
+    let result = batch::enable_all(&mut [&mut first, &mut second]);
+    unsafe { hooks::clear_thread_hooks() };
+
+    let failures = result.unwrap_err().into_failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, 0);
+    assert_eq!(failures[0].1.raw_os_error(), Some(libc::EBUSY));
+
+    // The second counter was still enabled.
+    spin();
+    assert!(second.read().unwrap() > 0);
+    assert_eq!(first.read().unwrap(), 0);
+}
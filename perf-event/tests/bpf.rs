@@ -0,0 +1,76 @@
+use perf_event::events::{Software, Tracepoint};
+use perf_event::Builder;
+use std::fs::File;
+use std::os::unix::io::FromRawFd;
+
+const BPF_PROG_LOAD: libc::c_long = 5;
+const BPF_PROG_TYPE_TRACEPOINT: u32 = 5;
+
+/// Load a BPF tracepoint program that does nothing, returning `None` if we
+/// aren't allowed to.
+fn load_noop_program() -> Option<File> {
+    // `r0 = 0; exit`
+    let insns: [u64; 2] = [0x0000_0000_0000_00b7, 0x0000_0000_0000_0095];
+    let license = b"GPL\0";
+
+    // The leading fields of the kernel's `union bpf_attr`, for
+    // `BPF_PROG_LOAD`. The rest stay zero.
+    let mut attr = [0_u64; 16];
+    attr[0] = BPF_PROG_TYPE_TRACEPOINT as u64 | (insns.len() as u64) << 32;
+    attr[1] = insns.as_ptr() as u64;
+    attr[2] = license.as_ptr() as u64;
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            BPF_PROG_LOAD,
+            attr.as_mut_ptr(),
+            std::mem::size_of_val(&attr),
+        )
+    };
+    if fd < 0 {
+        eprintln!(
+            "skipping: can't load BPF program: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+    Some(unsafe { File::from_raw_fd(fd as i32) })
+}
+
+#[test]
+fn attach_to_tracepoint() {
+    let tracepoint = match Tracepoint::new("syscalls:sys_enter_getpid") {
+        Ok(tp) => tp,
+        Err(e) => {
+            eprintln!("skipping: {}", e);
+            return;
+        }
+    };
+    let mut counter = match Builder::new().kind(tracepoint).build() {
+        Ok(counter) => counter,
+        Err(e) => {
+            eprintln!("skipping: can't open tracepoint: {}", e);
+            return;
+        }
+    };
+    let program = match load_noop_program() {
+        Some(program) => program,
+        None => return,
+    };
+
+    assert_eq!(counter.bpf_programs().unwrap(), Vec::<u32>::new());
+    counter.attach_bpf(&program).unwrap();
+    assert_eq!(counter.bpf_programs().unwrap().len(), 1);
+}
+
+#[test]
+fn attach_non_program() {
+    let mut counter = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .build()
+        .unwrap();
+    let not_a_program = File::open("/dev/null").unwrap();
+    assert!(counter.attach_bpf(&not_a_program).is_err());
+}
@@ -51,3 +51,69 @@ fn execute() {
     counter.disable().unwrap();
     assert_eq!(counter.read().unwrap(), 1000);
 }
+
+#[test]
+fn watch() {
+    let value = std::cell::Cell::new(0_u32);
+    let mut counter = Builder::new()
+        .kind(events::Breakpoint::watch(&value, events::BreakpointAccess::WRITE).unwrap())
+        .observe_self()
+        .build()
+        .expect("Unable to build performance counter");
+    counter.enable().unwrap();
+
+    for i in 0..100 {
+        unsafe { std::ptr::write_volatile(value.as_ptr(), i) };
+    }
+
+    counter.disable().unwrap();
+    assert_eq!(counter.read().unwrap(), 100);
+}
+
+#[test]
+fn watch_too_large() {
+    let value = [0_u64; 2];
+    let err = events::Breakpoint::watch(&value, events::BreakpointAccess::READ_WRITE).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn watch_range() {
+    use events::{Breakpoint, BreakpointAccess};
+
+    let access = BreakpointAccess::WRITE;
+    for addr in 0x1000..0x1010 {
+        for len in 1..=24 {
+            let breakpoints = Breakpoint::watch_range(access, addr, len).unwrap();
+
+            // The breakpoints must be valid, and cover the range exactly.
+            let mut next = addr;
+            for bp in &breakpoints {
+                bp.validate().unwrap();
+                match *bp {
+                    Breakpoint::Data {
+                        access: a,
+                        addr: start,
+                        len,
+                    } => {
+                        assert_eq!(a, access);
+                        assert_eq!(start, next);
+                        next += len;
+                    }
+                    Breakpoint::Code { .. } => panic!("expected a data breakpoint"),
+                }
+            }
+            assert_eq!(next, addr + len);
+        }
+    }
+
+    let aligned = Breakpoint::watch_range(access, 0x1000, 16).unwrap();
+    assert_eq!(aligned.len(), if cfg!(target_arch = "x86") { 4 } else { 2 });
+
+    assert_eq!(
+        Breakpoint::watch_range(access, 0x1000, 0)
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+}
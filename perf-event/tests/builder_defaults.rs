@@ -0,0 +1,32 @@
+// This is its own test binary, since changing the defaults affects every
+// `Builder` created afterwards, in any thread.
+
+use perf_event::events::Software;
+use perf_event::{Builder, BuilderDefaults};
+use std::io;
+
+#[test]
+fn install_defaults() {
+    assert_eq!(BuilderDefaults::current(), BuilderDefaults::new());
+
+    let previous = BuilderDefaults {
+        times: false,
+        ..BuilderDefaults::new()
+    }
+    .install();
+    assert_eq!(previous, BuilderDefaults::new());
+
+    let mut counter = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    counter.enable().unwrap();
+    std::hint::black_box((0..100_000).sum::<u64>());
+    counter.disable().unwrap();
+    assert!(counter.read().unwrap() > 0);
+    match counter.read_count_and_time() {
+        Ok(_) => panic!("counter without times returned times"),
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+    }
+
+    BuilderDefaults::new().install();
+    let mut counter = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    assert!(counter.read_count_and_time().is_ok());
+}
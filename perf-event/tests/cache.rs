@@ -0,0 +1,29 @@
+use perf_event::events::{Cache, CacheOp, CacheResult, WhichCache};
+use perf_event::Builder;
+
+#[test]
+fn all() {
+    let all: Vec<Cache> = Cache::all().collect();
+    assert_eq!(
+        all.len(),
+        WhichCache::ALL.len() * CacheOp::ALL.len() * CacheResult::ALL.len()
+    );
+    for (i, a) in all.iter().enumerate() {
+        assert!(!all[i + 1..].contains(a));
+    }
+}
+
+#[test]
+fn supported() {
+    let supported = match Cache::supported() {
+        Ok(supported) => supported,
+        Err(e) => {
+            eprintln!("skipping: can't probe cache events: {}", e);
+            return;
+        }
+    };
+    eprintln!("{} supported cache events", supported.len());
+    for cache in supported {
+        Builder::new().kind(cache).build().unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use perf_event::cgroup;
+use perf_event::events::Software;
+use perf_event::Builder;
+use std::io::ErrorKind;
+
+/// Return our own cgroup's path within the hierarchy perf uses.
+fn own_cgroup() -> Option<String> {
+    let root = cgroup::root().ok()?;
+    let contents = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    contents.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let matches = if root.unified {
+            controllers.is_empty()
+        } else {
+            controllers.split(',').any(|c| c == "perf_event")
+        };
+        if matches {
+            Some(path.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+#[test]
+fn observe_own_cgroup() {
+    let own = match own_cgroup() {
+        Some(own) => own,
+        None => {
+            eprintln!("skipping: can't find our own cgroup");
+            return;
+        }
+    };
+    assert!(cgroup::path(&own).unwrap().is_dir());
+
+    let mut counter = match Builder::new()
+        .observe_cgroup_path(&own)
+        .unwrap()
+        .one_cpu(0)
+        .kind(Software::CPU_CLOCK)
+        .build()
+    {
+        Ok(counter) => counter,
+        Err(e) => {
+            eprintln!("skipping: can't count cgroup events: {}", e);
+            return;
+        }
+    };
+    counter.enable().unwrap();
+    std::hint::black_box((0..1_000_000_u64).sum::<u64>());
+    counter.disable().unwrap();
+    counter.read().unwrap();
+}
+
+#[test]
+fn missing_cgroup() {
+    if cgroup::root().is_err() {
+        return;
+    }
+    let err = match Builder::new().observe_cgroup_path("no-such.slice/no-such.service") {
+        Ok(_) => panic!("found a nonexistent cgroup"),
+        Err(err) => err,
+    };
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert!(err.to_string().contains("hierarchy"));
+
+    let err = cgroup::path("../etc").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
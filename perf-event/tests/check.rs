@@ -0,0 +1,24 @@
+use perf_event::check::{self, Feature, Status};
+
+#[test]
+fn report_covers_every_feature() {
+    let report = check::report();
+    assert_eq!(report.iter().count(), Feature::ALL.len());
+
+    // Every test in this suite counts software events.
+    assert_eq!(*report.status(Feature::SoftwareEvents), Status::Available);
+
+    let text = report.to_string();
+    assert!(text.contains("perf_event_paranoid"));
+    for feature in Feature::ALL {
+        assert!(text.contains(feature.description()));
+    }
+}
+
+#[test]
+fn probe_matches_report() {
+    let report = check::report();
+    for feature in Feature::ALL {
+        assert_eq!(&check::probe(feature), report.status(feature));
+    }
+}
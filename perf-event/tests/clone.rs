@@ -0,0 +1,49 @@
+use perf_event::events::Software;
+use perf_event::{Builder, Group};
+
+fn spin() {
+    let _spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+}
+
+#[test]
+fn counter_clones_share_state() {
+    let mut controller = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    let mut reader = controller.try_clone().unwrap();
+    assert_eq!(reader.id(), controller.id());
+
+    controller.enable().unwrap();
+    spin();
+    controller.disable().unwrap();
+
+    let count = std::thread::spawn(move || reader.read().unwrap())
+        .join()
+        .unwrap();
+    assert!(count > 0);
+    assert_eq!(controller.read().unwrap(), count);
+}
+
+#[test]
+fn member_outlives_dropped_clone() {
+    let mut group = Group::new().unwrap();
+    let member = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let clone = member.try_clone().unwrap();
+    drop(member);
+
+    let mut other = group.try_clone().unwrap();
+    other.enable().unwrap();
+    spin();
+    group.disable().unwrap();
+
+    // Both handles on the group see the member, since a clone remains.
+    let counts = other.read().unwrap();
+    assert!(counts[&clone] > 0);
+    assert_eq!(group.read().unwrap()[&clone], counts[&clone]);
+
+    // Dropping the last clone removes the member, leaving the leader.
+    drop(clone);
+    assert_eq!(group.read().unwrap().len(), 1);
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "counting_alloc")]
+
+use perf_event::counting_alloc::CountingAlloc;
+use perf_event::events::Software;
+use std::alloc::System;
+
+// The task clock works everywhere, even without a performance monitoring
+// unit; it just has to be read with a system call.
+#[global_allocator]
+static ALLOC: CountingAlloc<System> = CountingAlloc::software(System, Software::TASK_CLOCK);
+
+// This is the only test in this file, so that the test harness's other
+// threads don't allocate in the size classes we check.
+#[test]
+fn size_classes() {
+    // Open this thread's counter.
+    drop(Box::new(0_u8));
+    ALLOC.reset();
+
+    let small: Vec<Box<[u8; 100]>> = (0..1000).map(|_| Box::new([1; 100])).collect();
+    let large: Vec<Vec<u8>> = (0..100).map(|_| vec![1; 5000]).collect();
+    drop(small);
+    drop(large);
+
+    let classes = ALLOC.size_classes();
+    assert!(classes
+        .windows(2)
+        .all(|pair| pair[0].max_size < pair[1].min_size));
+
+    let class = |size: usize| {
+        *classes
+            .iter()
+            .find(|class| class.min_size <= size && size <= class.max_size)
+            .unwrap()
+    };
+    let small = class(100);
+    assert_eq!((small.min_size, small.max_size), (65, 128));
+    assert!(small.alloc.calls >= 1000);
+    assert!(small.dealloc.calls >= 1000);
+    assert!(small.alloc.events > 0);
+    assert!(small.alloc.mean().unwrap() > 0.0);
+
+    let large = class(5000);
+    assert_eq!((large.min_size, large.max_size), (4097, 8192));
+    assert!(large.alloc.calls >= 100);
+    assert!(large.dealloc.calls >= 100);
+
+    ALLOC.reset();
+    assert!(ALLOC
+        .size_classes()
+        .iter()
+        .all(|class| class.alloc.calls < 1000));
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "criterion")]
+
+use criterion::measurement::Measurement;
+use criterion::Throughput;
+use perf_event::criterion::PerfMeasurement;
+use perf_event::events::Software;
+use std::time::Duration;
+
+fn spin(iterations: u64) -> u64 {
+    (0..iterations).map(std::hint::black_box).sum()
+}
+
+#[test]
+fn measure() {
+    let measurement = PerfMeasurement::new(Software::TASK_CLOCK, "ns").unwrap();
+
+    let start = measurement.start();
+    spin(1_000_000);
+    let short = measurement.end(start);
+
+    let start = measurement.start();
+    spin(10_000_000);
+    let long = measurement.end(start);
+
+    assert!(short > 0);
+    assert!(long > short);
+    assert_eq!(measurement.add(&short, &long), short + long);
+    assert_eq!(measurement.to_f64(&measurement.zero()), 0.0);
+}
+
+#[test]
+fn format() {
+    let measurement = PerfMeasurement::new(Software::TASK_CLOCK, "ns").unwrap();
+    let formatter = measurement.formatter();
+
+    let mut values = [100.0, 200.0];
+    assert_eq!(formatter.scale_values(150.0, &mut values), "ns");
+    assert_eq!(values, [100.0, 200.0]);
+
+    let unit = formatter.scale_throughputs(150.0, &Throughput::Bytes(4), &mut values);
+    assert_eq!(unit, "per byte");
+    assert_eq!(values, [25.0, 50.0]);
+}
+
+#[test]
+fn bench() {
+    let mut criterion = criterion::Criterion::default()
+        .with_measurement(PerfMeasurement::new(Software::TASK_CLOCK, "ns").unwrap())
+        .sample_size(10)
+        .warm_up_time(Duration::from_millis(10))
+        .measurement_time(Duration::from_millis(50))
+        .without_plots();
+    criterion.bench_function("spin", |b| b.iter(|| spin(1_000)));
+}
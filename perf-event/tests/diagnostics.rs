@@ -0,0 +1,23 @@
+use perf_event::diagnostics;
+use perf_event::events::Software;
+use perf_event::Builder;
+
+#[test]
+fn finds_own_events() {
+    let _counter = Builder::new()
+        .kind(Software::DUMMY)
+        .build()
+        .expect("couldn't build counter");
+
+    let report = diagnostics::contention();
+    let own = report
+        .perf_users()
+        .iter()
+        .find(|user| user.pid == std::process::id())
+        .expect("contention report didn't include this process");
+    assert!(own.events >= 1);
+
+    // The report shouldn't mention ourselves as competition.
+    let text = report.to_string();
+    assert!(!text.contains(&format!("{} (", std::process::id())));
+}
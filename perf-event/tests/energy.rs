@@ -0,0 +1,36 @@
+use perf_event::events::{Energy, EnergyDomain};
+use std::io::ErrorKind;
+use std::time::Duration;
+
+#[test]
+fn available() {
+    for energy in Energy::available() {
+        assert!(EnergyDomain::ALL.contains(&energy.domain()));
+        assert_eq!(energy.unit(), "Joules");
+        assert!(energy.scale() > 0.0);
+        assert_eq!(energy.joules(0), 0.0);
+    }
+}
+
+#[test]
+fn measure() {
+    let energy = match Energy::available().into_iter().next() {
+        Some(energy) => energy,
+        None => {
+            eprintln!("skipping: no energy counters");
+            return;
+        }
+    };
+    let mut counter = match energy.build() {
+        Ok(counter) => counter,
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            eprintln!("skipping: {}", e);
+            return;
+        }
+        Err(e) => panic!("{}", e),
+    };
+    let measured = counter.measure_for(Duration::from_millis(20)).unwrap();
+    let joules = energy.joules(measured.counts.count);
+    assert!(joules >= 0.0);
+    assert!(joules.is_finite());
+}
@@ -0,0 +1,92 @@
+use perf_event::events::Software;
+use perf_event::paranoid::Level;
+use perf_event::{Builder, Error};
+use std::error::Error as _;
+use std::io;
+
+fn classify(errno: i32) -> Error {
+    Error::from(io::Error::from_raw_os_error(errno))
+}
+
+#[test]
+fn classification() {
+    assert!(matches!(
+        classify(libc::EACCES),
+        Error::PermissionDenied { .. }
+    ));
+    assert!(matches!(
+        classify(libc::EPERM),
+        Error::PermissionDenied { .. }
+    ));
+    assert!(matches!(classify(libc::ENOENT), Error::UnsupportedEvent(_)));
+    assert!(matches!(classify(libc::E2BIG), Error::KernelTooOld(_)));
+    assert!(matches!(
+        classify(libc::EINVAL),
+        Error::InvalidConfiguration(_)
+    ));
+    assert!(matches!(classify(libc::ENOSPC), Error::TooManyEvents(_)));
+    assert!(matches!(classify(libc::EBADF), Error::Other(_)));
+}
+
+#[test]
+fn round_trip() {
+    let error = classify(libc::E2BIG);
+    assert_eq!(error.raw_os_error(), Some(libc::E2BIG));
+    assert!(error.source().is_some());
+    assert!(error.to_string().contains("too old"));
+
+    let error = io::Error::from(error);
+    assert_eq!(error.raw_os_error(), Some(libc::E2BIG));
+}
+
+#[test]
+fn message_not_repeated() {
+    // An explained error leaves the original message to `source`.
+    let error = classify(libc::E2BIG);
+    let source = error.source().unwrap().to_string();
+    assert!(!error.to_string().contains(&source));
+
+    // An unexplained error shows the original message, and skips it in the
+    // chain of sources.
+    let error = classify(libc::EBADF);
+    assert_eq!(
+        error.to_string(),
+        io::Error::from_raw_os_error(libc::EBADF).to_string()
+    );
+    assert!(error.source().is_none());
+}
+
+#[test]
+fn builder_checks() {
+    // `Builder::build` catches this itself, without the kernel's help.
+    let error = Builder::new()
+        .any_pid()
+        .kind(Software::CONTEXT_SWITCHES)
+        .try_build()
+        .unwrap_err();
+    assert!(matches!(error, Error::InvalidConfiguration(_)));
+    assert_eq!(io::Error::from(error).kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn permission_denied() {
+    let result = Builder::new()
+        .system_wide_on_cpu(0)
+        .kind(Software::CONTEXT_SWITCHES)
+        .try_build();
+    match result {
+        // We have the privileges; nothing to check.
+        Ok(_) => {}
+        Err(error @ Error::PermissionDenied { .. }) => {
+            assert!(matches!(
+                error,
+                Error::PermissionDenied {
+                    required: Some(Level::NoRawTracepoints),
+                    ..
+                }
+            ));
+            assert!(error.to_string().contains("perf_event_paranoid"));
+        }
+        Err(error) => panic!("{}", error),
+    }
+}
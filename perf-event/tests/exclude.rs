@@ -0,0 +1,34 @@
+use perf_event::events::Software;
+use perf_event::{Builder, Counter};
+
+/// Count page faults while touching some fresh memory.
+fn count_faults(mut counter: Counter) -> u64 {
+    counter.enable().unwrap();
+    let mut pages = vec![0_u8; 64 * 4096];
+    for page in pages.chunks_mut(4096) {
+        page[0] = 1;
+    }
+    std::hint::black_box(&pages);
+    counter.disable().unwrap();
+    counter.read().unwrap()
+}
+
+#[test]
+fn exclude_user() {
+    let builder = || Builder::new().kind(Software::PAGE_FAULTS);
+    assert!(count_faults(builder().build().unwrap()) > 0);
+
+    // The kernel is excluded by default, so this excludes everything.
+    assert_eq!(count_faults(builder().exclude_user().build().unwrap()), 0);
+}
+
+#[test]
+fn exclude_flags_accepted() {
+    let counter = Builder::new()
+        .exclude_idle()
+        .exclude_guest()
+        .kind(Software::PAGE_FAULTS)
+        .build()
+        .unwrap();
+    assert!(count_faults(counter) > 0);
+}
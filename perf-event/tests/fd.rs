@@ -0,0 +1,125 @@
+use perf_event::events::{Event, Software};
+#[cfg(feature = "sampling")]
+use perf_event::samples::SampleType;
+use perf_event::{Builder, Counter, Group};
+use perf_event_open_sys::bindings::{
+    PERF_FORMAT_GROUP, PERF_FORMAT_ID, PERF_FORMAT_TOTAL_TIME_ENABLED,
+    PERF_FORMAT_TOTAL_TIME_RUNNING,
+};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+
+/// `PERF_FORMAT_LOST`, which these bindings predate.
+const LOST: u64 = 1 << 4;
+
+const TIMES: u64 = (PERF_FORMAT_TOTAL_TIME_ENABLED | PERF_FORMAT_TOTAL_TIME_RUNNING) as u64;
+
+fn spin() {
+    let _spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+}
+
+#[test]
+fn counter_round_trip() {
+    let mut counter = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    counter.enable().unwrap();
+    spin();
+    counter.disable().unwrap();
+    let (id, count) = (counter.id(), counter.read().unwrap());
+    assert_eq!(
+        counter.event(),
+        Some(&Event::Software(Software::TASK_CLOCK))
+    );
+
+    let read_format = counter.read_format();
+    assert_eq!(read_format, TIMES);
+    let fd = OwnedFd::from(counter);
+    let mut counter = Counter::from_owned_fd(fd, read_format).unwrap();
+    assert_eq!(counter.id(), id);
+    assert_eq!(counter.event(), None);
+    assert_eq!(counter.read().unwrap(), count);
+    assert!(counter.read_count_and_time().is_ok());
+
+    let raw = counter.into_raw_fd();
+    let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+    let mut counter = Counter::from_owned_fd(fd, read_format).unwrap();
+    assert_eq!(counter.as_raw_fd(), raw);
+    assert_eq!(counter.read().unwrap(), count);
+}
+
+#[test]
+fn group_member_round_trip() {
+    let mut group = Group::new().unwrap();
+    let member = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let id = member.id();
+    let read_format = member.read_format();
+
+    // Group members read only themselves, so they come back as plain
+    // counters.
+    let mut member = Counter::from_owned_fd(member.into(), read_format).unwrap();
+    assert_eq!(member.id(), id);
+    assert!(member.read().is_ok());
+
+    // The group's own descriptor reads the whole group.
+    let group_format = TIMES | (PERF_FORMAT_ID | PERF_FORMAT_GROUP) as u64;
+    let mut leader = Counter::from_owned_fd(group.into(), group_format).unwrap();
+    assert!(leader.read().is_err());
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn sampler_into_fd() {
+    let sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    let id = sampler.id();
+    let read_format = sampler.read_format();
+    let counter = Counter::from_owned_fd(sampler.into(), read_format).unwrap();
+    assert_eq!(counter.id(), id);
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn read_sampler_into_fd() {
+    // This sampler's reads include its id.
+    let sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::READ)
+        .build_sampler()
+        .unwrap();
+    let read_format = sampler.read_format();
+    assert_ne!(read_format & PERF_FORMAT_ID as u64, 0);
+    let mut counter = Counter::from_owned_fd(sampler.into(), read_format).unwrap();
+    counter.read().unwrap();
+    counter.read_count_and_time().unwrap();
+}
+
+#[test]
+fn not_a_counter() {
+    let file = std::fs::File::open("/dev/null").unwrap();
+    assert!(Counter::from_owned_fd(file.into(), TIMES).is_err());
+}
+
+#[test]
+fn wrong_read_format() {
+    let counter = || Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+
+    // Reads are longer than this format says.
+    let err = Counter::from_owned_fd(counter().into(), 0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    // Shorter, too.
+    let err = Counter::from_owned_fd(counter().into(), TIMES | PERF_FORMAT_ID as u64).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    // Formats `Counter` can't read are rejected outright.
+    for format in [TIMES | LOST, PERF_FORMAT_TOTAL_TIME_ENABLED as u64] {
+        let err = Counter::from_owned_fd(counter().into(), format).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
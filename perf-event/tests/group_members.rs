@@ -0,0 +1,62 @@
+use perf_event::events::Software;
+use perf_event::{Builder, Group};
+
+fn member(group: &mut Group) -> perf_event::Counter {
+    Builder::new()
+        .group(group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn read_after_drop() {
+    let mut group = Group::new().unwrap();
+    let kept = member(&mut group);
+    let dropped = member(&mut group);
+    drop(dropped);
+
+    let counts = group.read().unwrap();
+    assert_eq!(counts.iter().count(), 1);
+    assert!(counts.get(&kept).is_some());
+}
+
+#[test]
+fn remove() {
+    let mut group = Group::new().unwrap();
+    let first = member(&mut group);
+    let second = member(&mut group);
+    let third = member(&mut group);
+
+    group.remove(second).unwrap();
+    let counts = group.read().unwrap();
+    assert_eq!(counts.len(), 3);
+    assert!(counts.get(&first).is_some());
+    assert!(counts.get(&third).is_some());
+
+    group.remove(first).unwrap();
+    group.remove(third).unwrap();
+    assert_eq!(group.read().unwrap().len(), 1);
+}
+
+#[test]
+fn remove_stranger() {
+    let mut group = Group::new().unwrap();
+    let _member = member(&mut group);
+    let mut other = Group::new().unwrap();
+    let stranger = member(&mut other);
+
+    let err = group.remove(stranger).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(group.read().unwrap().len(), 2);
+}
+
+#[test]
+fn remove_leader() {
+    let (mut group, leader) = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .build_group_leader()
+        .unwrap();
+    let err = group.remove(leader).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
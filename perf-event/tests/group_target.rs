@@ -0,0 +1,115 @@
+use perf_event::events::Software;
+use perf_event::{Builder, Group};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+
+#[test]
+fn other_thread() {
+    let tid = Arc::new(AtomicI32::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let worker = {
+        let tid = tid.clone();
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            tid.store(unsafe { libc::gettid() }, Ordering::SeqCst);
+            while !stop.load(Ordering::SeqCst) {
+                std::hint::black_box(0);
+            }
+        })
+    };
+    while tid.load(Ordering::SeqCst) == 0 {
+        std::thread::yield_now();
+    }
+
+    let mut group = Builder::new()
+        .observe_pid(tid.load(Ordering::SeqCst))
+        .build_group()
+        .unwrap();
+    let clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+
+    group.enable().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    group.disable().unwrap();
+    stop.store(true, Ordering::SeqCst);
+    worker.join().unwrap();
+
+    // We only slept, so any time counted must be the worker's.
+    let counts = group.read().unwrap();
+    assert!(counts[&clock] > 0);
+}
+
+#[test]
+fn one_cpu() {
+    let mut group = Builder::new().one_cpu(0).build_group().unwrap();
+    let mut clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+
+    let cpu = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(0, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set)
+    };
+    assert_eq!(cpu, 0);
+
+    group.enable().unwrap();
+    std::hint::black_box((0..1_000_000).sum::<u64>());
+    group.disable().unwrap();
+
+    let counts = group.read().unwrap();
+    assert!(counts[&clock] > 0);
+    assert_eq!(counts[&clock], clock.read().unwrap());
+}
+
+#[test]
+fn real_leader() {
+    let (mut group, clock) = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .build_group_leader()
+        .unwrap();
+    let faults = Builder::new()
+        .group(&mut group)
+        .kind(Software::PAGE_FAULTS)
+        .build()
+        .unwrap();
+
+    group.enable().unwrap();
+    let pages: Vec<Vec<u8>> = (0..100).map(|_| vec![1; 4096]).collect();
+    group.disable().unwrap();
+    drop(pages);
+
+    let counts = group.read().unwrap();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts.iter().count(), 2);
+    assert_eq!(counts.iter().next().unwrap().0, clock.id());
+    assert!(counts[&clock] > 0);
+    assert!(counts[&faults] > 0);
+}
+
+#[test]
+fn leader_not_in_group() {
+    let mut group = Group::new().unwrap();
+    let err = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build_group_leader()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn leader_period_and_frequency_conflict() {
+    let err = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample_frequency(1000)
+        .build_group_leader()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,55 @@
+use perf_event::events::Software;
+use perf_event::{Builder, Group, MemberCount};
+
+#[test]
+fn late_member_has_own_times() {
+    let mut group = Group::new().unwrap();
+    let mut early = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    group.enable().unwrap();
+    std::hint::black_box((0..1_000_000).sum::<u64>());
+
+    // Add a second member while the group is running.
+    let mut late = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    late.enable().unwrap();
+    std::hint::black_box((0..1_000_000).sum::<u64>());
+    group.disable().unwrap();
+
+    let data = group.read_with_times(&mut [&mut early, &mut late]).unwrap();
+    assert_eq!(data.len(), 2);
+    assert!(data[&late].time_enabled < data[&early].time_enabled);
+    assert!(data[&late].time_running <= data[&late].time_enabled);
+}
+
+#[test]
+fn not_a_member() {
+    let mut group = Group::new().unwrap();
+    let _member = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let mut stranger = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    let err = group.read_with_times(&mut [&mut stranger]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn scaled() {
+    let member = |time_enabled, time_running| MemberCount {
+        id: 0,
+        value: 100,
+        time_enabled,
+        time_running,
+    };
+    assert_eq!(member(10, 10).scaled(), Some(100));
+    assert_eq!(member(10, 5).scaled(), Some(200));
+    assert_eq!(member(10, 0).scaled(), None);
+}
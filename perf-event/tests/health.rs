@@ -0,0 +1,54 @@
+use perf_event::events::Software;
+use perf_event::health::{GroupHealth, Schedule};
+use perf_event::{Builder, Group};
+
+#[test]
+fn transitions() {
+    let mut health = GroupHealth::with_window(4);
+    assert_eq!(health.state(), Schedule::Unknown);
+
+    // The first reading is only a baseline.
+    assert_eq!(health.observe_times(100, 100), None);
+    assert_eq!(health.ratio(), None);
+
+    let change = health.observe_times(200, 200).unwrap();
+    assert_eq!(
+        (change.from, change.to),
+        (Schedule::Unknown, Schedule::Running)
+    );
+    assert_eq!(health.observe_times(300, 300), None);
+
+    // Someone else takes half the time, then all of it.
+    let change = health.observe_times(400, 350).unwrap();
+    assert_eq!(change.to, Schedule::Multiplexed);
+    assert_eq!(change.ratio, 0.5);
+    let change = health.observe_times(500, 350).unwrap();
+    assert_eq!(change.to, Schedule::Starved);
+    assert_eq!(health.trend(), Some((1.0 + 1.0 + 0.5 + 0.0) / 4.0));
+
+    // Disabled intervals don't count.
+    assert_eq!(health.observe_times(500, 350), None);
+    assert_eq!(health.state(), Schedule::Starved);
+
+    // A reset makes a new baseline.
+    assert_eq!(health.observe_times(0, 0), None);
+    assert_eq!(health.observe_times(100, 0), None);
+    assert_eq!(health.state(), Schedule::Starved);
+}
+
+#[test]
+fn software_group_runs() {
+    let mut group = Group::new().unwrap();
+    let _clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let mut health = GroupHealth::new();
+
+    group.enable().unwrap();
+    health.observe(&group.read().unwrap());
+    std::hint::black_box((0..100_000).sum::<u64>());
+    health.observe(&group.read().unwrap());
+    assert_eq!(health.state(), Schedule::Running);
+}
@@ -0,0 +1,98 @@
+use perf_event::histogram::{Bucket, Log2Histogram};
+
+#[test]
+fn buckets() {
+    let histogram: Log2Histogram = vec![0, 1, 2, 3, 4, 7, 8, u64::MAX].into_iter().collect();
+    let buckets: Vec<Bucket> = histogram.buckets().collect();
+    let bounds: Vec<(u64, u64, u64)> = buckets.iter().map(|b| (b.start, b.end, b.count)).collect();
+    assert_eq!(
+        bounds,
+        vec![
+            (0, 0, 1),
+            (1, 1, 1),
+            (2, 3, 2),
+            (4, 7, 2),
+            (8, 15, 1),
+            (1 << 63, u64::MAX, 1),
+        ]
+    );
+    assert_eq!(histogram.count(), 8);
+    assert_eq!(histogram.min(), Some(0));
+    assert_eq!(histogram.max(), Some(u64::MAX));
+}
+
+#[test]
+fn summary() {
+    let mut histogram = Log2Histogram::new();
+    assert_eq!(histogram.mean(), None);
+    assert_eq!(histogram.quantile(0.5), None);
+
+    histogram.record_n(10, 99);
+    histogram.record(1000);
+    assert_eq!(histogram.sum(), 1990);
+    assert_eq!(histogram.mean(), Some(19.9));
+    // 10 lands in the 8..=15 bucket.
+    assert_eq!(histogram.quantile(0.5), Some(15));
+    assert_eq!(histogram.quantile(0.99), Some(15));
+    // The top bucket is 512..=1023, clamped to the maximum.
+    assert_eq!(histogram.quantile(1.0), Some(1000));
+}
+
+#[test]
+fn merge() {
+    let mut a: Log2Histogram = vec![1, 2, 3].into_iter().collect();
+    let b: Log2Histogram = vec![100, 200].into_iter().collect();
+    a.merge(&b);
+    assert_eq!(a.count(), 5);
+    assert_eq!(a.sum(), 306);
+    assert_eq!(a.min(), Some(1));
+    assert_eq!(a.max(), Some(200));
+
+    a.merge(&Log2Histogram::new());
+    assert_eq!(a.min(), Some(1));
+
+    a.clear();
+    assert_eq!(a, Log2Histogram::new());
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn histogramming_consumer() {
+    use perf_event::events::Software;
+    use perf_event::histogram::{HistogrammingConsumer, SampleField};
+    use perf_event::samples::SampleType;
+    use perf_event::Builder;
+
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::PERIOD)
+        .build_sampler()
+        .unwrap();
+    let mut periods = HistogrammingConsumer::new(SampleField::Period);
+    let mut weights = HistogrammingConsumer::new(SampleField::Weight);
+
+    sampler.enable().unwrap();
+    let sum: u64 = (0..10_000_000_u64).map(std::hint::black_box).sum();
+    std::hint::black_box(sum);
+    sampler.disable().unwrap();
+
+    let mut records = vec![];
+    sampler.drain_into(&mut records).unwrap();
+    for record in &records {
+        periods.consume(record, sampler.config());
+        weights.consume(record, sampler.config());
+    }
+
+    // Every sample has the fixed period, and none has a weight.
+    let histogram = periods.histogram();
+    assert!(histogram.count() > 0);
+    assert_eq!(histogram.min(), Some(100_000));
+    assert_eq!(histogram.max(), Some(100_000));
+    assert_eq!(periods.missing(), 0);
+    assert_eq!(weights.histogram().count(), 0);
+    assert_eq!(weights.missing(), histogram.count());
+
+    // Once the buffer is drained, there's nothing more to read.
+    assert_eq!(periods.drain(&mut sampler), 0);
+}
@@ -0,0 +1,75 @@
+use perf_event::events::Software;
+use perf_event::{Builder, Counter};
+use std::time::{Duration, Instant};
+
+/// Spin in a new thread until it has used 20ms of CPU time, so the task
+/// clock has something to count. (Wall-clock time won't do: other tests
+/// compete for the CPU.)
+fn busy_thread() {
+    std::thread::spawn(|| {
+        let thread_cpu_time = || {
+            let mut ts = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+            Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+        };
+        while thread_cpu_time() < Duration::from_millis(20) {
+            std::hint::black_box(0);
+        }
+    })
+    .join()
+    .unwrap();
+}
+
+/// Wait for `counter` to reach `min`, giving up after a second.
+///
+/// A thread's inherited counts are added to its parent's counter as the
+/// thread exits, but `JoinHandle::join` can return before the kernel has
+/// done so.
+fn wait_for_count(counter: &mut Counter, min: u64) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        if counter.read().unwrap() >= min {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
+
+#[test]
+fn inherit_counts_new_threads() {
+    let mut counter = Builder::new()
+        .inherit(true)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    counter.enable().unwrap();
+    busy_thread();
+    counter.disable().unwrap();
+
+    // The thread's 20ms of spinning should show up.
+    assert!(wait_for_count(&mut counter, 20_000_000));
+}
+
+#[test]
+fn inherit_thread() {
+    let counter = Builder::new()
+        .inherit(true)
+        .inherit_thread(true)
+        .inherit_stat(true)
+        .kind(Software::TASK_CLOCK)
+        .build();
+    let mut counter = match counter {
+        Ok(counter) => counter,
+        // `inherit_thread` needs Linux 5.13.
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => return,
+        Err(e) => panic!("{}", e),
+    };
+    counter.enable().unwrap();
+    busy_thread();
+    counter.disable().unwrap();
+    assert!(wait_for_count(&mut counter, 20_000_000));
+}
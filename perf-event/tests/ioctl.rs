@@ -0,0 +1,35 @@
+use perf_event::events::Software;
+use perf_event::ioctl::{self, Scope};
+use perf_event::Builder;
+
+#[test]
+fn id_matches_counter() {
+    let counter = Builder::new()
+        .kind(Software::DUMMY)
+        .build()
+        .expect("Unable to build performance counter");
+    assert_eq!(ioctl::id(&counter).unwrap(), counter.id());
+}
+
+#[test]
+fn enable_disable_reset() {
+    let mut counter = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .expect("Unable to build performance counter");
+
+    ioctl::enable(&counter, Scope::Event).unwrap();
+    let v: Vec<u64> = (0..10000).collect();
+    std::hint::black_box(&v);
+    ioctl::disable(&counter, Scope::Event).unwrap();
+    assert!(counter.read().unwrap() > 0);
+
+    ioctl::reset(&counter, Scope::Event).unwrap();
+    assert_eq!(counter.read().unwrap(), 0);
+}
+
+#[test]
+fn bad_fd() {
+    let not_an_event = std::fs::File::open("/dev/null").unwrap();
+    assert!(ioctl::id(&not_an_event).is_err());
+}
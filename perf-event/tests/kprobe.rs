@@ -0,0 +1,64 @@
+use perf_event::events::KProbe;
+use perf_event::Builder;
+use std::io::ErrorKind;
+
+/// Return `probe`, or `None` if this kernel has no kprobe PMU.
+fn available(probe: std::io::Result<KProbe>) -> Option<KProbe> {
+    match probe {
+        Ok(probe) => Some(probe),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("skipping: {}", e);
+            None
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn bad_name() {
+    let error = KProbe::function("do_sys\0openat2").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn count_calls() {
+    let probe = match available(KProbe::function("__x64_sys_getppid")) {
+        Some(probe) => probe,
+        None => return,
+    };
+    assert!(!probe.is_return());
+
+    let mut counter = match Builder::new().kind(probe).build() {
+        Ok(counter) => counter,
+        // Not x86_64, or the symbol has a different name here.
+        Err(e) if e.kind() == ErrorKind::InvalidInput || e.kind() == ErrorKind::NotFound => return,
+        Err(e) => panic!("{}", e),
+    };
+    assert_eq!(
+        counter.event_data().unwrap().to_str(),
+        Ok("__x64_sys_getppid")
+    );
+    counter.enable().unwrap();
+    for _ in 0..10 {
+        unsafe { libc::getppid() };
+    }
+    counter.disable().unwrap();
+    assert!(counter.read().unwrap() >= 10);
+}
+
+#[test]
+fn retprobe() {
+    let probe = match available(KProbe::function_return("do_sys_openat2")) {
+        Some(probe) => probe,
+        None => return,
+    };
+    assert!(probe.is_return());
+}
+
+#[test]
+fn no_event_data() {
+    use perf_event::events::Software;
+
+    let counter = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    assert_eq!(counter.event_data(), None);
+}
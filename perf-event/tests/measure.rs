@@ -0,0 +1,40 @@
+use perf_event::events::Software;
+use perf_event::{Builder, CountAndTime, Group};
+use std::time::Duration;
+
+#[test]
+fn counter_measure_for() {
+    let mut counter = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    let measured = counter.measure_for(Duration::from_millis(20)).unwrap();
+    assert!(measured.elapsed >= Duration::from_millis(20));
+
+    // The counter was left disabled.
+    let after = counter.read().unwrap();
+    std::hint::black_box((0..100_000).sum::<u64>());
+    assert_eq!(counter.read().unwrap(), after);
+}
+
+#[test]
+fn group_measure_for() {
+    let mut group = Group::new().unwrap();
+    let clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let measured = group.measure_for(Duration::from_millis(20)).unwrap();
+    assert!(measured.elapsed >= Duration::from_millis(20));
+    assert!(measured.counts.get(&clock).is_some());
+}
+
+#[test]
+fn count_and_time_scaled() {
+    let cat = |time_enabled, time_running| CountAndTime {
+        count: 300,
+        time_enabled,
+        time_running,
+    };
+    assert_eq!(cat(30, 30).scaled(), Some(300));
+    assert_eq!(cat(30, 10).scaled(), Some(900));
+    assert_eq!(cat(30, 0).scaled(), None);
+}
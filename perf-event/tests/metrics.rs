@@ -0,0 +1,97 @@
+use perf_event::events::Software;
+use perf_event::metrics::{Metric, MetricSet};
+use perf_event::{Builder, Group};
+use std::io;
+
+fn lookup(name: &str) -> Option<f64> {
+    match name {
+        "cycles" => Some(300.0),
+        "instructions" => Some(100.0),
+        "stalled-cycles-frontend" => Some(50.0),
+        "zero" => Some(0.0),
+        _ => None,
+    }
+}
+
+#[test]
+fn formulas() {
+    let eval = |formula| Metric::new("m", formula).unwrap().evaluate_with(lookup);
+    assert_eq!(eval("cycles / instructions"), Some(3.0));
+    assert_eq!(eval("cycles - stalled-cycles-frontend"), Some(250.0));
+    assert_eq!(eval("1 + cycles / instructions * 2"), Some(7.0));
+    assert_eq!(eval("(1 + cycles) / 301"), Some(1.0));
+    assert_eq!(eval("-instructions + 100.5"), Some(0.5));
+    assert_eq!(eval("cycles / zero"), None);
+    assert_eq!(eval("cycles / missing"), None);
+
+    let metric = Metric::new("m", "cycles / instructions - cycles").unwrap();
+    assert_eq!(metric.events(), ["cycles", "instructions"]);
+    assert_eq!(metric.formula(), "cycles / instructions - cycles");
+}
+
+#[test]
+fn bad_formulas() {
+    for formula in [
+        "",
+        "cycles /",
+        "(cycles",
+        "cycles)",
+        "cycles instructions",
+        "a % b",
+    ] {
+        let err = Metric::new("bad", formula).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", formula);
+        assert!(err.to_string().contains("metric `bad`"));
+    }
+}
+
+#[test]
+fn evaluate_counts() {
+    let mut group = Group::new().unwrap();
+    let clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let faults = Builder::new()
+        .group(&mut group)
+        .kind(Software::PAGE_FAULTS)
+        .build()
+        .unwrap();
+
+    let mut metrics = MetricSet::new();
+    metrics
+        .bind("task-clock", &clock)
+        .bind("page-faults", &faults);
+    metrics
+        .add("double-clock", "task-clock * 2")
+        .unwrap()
+        .add("unbound", "cycles + 1")
+        .unwrap();
+
+    group.enable().unwrap();
+    let _pages = vec![1_u8; 1 << 20];
+    group.disable().unwrap();
+
+    let counts = group.read().unwrap();
+    let values = metrics.evaluate(&counts);
+    assert_eq!(values.len(), 2);
+    assert_eq!(
+        values[0],
+        ("double-clock", Some(counts[&clock] as f64 * 2.0))
+    );
+    assert_eq!(values[1], ("unbound", None));
+
+    // Snapshot labels take precedence over bindings.
+    let snapshot = counts.snapshot().label(&faults, "task-clock");
+    let values = metrics.evaluate_snapshot(&snapshot);
+    assert_eq!(
+        values[0],
+        ("double-clock", Some(counts[&faults] as f64 * 2.0))
+    );
+    let values = metrics.evaluate_snapshot(&counts.snapshot());
+    assert_eq!(
+        values[0],
+        ("double-clock", Some(counts[&clock] as f64 * 2.0))
+    );
+}
@@ -0,0 +1,31 @@
+use perf_event::events::Software;
+use perf_event::paranoid::Level;
+use perf_event::Builder;
+
+#[test]
+fn levels() {
+    for value in -1..=3 {
+        assert_eq!(Level::from_value(value).value(), value);
+    }
+    assert_eq!(Level::from_value(-5), Level::Unrestricted);
+    assert_eq!(Level::from_value(4), Level::Disallowed);
+
+    assert!(Level::NoCpuEvents.permits(Level::NoKernel));
+    assert!(!Level::NoKernel.permits(Level::NoCpuEvents));
+    assert!(Level::current().is_ok());
+}
+
+#[test]
+fn required_for() {
+    let builder = Builder::new().kind(Software::TASK_CLOCK);
+    assert_eq!(Level::required_for(&builder), Level::NoKernel);
+
+    let builder = Builder::new().include_kernel().kind(Software::TASK_CLOCK);
+    assert_eq!(Level::required_for(&builder), Level::NoCpuEvents);
+
+    let builder = Builder::new().system_wide_on_cpu(0);
+    assert_eq!(Level::required_for(&builder), Level::NoRawTracepoints);
+    assert!(Level::NoRawTracepoints
+        .instructions()
+        .contains("kernel.perf_event_paranoid=0"));
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "discovery")]
+
+use perf_event::events::{Cache, CacheOp, CacheResult, Event, Hardware, Software, WhichCache};
+
+#[test]
+fn known_names() {
+    assert_eq!(
+        "cycles".parse::<Event>().unwrap(),
+        Event::Hardware(Hardware::CPU_CYCLES)
+    );
+    assert_eq!(
+        Event::parse("CS").unwrap(),
+        Event::Software(Software::CONTEXT_SWITCHES)
+    );
+    assert_eq!(
+        Event::parse("l1-dcache-load-misses").unwrap(),
+        Event::Cache(Cache {
+            which: WhichCache::L1D,
+            operation: CacheOp::READ,
+            result: CacheResult::MISS,
+        })
+    );
+    assert_eq!(
+        Event::parse("dTLB-stores").unwrap(),
+        Event::Cache(Cache {
+            which: WhichCache::DTLB,
+            operation: CacheOp::WRITE,
+            result: CacheResult::ACCESS,
+        })
+    );
+}
+
+#[test]
+fn suggestions() {
+    let err = Event::parse("task-clok").unwrap_err();
+    assert_eq!(err.name(), "task-clok");
+    assert_eq!(err.suggestions()[0], "task-clock");
+
+    let err = Event::parse("LLC-load-mises").unwrap_err();
+    assert_eq!(err.suggestions()[0], "LLC-load-misses");
+
+    let err = Event::parse("zzzzzzzzzzzzzzzz").unwrap_err();
+    assert!(err.suggestions().is_empty());
+    assert_eq!(err.to_string(), "unknown event `zzzzzzzzzzzzzzzz`");
+}
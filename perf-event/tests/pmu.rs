@@ -0,0 +1,97 @@
+use perf_event::events::Pmu;
+use perf_event::Builder;
+use perf_event_open_sys::bindings::{PERF_COUNT_SW_TASK_CLOCK, PERF_TYPE_SOFTWARE};
+use std::io::ErrorKind;
+
+#[test]
+fn software_pmu() {
+    // The software PMU is always registered, under its fixed type number.
+    let pmu = Pmu::named("software").unwrap();
+    assert_eq!(pmu.pmu_type(), PERF_TYPE_SOFTWARE);
+
+    let mut counter = Builder::new()
+        .kind(pmu.config(PERF_COUNT_SW_TASK_CLOCK as u64))
+        .build()
+        .unwrap();
+    counter.enable().unwrap();
+    let sum: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+    std::hint::black_box(sum);
+    counter.disable().unwrap();
+    assert!(counter.read().unwrap() > 0);
+}
+
+#[test]
+fn missing_pmu() {
+    let error = Pmu::named("no_such_pmu").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn on_pmu() {
+    use perf_event::events::{Cache, CacheOp, CacheResult, Hardware, WhichCache};
+
+    let cycles = Hardware::CPU_CYCLES.on_pmu(8);
+    assert_eq!(cycles.pmu_type(), 8);
+    assert_eq!(cycles.config(), 8 << 32 | Hardware::CPU_CYCLES as u64);
+
+    let misses = Cache {
+        which: WhichCache::L1D,
+        operation: CacheOp::READ,
+        result: CacheResult::MISS,
+    }
+    .on_pmu(9);
+    assert_eq!(misses.config(), 9 << 32 | 1 << 16);
+
+    // Where the processor has a single `cpu` PMU, binding to it should
+    // count the same events as the plain generic event.
+    let cpu = match Pmu::named("cpu") {
+        Ok(cpu) => cpu,
+        Err(_) => return,
+    };
+    let instructions = Hardware::INSTRUCTIONS.on_pmu(cpu.pmu_type());
+    match Builder::new().kind(instructions).build() {
+        Ok(mut counter) => {
+            counter.enable().unwrap();
+            std::hint::black_box((0..1_000_000_u64).sum::<u64>());
+            counter.disable().unwrap();
+            assert!(counter.read().unwrap() > 0);
+        }
+        Err(e) => eprintln!("skipping: extended event types unsupported: {}", e),
+    }
+}
+
+#[test]
+#[cfg(feature = "discovery")]
+fn pmu_error_details() {
+    use perf_event::events::PmuEventError;
+
+    // The `msr` PMU, on x86, has a single `event` field in `config`, and
+    // rejects event numbers it doesn't know.
+    let msr = match Pmu::named("msr") {
+        Ok(msr) => msr,
+        Err(_) => return,
+    };
+    let err = Builder::new()
+        .kind(msr.config(0x1000))
+        .any_pid()
+        .one_cpu(0)
+        .build()
+        .unwrap_err();
+    let details = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<PmuEventError>())
+        .unwrap();
+    assert_eq!(details.pmu_name(), "msr");
+    assert_eq!(details.config(), [0x1000, 0, 0]);
+    assert_eq!(details.matching_event(), None);
+    let event = details
+        .format_fields()
+        .iter()
+        .find(|field| field.name() == "event")
+        .unwrap();
+    assert_eq!(event.value(details.config()), 0x1000);
+
+    let message = err.to_string();
+    assert!(message.contains("PMU `msr`"), "{}", message);
+    assert!(message.contains("event=0x1000"), "{}", message);
+}
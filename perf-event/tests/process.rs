@@ -0,0 +1,215 @@
+use perf_event::events::Software;
+#[cfg(feature = "sampling")]
+use perf_event::process::ProcessSampler;
+use perf_event::process::{PidFd, ProcessCounter};
+#[cfg(feature = "sampling")]
+use perf_event::samples::{RecordEvent, SampleType};
+use perf_event::Builder;
+#[cfg(feature = "sampling")]
+use std::io;
+use std::process::Command;
+use std::sync::mpsc;
+#[cfg(feature = "sampling")]
+use std::time::{Duration, Instant};
+
+fn spin() {
+    let mut v: Vec<u32> = (0..200_000).rev().collect();
+    v.sort();
+    std::hint::black_box(v);
+}
+
+#[test]
+fn follows_threads() {
+    let pid = std::process::id() as libc::pid_t;
+    let mut counter = ProcessCounter::observe_process(pid, |tid| {
+        Builder::new()
+            .observe_pid(tid)
+            .kind(Software::TASK_CLOCK)
+            .build()
+    })
+    .unwrap();
+    counter.enable().unwrap();
+    let before = counter.threads();
+
+    // Start a thread, and let it do its work only once we've refreshed and
+    // attached a counter to it.
+    let (go_tx, go_rx) = mpsc::channel();
+    let worker = std::thread::spawn(move || {
+        go_rx.recv().unwrap();
+        spin();
+    });
+    counter.refresh().unwrap();
+    assert!(counter.threads() > before);
+    go_tx.send(()).unwrap();
+    worker.join().unwrap();
+
+    // The worker's counts should survive its exit.
+    let with_worker = counter.read().unwrap();
+    counter.refresh().unwrap();
+    assert_eq!(counter.threads(), before);
+    counter.disable().unwrap();
+    assert!(counter.read().unwrap() >= with_worker);
+    assert!(with_worker > 0);
+}
+
+#[test]
+fn observe_all_threads_of() {
+    let pid = std::process::id() as libc::pid_t;
+    let mut counter = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .observe_all_threads_of(pid)
+        .unwrap();
+    assert_eq!(counter.pid(), pid);
+
+    // The test harness runs each test in its own thread, so there are at
+    // least two.
+    assert!(counter.threads() >= 2);
+
+    counter.enable().unwrap();
+    spin();
+    counter.disable().unwrap();
+    assert!(counter.read().unwrap() > 0);
+}
+
+#[test]
+fn observe_tid() {
+    let tid = unsafe { libc::gettid() };
+    let mut counter = Builder::new()
+        .observe_tid(tid)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    counter.enable().unwrap();
+    spin();
+    counter.disable().unwrap();
+    assert!(counter.read().unwrap() > 0);
+}
+
+#[cfg(feature = "sampling")]
+fn gettid() -> libc::pid_t {
+    unsafe { libc::syscall(libc::SYS_gettid) as libc::pid_t }
+}
+
+/// Drain `sampler`, returning the thread ids of the samples it produced.
+#[cfg(feature = "sampling")]
+fn sampled_tids(sampler: &mut ProcessSampler) -> Vec<u32> {
+    let mut tids = vec![];
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            tids.extend(sample.tid);
+        }
+    }
+    tids
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn sampler_follows_new_threads() {
+    let pid = std::process::id() as libc::pid_t;
+    let mut sampler = ProcessSampler::observe_process(pid, |builder| {
+        builder
+            .kind(Software::TASK_CLOCK)
+            .sample_period(100_000)
+            .sample(SampleType::TID)
+    })
+    .unwrap();
+    sampler.enable().unwrap();
+    assert!(sampler.threads().contains(&gettid()));
+
+    // Start a thread, and let it do its work only once its `FORK` record
+    // has led the sampler to attach to it.
+    let (tid_tx, tid_rx) = mpsc::channel();
+    let (go_tx, go_rx) = mpsc::channel();
+    let worker = std::thread::spawn(move || {
+        tid_tx.send(gettid()).unwrap();
+        go_rx.recv().unwrap();
+        spin();
+    });
+    let tid = tid_rx.recv().unwrap();
+    let start = Instant::now();
+    while !sampler.threads().contains(&tid) {
+        assert!(start.elapsed() < Duration::from_secs(5), "never attached");
+        while sampler.next().is_some() {}
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    go_tx.send(()).unwrap();
+    worker.join().unwrap();
+    sampler.disable().unwrap();
+
+    assert!(sampled_tids(&mut sampler).contains(&(tid as u32)));
+    assert!(!sampler.threads().contains(&tid));
+    assert_eq!(sampler.missed_threads(), 0);
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn inherited_sampler() {
+    let mut sampler = ProcessSampler::inherit(gettid(), |builder| {
+        builder
+            .kind(Software::TASK_CLOCK)
+            .sample_period(100_000)
+            .sample(SampleType::TID)
+    })
+    .unwrap();
+    sampler.enable().unwrap();
+    let tid = std::thread::spawn(|| {
+        spin();
+        gettid()
+    })
+    .join()
+    .unwrap();
+    sampler.disable().unwrap();
+
+    assert!(sampler.threads().is_empty());
+    assert!(sampled_tids(&mut sampler).contains(&(tid as u32)));
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn inherited_sampler_on_every_cpu() {
+    let result = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .inherit(true)
+        .sample_period(100_000)
+        .build_sampler();
+    match result {
+        Ok(_) => {} // Perhaps some future kernel will allow this.
+        Err(e) => {
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert!(e.to_string().contains("one_cpu"));
+        }
+    }
+}
+
+#[test]
+fn observe_pidfd() {
+    let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+    let pid = child.id() as libc::pid_t;
+    let pidfd = PidFd::open(pid).unwrap();
+    assert_eq!(pidfd.pid().unwrap(), Some(pid));
+
+    let counter = Builder::new()
+        .observe_pidfd(&pidfd)
+        .kind(Software::TASK_CLOCK)
+        .build();
+    assert!(counter.is_ok());
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert_eq!(pidfd.pid().unwrap(), None);
+
+    // Once the child is reaped, its pid may belong to some other process,
+    // so we must refuse to observe it.
+    let err = Builder::new()
+        .observe_pidfd(&pidfd)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ESRCH));
+    let err = Builder::new()
+        .observe_pidfd(&pidfd)
+        .build_group()
+        .unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ESRCH));
+}
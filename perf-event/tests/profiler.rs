@@ -0,0 +1,58 @@
+use perf_event::events::Software;
+use perf_event::profiler::SelfProfiler;
+
+fn spin() {
+    let mut v: Vec<u32> = (0..100_000).rev().collect();
+    v.sort();
+    std::hint::black_box(v);
+}
+
+#[test]
+fn regions_across_threads() {
+    let profiler = SelfProfiler::new(vec![Software::TASK_CLOCK.into()]);
+
+    let threads: Vec<_> = (0..3)
+        .map(|_| {
+            let profiler = profiler.clone();
+            std::thread::spawn(move || {
+                for _ in 0..5 {
+                    profiler.region("outer", || profiler.region("inner", spin));
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(profiler.region("main", || 42), 42);
+
+    let regions = profiler.regions();
+    let names: Vec<_> = regions.iter().map(|&(name, _)| name).collect();
+    assert_eq!(names, ["inner", "main", "outer"]);
+    let (inner, outer) = (&regions[0].1, &regions[2].1);
+    assert_eq!(inner.calls, 15);
+    assert_eq!(outer.calls, 15);
+    assert!(inner.counts[0] > 0);
+    assert!(outer.counts[0] >= inner.counts[0]);
+
+    profiler.clear();
+    assert!(profiler.regions().is_empty());
+}
+
+#[test]
+fn several_profilers_on_one_thread() {
+    let a = SelfProfiler::new(vec![Software::TASK_CLOCK.into()]);
+    let b = SelfProfiler::new(vec![Software::PAGE_FAULTS.into()]);
+    for _ in 0..3 {
+        a.region("a", || b.region("b", spin));
+    }
+    assert_eq!(a.regions()[0].1.calls, 3);
+    assert_eq!(b.regions()[0].1.calls, 3);
+    assert!(a.regions()[0].1.counts[0] > 0);
+
+    // A profiler dropped and replaced doesn't confuse the new one.
+    drop(a);
+    let c = SelfProfiler::new(vec![Software::TASK_CLOCK.into()]);
+    c.region("c", spin);
+    assert_eq!(c.regions()[0].1.calls, 1);
+}
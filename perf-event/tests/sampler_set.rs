@@ -0,0 +1,202 @@
+#![cfg(feature = "sampler_set")]
+
+use perf_event::events::Software;
+use perf_event::per_cpu::online_cpus;
+use perf_event::sampler_set::SamplerSet;
+use perf_event::samples::{RecordEvent, SampleType, Sampler};
+use perf_event::{Builder, Group};
+use std::io;
+use std::time::Duration;
+
+fn spin(iterations: u64) -> u64 {
+    (0..iterations).map(std::hint::black_box).sum()
+}
+
+fn clock_sampler() -> Sampler {
+    Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::TIME | SampleType::IP)
+        .build_sampler()
+        .unwrap()
+}
+
+#[test]
+fn unsorted() {
+    let mut set = SamplerSet::new();
+    assert_eq!(set.push(clock_sampler()), 0);
+    assert_eq!(set.push(clock_sampler()), 1);
+    assert!(set.next().is_none());
+
+    set.enable().unwrap();
+    spin(10_000_000);
+    set.disable().unwrap();
+
+    let mut from = [0, 0];
+    while let Some((index, _record)) = set.next() {
+        from[index] += 1;
+    }
+    assert!(from[0] > 0 && from[1] > 0);
+}
+
+#[test]
+fn sorted() {
+    let mut set = SamplerSet::sorted(Duration::from_secs(3600));
+    set.push(clock_sampler());
+    set.push(clock_sampler());
+
+    set.enable().unwrap();
+    spin(10_000_000);
+    set.disable().unwrap();
+
+    // Everything is within the window, until we flush.
+    assert!(set.next().is_none());
+    set.flush();
+
+    let mut times = vec![];
+    let mut from = [0, 0];
+    while let Some((index, record)) = set.next() {
+        let config = set.samplers()[index].config();
+        if let RecordEvent::Sample(sample) = record.parse(config) {
+            assert_eq!(record.time(config), sample.time);
+            times.push(sample.time.unwrap());
+            from[index] += 1;
+        }
+    }
+    assert!(from[0] > 0 && from[1] > 0);
+    assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn sorted_window() {
+    // With no window, records come out as soon as they're read.
+    let mut set = SamplerSet::sorted(Duration::ZERO);
+    set.push(clock_sampler());
+
+    set.enable().unwrap();
+    spin(10_000_000);
+    set.disable().unwrap();
+
+    assert!(set.next().is_some());
+}
+
+#[test]
+fn next_blocking_timeout() {
+    let mut set = SamplerSet::sorted(Duration::from_millis(1));
+    set.push(clock_sampler());
+    assert!(set
+        .next_blocking(Some(Duration::from_millis(20)))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn next_blocking_empty() {
+    // With no timeout, this would wait forever if it polled.
+    let mut set = SamplerSet::new();
+    assert!(set.next_blocking(None).unwrap().is_none());
+}
+
+#[test]
+fn shutdown() {
+    let mut set = SamplerSet::sorted(Duration::from_secs(3600));
+    set.push(clock_sampler());
+    set.push(clock_sampler());
+
+    set.enable().unwrap();
+    spin(10_000_000);
+
+    // Shutting down disables the samplers and releases everything held in
+    // the reordering window.
+    let shutdown = set.shutdown().unwrap();
+    assert_eq!(shutdown.lost.len(), 2);
+    assert_eq!(shutdown.configs.len(), 2);
+    assert_eq!(shutdown.total_lost(), 0);
+
+    let mut from = [0, 0];
+    let mut times = vec![];
+    for (index, record) in &shutdown.records {
+        let config = &shutdown.configs[*index];
+        if let RecordEvent::Sample(sample) = record.parse(config) {
+            times.push(sample.time.unwrap());
+            from[*index] += 1;
+        }
+    }
+    assert!(from[0] > 0 && from[1] > 0);
+    assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn spawn_pinned() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    let cpu = online_cpus().unwrap()[0];
+    let mut set = SamplerSet::new();
+    assert_eq!(set.push_on_cpu(clock_sampler(), cpu), 0);
+    assert_eq!(set.cpu(0), Some(cpu));
+    set.enable().unwrap();
+
+    let samples = Arc::new(AtomicU64::new(0));
+    let counted = samples.clone();
+    let consumers = set
+        .spawn_pinned(move |index, sampler, record| {
+            assert_eq!(index, 0);
+            assert_eq!(unsafe { libc::sched_getcpu() }, cpu as i32);
+            if let RecordEvent::Sample(_) = record.parse(sampler.config()) {
+                counted.fetch_add(1, Ordering::Relaxed);
+            }
+        })
+        .unwrap();
+    spin(10_000_000);
+    let samplers = consumers.stop().unwrap();
+    assert_eq!(samplers.len(), 1);
+    assert!(samples.load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn on_cpus() {
+    let set = Builder::new()
+        .kind(Software::CPU_CLOCK)
+        .include_kernel()
+        .sample_period(100_000)
+        .sample(SampleType::CPU | SampleType::TID)
+        .build_sampler_on_cpus();
+
+    // System-wide sampling needs privileges the test may not have.
+    let mut set = match set {
+        Ok(set) => set,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            eprintln!("skipping: {}", e);
+            return;
+        }
+        Err(e) => panic!("couldn't open per-CPU samplers: {}", e),
+    };
+    let cpus = online_cpus().unwrap();
+    assert_eq!(set.samplers().len(), cpus.len());
+    assert_eq!(set.cpu(0), Some(cpus[0]));
+
+    set.enable().unwrap();
+    spin(10_000_000);
+    set.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some((index, record)) = set.next() {
+        if let RecordEvent::Sample(sample) = record.parse(set.samplers()[index].config()) {
+            assert_eq!(sample.cpu, Some(cpus[index] as u32));
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn on_cpus_rejects_group() {
+    let mut group = Group::new().unwrap();
+    let err = Builder::new()
+        .group(&mut group)
+        .kind(Software::CPU_CLOCK)
+        .build_sampler_on_cpus()
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,2020 @@
+#![cfg(feature = "sampling")]
+
+use perf_event::events::Software;
+use perf_event::samples::{BufferSize, ParseConfig, Record, RecordEvent, SampleType};
+use perf_event::Builder;
+use perf_event_open_sys::bindings::PERF_RECORD_SAMPLE;
+use std::borrow::Cow;
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn spin(iterations: u64) -> u64 {
+    (0..iterations).map(std::hint::black_box).sum()
+}
+
+#[test]
+fn next() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    assert!(sampler.next().is_none());
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if record.ty == PERF_RECORD_SAMPLE {
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+    assert!(sampler.next().is_none());
+}
+
+#[test]
+fn next_blocking_times_out() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+
+    let start = Instant::now();
+    let record = sampler
+        .next_blocking(Some(Duration::from_millis(50)))
+        .unwrap();
+    assert!(record.is_none());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn next_blocking_wakes() {
+    // Sample a worker thread, so that this thread can block.
+    let (tid_tx, tid_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let worker = thread::spawn(move || {
+        tid_tx.send(unsafe { libc::gettid() }).unwrap();
+        while done_rx.try_recv().is_err() {
+            spin(100_000);
+        }
+    });
+
+    let mut sampler = Builder::new()
+        .observe_pid(tid_rx.recv().unwrap())
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .build_sampler()
+        .unwrap();
+    sampler.enable().unwrap();
+
+    let record = sampler
+        .next_blocking(Some(Duration::from_secs(10)))
+        .unwrap();
+    done_tx.send(()).unwrap();
+    worker.join().unwrap();
+
+    assert_eq!(record.unwrap().ty, PERF_RECORD_SAMPLE);
+}
+
+#[test]
+fn parse_sample() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::IP | SampleType::TID | SampleType::TIME)
+        .sample(SampleType::CPU | SampleType::PERIOD | SampleType::IDENTIFIER)
+        .build_sampler()
+        .unwrap();
+    let id = sampler.id();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut last_time = 0;
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        let sample = match record.parse(sampler.config()) {
+            RecordEvent::Sample(sample) => sample,
+            _ => continue,
+        };
+        samples += 1;
+        assert_eq!(sample.id, Some(id));
+        assert_ne!(sample.ip, Some(0));
+        assert_eq!(sample.pid, Some(std::process::id()));
+        assert_eq!(sample.tid, Some(unsafe { libc::gettid() } as u32));
+        assert!(sample.time.unwrap() >= last_time);
+        last_time = sample.time.unwrap();
+        assert!(sample.cpu.is_some());
+        assert!(sample.period.unwrap() > 0);
+        assert_eq!(sample.addr, None);
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn parse_mmap() {
+    let mut sampler = Builder::new()
+        .kind(Software::DUMMY)
+        .mmap(true)
+        .build_sampler()
+        .unwrap();
+
+    let exe = std::fs::File::open("/proc/self/exe").unwrap();
+    sampler.enable().unwrap();
+    let addr = unsafe {
+        use std::os::unix::io::AsRawFd;
+        libc::mmap(
+            std::ptr::null_mut(),
+            4096,
+            libc::PROT_READ | libc::PROT_EXEC,
+            libc::MAP_PRIVATE,
+            exe.as_raw_fd(),
+            0,
+        )
+    };
+    assert_ne!(addr, libc::MAP_FAILED);
+    sampler.disable().unwrap();
+
+    let exe_path = std::fs::read_link("/proc/self/exe").unwrap();
+    let mut found = false;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Mmap(mmap) = record.parse(sampler.config()) {
+            if mmap.addr == addr as u64 {
+                assert_eq!(mmap.len, 4096);
+                assert_eq!(mmap.pgoff, 0);
+                assert_eq!(mmap.pid, std::process::id());
+                assert_eq!(
+                    mmap.filename,
+                    exe_path.as_os_str().to_str().unwrap().as_bytes()
+                );
+                found = true;
+            }
+        }
+    }
+    unsafe { libc::munmap(addr, 4096) };
+    assert!(found);
+}
+
+#[test]
+fn parse_unknown() {
+    let record = Record {
+        ty: 0xffff,
+        misc: 0,
+        data: vec![],
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(record.parse(&config), RecordEvent::Unknown);
+}
+
+#[test]
+fn parse_mmap2() {
+    use perf_event::samples::Mmap2File;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut sampler = Builder::new()
+        .kind(Software::DUMMY)
+        .mmap(true)
+        .mmap2(true)
+        .build_sampler()
+        .unwrap();
+
+    let exe = std::fs::File::open("/proc/self/exe").unwrap();
+    let metadata = exe.metadata().unwrap();
+    sampler.enable().unwrap();
+    let addr = unsafe {
+        use std::os::unix::io::AsRawFd;
+        libc::mmap(
+            std::ptr::null_mut(),
+            4096,
+            libc::PROT_READ | libc::PROT_EXEC,
+            libc::MAP_PRIVATE,
+            exe.as_raw_fd(),
+            0,
+        )
+    };
+    assert_ne!(addr, libc::MAP_FAILED);
+    sampler.disable().unwrap();
+
+    let mut found = false;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Mmap2(mmap) = record.parse(sampler.config()) {
+            if mmap.addr == addr as u64 {
+                assert_eq!(mmap.len, 4096);
+                assert_eq!(mmap.pid, std::process::id());
+                assert_eq!(mmap.prot, (libc::PROT_READ | libc::PROT_EXEC) as u32);
+                assert_eq!(
+                    mmap.flags & libc::MAP_PRIVATE as u32,
+                    libc::MAP_PRIVATE as u32
+                );
+                match mmap.file {
+                    Mmap2File::Inode { ino, .. } => assert_eq!(ino, metadata.ino()),
+                    Mmap2File::BuildId(id) => panic!("unexpected build id {:?}", id),
+                }
+                found = true;
+            }
+        }
+    }
+    unsafe { libc::munmap(addr, 4096) };
+    assert!(found);
+}
+
+#[test]
+fn parse_mmap2_build_id() {
+    use perf_event::samples::{Mmap2, Mmap2File};
+    use perf_event_open_sys::bindings::{PERF_RECORD_MISC_MMAP_BUILD_ID, PERF_RECORD_MMAP2};
+
+    let mut data = 10_u32.to_ne_bytes().to_vec();
+    data.extend(11_u32.to_ne_bytes());
+    for word in [0x7f00_0000_u64, 0x2000, 0x1000] {
+        data.extend(word.to_ne_bytes());
+    }
+    data.extend([3, 0, 0, 0]);
+    data.extend([0xab, 0xcd, 0xef]);
+    data.extend([0; 17]);
+    data.extend((libc::PROT_READ as u32).to_ne_bytes());
+    data.extend((libc::MAP_SHARED as u32).to_ne_bytes());
+    data.extend(b"/lib/x.so\0\0\0\0\0\0\0");
+    let record = Record {
+        ty: PERF_RECORD_MMAP2,
+        misc: PERF_RECORD_MISC_MMAP_BUILD_ID as u16,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::Mmap2(Mmap2 {
+            pid: 10,
+            tid: 11,
+            addr: 0x7f00_0000,
+            len: 0x2000,
+            pgoff: 0x1000,
+            file: Mmap2File::BuildId(vec![0xab, 0xcd, 0xef]),
+            prot: libc::PROT_READ as u32,
+            flags: libc::MAP_SHARED as u32,
+            filename: b"/lib/x.so".to_vec(),
+        })
+    );
+}
+
+#[test]
+fn parse_throttle() {
+    use perf_event::samples::Throttle;
+    use perf_event_open_sys::bindings::{PERF_RECORD_THROTTLE, PERF_RECORD_UNTHROTTLE};
+
+    let mut data = vec![];
+    for word in [1_000_u64, 7, 5] {
+        data.extend(word.to_ne_bytes());
+    }
+    let throttle = Throttle {
+        time: 1_000,
+        id: 7,
+        stream_id: 5,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+
+    let record = Record {
+        ty: PERF_RECORD_THROTTLE,
+        misc: 0,
+        data: data.clone(),
+    };
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::Throttle(throttle.clone())
+    );
+
+    let record = Record {
+        ty: PERF_RECORD_UNTHROTTLE,
+        misc: 0,
+        data,
+    };
+    assert_eq!(record.parse(&config), RecordEvent::Unthrottle(throttle));
+
+    // Too short for the stream id.
+    let record = Record {
+        ty: PERF_RECORD_THROTTLE,
+        misc: 0,
+        data: vec![0; 16],
+    };
+    assert!(record.try_parse(&config).is_err());
+}
+
+#[test]
+fn parse_namespaces() {
+    use perf_event::samples::{NamespaceId, Namespaces};
+    use perf_event_open_sys::bindings::PERF_RECORD_NAMESPACES;
+
+    let mut data = 10_u32.to_ne_bytes().to_vec();
+    data.extend(11_u32.to_ne_bytes());
+    for word in [2_u64, 4, 0xf000_0001, 4, 0xf000_0002] {
+        data.extend(word.to_ne_bytes());
+    }
+    let record = Record {
+        ty: PERF_RECORD_NAMESPACES,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::Namespaces(Namespaces {
+            pid: 10,
+            tid: 11,
+            namespaces: vec![
+                NamespaceId {
+                    dev: 4,
+                    inode: 0xf000_0001
+                },
+                NamespaceId {
+                    dev: 4,
+                    inode: 0xf000_0002
+                },
+            ],
+        })
+    );
+}
+
+#[test]
+fn parse_ksymbol() {
+    use perf_event::samples::Ksymbol;
+    use perf_event_open_sys::bindings::{
+        PERF_RECORD_KSYMBOL, PERF_RECORD_KSYMBOL_FLAGS_UNREGISTER, PERF_RECORD_KSYMBOL_TYPE_BPF,
+    };
+
+    let mut data = 0xffff_ffff_c000_0000_u64.to_ne_bytes().to_vec();
+    data.extend(0x80_u32.to_ne_bytes());
+    data.extend((PERF_RECORD_KSYMBOL_TYPE_BPF as u16).to_ne_bytes());
+    data.extend((PERF_RECORD_KSYMBOL_FLAGS_UNREGISTER as u16).to_ne_bytes());
+    data.extend(b"bpf_prog_f00d\0\0\0");
+    let record = Record {
+        ty: PERF_RECORD_KSYMBOL,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    let ksymbol = match record.parse(&config) {
+        RecordEvent::Ksymbol(ksymbol) => ksymbol,
+        other => panic!("expected Ksymbol, got {:?}", other),
+    };
+    assert_eq!(
+        ksymbol,
+        Ksymbol {
+            addr: 0xffff_ffff_c000_0000,
+            len: 0x80,
+            ksym_type: PERF_RECORD_KSYMBOL_TYPE_BPF as u16,
+            flags: PERF_RECORD_KSYMBOL_FLAGS_UNREGISTER as u16,
+            name: b"bpf_prog_f00d".to_vec(),
+        }
+    );
+    assert!(ksymbol.unregistered());
+}
+
+#[test]
+fn parse_bpf_event() {
+    use perf_event::samples::BpfEvent;
+    use perf_event_open_sys::bindings::{PERF_BPF_EVENT_PROG_LOAD, PERF_RECORD_BPF_EVENT};
+
+    let mut data = (PERF_BPF_EVENT_PROG_LOAD as u16).to_ne_bytes().to_vec();
+    data.extend(0_u16.to_ne_bytes());
+    data.extend(42_u32.to_ne_bytes());
+    data.extend([1, 2, 3, 4, 5, 6, 7, 8]);
+    let record = Record {
+        ty: PERF_RECORD_BPF_EVENT,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::BpfEvent(BpfEvent {
+            event_type: PERF_BPF_EVENT_PROG_LOAD as u16,
+            flags: 0,
+            id: 42,
+            tag: [1, 2, 3, 4, 5, 6, 7, 8],
+        })
+    );
+}
+
+#[test]
+fn namespaces_records() {
+    // A new thread gets a NAMESPACES record.
+    let mut sampler = Builder::new()
+        .kind(Software::DUMMY)
+        .namespaces(true)
+        .build_sampler()
+        .unwrap();
+    sampler.enable().unwrap();
+    std::thread::spawn(|| ()).join().unwrap();
+    sampler.disable().unwrap();
+
+    let mut found = false;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Namespaces(namespaces) = record.parse(sampler.config()) {
+            assert_eq!(namespaces.pid, std::process::id());
+            assert!(!namespaces.namespaces.is_empty());
+            found = true;
+        }
+    }
+    assert!(found);
+}
+
+#[test]
+fn parse_truncated() {
+    // A call chain claiming more addresses than the record holds.
+    let words: [u64; 3] = [1 << 60, 0x1001, 0x1002];
+    let record = Record {
+        ty: PERF_RECORD_SAMPLE,
+        misc: 0,
+        data: words.iter().flat_map(|w| w.to_ne_bytes()).collect(),
+    };
+    let config = ParseConfig::new(SampleType::CALLCHAIN);
+    let err = record.try_parse(&config).unwrap_err();
+    assert_eq!(err.record_type(), PERF_RECORD_SAMPLE);
+    assert_eq!(err.offset(), 24);
+    assert_eq!(err.needed(), 8);
+    assert_eq!(err.record_len(), 24);
+    assert_eq!(record.time(&config), None);
+
+    // A sample missing the time its configuration promises.
+    let record = Record {
+        ty: PERF_RECORD_SAMPLE,
+        misc: 0,
+        data: 0x1234_u64.to_ne_bytes().to_vec(),
+    };
+    let config = ParseConfig::new(SampleType::IP | SampleType::TIME);
+    let err = record.try_parse(&config).unwrap_err();
+    assert_eq!((err.offset(), err.needed()), (8, 8));
+    assert_eq!(record.time(&config), None);
+
+    let config = ParseConfig::new(SampleType::IP);
+    assert!(record.try_parse(&config).is_ok());
+}
+
+#[test]
+#[should_panic(expected = "malformed perf record")]
+fn parse_truncated_panics() {
+    let record = Record {
+        ty: PERF_RECORD_SAMPLE,
+        misc: 0,
+        data: vec![0; 4],
+    };
+    record.parse(&ParseConfig::new(SampleType::IP));
+}
+
+#[test]
+fn callchain_sections() {
+    use perf_event::samples::CallchainContext;
+    use perf_event_open_sys::bindings::{PERF_CONTEXT_KERNEL, PERF_CONTEXT_USER};
+
+    let words: [u64; 7] = [
+        6,
+        PERF_CONTEXT_KERNEL,
+        0xffff1,
+        0xffff2,
+        PERF_CONTEXT_USER,
+        0x1001,
+        0x1002,
+    ];
+    let record = Record {
+        ty: PERF_RECORD_SAMPLE,
+        misc: 0,
+        data: words.iter().flat_map(|w| w.to_ne_bytes()).collect(),
+    };
+    let config = ParseConfig::new(SampleType::CALLCHAIN);
+    let sample = match record.parse(&config) {
+        RecordEvent::Sample(sample) => sample,
+        other => panic!("unexpected record: {:?}", other),
+    };
+
+    let callchain = sample.callchain.unwrap();
+    let contexts: Vec<_> = callchain.sections().iter().map(|s| s.context).collect();
+    assert_eq!(contexts, [CallchainContext::Kernel, CallchainContext::User]);
+    assert_eq!(callchain.kernel().collect::<Vec<_>>(), [0xffff1, 0xffff2]);
+    assert_eq!(callchain.user().collect::<Vec<_>>(), [0x1001, 0x1002]);
+}
+
+#[test]
+fn callchain_sampled() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::IP | SampleType::CALLCHAIN)
+        .build_sampler()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            let callchain = sample.callchain.unwrap();
+            let user: Vec<u64> = callchain.user().collect();
+            if !user.is_empty() {
+                assert_eq!(user[0], sample.ip.unwrap());
+                samples += 1;
+            }
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn sample_frequency() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_frequency(1000)
+        .sample(SampleType::PERIOD)
+        .build_sampler()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            // The kernel chooses the period to reach the frequency.
+            assert!(sample.period.unwrap() > 0);
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn period_and_frequency_conflict() {
+    let err = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample_frequency(1000)
+        .build_sampler()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn set_sample_period() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(10_000_000)
+        .build_sampler()
+        .unwrap();
+    sampler.set_sample_period(100_000).unwrap();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    // Clock events' samples report the original period, whatever the
+    // current one is, so count samples instead.
+    let nanos = sampler.read().unwrap();
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(_) = record.parse(sampler.config()) {
+            samples += 1;
+        }
+    }
+    assert!(
+        samples > 2 * (nanos / 10_000_000 + 1),
+        "{} samples in {}ns",
+        samples,
+        nanos
+    );
+
+    let err = sampler.set_sample_period(0).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+}
+
+#[test]
+fn set_sample_period_not_sampling() {
+    let mut counter = Builder::new().kind(Software::TASK_CLOCK).build().unwrap();
+    let err = counter.set_sample_period(1000).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+}
+
+/// Return the ids of the events whose samples are in `sampler`'s buffer.
+fn sample_ids(sampler: &mut perf_event::samples::Sampler) -> Vec<u64> {
+    let mut ids = vec![];
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            ids.push(sample.id.unwrap());
+        }
+    }
+    ids
+}
+
+#[test]
+fn output_to() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::ID)
+        .build_sampler()
+        .unwrap();
+    let mut clock = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::ID)
+        .output_to(&sampler)
+        .build()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    clock.enable().unwrap();
+    spin(10_000_000);
+    clock.disable().unwrap();
+    sampler.disable().unwrap();
+
+    let ids = sample_ids(&mut sampler);
+    assert!(ids.contains(&sampler.id()));
+    assert!(ids.contains(&clock.id()));
+}
+
+#[test]
+fn redirect_output() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::ID)
+        .build_sampler()
+        .unwrap();
+    let mut clock = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::ID)
+        .build()
+        .unwrap();
+    sampler.redirect_output(&clock).unwrap();
+
+    clock.enable().unwrap();
+    spin(10_000_000);
+    clock.disable().unwrap();
+
+    let ids = sample_ids(&mut sampler);
+    assert!(!ids.is_empty());
+    assert!(ids.iter().all(|&id| id == clock.id()));
+
+    // A sampler's counter already has a buffer of its own.
+    let other = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::ID)
+        .build_sampler()
+        .unwrap();
+    assert!(sampler.redirect_output(&other).is_err());
+}
+
+#[test]
+fn pause_and_resume() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+
+    sampler.pause().unwrap();
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+    assert!(sampler.read().unwrap() > 0);
+    assert!(sampler.next().is_none());
+
+    sampler.resume().unwrap();
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(_) = record.parse(sampler.config()) {
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn flight_recorder() {
+    let mut recorder = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(10_000)
+        .sample(SampleType::TIME)
+        .overwrite(true)
+        .build_sampler()
+        .unwrap();
+    assert!(recorder.snapshot().unwrap().is_empty());
+
+    // Run long enough to fill the buffer several times over.
+    recorder.enable().unwrap();
+    while recorder.read().unwrap() < 200_000_000 {
+        spin(1_000_000);
+    }
+    recorder.disable().unwrap();
+    assert!(recorder.next().is_none());
+
+    let snapshot = recorder.snapshot().unwrap();
+    let times: Vec<u64> = snapshot
+        .iter()
+        .filter_map(|record| match record.parse(recorder.config()) {
+            RecordEvent::Sample(sample) => sample.time,
+            _ => None,
+        })
+        .collect();
+    assert!(times.len() > 1000, "only {} samples", times.len());
+    assert!(times.windows(2).all(|pair| pair[0] >= pair[1]));
+
+    // Records stay in the buffer.
+    assert_eq!(recorder.snapshot().unwrap(), snapshot);
+}
+
+#[test]
+fn snapshot_needs_overwrite() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    let err = sampler.snapshot().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn parse_aux() {
+    use perf_event::samples::Aux;
+    use perf_event_open_sys::bindings::{PERF_AUX_FLAG_TRUNCATED, PERF_RECORD_AUX};
+
+    let words: [u64; 3] = [0x10000, 0x2000, PERF_AUX_FLAG_TRUNCATED as u64];
+    let record = Record {
+        ty: PERF_RECORD_AUX,
+        misc: 0,
+        data: words.iter().flat_map(|w| w.to_ne_bytes()).collect(),
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    let aux = match record.parse(&config) {
+        RecordEvent::Aux(aux) => aux,
+        other => panic!("expected Aux, got {:?}", other),
+    };
+    assert_eq!(
+        aux,
+        Aux {
+            aux_offset: 0x10000,
+            aux_size: 0x2000,
+            flags: PERF_AUX_FLAG_TRUNCATED as u64,
+        }
+    );
+    assert!(aux.truncated());
+}
+
+#[test]
+fn parse_aux_output_hw_id() {
+    use perf_event_open_sys::bindings::PERF_RECORD_AUX_OUTPUT_HW_ID;
+
+    let record = Record {
+        ty: PERF_RECORD_AUX_OUTPUT_HW_ID,
+        misc: 0,
+        data: 3_u64.to_ne_bytes().to_vec(),
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(record.parse(&config), RecordEvent::AuxOutputHwId(3));
+}
+
+#[test]
+fn parse_itrace_start() {
+    use perf_event::samples::ItraceStart;
+    use perf_event_open_sys::bindings::PERF_RECORD_ITRACE_START;
+
+    let mut data = 1234_u32.to_ne_bytes().to_vec();
+    data.extend(5678_u32.to_ne_bytes());
+    let record = Record {
+        ty: PERF_RECORD_ITRACE_START,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::ItraceStart(ItraceStart {
+            pid: 1234,
+            tid: 5678
+        })
+    );
+}
+
+#[test]
+fn aux_sampler_needs_aux_pmu() {
+    // Software events have no AUX area.
+    let err = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .build_aux_sampler(4)
+        .unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EOPNOTSUPP));
+
+    let err = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .build_aux_sampler(3)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+/// Return true if `sampler` would wake a reader right now.
+fn readable(sampler: &perf_event::samples::Sampler) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let mut pollfd = libc::pollfd {
+        fd: sampler.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    assert!(unsafe { libc::poll(&mut pollfd, 1, 0) } >= 0);
+    pollfd.revents & libc::POLLIN != 0
+}
+
+#[test]
+fn wakeup_settings() {
+    let run = |builder: Builder| {
+        let mut sampler = builder
+            .kind(Software::TASK_CLOCK)
+            .sample_period(100_000)
+            .build_sampler()
+            .unwrap();
+        sampler.enable().unwrap();
+        spin(10_000_000);
+        sampler.disable().unwrap();
+        let awake = readable(&sampler);
+        assert!(sampler.next().is_some());
+        awake
+    };
+
+    // By default, every sample wakes the reader.
+    assert!(run(Builder::new()));
+
+    // Far fewer samples than these thresholds arrive.
+    assert!(!run(Builder::new().wakeup_events(1_000_000)));
+    assert!(!run(Builder::new().wakeup_watermark(60_000)));
+    assert!(run(Builder::new()
+        .wakeup_watermark(60_000)
+        .wakeup_events(1)));
+}
+
+#[test]
+fn aux_watermark() {
+    // Software events have no AUX area, but the kernel still accepts the
+    // setting.
+    Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .aux_watermark(4096)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+}
+
+#[test]
+fn aux_output() {
+    // Software events can't send their output to an AUX area, and there's
+    // no tracing event leading a group to send it to anyway.
+    let err = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .aux_output(true)
+        .build()
+        .unwrap_err();
+    let errno = err.raw_os_error().unwrap();
+    assert!(errno == libc::EOPNOTSUPP || errno == libc::EINVAL);
+}
+
+#[test]
+fn comm_and_fork() {
+    let mut sampler = Builder::new()
+        .kind(Software::DUMMY)
+        .comm(true)
+        .task(true)
+        .build_sampler()
+        .unwrap();
+
+    let mut old_name = [0_u8; 16];
+    unsafe { libc::prctl(libc::PR_GET_NAME, old_name.as_mut_ptr()) };
+
+    sampler.enable().unwrap();
+    unsafe { libc::prctl(libc::PR_SET_NAME, b"comm-test\0".as_ptr()) };
+    let worker = thread::spawn(|| unsafe { libc::gettid() } as u32);
+    let worker_tid = worker.join().unwrap();
+    sampler.disable().unwrap();
+    unsafe { libc::prctl(libc::PR_SET_NAME, old_name.as_ptr()) };
+
+    let pid = std::process::id();
+    let tid = unsafe { libc::gettid() } as u32;
+    let (mut forked, mut named) = (false, false);
+    while let Some(record) = sampler.next() {
+        match record.parse(sampler.config()) {
+            RecordEvent::Fork(task) if task.tid == worker_tid => {
+                assert_eq!((task.pid, task.ppid, task.ptid), (pid, pid, tid));
+                forked = true;
+            }
+            RecordEvent::Comm(comm) if comm.tid == tid => {
+                assert_eq!(comm.pid, pid);
+                assert_eq!(comm.comm, b"comm-test");
+                assert!(!comm.exec);
+                named = true;
+            }
+            _ => {}
+        }
+    }
+    assert!(forked && named);
+}
+
+#[test]
+fn parse_exit() {
+    use perf_event::samples::Task;
+    use perf_event_open_sys::bindings::PERF_RECORD_EXIT;
+
+    let mut data = vec![];
+    for word in [10_u32, 1, 11, 10] {
+        data.extend(word.to_ne_bytes());
+    }
+    data.extend(123456_u64.to_ne_bytes());
+    let record = Record {
+        ty: PERF_RECORD_EXIT,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::Exit(Task {
+            pid: 10,
+            ppid: 1,
+            tid: 11,
+            ptid: 10,
+            time: 123456,
+        })
+    );
+}
+
+#[test]
+fn context_switch() {
+    let mut sampler = Builder::new()
+        .kind(Software::DUMMY)
+        .context_switch(true)
+        .build_sampler()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    thread::sleep(Duration::from_millis(1));
+    sampler.disable().unwrap();
+
+    let mut switches = vec![];
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Switch(switch) = record.parse(sampler.config()) {
+            assert_eq!(switch.next_prev, None);
+            switches.push(switch);
+        }
+    }
+
+    // Sleeping blocks, so we're switched out without being preempted, and
+    // then switched back in.
+    let out = switches.iter().position(|s| s.out && !s.preempted).unwrap();
+    assert!(switches[out..].iter().any(|s| !s.out));
+}
+
+#[test]
+fn parse_switch_cpu_wide() {
+    use perf_event::samples::Switch;
+    use perf_event_open_sys::bindings::{
+        PERF_RECORD_MISC_SWITCH_OUT, PERF_RECORD_MISC_SWITCH_OUT_PREEMPT,
+        PERF_RECORD_SWITCH_CPU_WIDE,
+    };
+
+    let mut data = 42_u32.to_ne_bytes().to_vec();
+    data.extend(43_u32.to_ne_bytes());
+    let record = Record {
+        ty: PERF_RECORD_SWITCH_CPU_WIDE,
+        misc: (PERF_RECORD_MISC_SWITCH_OUT | PERF_RECORD_MISC_SWITCH_OUT_PREEMPT) as u16,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::Switch(Switch {
+            out: true,
+            preempted: true,
+            next_prev: Some((42, 43)),
+        })
+    );
+}
+
+#[test]
+fn lost_records() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(10_000)
+        .sample(SampleType::IP | SampleType::TID | SampleType::TIME)
+        .build_sampler()
+        .unwrap();
+
+    // Overflow the ring buffer, then read it, making room for the kernel's
+    // report of what it dropped, and then take a few more samples.
+    sampler.enable().unwrap();
+    while sampler.read().unwrap() < 200_000_000 {
+        spin(1_000_000);
+    }
+    let mut lost = 0;
+    let mut drain = |sampler: &mut perf_event::samples::Sampler| {
+        while let Some(record) = sampler.next() {
+            if let RecordEvent::Lost(record) = record.parse(sampler.config()) {
+                assert_eq!(record.id, sampler.id());
+                lost += record.lost;
+            }
+        }
+    };
+    drain(&mut sampler);
+    spin(10_000_000);
+    sampler.disable().unwrap();
+    drain(&mut sampler);
+
+    assert!(lost > 0);
+    assert_eq!(sampler.lost_records(), lost);
+}
+
+#[test]
+fn parse_lost_samples() {
+    use perf_event_open_sys::bindings::PERF_RECORD_LOST_SAMPLES;
+
+    let record = Record {
+        ty: PERF_RECORD_LOST_SAMPLES,
+        misc: 0,
+        data: 17_u64.to_ne_bytes().to_vec(),
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(record.parse(&config), RecordEvent::LostSamples(17));
+}
+
+#[test]
+fn parse_read() {
+    use perf_event::samples::{ReadFormat, ReadValue};
+    use perf_event_open_sys::bindings::PERF_RECORD_READ;
+
+    // A single event's value comes before its times and id.
+    let mut data = 10_u32.to_ne_bytes().to_vec();
+    data.extend(11_u32.to_ne_bytes());
+    for word in [7_u64, 1000, 100] {
+        data.extend(word.to_ne_bytes());
+    }
+    let record = Record {
+        ty: PERF_RECORD_READ,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty())
+        .with_read_format(ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::ID);
+    let read = match record.parse(&config) {
+        RecordEvent::Read(read) => read,
+        other => panic!("expected Read, got {:?}", other),
+    };
+    assert_eq!((read.pid, read.tid), (10, 11));
+    assert_eq!(read.values.time_enabled, Some(1000));
+    assert_eq!(read.values.time_running, None);
+    assert_eq!(
+        read.values.values,
+        vec![ReadValue {
+            value: 7,
+            id: Some(100)
+        }]
+    );
+}
+
+#[test]
+fn parse_group_read() {
+    use perf_event::samples::{ReadFormat, ReadValue, ReadValues};
+    use perf_event_open_sys::bindings::PERF_RECORD_READ;
+
+    let mut data = 10_u32.to_ne_bytes().to_vec();
+    data.extend(11_u32.to_ne_bytes());
+    for word in [2_u64, 1000, 500, 7, 100, 8, 200] {
+        data.extend(word.to_ne_bytes());
+    }
+    let record = Record {
+        ty: PERF_RECORD_READ,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty()).with_read_format(
+        ReadFormat::GROUP
+            | ReadFormat::ID
+            | ReadFormat::TOTAL_TIME_ENABLED
+            | ReadFormat::TOTAL_TIME_RUNNING,
+    );
+    let read = match record.parse(&config) {
+        RecordEvent::Read(read) => read,
+        other => panic!("expected Read, got {:?}", other),
+    };
+    assert_eq!((read.pid, read.tid), (10, 11));
+    assert_eq!(
+        read.values,
+        ReadValues {
+            time_enabled: Some(1000),
+            time_running: Some(500),
+            values: vec![
+                ReadValue {
+                    value: 7,
+                    id: Some(100)
+                },
+                ReadValue {
+                    value: 8,
+                    id: Some(200)
+                },
+            ],
+        }
+    );
+
+    let counts = read.values.to_counts(&config).unwrap();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts.time_enabled(), 1000);
+    // As with `Group::read`, the `Counts` skips the placeholder leader.
+    assert_eq!(counts.iter().collect::<Vec<_>>(), vec![(200, &8)]);
+
+    // A real leader is included.
+    let counts = read
+        .values
+        .to_counts(&config.with_counts_leader(true))
+        .unwrap();
+    assert_eq!(
+        counts.iter().collect::<Vec<_>>(),
+        vec![(100, &7), (200, &8)]
+    );
+}
+
+#[test]
+fn sample_cgroup() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::CGROUP | SampleType::TIME | SampleType::PERIOD)
+        .cgroup(true)
+        .build_sampler()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut cgroups = vec![];
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            assert!(sample.time.is_some());
+            assert_eq!(sample.period, Some(100_000));
+            cgroups.push(sample.cgroup.unwrap());
+        }
+    }
+    assert!(!cgroups.is_empty());
+    assert!(cgroups.iter().all(|&id| id != 0 && id == cgroups[0]));
+}
+
+#[test]
+fn parse_cgroup() {
+    use perf_event::samples::Cgroup;
+    use perf_event_open_sys::bindings::PERF_RECORD_CGROUP;
+
+    let mut data = 77_u64.to_ne_bytes().to_vec();
+    data.extend(b"/a/b\0\0\0\0");
+    let record = Record {
+        ty: PERF_RECORD_CGROUP,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::Cgroup(Cgroup {
+            id: 77,
+            path: b"/a/b".to_vec(),
+        })
+    );
+}
+
+#[test]
+fn parse_text_poke() {
+    use perf_event::samples::TextPoke;
+    use perf_event_open_sys::bindings::PERF_RECORD_TEXT_POKE;
+
+    let mut data = 0xffff_ffff_8100_0000_u64.to_ne_bytes().to_vec();
+    data.extend(2_u16.to_ne_bytes());
+    data.extend(5_u16.to_ne_bytes());
+    data.extend([0x66, 0x90]);
+    data.extend([0xe9, 1, 2, 3, 4]);
+    data.push(0);
+    let record = Record {
+        ty: PERF_RECORD_TEXT_POKE,
+        misc: 0,
+        data,
+    };
+    let config = ParseConfig::new(SampleType::empty());
+    assert_eq!(
+        record.parse(&config),
+        RecordEvent::TextPoke(TextPoke {
+            addr: 0xffff_ffff_8100_0000,
+            old_bytes: vec![0x66, 0x90],
+            new_bytes: vec![0xe9, 1, 2, 3, 4],
+        })
+    );
+}
+
+#[test]
+fn text_poke() {
+    // Text poke records are about the kernel, so they need the privileges
+    // for counting kernel activity.
+    match Builder::new()
+        .kind(Software::DUMMY)
+        .include_kernel()
+        .text_poke(true)
+        .build_sampler()
+    {
+        Ok(_) => {}
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EACCES) | Some(libc::EPERM)) => {
+            eprintln!("skipping: {}", e)
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn parse_branch_stack() {
+    use perf_event::samples::{BranchEntry, SampleBranchFlag};
+    use perf_event_open_sys::bindings::PERF_BR_CALL;
+
+    // Two entries: a mispredicted call that took 300 cycles, and a plain
+    // branch. The hardware index comes first, since we ask for it.
+    let call_flags = 1 | (300 << 4) | ((PERF_BR_CALL as u64) << 20);
+    let words: [u64; 8] = [2, 5, 0x1000, 0x2000, call_flags, 0x3000, 0x4000, 2];
+    let record = Record {
+        ty: PERF_RECORD_SAMPLE,
+        misc: 0,
+        data: words.iter().flat_map(|w| w.to_ne_bytes()).collect(),
+    };
+    let config = ParseConfig::new(SampleType::BRANCH_STACK)
+        .with_branch_sample_type(SampleBranchFlag::ANY | SampleBranchFlag::HW_INDEX);
+    let sample = match record.parse(&config) {
+        RecordEvent::Sample(sample) => sample,
+        other => panic!("expected Sample, got {:?}", other),
+    };
+    assert_eq!(sample.branch_hw_index, Some(5));
+    assert_eq!(
+        sample.branch_stack.unwrap(),
+        vec![
+            BranchEntry {
+                from: 0x1000,
+                to: 0x2000,
+                mispredicted: true,
+                predicted: false,
+                in_tx: false,
+                abort: false,
+                cycles: 300,
+                branch_type: PERF_BR_CALL as u8,
+            },
+            BranchEntry {
+                from: 0x3000,
+                to: 0x4000,
+                mispredicted: false,
+                predicted: true,
+                in_tx: false,
+                abort: false,
+                cycles: 0,
+                branch_type: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn branch_stack() {
+    use perf_event::events::Hardware;
+    use perf_event::samples::SampleBranchFlag;
+
+    let mut sampler = match Builder::new()
+        .kind(Hardware::BRANCH_INSTRUCTIONS)
+        .sample_period(10_000)
+        .sample_branch_stack(SampleBranchFlag::USER | SampleBranchFlag::ANY)
+        .build_sampler()
+    {
+        Ok(sampler) => sampler,
+        Err(e) => {
+            eprintln!("skipping: no branch stack sampling: {}", e);
+            return;
+        }
+    };
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            assert!(sample.branch_stack.is_some());
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn parse_regs_user() {
+    use perf_event::regs::{Register, Registers};
+    use perf_event_open_sys::bindings::{PERF_SAMPLE_REGS_ABI_64, PERF_SAMPLE_REGS_ABI_NONE};
+
+    let first = Register::ALL[0];
+    let mask = 1 << first as u32 | 1 << Register::SP as u32;
+    let config = ParseConfig::new(SampleType::REGS_USER).with_sample_regs_user(mask);
+    let parse = |words: &[u64]| {
+        let record = Record {
+            ty: PERF_RECORD_SAMPLE,
+            misc: 0,
+            data: words.iter().flat_map(|w| w.to_ne_bytes()).collect(),
+        };
+        match record.parse(&config) {
+            RecordEvent::Sample(sample) => sample.regs_user.unwrap(),
+            other => panic!("expected Sample, got {:?}", other),
+        }
+    };
+
+    let regs = parse(&[PERF_SAMPLE_REGS_ABI_64 as u64, 17, 0x7fff_0000]);
+    assert_eq!(regs.abi, PERF_SAMPLE_REGS_ABI_64 as u64);
+    assert_eq!(regs.values, vec![(first, 17), (Register::SP, 0x7fff_0000)]);
+    assert_eq!(regs.get(Register::SP), Some(0x7fff_0000));
+
+    // Samples from kernel threads have no user registers.
+    let regs = parse(&[PERF_SAMPLE_REGS_ABI_NONE as u64]);
+    assert_eq!(
+        regs,
+        Registers {
+            abi: PERF_SAMPLE_REGS_ABI_NONE as u64,
+            values: vec![],
+        }
+    );
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn regs_match_bindings() {
+    use perf_event::regs::Register;
+    use perf_event_open_sys::bindings::*;
+
+    let expected = [
+        (Register::AX, PERF_REG_X86_AX),
+        (Register::BX, PERF_REG_X86_BX),
+        (Register::CX, PERF_REG_X86_CX),
+        (Register::DX, PERF_REG_X86_DX),
+        (Register::SI, PERF_REG_X86_SI),
+        (Register::DI, PERF_REG_X86_DI),
+        (Register::BP, PERF_REG_X86_BP),
+        (Register::SP, PERF_REG_X86_SP),
+        (Register::IP, PERF_REG_X86_IP),
+        (Register::FLAGS, PERF_REG_X86_FLAGS),
+        (Register::CS, PERF_REG_X86_CS),
+        (Register::SS, PERF_REG_X86_SS),
+        (Register::DS, PERF_REG_X86_DS),
+        (Register::ES, PERF_REG_X86_ES),
+        (Register::FS, PERF_REG_X86_FS),
+        (Register::GS, PERF_REG_X86_GS),
+        (Register::R8, PERF_REG_X86_R8),
+        (Register::R9, PERF_REG_X86_R9),
+        (Register::R10, PERF_REG_X86_R10),
+        (Register::R11, PERF_REG_X86_R11),
+        (Register::R12, PERF_REG_X86_R12),
+        (Register::R13, PERF_REG_X86_R13),
+        (Register::R14, PERF_REG_X86_R14),
+        (Register::R15, PERF_REG_X86_R15),
+    ];
+    for (register, index) in expected {
+        assert_eq!(register as u32, index, "{}", register.name());
+    }
+    assert_eq!(Register::ALL.len() as u32, PERF_REG_X86_64_MAX);
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn regs_user() {
+    use perf_event::regs::Register;
+    use perf_event_open_sys::bindings::PERF_SAMPLE_REGS_ABI_64;
+
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::IP)
+        .sample_regs_user(&[Register::IP, Register::SP])
+        .build_sampler()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            let regs = sample.regs_user.unwrap();
+            assert_eq!(regs.abi, PERF_SAMPLE_REGS_ABI_64 as u64);
+            assert_eq!(regs.get(Register::IP), sample.ip);
+            assert!(regs.get(Register::SP).unwrap() != 0);
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn parse_data_src() {
+    use perf_event::samples::{DataSource, Hops, MemLevel, MemOp, Snoop, TlbLevel};
+    use perf_event_open_sys::bindings::*;
+
+    let parse = |bits: u64| {
+        let record = Record {
+            ty: PERF_RECORD_SAMPLE,
+            misc: 0,
+            data: bits.to_ne_bytes().to_vec(),
+        };
+        match record.parse(&ParseConfig::new(SampleType::DATA_SRC)) {
+            RecordEvent::Sample(sample) => sample.data_src.unwrap(),
+            other => panic!("expected Sample, got {:?}", other),
+        }
+    };
+
+    // A locked load that hit in a remote node's L3 cache, found modified,
+    // whose translation hit in the L1 or L2 TLB.
+    let bits = (PERF_MEM_OP_LOAD as u64) << PERF_MEM_OP_SHIFT
+        | ((PERF_MEM_LVL_HIT | PERF_MEM_LVL_L3) as u64) << PERF_MEM_LVL_SHIFT
+        | (PERF_MEM_LVLNUM_L3 as u64) << PERF_MEM_LVLNUM_SHIFT
+        | (PERF_MEM_REMOTE_REMOTE as u64) << PERF_MEM_REMOTE_SHIFT
+        | (PERF_MEM_HOPS_1 as u64) << PERF_MEM_HOPS_SHIFT
+        | (PERF_MEM_SNOOP_HITM as u64) << PERF_MEM_SNOOP_SHIFT
+        | (PERF_MEM_LOCK_LOCKED as u64) << PERF_MEM_LOCK_SHIFT
+        | ((PERF_MEM_TLB_HIT | PERF_MEM_TLB_L1 | PERF_MEM_TLB_L2) as u64) << PERF_MEM_TLB_SHIFT;
+    let source = parse(bits);
+    assert_eq!(source.op, Some(MemOp::Load));
+    assert_eq!(source.level, Some(MemLevel::L3));
+    assert_eq!(source.hit, Some(true));
+    assert!(source.remote);
+    assert_eq!(source.hops, Some(Hops::Node));
+    assert_eq!(source.snoop, Some(Snoop::HitModified));
+    assert!(source.locked);
+    assert_eq!(source.tlb, TlbLevel::L1 | TlbLevel::L2);
+    assert_eq!(source.tlb_hit, Some(true));
+    assert_eq!(source.bits(), bits);
+
+    // Older kernels only use the level bitmask, which marks remote levels
+    // itself.
+    let bits = (PERF_MEM_OP_STORE as u64) << PERF_MEM_OP_SHIFT
+        | ((PERF_MEM_LVL_MISS | PERF_MEM_LVL_REM_RAM1) as u64) << PERF_MEM_LVL_SHIFT
+        | (PERF_MEM_SNOOP_NA as u64) << PERF_MEM_SNOOP_SHIFT
+        | (PERF_MEM_TLB_NA as u64) << PERF_MEM_TLB_SHIFT;
+    let source = parse(bits);
+    assert_eq!(source.op, Some(MemOp::Store));
+    assert_eq!(source.level, Some(MemLevel::Ram));
+    assert_eq!(source.hit, Some(false));
+    assert!(source.remote);
+    assert_eq!(source.hops, None);
+    assert_eq!(source.snoop, None);
+    assert!(!source.locked);
+    assert_eq!(source.tlb, TlbLevel::empty());
+    assert_eq!(source.tlb_hit, None);
+
+    // Everything not available.
+    let source: DataSource = parse(0);
+    assert_eq!(source.op, None);
+    assert_eq!(source.level, None);
+}
+
+#[test]
+fn parse_transaction() {
+    use perf_event::samples::Transaction;
+
+    let bits = Transaction::TRANSACTION.bits() | Transaction::CONFLICT.bits() | 0xab << 32;
+    let record = Record {
+        ty: PERF_RECORD_SAMPLE,
+        misc: 0,
+        data: bits.to_ne_bytes().to_vec(),
+    };
+    let txn = match record.parse(&ParseConfig::new(SampleType::TRANSACTION)) {
+        RecordEvent::Sample(sample) => sample.transaction.unwrap(),
+        other => panic!("expected Sample, got {:?}", other),
+    };
+    assert!(txn.contains(Transaction::TRANSACTION | Transaction::CONFLICT));
+    assert!(!txn.intersects(Transaction::ELISION | Transaction::CAPACITY_WRITE));
+    assert_eq!(txn.abort_code(), 0xab);
+}
+
+#[test]
+fn sample_group_read() {
+    use perf_event::Group;
+
+    let mut group = Group::new().unwrap();
+    let mut sampler = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .sample(SampleType::READ)
+        .build_sampler()
+        .unwrap();
+    let clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::CPU_CLOCK)
+        .build()
+        .unwrap();
+
+    group.enable().unwrap();
+    spin(10_000_000);
+    group.disable().unwrap();
+
+    let mut samples = 0;
+    let mut last = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            let counts = sample.read.unwrap().to_counts(sampler.config()).unwrap();
+            assert!(counts.get(sampler.counter()).is_some());
+            assert!(counts[&clock] >= last);
+            last = counts[&clock];
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+    assert!(last > 0);
+
+    // The sampler's own reads would produce the whole group's values.
+    assert_eq!(
+        sampler.read().unwrap_err().kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+    assert!(group.read().unwrap()[&clock] >= last);
+}
+
+#[test]
+fn sample_group_read_with_leader() {
+    let (mut group, leader) = Builder::new()
+        .kind(Software::CPU_CLOCK)
+        .build_group_leader()
+        .unwrap();
+    let mut sampler = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .sample(SampleType::READ)
+        .build_sampler()
+        .unwrap();
+    assert!(sampler.config().counts_leader());
+
+    group.enable().unwrap();
+    spin(10_000_000);
+    group.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            let counts = sample.read.unwrap().to_counts(sampler.config()).unwrap();
+            assert_eq!(counts.len(), 2);
+            assert!(counts.get(&leader).is_some());
+            assert!(counts.get(sampler.counter()).is_some());
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+fn sample_read_standalone() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .sample(SampleType::READ)
+        .build_sampler()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let mut last = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            let read = sample.read.unwrap();
+            assert_eq!(read.values.len(), 1);
+            assert_eq!(read.values[0].id, Some(sampler.id()));
+            last = read.values[0].value;
+        }
+    }
+    assert!(last > 0);
+
+    // Unlike a group member, a standalone sampler can be read on its own,
+    // even though its reads include its id.
+    assert!(sampler.read().unwrap() >= last);
+    let cat = sampler.read_count_and_time().unwrap();
+    assert!(cat.count >= last);
+    assert!(cat.time_enabled > 0);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn next_async() {
+    // Sample a worker thread, so that this thread can wait.
+    let (tid_tx, tid_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let worker = thread::spawn(move || {
+        tid_tx.send(unsafe { libc::gettid() }).unwrap();
+        while done_rx.try_recv().is_err() {
+            spin(100_000);
+        }
+    });
+
+    let mut sampler = Builder::new()
+        .observe_pid(tid_rx.recv().unwrap())
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .build_sampler()
+        .unwrap();
+    sampler.enable().unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .unwrap();
+    let record = runtime.block_on(sampler.next_async()).unwrap();
+    done_tx.send(()).unwrap();
+    worker.join().unwrap();
+
+    assert_eq!(record.unwrap().ty, PERF_RECORD_SAMPLE);
+}
+
+#[test]
+fn poll_events() {
+    use perf_event::samples::PollEvents;
+    use std::os::unix::io::{AsFd, AsRawFd};
+
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    assert_eq!(sampler.poll_events(), PollEvents::default());
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    // The file descriptor is readable, as an event loop would see.
+    let mut pollfd = libc::pollfd {
+        fd: sampler.as_fd().as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 0) }, 1);
+    assert!(pollfd.revents & libc::POLLIN != 0);
+    assert_eq!(
+        sampler.poll_events(),
+        PollEvents {
+            data: true,
+            aux: false
+        }
+    );
+
+    while sampler.next().is_some() {}
+    assert_eq!(sampler.poll_events(), PollEvents::default());
+}
+
+#[test]
+fn clock() {
+    use perf_event::samples::Clock;
+
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .sample(SampleType::TIME)
+        .clock(Clock::Monotonic)
+        .build_sampler()
+        .unwrap();
+    assert_eq!(sampler.clock(), Some(Clock::Monotonic));
+
+    let start = Clock::Monotonic.now();
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+    let end = Clock::Monotonic.now();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            let time = sample.time.unwrap();
+            assert!(start <= time && time <= end);
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+
+    let default = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .build_sampler()
+        .unwrap();
+    assert_eq!(default.clock(), None);
+}
+
+#[test]
+fn time_converter() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .sample(SampleType::TIME)
+        .build_sampler()
+        .unwrap();
+    let converter = match sampler.time_converter() {
+        Some(converter) => converter,
+        None => {
+            eprintln!("skipping: kernel doesn't provide cycle counter conversions");
+            return;
+        }
+    };
+
+    // Conversions round-trip, give or take rounding.
+    let time = 1_000_000_000_000;
+    let cycles = converter.time_to_cycles(time);
+    assert!(converter.cycles_to_time(cycles).abs_diff(time) <= 1);
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        let rdtsc = || unsafe { std::arch::x86_64::_rdtsc() };
+
+        let start = rdtsc();
+        sampler.enable().unwrap();
+        spin(10_000_000);
+        sampler.disable().unwrap();
+        let end = rdtsc();
+
+        let mut samples = 0;
+        while let Some(record) = sampler.next() {
+            if let Some(time) = record.time(sampler.config()) {
+                let cycles = converter.time_to_cycles(time);
+                assert!(start <= cycles && cycles <= end);
+                samples += 1;
+            }
+        }
+        assert!(samples > 0);
+    }
+}
+
+#[test]
+fn buffer_size() {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+
+    assert_eq!(BufferSize::pages(4).unwrap().data_pages(), 4);
+    for pages in [0, 3, 12] {
+        let err = BufferSize::pages(pages).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    assert_eq!(BufferSize::bytes_at_least(0).data_pages(), 1);
+    assert_eq!(BufferSize::bytes_at_least(page_size).data_pages(), 1);
+    assert_eq!(BufferSize::bytes_at_least(page_size + 1).data_pages(), 2);
+    assert_eq!(BufferSize::bytes_at_least(5 * page_size).data_pages(), 8);
+    let size = BufferSize::pages(8).unwrap();
+    assert_eq!(size.data_bytes(), 8 * page_size);
+    assert_eq!(size.mmap_len(), 9 * page_size);
+
+    let sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    assert_eq!(sampler.buffer_size(), BufferSize::default());
+
+    let sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .buffer_size(BufferSize::pages(2).unwrap())
+        .build_sampler()
+        .unwrap();
+    assert_eq!(sampler.buffer_size().data_pages(), 2);
+}
+
+#[test]
+fn iter_chunks() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::IP | SampleType::TID)
+        .build_sampler()
+        .unwrap();
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let config = *sampler.config();
+    let mut samples = 0;
+    let mut chunks = sampler.iter_chunks();
+    while let Some(record) = chunks.next() {
+        if let RecordEvent::Sample(sample) = record.parse(&config) {
+            assert_eq!(sample.pid, Some(std::process::id()));
+            samples += 1;
+        }
+    }
+    drop(chunks);
+    assert!(samples > 0);
+
+    // The records were consumed.
+    assert!(sampler.next().is_none());
+}
+
+#[test]
+fn iter_chunks_wrap_around() {
+    // Samples of 40 bytes don't divide the buffer evenly, so some must wrap
+    // around its end.
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(10_000)
+        .sample(SampleType::IP | SampleType::TID | SampleType::TIME | SampleType::CPU)
+        .buffer_size(BufferSize::pages(1).unwrap())
+        .build_sampler()
+        .unwrap();
+    let config = *sampler.config();
+
+    let mut borrowed = 0;
+    let mut copied = 0;
+    let start = Instant::now();
+    while copied == 0 && start.elapsed() < Duration::from_secs(10) {
+        sampler.enable().unwrap();
+        spin(100_000);
+        sampler.disable().unwrap();
+
+        let mut chunks = sampler.iter_chunks();
+        while let Some(record) = chunks.next() {
+            if let RecordEvent::Sample(sample) = record.parse(&config) {
+                assert_eq!(sample.pid, Some(std::process::id()));
+                assert!(sample.time.is_some());
+                match record.data {
+                    Cow::Borrowed(_) => borrowed += 1,
+                    Cow::Owned(_) => copied += 1,
+                }
+            }
+        }
+    }
+    assert!(borrowed > 0);
+    assert!(copied > 0);
+}
+
+#[test]
+fn drain_into() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::IP | SampleType::TID)
+        .build_sampler()
+        .unwrap();
+    let mut records = vec![];
+    assert_eq!(sampler.drain_into(&mut records).unwrap(), 0);
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    let drained = sampler.drain_into(&mut records).unwrap();
+    assert!(drained > 0);
+    assert_eq!(records.len(), drained);
+    assert!(records
+        .iter()
+        .any(|record| matches!(record.parse(sampler.config()), RecordEvent::Sample(_))));
+
+    // The records were consumed.
+    assert!(sampler.next().is_none());
+    assert_eq!(sampler.drain_into(&mut records).unwrap(), 0);
+    assert_eq!(records.len(), drained);
+}
+
+/// A second mapping of a sampler's ring buffer, for pretending that the
+/// kernel has written records.
+struct RingBuffer {
+    page: *mut perf_event_open_sys::bindings::perf_event_mmap_page,
+    len: usize,
+}
+
+impl RingBuffer {
+    fn map(sampler: &perf_event::samples::Sampler) -> RingBuffer {
+        use std::os::unix::io::AsRawFd;
+
+        let len = sampler.buffer_size().mmap_len();
+        let page = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                sampler.as_raw_fd(),
+                0,
+            )
+        };
+        assert_ne!(page, libc::MAP_FAILED);
+        RingBuffer {
+            page: page as *mut _,
+            len,
+        }
+    }
+
+    /// Claim that the kernel has written records up to `head`. The data
+    /// area is all zeros, so each record's header says its size is zero.
+    fn set_head(&self, head: u64) {
+        unsafe { std::ptr::write_volatile(&mut (*self.page).data_head, head) }
+    }
+
+    fn tail(&self) -> u64 {
+        unsafe { std::ptr::read_volatile(&(*self.page).data_tail) }
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.page as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[test]
+fn drain_into_zero_size() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    let ring = RingBuffer::map(&sampler);
+    ring.set_head(8);
+
+    let mut records = vec![];
+    let error = sampler.drain_into(&mut records).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(records.is_empty());
+    assert_eq!(ring.tail(), 0);
+}
+
+#[test]
+fn next_zero_size() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    let ring = RingBuffer::map(&sampler);
+
+    // A corrupt record makes `next` give up on the whole buffer, rather
+    // than return the same empty record forever.
+    ring.set_head(8);
+    assert!(sampler.next().is_none());
+    assert_eq!(ring.tail(), 8);
+    assert!(sampler.next().is_none());
+
+    ring.set_head(24);
+    assert!(sampler.iter_chunks().next().is_none());
+    assert_eq!(ring.tail(), 24);
+    assert!(sampler.iter_chunks().next().is_none());
+}
+
+#[test]
+fn register_parser() {
+    use perf_event_open_sys::bindings::PERF_RECORD_LOST_SAMPLES;
+
+    #[derive(Debug, PartialEq)]
+    struct Future(usize);
+
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .build_sampler()
+        .unwrap();
+    let future = Record {
+        ty: 0xfff0,
+        misc: 0,
+        data: vec![0; 16],
+    };
+    let lost = Record {
+        ty: PERF_RECORD_LOST_SAMPLES,
+        misc: 0,
+        data: 7_u64.to_ne_bytes().to_vec(),
+    };
+    assert_eq!(sampler.parse(&future), RecordEvent::Unknown);
+
+    sampler.register_parser(0xfff0, |record, _config| Future(record.data.len()));
+    let custom = match sampler.parse(&future) {
+        RecordEvent::Custom(custom) => custom,
+        other => panic!("unexpected record: {:?}", other),
+    };
+    assert_eq!(custom.record_type(), 0xfff0);
+    assert_eq!(custom.downcast_ref::<Future>(), Some(&Future(16)));
+    assert_eq!(custom.downcast_ref::<u64>(), None);
+
+    // Other types are still decoded as usual, unless overridden.
+    assert_eq!(sampler.parse(&lost), RecordEvent::LostSamples(7));
+    sampler.register_parser(PERF_RECORD_LOST_SAMPLES, |_record, _config| "overridden");
+    match sampler.parse(&lost) {
+        RecordEvent::Custom(custom) => assert_eq!(custom.downcast_ref(), Some(&"overridden")),
+        other => panic!("unexpected record: {:?}", other),
+    }
+}
@@ -0,0 +1,109 @@
+#![cfg(feature = "serde")]
+
+use perf_event::events::{Cache, CacheOp, CacheResult, Event, Software, WhichCache};
+#[cfg(feature = "sampling")]
+use perf_event::samples::{ParseConfig, Record, RecordEvent, SampleType};
+use perf_event::{Builder, Counts, Group};
+
+fn spin(iterations: u64) -> u64 {
+    (0..iterations).map(std::hint::black_box).sum()
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn records() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::IP | SampleType::TID | SampleType::TIME)
+        .build_sampler()
+        .unwrap();
+
+    sampler.enable().unwrap();
+    spin(10_000_000);
+    sampler.disable().unwrap();
+
+    // Ship the raw records and the configuration needed to decode them, and
+    // check that the far end decodes the same samples.
+    let config = *sampler.config();
+    let mut records = vec![];
+    while let Some(record) = sampler.next() {
+        records.push(record);
+    }
+    assert!(!records.is_empty());
+
+    let json = serde_json::to_string(&(config, &records)).unwrap();
+    let (shipped_config, shipped): (ParseConfig, Vec<Record>) =
+        serde_json::from_str(&json).unwrap();
+    assert_eq!(shipped_config, config);
+    assert_eq!(shipped, records);
+
+    for record in &records {
+        let event = record.parse(&config);
+        if let RecordEvent::Sample(sample) = &event {
+            assert!(sample.time.is_some());
+        }
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(serde_json::from_str::<RecordEvent>(&json).unwrap(), event);
+    }
+}
+
+#[test]
+fn events() {
+    let events: Vec<Event> = vec![
+        Software::PAGE_FAULTS.into(),
+        Cache {
+            which: WhichCache::L1D,
+            operation: CacheOp::READ,
+            result: CacheResult::MISS,
+        }
+        .into(),
+    ];
+    let json = serde_json::to_string(&events).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Event>>(&json).unwrap(), events);
+}
+
+#[test]
+fn counts() {
+    let mut group = Group::new().unwrap();
+    let mut clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let faults = Builder::new()
+        .group(&mut group)
+        .kind(Software::PAGE_FAULTS)
+        .build()
+        .unwrap();
+
+    group.enable().unwrap();
+    spin(1_000_000);
+    group.disable().unwrap();
+
+    let counts = group.read().unwrap();
+    let json = serde_json::to_string(&counts).unwrap();
+    let shipped: Counts = serde_json::from_str(&json).unwrap();
+    assert_eq!(shipped.time_enabled(), counts.time_enabled());
+    assert_eq!(shipped[&clock], counts[&clock]);
+    assert_eq!(shipped[&faults], counts[&faults]);
+
+    // Data that doesn't have the layout a group read produces is rejected,
+    // rather than making the accessors panic later.
+    for bad in [
+        r#"{"data":[],"first":0}"#,
+        r#"{"data":[2,0,0,1,1],"first":0}"#,
+        r#"{"data":[18446744073709551615,0,0],"first":0}"#,
+        r#"{"data":[1,0,0,1,1],"first":2}"#,
+        r#"{"data":[0,0,0],"first":1}"#,
+    ] {
+        assert!(serde_json::from_str::<Counts>(bad).is_err(), "{}", bad);
+    }
+
+    let with_times = clock.read_count_and_time().unwrap();
+    let json = serde_json::to_string(&with_times).unwrap();
+    assert_eq!(
+        serde_json::from_str::<perf_event::CountAndTime>(&json).unwrap(),
+        with_times
+    );
+}
@@ -0,0 +1,63 @@
+use perf_event::events::Software;
+use perf_event::Builder;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The `si_code` of signals sent for perf events.
+const TRAP_PERF: i32 = 6;
+
+static SIGNALS: AtomicU64 = AtomicU64::new(0);
+static LAST_DATA: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn handler(
+    _signo: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    unsafe {
+        if (*info).si_code != TRAP_PERF {
+            return;
+        }
+        // libc doesn't expose `si_perf_data`. On 64-bit Linux, it follows
+        // the three `int` header fields, padding, and `si_addr`.
+        let data = *((info as *const u8).add(24) as *const u64);
+        LAST_DATA.store(data, Ordering::SeqCst);
+    }
+    SIGNALS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn sigtrap() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as usize;
+        action.sa_flags = libc::SA_SIGINFO;
+        assert_eq!(
+            libc::sigaction(libc::SIGTRAP, &action, std::ptr::null_mut()),
+            0
+        );
+    }
+
+    let mut counter = match Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(1_000_000)
+        .sigtrap(0xfeed)
+        .build()
+    {
+        Ok(counter) => counter,
+        Err(e) => {
+            eprintln!("skipping: no sigtrap support: {}", e);
+            return;
+        }
+    };
+
+    counter.enable().unwrap();
+    let start = Instant::now();
+    while SIGNALS.load(Ordering::SeqCst) < 3 && start.elapsed() < Duration::from_secs(5) {
+        std::hint::black_box(0);
+    }
+    counter.disable().unwrap();
+
+    assert!(SIGNALS.load(Ordering::SeqCst) >= 3);
+    assert_eq!(LAST_DATA.load(Ordering::SeqCst), 0xfeed);
+}
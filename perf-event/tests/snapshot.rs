@@ -0,0 +1,42 @@
+use perf_event::events::Software;
+use perf_event::{Builder, Group};
+
+#[test]
+fn outlives_counters() {
+    let mut group = Group::new().unwrap();
+    let clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build()
+        .unwrap();
+    let faults = Builder::new()
+        .group(&mut group)
+        .kind(Software::PAGE_FAULTS)
+        .build()
+        .unwrap();
+
+    group.enable().unwrap();
+    let _ = vec![0_u8; 1 << 20];
+    group.disable().unwrap();
+
+    let counts = group.read().unwrap();
+    let snapshot = counts.snapshot().label(&clock, "clock");
+    let (clock_id, faults_id) = (clock.id(), faults.id());
+    let (clock_value, faults_value) = (counts[&clock], counts[&faults]);
+    drop((clock, faults, group));
+
+    let copy = snapshot.clone();
+    assert_eq!(copy, snapshot);
+    assert_eq!(copy.len(), 2);
+    assert_eq!(copy.time_enabled(), counts.time_enabled());
+    assert_eq!(copy.time_running(), counts.time_running());
+    assert_eq!(copy["clock"], clock_value);
+    assert_eq!(copy.get_id(clock_id), Some(clock_value));
+    assert_eq!(copy.get_id(faults_id), Some(faults_value));
+    assert_eq!(copy.get("faults"), None);
+
+    let copy = copy.label_id(faults_id, "faults");
+    assert_eq!(copy.get("faults"), Some(faults_value));
+    let labels: Vec<_> = copy.iter().map(|entry| entry.label.as_deref()).collect();
+    assert_eq!(labels, [Some("clock"), Some("faults")]);
+}
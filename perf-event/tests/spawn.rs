@@ -0,0 +1,66 @@
+use perf_event::events::Software;
+use perf_event::spawn::{spawn_counted, wait_with_usage};
+use perf_event::Builder;
+use std::io;
+use std::process::Command;
+
+#[test]
+fn counts_child() {
+    let (mut child, mut counter) = spawn_counted(
+        Command::new("sh").args(["-c", "i=0; while [ $i -lt 1000 ]; do i=$((i+1)); done"]),
+        |pid| {
+            Builder::new()
+                .observe_pid(pid)
+                .enable_on_exec()
+                .kind(Software::TASK_CLOCK)
+                .build()
+        },
+    )
+    .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(counter.read().unwrap() > 0);
+}
+
+#[test]
+fn usage() {
+    let (child, mut counter) = spawn_counted(
+        Command::new("sh").args([
+            "-c",
+            "i=0; while [ $i -lt 1000 ]; do i=$((i+1)); done; exit 3",
+        ]),
+        |pid| {
+            Builder::new()
+                .observe_pid(pid)
+                .enable_on_exec()
+                .kind(Software::TASK_CLOCK)
+                .build()
+        },
+    )
+    .unwrap();
+    let (status, usage) = wait_with_usage(child).unwrap();
+    assert_eq!(status.code(), Some(3));
+    assert!(usage.max_rss_kb > 0);
+    assert!(usage.minor_faults > 0);
+    assert!(counter.read().unwrap() > 0);
+}
+
+#[test]
+fn attach_fails() {
+    let result = spawn_counted(&mut Command::new("true"), |_pid| -> io::Result<()> {
+        Err(io::Error::other("no thanks"))
+    });
+    let err = result.unwrap_err();
+    assert_eq!(err.to_string(), "no thanks");
+}
+
+#[test]
+fn spawn_fails() {
+    let result = spawn_counted(&mut Command::new("/nonexistent/program"), |pid| {
+        Builder::new()
+            .observe_pid(pid)
+            .enable_on_exec()
+            .kind(Software::TASK_CLOCK)
+            .build()
+    });
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+}
@@ -0,0 +1,148 @@
+#![cfg(feature = "spool")]
+
+use perf_event::events::Software;
+use perf_event::samples::{ParseConfig, Record, RecordEvent, SampleType};
+use perf_event::spool::{Compression, RecordReader, RecordWriter};
+use perf_event::Builder;
+use perf_event_open_sys::bindings::PERF_RECORD_SAMPLE;
+use std::io;
+
+fn records(count: u64) -> Vec<Record> {
+    (0..count)
+        .map(|ip| Record {
+            ty: PERF_RECORD_SAMPLE,
+            misc: 0,
+            data: (0x40_0000 + ip * 4).to_ne_bytes().to_vec(),
+        })
+        .collect()
+}
+
+/// Write `records` with `compression`, ending a frame every `per_frame`
+/// records. Return the file, and its length after each frame.
+fn spool(records: &[Record], compression: Compression, per_frame: usize) -> (Vec<u8>, Vec<usize>) {
+    let config = ParseConfig::new(SampleType::IP);
+    let mut writer = RecordWriter::with_compression(vec![], &config, compression).unwrap();
+    writer.set_frame_size(usize::MAX);
+    let mut ends = vec![];
+    for chunk in records.chunks(per_frame) {
+        for record in chunk {
+            writer.write(record).unwrap();
+        }
+        writer.flush().unwrap();
+        ends.push(writer.get_ref().len());
+    }
+    (writer.finish().unwrap(), ends)
+}
+
+fn read_all(file: &[u8]) -> (ParseConfig, Vec<io::Result<Record>>) {
+    let reader = RecordReader::new(file).unwrap();
+    let config = *reader.config();
+    (config, reader.collect())
+}
+
+#[test]
+fn plain() {
+    let records = records(100);
+    let (file, _) = spool(&records, Compression::None, 30);
+
+    // Plain spool files are JSON Lines, one record per line after the
+    // configuration.
+    let text = std::str::from_utf8(&file).unwrap();
+    assert_eq!(text.lines().count(), 101);
+
+    let (config, read) = read_all(&file);
+    assert_eq!(config, ParseConfig::new(SampleType::IP));
+    let read: Vec<Record> = read.into_iter().map(Result::unwrap).collect();
+    assert_eq!(read, records);
+}
+
+#[test]
+fn sampler() {
+    let mut sampler = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .sample_period(100_000)
+        .sample(SampleType::IP | SampleType::TIME)
+        .build_sampler()
+        .unwrap();
+    sampler.enable().unwrap();
+    let _spin: u64 = (0..10_000_000_u64).map(std::hint::black_box).sum();
+    sampler.disable().unwrap();
+
+    let mut writer = RecordWriter::new(vec![], sampler.config()).unwrap();
+    let mut written = vec![];
+    while let Some(record) = sampler.next() {
+        writer.write(&record).unwrap();
+        written.push(record);
+    }
+    assert!(!written.is_empty());
+    let file = writer.finish().unwrap();
+
+    let (config, read) = read_all(&file);
+    assert_eq!(&config, sampler.config());
+    let read: Vec<Record> = read.into_iter().map(Result::unwrap).collect();
+    assert_eq!(read, written);
+    assert!(read
+        .iter()
+        .any(|record| matches!(record.parse(&config), RecordEvent::Sample(_))));
+}
+
+#[test]
+fn empty() {
+    let err = RecordReader::new(&b""[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn dropped_writer_flushes() {
+    let records = records(3);
+    let mut file = vec![];
+    {
+        let config = ParseConfig::new(SampleType::IP);
+        let mut writer = RecordWriter::new(&mut file, &config).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+    }
+    let (_, read) = read_all(&file);
+    assert_eq!(read.len(), 3);
+}
+
+/// Check that `compression` round-trips, and that a file cut off in its
+/// last frame still yields the records of the frames before it.
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+fn compressed(compression: Compression, magic: [u8; 4]) {
+    let records = records(1000);
+    let (file, ends) = spool(&records, compression, 100);
+    assert!(file.starts_with(&magic));
+    assert!(file.len() < serde_json::to_vec(&records).unwrap().len());
+
+    let (_, read) = read_all(&file);
+    let read: Vec<Record> = read.into_iter().map(Result::unwrap).collect();
+    assert_eq!(read, records);
+
+    let cut = ends[ends.len() - 2] + (ends[ends.len() - 1] - ends[ends.len() - 2]) / 2;
+    let (_, read) = read_all(&file[..cut]);
+    let good: Vec<Record> = read.into_iter().map_while(Result::ok).collect();
+    assert!(good.len() >= 900);
+    assert_eq!(good[..900], records[..900]);
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn lz4() {
+    compressed(Compression::Lz4, [0x04, 0x22, 0x4d, 0x18]);
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn zstd() {
+    compressed(Compression::Zstd(3), [0x28, 0xb5, 0x2f, 0xfd]);
+}
+
+#[test]
+#[cfg(not(feature = "lz4"))]
+fn lz4_unsupported() {
+    let file = [0x04, 0x22, 0x4d, 0x18, 0, 0, 0, 0];
+    let err = RecordReader::new(&file[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
@@ -0,0 +1,77 @@
+use perf_event::events::{Hardware, Software};
+use perf_event::stat::StatBuilder;
+use std::process::Command;
+
+fn spin(iterations: u64) -> u64 {
+    (0..iterations).map(std::hint::black_box).sum()
+}
+
+#[test]
+#[cfg(feature = "discovery")]
+fn run_closure() {
+    let (sum, report) = StatBuilder::new()
+        .event("clock", Software::TASK_CLOCK)
+        .parse_events("page-faults,cs")
+        .unwrap()
+        .run(|| spin(1_000_000))
+        .unwrap();
+    assert_eq!(sum, 999_999 * 1_000_000 / 2);
+
+    let names: Vec<_> = report.entries().iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["clock", "page-faults", "cs"]);
+    assert!(report.get("clock").unwrap() > 0);
+    assert_eq!(report.value(Software::TASK_CLOCK), report.get("clock"));
+    assert!(report.elapsed().as_nanos() > 0);
+    assert!(report.usage().is_none());
+}
+
+#[test]
+fn threads_are_counted() {
+    let (_, report) = StatBuilder::new()
+        .event("clock", Software::TASK_CLOCK)
+        .run(|| std::thread::spawn(|| spin(10_000_000)).join().unwrap())
+        .unwrap();
+
+    // The spawned thread did nearly all the work, so its time must be
+    // included for the count to reach the elapsed time's scale.
+    let clock = report.get("clock").unwrap();
+    assert!(clock > report.elapsed().as_nanos() as u64 / 4);
+}
+
+#[test]
+fn run_command() {
+    let (status, report) = StatBuilder::new()
+        .event("clock", Software::TASK_CLOCK)
+        .run_command(Command::new("true").arg("ignored"))
+        .unwrap();
+    assert!(status.success());
+    assert!(report.get("clock").unwrap() > 0);
+    assert!(report.usage().unwrap().max_rss_kb > 0);
+    assert!(report.to_string().contains("seconds user"));
+}
+
+#[test]
+fn unsupported_events() {
+    let (_, report) = StatBuilder::new()
+        .default_events()
+        .event("misses", Hardware::CACHE_MISSES)
+        .run(|| spin(1_000_000))
+        .unwrap();
+    assert!(report.get("task-clock").unwrap() > 0);
+
+    // Whether or not this machine can count hardware events, the derived
+    // metrics must agree with the counts.
+    match (report.get("instructions"), report.get("cycles")) {
+        (Some(insns), Some(cycles)) if cycles > 0 => {
+            assert_eq!(report.ipc(), Some(insns as f64 / cycles as f64))
+        }
+        _ => assert_eq!(report.ipc(), None),
+    }
+    if report.value(Hardware::CACHE_REFERENCES).is_none() {
+        assert_eq!(report.cache_miss_rate(), None);
+    }
+
+    let text = report.to_string();
+    assert!(text.contains("task-clock"));
+    assert!(text.contains("seconds time elapsed"));
+}
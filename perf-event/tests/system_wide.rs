@@ -0,0 +1,88 @@
+use perf_event::events::Software;
+use perf_event::per_cpu::{online_cpus, PerCpuCounter};
+use perf_event::Builder;
+use std::io;
+use std::time::Duration;
+
+#[test]
+fn context_switches_on_each_cpu() {
+    let cpus = std::thread::available_parallelism().unwrap().get();
+    let counters = (0..cpus)
+        .map(|cpu| {
+            Builder::new()
+                .system_wide_on_cpu(cpu)
+                .include_kernel()
+                .kind(Software::CONTEXT_SWITCHES)
+                .build()
+        })
+        .collect::<io::Result<Vec<_>>>();
+
+    // System-wide monitoring needs privileges the test may not have.
+    let mut counters = match counters {
+        Ok(counters) => counters,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            eprintln!("skipping: {}", e);
+            return;
+        }
+        Err(e) => panic!("couldn't open system-wide counters: {}", e),
+    };
+
+    for counter in &mut counters {
+        counter.enable().unwrap();
+    }
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let total: u64 = counters.iter_mut().map(|c| c.read().unwrap()).sum();
+
+    // Each sleep is at least one context switch away from this thread.
+    assert!(total >= 10, "only {} context switches", total);
+}
+
+#[test]
+fn any_pid_requires_cpu() {
+    let err = Builder::new()
+        .any_pid()
+        .kind(Software::CONTEXT_SWITCHES)
+        .build()
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn online() {
+    let cpus = online_cpus().unwrap();
+    assert!(!cpus.is_empty());
+    assert!(cpus.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn per_cpu_counter() {
+    let counter = PerCpuCounter::on_online_cpus(|cpu| {
+        Builder::new()
+            .system_wide_on_cpu(cpu)
+            .include_kernel()
+            .kind(Software::CONTEXT_SWITCHES)
+            .build()
+    });
+    let mut counter = match counter {
+        Ok(counter) => counter,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            eprintln!("skipping: {}", e);
+            return;
+        }
+        Err(e) => panic!("couldn't open per-CPU counters: {}", e),
+    };
+    assert!(counter.cpus().eq(online_cpus().unwrap()));
+
+    counter.enable().unwrap();
+    for _ in 0..10 {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    counter.disable().unwrap();
+
+    let per_cpu = counter.read_per_cpu().unwrap();
+    let total = counter.read().unwrap();
+    assert_eq!(per_cpu.iter().map(|&(_, count)| count).sum::<u64>(), total);
+    assert!(total >= 10, "only {} context switches", total);
+}
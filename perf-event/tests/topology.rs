@@ -0,0 +1,42 @@
+#![cfg(feature = "discovery")]
+
+use perf_event::events::{Hardware, Software};
+use perf_event::per_cpu::online_cpus;
+use perf_event::topology::{core_types, is_hybrid, HybridCounter};
+use perf_event::Builder;
+
+#[test]
+fn core_types_cover_online_cpus() {
+    let types = core_types().unwrap();
+    assert!(!types.is_empty());
+    assert_eq!(is_hybrid().unwrap(), types.len() > 1);
+
+    let mut cpus: Vec<usize> = types.iter().flat_map(|t| t.cpus().to_vec()).collect();
+    cpus.sort_unstable();
+    for cpu in online_cpus().unwrap() {
+        assert!(cpus.contains(&cpu), "cpu {} has no core type", cpu);
+    }
+
+    if types.len() == 1 {
+        assert_eq!(types[0].name(), "cpu");
+        assert_eq!(types[0].pmu_type(), None);
+        assert_eq!(
+            types[0].hardware(Hardware::CPU_CYCLES),
+            Hardware::CPU_CYCLES.into()
+        );
+    }
+}
+
+#[test]
+fn hybrid_counter() {
+    let mut clock =
+        HybridCounter::new(|_core| Builder::new().kind(Software::TASK_CLOCK).build()).unwrap();
+    clock.enable().unwrap();
+    std::hint::black_box((0..1_000_000_u64).sum::<u64>());
+    clock.disable().unwrap();
+
+    let per_type = clock.read_per_core_type().unwrap();
+    assert_eq!(per_type.len(), core_types().unwrap().len());
+    let sum: u64 = per_type.iter().map(|&(_, count)| count).sum();
+    assert!(sum > 0);
+}
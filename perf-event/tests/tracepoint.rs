@@ -0,0 +1,184 @@
+use perf_event::events::Tracepoint;
+#[cfg(feature = "sampling")]
+use perf_event::samples::{RecordEvent, SampleType};
+#[cfg(feature = "sampling")]
+use perf_event::tracepoint::{Format, Value};
+use perf_event::Builder;
+use std::io::ErrorKind;
+
+/// Look up `name`, or return `None` if tracefs isn't available here.
+fn tracepoint(name: &str) -> Option<Tracepoint> {
+    match Tracepoint::new(name) {
+        Ok(tp) => Some(tp),
+        Err(e) if e.kind() == ErrorKind::NotFound || e.kind() == ErrorKind::PermissionDenied => {
+            eprintln!("skipping: {}", e);
+            None
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn bad_names() {
+    assert_eq!(
+        Tracepoint::new("sched_switch").unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        Tracepoint::new("sched:").unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    let error = Tracepoint::new("no_such_system:no_such_event").unwrap_err();
+    assert!(error.kind() == ErrorKind::NotFound || error.kind() == ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn count_switches() {
+    let switches = match tracepoint("sched:sched_switch") {
+        Some(tp) => tp,
+        None => return,
+    };
+    assert_eq!(switches.system(), "sched");
+    assert_eq!(switches.name(), "sched_switch");
+    assert_eq!(switches.to_string(), "sched:sched_switch");
+
+    let mut counter = Builder::new().kind(switches).build().unwrap();
+    counter.enable().unwrap();
+    for _ in 0..10 {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    counter.disable().unwrap();
+    assert!(counter.read().unwrap() >= 10);
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn sample_raw() {
+    let switches = match tracepoint("sched:sched_switch") {
+        Some(tp) => tp,
+        None => return,
+    };
+
+    let mut sampler = Builder::new()
+        .kind(switches)
+        .sample_period(1)
+        .sample(SampleType::RAW)
+        .build_sampler()
+        .unwrap();
+    sampler.enable().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1));
+    sampler.disable().unwrap();
+
+    let mut samples = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            // Every tracepoint record starts with a common header.
+            assert!(sample.raw.unwrap().len() > 8);
+            samples += 1;
+        }
+    }
+    assert!(samples > 0);
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn decode_switch() {
+    let switches = match tracepoint("sched:sched_switch") {
+        Some(tp) => tp,
+        None => return,
+    };
+    let format = switches.format().unwrap();
+    assert_eq!(format.name(), "sched_switch");
+    assert_eq!(format.id(), switches.id());
+    assert_eq!(format.field("prev_comm").unwrap().type_name, "char[16]");
+
+    let mut sampler = Builder::new()
+        .kind(switches)
+        .sample_period(1)
+        .sample(SampleType::RAW | SampleType::TID)
+        .build_sampler()
+        .unwrap();
+    sampler.enable().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(1));
+    sampler.disable().unwrap();
+
+    let mut ours = 0;
+    while let Some(record) = sampler.next() {
+        if let RecordEvent::Sample(sample) = record.parse(sampler.config()) {
+            let raw = sample.raw.unwrap();
+            let (pid, tid) = (sample.pid.unwrap(), sample.tid.unwrap());
+            // We only observe our own thread, so it's always switching out.
+            assert_eq!(
+                format.value("prev_pid", &raw),
+                Some(Value::Signed(tid as i64))
+            );
+            assert_eq!(
+                format.value("common_pid", &raw),
+                Some(Value::Signed(tid as i64))
+            );
+            assert!(matches!(
+                format.value("next_comm", &raw),
+                Some(Value::Str(_))
+            ));
+            assert!(format
+                .values(&raw)
+                .iter()
+                .any(|(name, _)| *name == "next_pid"));
+            assert_eq!(pid, std::process::id());
+            ours += 1;
+        }
+    }
+    assert!(ours > 0);
+}
+
+#[test]
+#[cfg(feature = "sampling")]
+fn parse_format() {
+    let format = Format::parse(
+        "name: example\n\
+         ID: 42\n\
+         format:\n\
+         \tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;\n\
+         \tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;\n\
+         \n\
+         \tfield:char comm[4];\toffset:8;\tsize:4;\tsigned:0;\n\
+         \tfield:short delta;\toffset:12;\tsize:2;\tsigned:1;\n\
+         \tfield:__data_loc char[] path;\toffset:16;\tsize:4;\tsigned:0;\n\
+         \tfield:u8 bytes[2];\toffset:20;\tsize:2;\tsigned:0;\n\
+         \n\
+         print fmt: \"whatever\"\n",
+    )
+    .unwrap();
+    assert_eq!(format.name(), "example");
+    assert_eq!(format.id(), 42);
+    assert_eq!(format.fields().len(), 6);
+    assert_eq!(format.field("path").unwrap().type_name, "__data_loc char[]");
+
+    let mut raw = vec![0_u8; 24];
+    raw[4..8].copy_from_slice(&1234_i32.to_ne_bytes());
+    raw[8..11].copy_from_slice(b"abc");
+    raw[12..14].copy_from_slice(&(-5_i16).to_ne_bytes());
+    raw[16..20].copy_from_slice(&(24_u32 | 6 << 16).to_ne_bytes());
+    raw[20..22].copy_from_slice(&[7, 8]);
+    raw.extend_from_slice(b"/tmp/\0");
+
+    assert_eq!(format.value("common_pid", &raw), Some(Value::Signed(1234)));
+    assert_eq!(format.value("comm", &raw), Some(Value::Str("abc".into())));
+    assert_eq!(format.value("delta", &raw), Some(Value::Signed(-5)));
+    assert_eq!(format.value("path", &raw), Some(Value::Str("/tmp/".into())));
+    assert_eq!(format.value("bytes", &raw), Some(Value::Bytes(&[7, 8])));
+    assert_eq!(format.value("missing", &raw), None);
+    assert_eq!(format.value("path", &raw[..20]), None);
+
+    let names: Vec<&str> = format
+        .values(&raw)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, ["comm", "delta", "path", "bytes"]);
+
+    assert_eq!(
+        Format::parse("not a format").unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
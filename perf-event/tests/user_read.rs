@@ -0,0 +1,82 @@
+use perf_event::events::{Hardware, Software};
+use perf_event::{Builder, Group};
+use std::io;
+
+#[test]
+fn software_falls_back() {
+    let mut clock = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .build_user_read()
+        .unwrap();
+    // Software events can't be read directly.
+    assert_eq!(clock.read_user(), None);
+
+    clock.enable().unwrap();
+    let first = clock.read().unwrap();
+    let _spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+    let second = clock.read().unwrap();
+    clock.disable().unwrap();
+    assert!(first < second);
+    assert!(second <= clock.counter_mut().read().unwrap());
+
+    let mut counter = clock.into_counter();
+    counter.read().unwrap();
+}
+
+#[test]
+fn hardware() {
+    let mut insns = match Builder::new()
+        .kind(Hardware::INSTRUCTIONS)
+        .build_user_read()
+    {
+        Ok(insns) => insns,
+        Err(e) => {
+            eprintln!("skipping: no hardware instruction counter: {}", e);
+            return;
+        }
+    };
+    // Disabled counters aren't on a hardware counter.
+    assert_eq!(insns.read_user(), None);
+
+    insns.enable().unwrap();
+    let first = insns.read().unwrap();
+    let _spin: u64 = (0..1_000_000_u64).map(std::hint::black_box).sum();
+    let second = insns.read().unwrap();
+    insns.disable().unwrap();
+    assert!(second - first >= 1_000_000);
+    assert!(second <= insns.counter_mut().read().unwrap());
+    eprintln!("read directly: {}", insns.read_user().is_some());
+}
+
+#[test]
+fn group_member() {
+    let mut group = Group::new().unwrap();
+    let mut clock = Builder::new()
+        .group(&mut group)
+        .kind(Software::TASK_CLOCK)
+        .build_user_read()
+        .unwrap();
+    group.enable().unwrap();
+    assert!(clock.read().unwrap() > 0);
+}
+
+#[test]
+fn other_targets_rejected() {
+    let other = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .observe_pid(std::process::id() as libc::pid_t)
+        .build_user_read();
+    assert_eq!(other.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+    let pinned = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .one_cpu(0)
+        .build_user_read();
+    assert_eq!(pinned.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+    let inherited = Builder::new()
+        .kind(Software::TASK_CLOCK)
+        .inherit(true)
+        .build_user_read();
+    assert_eq!(inherited.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
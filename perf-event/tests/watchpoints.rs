@@ -0,0 +1,89 @@
+use perf_event::events::Breakpoint;
+use perf_event::watchpoints::{DebugRegisters, Watchpoints};
+use perf_event::Builder;
+use std::io::ErrorKind;
+
+#[inline(never)]
+fn touch(data: &mut [u64]) {
+    for word in data {
+        unsafe { std::ptr::write_volatile(word, std::ptr::read_volatile(word) + 1) };
+    }
+}
+
+#[test]
+fn validate() {
+    assert!(Breakpoint::read_write(0x1000, 8).validate().is_ok());
+    assert!(Breakpoint::read_write(0x1000, 16).validate().is_err());
+
+    let error = Breakpoint::read_write(0x1000, 0).validate().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+    if cfg!(target_arch = "x86_64") {
+        assert!(Breakpoint::write(0x1004, 8).validate().is_err());
+        assert!(Breakpoint::write(0x1004, 4).validate().is_ok());
+        assert!(Breakpoint::write(0x1001, 3).validate().is_err());
+        assert!(Breakpoint::execute(0x1003).validate().is_ok());
+    }
+    if cfg!(target_arch = "aarch64") {
+        assert!(Breakpoint::write(0x1001, 3).validate().is_ok());
+        assert!(Breakpoint::write(0x1006, 4).validate().is_err());
+        assert!(Breakpoint::execute(0x1003).validate().is_err());
+    }
+}
+
+#[test]
+fn build_rejects_invalid() {
+    let data = [0_u64; 2];
+    let addr = &data[0] as *const u64 as u64;
+    let error = Builder::new()
+        .kind(Breakpoint::write(addr, 16))
+        .build()
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn budget() {
+    let mut data = [0_u64; 4];
+    let addr = |i: usize| &data[i] as *const u64 as u64;
+    let mut watches = Watchpoints::with_budget(DebugRegisters::Shared(2));
+
+    let first = watches
+        .add(Builder::new(), Breakpoint::write(addr(0), 8))
+        .unwrap();
+    let second = watches
+        .add(Builder::new(), Breakpoint::write(addr(2), 8))
+        .unwrap();
+    let third = Breakpoint::write(addr(3), 8);
+    assert!(!watches.has_room_for(&third));
+    let error = watches.add(Builder::new(), third).unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(libc::ENOSPC));
+
+    // Removing one makes room again, and its id gets reused.
+    assert!(watches.remove(first).is_some());
+    assert!(watches.remove(first).is_none());
+    assert_eq!(watches.len(), 1);
+    let third = watches.add(Builder::new(), third).unwrap();
+    assert_eq!(third, first);
+
+    watches.enable_all().unwrap();
+    touch(&mut data[2..]);
+    touch(&mut data[2..]);
+    watches.disable_all().unwrap();
+    assert_eq!(watches.get_mut(second).unwrap().read().unwrap(), 2);
+    assert_eq!(watches.get_mut(third).unwrap().read().unwrap(), 2);
+}
+
+#[test]
+fn separate_budget() {
+    let data = [0_u64; 2];
+    let mut watches = Watchpoints::with_budget(DebugRegisters::Separate { code: 0, data: 1 });
+    assert!(!watches.has_room_for(&Breakpoint::execute(touch as fn(_) as usize as u64)));
+    watches
+        .add(
+            Builder::new(),
+            Breakpoint::read_write(&data[0] as *const u64 as u64, 8),
+        )
+        .unwrap();
+    assert!(!watches.has_room_for(&Breakpoint::read(&data[1] as *const u64 as u64, 8)));
+}
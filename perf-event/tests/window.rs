@@ -0,0 +1,56 @@
+use perf_event::events::Breakpoint;
+use perf_event::window::TriggeredWindow;
+use perf_event::Builder;
+
+#[inline(never)]
+fn start() {
+    std::hint::black_box(());
+}
+
+#[inline(never)]
+fn stop() {
+    std::hint::black_box(());
+}
+
+#[inline(never)]
+fn work() {
+    std::hint::black_box(());
+}
+
+fn addr(f: fn()) -> u64 {
+    f as usize as u64
+}
+
+#[test]
+fn counts_only_inside_window() {
+    let work_calls = Builder::new()
+        .kind(Breakpoint::execute(addr(work)))
+        .build()
+        .expect("Unable to build performance counter");
+
+    let mut window = TriggeredWindow::new(
+        work_calls,
+        Breakpoint::execute(addr(start)),
+        Breakpoint::execute(addr(stop)),
+    )
+    .expect("Unable to build window");
+    window.arm().unwrap();
+
+    for _ in 0..5 {
+        work();
+    }
+    for _ in 0..10 {
+        start();
+        for _ in 0..3 {
+            work();
+        }
+        stop();
+        work();
+    }
+
+    window.disarm().unwrap();
+    assert_eq!(window.entries().unwrap(), 10);
+
+    let mut work_calls = window.into_inner();
+    assert_eq!(work_calls.read().unwrap(), 30);
+}
@@ -0,0 +1,88 @@
+use perf_event::events::Software;
+use perf_event::workers::WorkerProfiler;
+
+fn spin() {
+    let mut v: Vec<u32> = (0..100_000).rev().collect();
+    v.sort();
+    std::hint::black_box(v);
+}
+
+#[test]
+fn spans_across_threads() {
+    let profiler = WorkerProfiler::new(vec![Software::TASK_CLOCK.into()]);
+
+    let workers: Vec<_> = (0..3)
+        .map(|_| {
+            let profiler = profiler.clone();
+            std::thread::spawn(move || {
+                profiler.start_worker().unwrap();
+                for _ in 0..5 {
+                    profiler.span("outer", || profiler.span("inner", spin));
+                }
+                profiler.stop_worker();
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let spans = profiler.spans();
+    assert_eq!(spans.len(), 2);
+    let (inner, outer) = (&spans[0], &spans[1]);
+    assert_eq!(inner.0, "inner");
+    assert_eq!(outer.0, "outer");
+    assert_eq!(inner.1.calls, 15);
+    assert_eq!(outer.1.calls, 15);
+    assert!(inner.1.counts[0] > 0);
+    assert!(outer.1.counts[0] >= inner.1.counts[0]);
+}
+
+#[test]
+fn unstarted_thread_runs_unrecorded() {
+    let profiler = WorkerProfiler::new(vec![Software::TASK_CLOCK.into()]);
+    assert_eq!(profiler.span("work", || 42), 42);
+    assert!(profiler.spans().is_empty());
+}
+
+#[test]
+fn span_panics() {
+    let profiler = WorkerProfiler::new(vec![Software::TASK_CLOCK.into()]);
+    profiler.start_worker().unwrap();
+
+    let result = std::panic::catch_unwind(|| {
+        profiler.span("outer", || profiler.span("boom", || panic!("boom")))
+    });
+    assert!(result.is_err());
+
+    // The panicking spans weren't recorded, and the worker's state was
+    // restored, so later spans are counted normally.
+    assert!(profiler.spans().is_empty());
+    profiler.span("after", spin);
+    let spans = profiler.spans();
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].0, "after");
+    assert_eq!(spans[0].1.calls, 1);
+    assert!(spans[0].1.time_enabled > 0);
+    profiler.stop_worker();
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn rayon_pool() {
+    let profiler = WorkerProfiler::new(vec![Software::TASK_CLOCK.into()]);
+    let pool = profiler
+        .rayon_pool(rayon::ThreadPoolBuilder::new().num_threads(2))
+        .build()
+        .unwrap();
+    pool.scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|_| profiler.span("spin", spin));
+        }
+    });
+    drop(pool);
+
+    let spans = profiler.spans();
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].1.calls, 4);
+}